@@ -9,8 +9,20 @@ pub use m20250101_000000_create_table::AccountType;
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20250101_000000_create_table::Migration)]
+        vec![
+            Box::new(m20250101_000000_create_table::Migration),
+            Box::new(m20261019_000001_create_audit_event::Migration),
+            Box::new(m20261019_000002_create_import_batch::Migration),
+            Box::new(m20261019_000003_create_expense_group::Migration),
+            Box::new(m20261019_000004_create_subscription::Migration),
+            Box::new(m20261019_000005_add_import_batch_entry_after::Migration),
+        ]
     }
 }
 
 mod m20250101_000000_create_table;
+mod m20261019_000001_create_audit_event;
+mod m20261019_000002_create_import_batch;
+mod m20261019_000003_create_expense_group;
+mod m20261019_000004_create_subscription;
+mod m20261019_000005_add_import_batch_entry_after;
@@ -0,0 +1,58 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditEvent::Table)
+                    .if_not_exists()
+                    .col(uuid(AuditEvent::Id).primary_key())
+                    .col(string(AuditEvent::EntityType))
+                    .col(string(AuditEvent::EntityId))
+                    .col(string(AuditEvent::Action))
+                    .col(json_null(AuditEvent::Before))
+                    .col(json_null(AuditEvent::After))
+                    .col(uuid(AuditEvent::UserId))
+                    .col(string_null(AuditEvent::RequestId))
+                    .col(timestamp(AuditEvent::CreatedAt).default("CURRENT_TIMESTAMP"))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_audit_event_entity")
+                    .table(AuditEvent::Table)
+                    .col(AuditEvent::EntityType)
+                    .col(AuditEvent::EntityId)
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuditEvent::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AuditEvent {
+    Table,
+    Id,
+    EntityType,
+    EntityId,
+    Action,
+    Before,
+    After,
+    UserId,
+    RequestId,
+    CreatedAt,
+}
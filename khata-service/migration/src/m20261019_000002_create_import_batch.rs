@@ -0,0 +1,97 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImportBatch::Table)
+                    .if_not_exists()
+                    .col(uuid(ImportBatch::Id).primary_key())
+                    .col(string(ImportBatch::FileName))
+                    .col(string(ImportBatch::Format))
+                    .col(integer(ImportBatch::RowCount))
+                    .col(timestamp(ImportBatch::CreatedAt).default("CURRENT_TIMESTAMP"))
+                    .col(timestamp_null(ImportBatch::RolledBackAt))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(ImportBatchEntry::Table)
+                    .if_not_exists()
+                    .col(uuid(ImportBatchEntry::Id).primary_key())
+                    .col(uuid(ImportBatchEntry::BatchId))
+                    .col(uuid(ImportBatchEntry::TransactionId))
+                    .col(string(ImportBatchEntry::Action))
+                    .col(json_null(ImportBatchEntry::Before))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_import_batch_entry_batch_id")
+                            .from(ImportBatchEntry::Table, ImportBatchEntry::BatchId)
+                            .to(ImportBatch::Table, ImportBatch::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Transaction::Table)
+                    .add_column(uuid_null(Transaction::ImportBatchId))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Transaction::Table)
+                    .drop_column(Transaction::ImportBatchId)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .drop_table(Table::drop().table(ImportBatchEntry::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(ImportBatch::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ImportBatch {
+    Table,
+    Id,
+    FileName,
+    Format,
+    RowCount,
+    CreatedAt,
+    RolledBackAt,
+}
+
+#[derive(DeriveIden)]
+enum ImportBatchEntry {
+    Table,
+    Id,
+    BatchId,
+    TransactionId,
+    Action,
+    Before,
+}
+
+#[derive(DeriveIden)]
+enum Transaction {
+    Table,
+    ImportBatchId,
+}
@@ -0,0 +1,225 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ExpenseGroup::Table)
+                    .if_not_exists()
+                    .col(uuid(ExpenseGroup::Id).primary_key())
+                    .col(string(ExpenseGroup::Name))
+                    .col(timestamp(ExpenseGroup::CreatedAt).default("CURRENT_TIMESTAMP"))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(GroupMember::Table)
+                    .if_not_exists()
+                    .col(uuid(GroupMember::Id).primary_key())
+                    .col(uuid(GroupMember::GroupId))
+                    .col(string(GroupMember::Name))
+                    .col(uuid_null(GroupMember::AccountId))
+                    .col(uuid_null(GroupMember::UserId))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_group_member_group_id")
+                            .from(GroupMember::Table, GroupMember::GroupId)
+                            .to(ExpenseGroup::Table, ExpenseGroup::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_group_member_account_id")
+                            .from(GroupMember::Table, GroupMember::AccountId)
+                            .to(Account::Table, Account::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(GroupExpense::Table)
+                    .if_not_exists()
+                    .col(uuid(GroupExpense::Id).primary_key())
+                    .col(uuid(GroupExpense::GroupId))
+                    .col(string(GroupExpense::Title))
+                    .col(uuid(GroupExpense::PaidBy))
+                    .col(big_integer(GroupExpense::Amount))
+                    .col(string(GroupExpense::CurrencyCode))
+                    .col(json(GroupExpense::Split))
+                    .col(timestamp(GroupExpense::Timestamp).default("CURRENT_TIMESTAMP"))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_group_expense_group_id")
+                            .from(GroupExpense::Table, GroupExpense::GroupId)
+                            .to(ExpenseGroup::Table, ExpenseGroup::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_group_expense_paid_by")
+                            .from(GroupExpense::Table, GroupExpense::PaidBy)
+                            .to(GroupMember::Table, GroupMember::Id)
+                            .on_delete(ForeignKeyAction::Restrict)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(GroupExpenseShare::Table)
+                    .if_not_exists()
+                    .col(uuid(GroupExpenseShare::Id).primary_key())
+                    .col(uuid(GroupExpenseShare::ExpenseId))
+                    .col(uuid(GroupExpenseShare::MemberId))
+                    .col(big_integer(GroupExpenseShare::Amount))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_group_expense_share_expense_id")
+                            .from(GroupExpenseShare::Table, GroupExpenseShare::ExpenseId)
+                            .to(GroupExpense::Table, GroupExpense::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_group_expense_share_member_id")
+                            .from(GroupExpenseShare::Table, GroupExpenseShare::MemberId)
+                            .to(GroupMember::Table, GroupMember::Id)
+                            .on_delete(ForeignKeyAction::Restrict)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(GroupSettlement::Table)
+                    .if_not_exists()
+                    .col(uuid(GroupSettlement::Id).primary_key())
+                    .col(uuid(GroupSettlement::GroupId))
+                    .col(uuid(GroupSettlement::FromMemberId))
+                    .col(uuid(GroupSettlement::ToMemberId))
+                    .col(big_integer(GroupSettlement::Amount))
+                    .col(string(GroupSettlement::CurrencyCode))
+                    .col(uuid_null(GroupSettlement::TransactionId))
+                    .col(timestamp(GroupSettlement::Timestamp).default("CURRENT_TIMESTAMP"))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_group_settlement_group_id")
+                            .from(GroupSettlement::Table, GroupSettlement::GroupId)
+                            .to(ExpenseGroup::Table, ExpenseGroup::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_group_settlement_transaction_id")
+                            .from(GroupSettlement::Table, GroupSettlement::TransactionId)
+                            .to(Transaction::Table, Transaction::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(GroupSettlement::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(GroupExpenseShare::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(GroupExpense::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(GroupMember::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(ExpenseGroup::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Account {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Transaction {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum ExpenseGroup {
+    Table,
+    Id,
+    Name,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum GroupMember {
+    Table,
+    Id,
+    GroupId,
+    Name,
+    AccountId,
+    UserId,
+}
+
+#[derive(DeriveIden)]
+enum GroupExpense {
+    Table,
+    Id,
+    GroupId,
+    Title,
+    PaidBy,
+    Amount,
+    CurrencyCode,
+    Split,
+    Timestamp,
+}
+
+#[derive(DeriveIden)]
+enum GroupExpenseShare {
+    Table,
+    Id,
+    ExpenseId,
+    MemberId,
+    Amount,
+}
+
+#[derive(DeriveIden)]
+enum GroupSettlement {
+    Table,
+    Id,
+    GroupId,
+    FromMemberId,
+    ToMemberId,
+    Amount,
+    CurrencyCode,
+    TransactionId,
+    Timestamp,
+}
@@ -0,0 +1,51 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Subscription::Table)
+                    .if_not_exists()
+                    .col(uuid(Subscription::Id).primary_key())
+                    .col(string(Subscription::Payee))
+                    .col(string(Subscription::CurrencyCode))
+                    .col(string(Subscription::Status))
+                    .col(timestamp(Subscription::UpdatedAt).default("CURRENT_TIMESTAMP"))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_subscription_payee")
+                    .table(Subscription::Table)
+                    .col(Subscription::Payee)
+                    .col(Subscription::CurrencyCode)
+                    .unique()
+                    .if_not_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Subscription::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Subscription {
+    Table,
+    Id,
+    Payee,
+    CurrencyCode,
+    Status,
+    UpdatedAt,
+}
@@ -1,12 +1,30 @@
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
-use axum_login::{AuthUser, AuthnBackend, UserId};
+use std::sync::Arc;
+
+use argon2::{
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
+    password_hash::{
+        SaltString,
+        rand_core::{OsRng, RngCore},
+    },
+};
+use axum_login::{
+    AuthUser, AuthnBackend, UserId,
+    tower_sessions::{SessionStore, session::Id},
+};
 use sea_orm::{DbConn, DbErr};
 use secrecy::{ExposeSecret, SecretString};
 use serde::Deserialize;
+use tower_sessions_sqlx_store::SqliteStore;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
-use crate::model::user::User;
+use crate::{
+    REGISTRATION,
+    model::{user::User, user_identity::UserIdentity, user_session::UserSession},
+    oidc::{IdTokenClaims, OidcClient, OidcPending},
+    registration::RegistrationMode,
+    totp::verify_second_factor,
+};
 
 impl AuthUser for User {
     type Id = Uuid;
@@ -23,16 +41,51 @@ impl AuthUser for User {
 #[derive(Clone)]
 pub struct Backend {
     db: DbConn,
+    oidc: Option<Arc<OidcClient>>,
+    sessions: SqliteStore,
 }
 
 impl Backend {
-    pub const fn new(db: DbConn) -> Self {
-        Self { db }
+    pub const fn new(db: DbConn, oidc: Option<Arc<OidcClient>>, sessions: SqliteStore) -> Self {
+        Self { db, oidc, sessions }
     }
 
     pub const fn db(&self) -> &DbConn {
         &self.db
     }
+
+    pub fn oidc(&self) -> Option<&OidcClient> {
+        self.oidc.as_deref()
+    }
+
+    /// Signs out tracked sessions of a user, only `only` if given and never
+    /// `keep`, by removing them from the session store.
+    pub async fn revoke_sessions(
+        &self,
+        user_id: Uuid,
+        only: Option<Uuid>,
+        keep: Option<Uuid>,
+    ) -> anyhow::Result<()> {
+        let session_ids = UserSession::delete_for_user(&self.db, user_id, only, keep).await?;
+        for session_id in session_ids {
+            let Ok(session_id) = session_id.parse::<Id>() else {
+                continue;
+            };
+            self.sessions
+                .delete(&session_id)
+                .await
+                .map_err(|e| anyhow::anyhow!(e))?;
+        }
+        Ok(())
+    }
+}
+
+/// Ways to sign in. All end up as the same [`User`] in the session.
+#[derive(Clone)]
+pub enum AuthCredentials {
+    Password(Credentials),
+    Oidc(OidcCredentials),
+    OidcSecondFactor(OidcSecondFactorCredentials),
 }
 
 #[derive(Clone, Deserialize, ToSchema)]
@@ -40,17 +93,62 @@ pub struct Credentials {
     email: String,
     #[serde(skip_serializing)]
     password: SecretString,
+    /// TOTP or recovery code, needed when the user has two-factor
+    /// authentication enabled.
+    #[serde(default)]
+    code: Option<String>,
 }
 
-impl AuthnBackend for Backend {
-    type User = User;
-    type Credentials = Credentials;
-    type Error = DbErr;
+impl Credentials {
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+}
 
-    async fn authenticate(
+/// The authorization code the identity provider redirected back with, and the
+/// state that was kept in the session for it.
+#[derive(Clone)]
+pub struct OidcCredentials {
+    pub code: String,
+    pub pending: OidcPending,
+}
+
+/// The TOTP or recovery code posted after single sign-on, for the user the
+/// identity provider signed in.
+#[derive(Clone)]
+pub struct OidcSecondFactorCredentials {
+    pub user_id: Uuid,
+    pub code: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error(transparent)]
+    DbErr(#[from] DbErr),
+    /// The password was right but the user has two-factor authentication
+    /// enabled and no valid code was posted yet.
+    #[error("Second factor required")]
+    SecondFactorRequired,
+    /// The identity provider signed in a user with two-factor authentication
+    /// enabled, who still has to post a code.
+    #[error("Second factor required")]
+    OidcSecondFactorRequired(Uuid),
+    /// The credentials were right but an administrator disabled the account.
+    #[error("Account disabled")]
+    Disabled,
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl Backend {
+    async fn authenticate_password(
         &self,
-        Credentials { email, password }: Self::Credentials,
-    ) -> Result<Option<Self::User>, Self::Error> {
+        Credentials {
+            email,
+            password,
+            code,
+        }: Credentials,
+    ) -> Result<Option<User>, AuthError> {
         let user = User::find_by_email(&self.db, &email).await?;
         match user {
             Some(user) => {
@@ -58,7 +156,20 @@ impl AuthnBackend for Backend {
                 let parsed_hash = PasswordHash::new(user.password_hash.expose_secret()).unwrap();
                 let verified = Argon2::default()
                     .verify_password(password.expose_secret().as_bytes(), &parsed_hash);
-                if verified.is_ok() {
+                if verified.is_err() {
+                    return Ok(None);
+                }
+                if user.disabled {
+                    return Err(AuthError::Disabled);
+                }
+                if !user.totp_enabled {
+                    return Ok(Some(user));
+                }
+                let code = code.ok_or(AuthError::SecondFactorRequired)?;
+                if verify_second_factor(&self.db, &user, &code)
+                    .await
+                    .map_err(AuthError::Other)?
+                {
                     Ok(Some(user))
                 } else {
                     Ok(None)
@@ -68,7 +179,335 @@ impl AuthnBackend for Backend {
         }
     }
 
+    /// Multi-factor authentication at the provider is not trusted, so users
+    /// with two-factor authentication enabled still need a code, see
+    /// [`Self::authenticate_oidc_second_factor`].
+    async fn authenticate_oidc(
+        &self,
+        OidcCredentials { code, pending }: OidcCredentials,
+    ) -> Result<Option<User>, AuthError> {
+        let oidc = self
+            .oidc()
+            .ok_or_else(|| AuthError::Other(anyhow::anyhow!("Single sign-on is not configured")))?;
+        let claims = oidc
+            .exchange(&code, &pending)
+            .await
+            .map_err(AuthError::Other)?;
+        match self.oidc_user(claims).await? {
+            Some(user) if user.disabled => Err(AuthError::Disabled),
+            Some(user) if user.totp_enabled => Err(AuthError::OidcSecondFactorRequired(user.id)),
+            user => Ok(user),
+        }
+    }
+
+    async fn authenticate_oidc_second_factor(
+        &self,
+        OidcSecondFactorCredentials { user_id, code }: OidcSecondFactorCredentials,
+    ) -> Result<Option<User>, AuthError> {
+        let Some(user) = User::find_by_id(&self.db, user_id).await? else {
+            return Ok(None);
+        };
+        if user.disabled {
+            return Err(AuthError::Disabled);
+        }
+        if !user.totp_enabled {
+            return Ok(Some(user));
+        }
+        if verify_second_factor(&self.db, &user, &code)
+            .await
+            .map_err(AuthError::Other)?
+        {
+            Ok(Some(user))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Finds the user linked to the external subject. Otherwise links the
+    /// user with the same email, but only when the provider asserts that it
+    /// owns the email, or creates a new user while registration is open.
+    async fn oidc_user(&self, claims: IdTokenClaims) -> Result<Option<User>, AuthError> {
+        if let Some(user_id) =
+            UserIdentity::find_user_id(&self.db, &claims.iss, &claims.sub).await?
+        {
+            let user = User::find_by_id(&self.db, user_id).await?;
+            return match user {
+                Some(user) if claims.email_verified && !user.email_verified => {
+                    User::update_email_verified(&self.db, user.id, true).await?;
+                    Ok(User::find_by_id(&self.db, user_id).await?)
+                }
+                user => Ok(user),
+            };
+        }
+        let Some(email) = claims.email else {
+            return Ok(None);
+        };
+        let user = match User::find_by_email(&self.db, &email).await? {
+            Some(user) if claims.email_verified => user,
+            Some(_) => return Ok(None),
+            None if REGISTRATION.mode != RegistrationMode::Open
+                || !REGISTRATION.allows_domain(&email) =>
+            {
+                return Ok(None);
+            }
+            None => {
+                // The account can only be signed into through the provider
+                // until a password is set with a reset.
+                let mut password = [0u8; 32];
+                OsRng.fill_bytes(&mut password);
+                let salt = SaltString::generate(&mut OsRng);
+                let password_hash = Argon2::default()
+                    .hash_password(&password, &salt)
+                    .map_err(|e| AuthError::Other(anyhow::anyhow!(e)))?
+                    .to_string();
+                let name = claims.name.as_deref().unwrap_or(&email);
+                User::create_user(&self.db, name, &email, &password_hash).await?
+            }
+        };
+        UserIdentity::link(&self.db, user.id, &claims.iss, &claims.sub).await?;
+        if claims.email_verified && !user.email_verified {
+            User::update_email_verified(&self.db, user.id, true).await?;
+            return Ok(User::find_by_id(&self.db, user.id).await?);
+        }
+        Ok(Some(user))
+    }
+}
+
+impl AuthnBackend for Backend {
+    type User = User;
+    type Credentials = AuthCredentials;
+    type Error = AuthError;
+
+    async fn authenticate(
+        &self,
+        creds: Self::Credentials,
+    ) -> Result<Option<Self::User>, Self::Error> {
+        match creds {
+            AuthCredentials::Password(creds) => self.authenticate_password(creds).await,
+            AuthCredentials::Oidc(creds) => self.authenticate_oidc(creds).await,
+            AuthCredentials::OidcSecondFactor(creds) => {
+                self.authenticate_oidc_second_factor(creds).await
+            }
+        }
+    }
+
     async fn get_user(&self, user_id: &UserId<Self>) -> Result<Option<Self::User>, Self::Error> {
-        User::find_by_id(&self.db, *user_id).await
+        // Disabled users are signed out on their next request.
+        Ok(User::find_by_id(&self.db, *user_id)
+            .await?
+            .filter(|u| !u.disabled))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::sync::{
+        Mutex,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    use axum::{Json, Router, extract::State, routing::get};
+    use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+    use jsonwebtoken::{EncodingKey, Header, encode};
+    use serde_json::json;
+    use tokio::net::TcpListener;
+    use user_migration::MigratorTrait;
+
+    use super::*;
+
+    const SIGNING_SECRET: &[u8] = b"mock identity provider secret";
+
+    /// The subject, email and nonce the mock provider puts in its next ID
+    /// token.
+    #[derive(Default, Clone)]
+    struct NextToken {
+        sub: String,
+        email: String,
+        nonce: String,
+    }
+
+    struct MockProvider {
+        issuer: String,
+        next: Mutex<NextToken>,
+        /// Id of the key tokens are signed with, changed to roll keys over.
+        kid: Mutex<String>,
+        signing_algs: serde_json::Value,
+        jwks_fetches: AtomicUsize,
+    }
+
+    async fn start_provider() -> Arc<MockProvider> {
+        start_provider_with(json!(["HS256"])).await
+    }
+
+    /// Serves discovery, the token endpoint and the key set of an identity
+    /// provider that signs its ID tokens with a shared secret and advertises
+    /// `signing_algs`.
+    async fn start_provider_with(signing_algs: serde_json::Value) -> Arc<MockProvider> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let provider = Arc::new(MockProvider {
+            issuer: format!("http://{}", listener.local_addr().unwrap()),
+            next: Mutex::default(),
+            kid: Mutex::new("mock".to_string()),
+            signing_algs,
+            jwks_fetches: AtomicUsize::new(0),
+        });
+        let app = Router::new()
+            .route(
+                "/.well-known/openid-configuration",
+                get(|State(p): State<Arc<MockProvider>>| async move {
+                    Json(json!({
+                        "issuer": p.issuer,
+                        "authorization_endpoint": format!("{}/authorize", p.issuer),
+                        "token_endpoint": format!("{}/token", p.issuer),
+                        "jwks_uri": format!("{}/jwks", p.issuer),
+                        "id_token_signing_alg_values_supported": p.signing_algs,
+                    }))
+                }),
+            )
+            .route(
+                "/jwks",
+                get(|State(p): State<Arc<MockProvider>>| async move {
+                    p.jwks_fetches.fetch_add(1, Ordering::SeqCst);
+                    Json(json!({
+                        "keys": [{
+                            "kty": "oct",
+                            "kid": *p.kid.lock().unwrap(),
+                            "alg": "HS256",
+                            "k": URL_SAFE_NO_PAD.encode(SIGNING_SECRET),
+                        }],
+                    }))
+                }),
+            )
+            .route(
+                "/token",
+                axum::routing::post(|State(p): State<Arc<MockProvider>>| async move {
+                    let next = p.next.lock().unwrap().clone();
+                    let claims = json!({
+                        "iss": p.issuer,
+                        "aud": "khata",
+                        "sub": next.sub,
+                        "email": next.email,
+                        "email_verified": true,
+                        "nonce": next.nonce,
+                        "exp": chrono::Utc::now().timestamp() + 300,
+                    });
+                    let header = Header {
+                        kid: Some(p.kid.lock().unwrap().clone()),
+                        ..Header::default()
+                    };
+                    let id_token =
+                        encode(&header, &claims, &EncodingKey::from_secret(SIGNING_SECRET))
+                            .unwrap();
+                    Json(json!({ "id_token": id_token, "token_type": "Bearer" }))
+                }),
+            )
+            .with_state(provider.clone());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        provider
+    }
+
+    async fn backend(issuer: &str) -> Backend {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        user_migration::Migrator::up(&db, None).await.unwrap();
+        let sessions = SqliteStore::new(db.get_sqlite_connection_pool().clone());
+        let oidc = OidcClient::new(
+            issuer,
+            "khata".to_string(),
+            "secret".to_string().into(),
+            "http://localhost/khata-api/api/oidc/callback".to_string(),
+        );
+        Backend::new(db, Some(Arc::new(oidc)), sessions)
+    }
+
+    /// Goes through the provider as `sub` and `email`, the way the callback
+    /// does after the browser comes back.
+    async fn sign_in(
+        backend: &Backend,
+        provider: &MockProvider,
+        sub: &str,
+        email: &str,
+    ) -> Result<Option<User>, AuthError> {
+        let (url, pending) = backend.oidc().unwrap().authorize_url().await.unwrap();
+        let nonce = url
+            .query_pairs()
+            .find(|(key, _)| key == "nonce")
+            .unwrap()
+            .1
+            .to_string();
+        *provider.next.lock().unwrap() = NextToken {
+            sub: sub.to_string(),
+            email: email.to_string(),
+            nonce,
+        };
+        backend
+            .authenticate(AuthCredentials::Oidc(OidcCredentials {
+                code: "code".to_string(),
+                pending,
+            }))
+            .await
+    }
+
+    #[tokio::test]
+    async fn oidc_sign_in_requires_second_factor() {
+        let provider = start_provider().await;
+        let backend = backend(&provider.issuer).await;
+        let db = backend.db();
+        let plain = User::create_user(db, "Ada", "ada@example.com", "hash")
+            .await
+            .unwrap();
+        let with_totp = User::create_user(db, "Grace", "grace@example.com", "hash")
+            .await
+            .unwrap();
+        User::update_totp(db, with_totp.id, Some(vec![1, 2, 3]), true, &[])
+            .await
+            .unwrap();
+
+        let user = sign_in(&backend, &provider, "ada", "ada@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.id, plain.id);
+        assert!(user.email_verified);
+
+        let result = sign_in(&backend, &provider, "grace", "grace@example.com").await;
+        assert!(
+            matches!(result, Err(AuthError::OidcSecondFactorRequired(id)) if id == with_totp.id)
+        );
+        // Linking the identity does not skip the second factor next time.
+        let result = sign_in(&backend, &provider, "grace", "grace@example.com").await;
+        assert!(matches!(
+            result,
+            Err(AuthError::OidcSecondFactorRequired(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn oidc_keys_are_cached_until_rolled_over() {
+        let provider = start_provider().await;
+        let backend = backend(&provider.issuer).await;
+        for _ in 0..2 {
+            sign_in(&backend, &provider, "ada", "ada@example.com")
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(provider.jwks_fetches.load(Ordering::SeqCst), 1);
+
+        *provider.kid.lock().unwrap() = "rolled".to_string();
+        sign_in(&backend, &provider, "ada", "ada@example.com")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(provider.jwks_fetches.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn oidc_tokens_must_use_an_advertised_algorithm() {
+        let provider = start_provider_with(json!(["RS256", "none"])).await;
+        let backend = backend(&provider.issuer).await;
+        let result = sign_in(&backend, &provider, "ada", "ada@example.com").await;
+        assert!(result.is_err());
+        assert_eq!(provider.jwks_fetches.load(Ordering::SeqCst), 0);
     }
 }
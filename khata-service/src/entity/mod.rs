@@ -3,7 +3,16 @@
 pub mod prelude;
 
 pub mod account;
+pub mod audit_event;
 pub mod category;
 pub mod currency;
+pub mod expense_group;
+pub mod group_expense;
+pub mod group_expense_share;
+pub mod group_member;
+pub mod group_settlement;
+pub mod import_batch;
+pub mod import_batch_entry;
+pub mod subscription;
 pub mod transaction;
 pub mod transaction_item;
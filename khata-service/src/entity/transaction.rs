@@ -12,6 +12,7 @@ pub struct Model {
     pub id: Uuid,
     pub title: String,
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub import_batch_id: Option<Uuid>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -1,12 +1,14 @@
 use axum::{
     extract::rejection::JsonRejection,
-    http::StatusCode,
+    http::{StatusCode, header::RETRY_AFTER},
     response::{IntoResponse, Response},
 };
 use sea_orm::DbErr;
 use utoipa::{ToResponse, ToSchema, openapi::ResponsesBuilder};
 use validator::ValidationErrors;
 
+use crate::model::ModelError;
+
 #[derive(Debug, thiserror::Error)]
 pub enum AppError {
     #[error(transparent)]
@@ -15,14 +17,39 @@ pub enum AppError {
     ValidationError(#[from] ValidationErrors),
     #[error(transparent)]
     AxumJsonRejection(#[from] JsonRejection),
+    /// A malformed request that is not a body validation error, e.g. a bad
+    /// header.
+    #[error(transparent)]
+    BadRequest(anyhow::Error),
     #[error(transparent)]
     Unauthorized(anyhow::Error),
     #[error(transparent)]
+    Forbidden(anyhow::Error),
+    /// The request conflicts with the current state, e.g. it was already
+    /// applied.
+    #[error(transparent)]
+    Conflict(anyhow::Error),
+    #[error(transparent)]
+    NotFound(anyhow::Error),
+    /// Rate limited or locked out, retry after the given seconds.
+    #[error("Too many requests, retry after {0} seconds")]
+    TooManyRequests(i64),
+    #[error(transparent)]
     Other(anyhow::Error),
 }
 
 pub type AppResult<T> = Result<T, AppError>;
 
+impl From<ModelError> for AppError {
+    fn from(e: ModelError) -> Self {
+        match e {
+            ModelError::Db(e) => Self::DbErr(e),
+            ModelError::Conflict(message) => Self::Conflict(anyhow::anyhow!(message)),
+            ModelError::NotFound(message) => Self::NotFound(anyhow::anyhow!(message)),
+        }
+    }
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         tracing::error!("Error: {:?}", self);
@@ -31,8 +58,21 @@ impl IntoResponse for AppError {
                 let message = format!("Input validation error: [{self}]").replace('\n', ", ");
                 (StatusCode::BAD_REQUEST, message)
             }
-            Self::AxumJsonRejection(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            Self::AxumJsonRejection(_) | Self::BadRequest(_) => {
+                (StatusCode::BAD_REQUEST, self.to_string())
+            }
             Self::Unauthorized(_) => (StatusCode::UNAUTHORIZED, self.to_string()),
+            Self::Forbidden(_) => (StatusCode::FORBIDDEN, self.to_string()),
+            Self::Conflict(_) => (StatusCode::CONFLICT, self.to_string()),
+            Self::NotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
+            Self::TooManyRequests(seconds) => {
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    [(RETRY_AFTER, seconds.to_string())],
+                    self.to_string(),
+                )
+                    .into_response();
+            }
             Self::DbErr(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
             Self::Other(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
         }
@@ -54,6 +94,10 @@ impl utoipa::IntoResponses for AppError {
         <String as ToSchema>::schemas(&mut string_schemas);
         builder = builder.response("400", <AppErrorSchema as ToResponse>::response().1);
         builder = builder.response("401", <AppErrorSchema as ToResponse>::response().1);
+        builder = builder.response("403", <AppErrorSchema as ToResponse>::response().1);
+        builder = builder.response("404", <AppErrorSchema as ToResponse>::response().1);
+        builder = builder.response("409", <AppErrorSchema as ToResponse>::response().1);
+        builder = builder.response("429", <AppErrorSchema as ToResponse>::response().1);
         builder = builder.response("500", <AppErrorSchema as ToResponse>::response().1);
         builder.build().into()
     }
@@ -0,0 +1,743 @@
+//! Projects the balances of the cash-flow accounts from recurring patterns.
+//!
+//! A pattern is a transaction title that keeps moving money in or out of the
+//! same account at a regular interval, like a salary, rent or a
+//! subscription. Besides detected patterns the projection uses transactions
+//! already entered with a future date and the instalments of loans, paid
+//! from the account that paid the last one. A future transaction that matches
+//! a pattern stands in for the occurrence it is close to.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use jiff::tz::TimeZone;
+use sea_orm::DbConn;
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    model::{account::AccountReq, account_extra::AccountExtra, transaction::TransactionReq},
+    period,
+    report::is_cash_flow_item,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Cadence {
+    Weekly,
+    Biweekly,
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl Cadence {
+    /// Classifies the usual number of days between two occurrences.
+    const fn from_days(days: i64) -> Option<Self> {
+        match days {
+            6..=8 => Some(Self::Weekly),
+            13..=16 => Some(Self::Biweekly),
+            27..=32 => Some(Self::Monthly),
+            85..=95 => Some(Self::Quarterly),
+            350..=380 => Some(Self::Yearly),
+            _ => None,
+        }
+    }
+
+    const fn days(self) -> i64 {
+        match self {
+            Self::Weekly => 7,
+            Self::Biweekly => 14,
+            Self::Monthly => 30,
+            Self::Quarterly => 91,
+            Self::Yearly => 365,
+        }
+    }
+
+    /// Days an interval may be off and still count as regular.
+    const fn tolerance(self) -> i64 {
+        match self {
+            Self::Weekly => 1,
+            Self::Biweekly => 2,
+            Self::Monthly => 4,
+            Self::Quarterly => 7,
+            Self::Yearly => 10,
+        }
+    }
+
+    pub const fn per_year(self) -> i64 {
+        match self {
+            Self::Weekly => 52,
+            Self::Biweekly => 26,
+            Self::Monthly => 12,
+            Self::Quarterly => 4,
+            Self::Yearly => 1,
+        }
+    }
+
+    const fn min_occurrences(self) -> usize {
+        match self {
+            Self::Yearly => 2,
+            _ => 3,
+        }
+    }
+
+    /// The `n`th occurrence after `last`. Months are counted from `last`, so
+    /// the day of the month does not drift after a short month.
+    fn nth(self, last: NaiveDate, n: u32) -> NaiveDate {
+        let months = |m: u32| period::add_months(last, i32::try_from(m).unwrap_or(i32::MAX));
+        match self {
+            Self::Weekly => last + Days::new(7 * u64::from(n)),
+            Self::Biweekly => last + Days::new(14 * u64::from(n)),
+            Self::Monthly => months(n),
+            Self::Quarterly => months(3 * n),
+            Self::Yearly => months(12 * n),
+        }
+    }
+}
+
+/// A regular transaction found in the history of an account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    pub cadence: Cadence,
+    /// Median amount, in minor units.
+    pub amount: i64,
+    pub last: NaiveDate,
+    pub occurrences: usize,
+}
+
+fn median(values: &mut [i64]) -> i64 {
+    values.sort_unstable();
+    let lower = values[(values.len() - 1) / 2];
+    let upper = values[values.len() / 2];
+    lower + (upper - lower) / 2
+}
+
+/// Sorts dated amounts and adds up the ones on the same day.
+pub fn by_day(occurrences: &[(NaiveDate, i64)]) -> Vec<(NaiveDate, i64)> {
+    let mut by_day = occurrences.to_vec();
+    by_day.sort_unstable();
+    by_day.dedup_by(|next, first| {
+        if next.0 == first.0 {
+            first.1 += next.1;
+            true
+        } else {
+            false
+        }
+    });
+    by_day
+}
+
+/// Finds the cadence of dated amounts, if three in four of the intervals
+/// between them are close to it and the last one is not long overdue.
+pub fn detect_pattern(occurrences: &[(NaiveDate, i64)], today: NaiveDate) -> Option<Pattern> {
+    let by_day = by_day(occurrences);
+    if by_day.len() < 2 {
+        return None;
+    }
+    let mut intervals = by_day
+        .windows(2)
+        .map(|w| (w[1].0 - w[0].0).num_days())
+        .collect::<Vec<_>>();
+    let cadence = Cadence::from_days(median(&mut intervals))?;
+    if by_day.len() < cadence.min_occurrences() {
+        return None;
+    }
+    let regular = intervals
+        .iter()
+        .filter(|&&days| (days - cadence.days()).abs() <= cadence.tolerance())
+        .count();
+    if regular * 4 < intervals.len() * 3 {
+        return None;
+    }
+    let last = by_day.last()?.0;
+    if (today - last).num_days() > cadence.days() * 3 / 2 + cadence.tolerance() {
+        return None;
+    }
+    let mut amounts = by_day.iter().map(|(_, amount)| *amount).collect::<Vec<_>>();
+    Some(Pattern {
+        cadence,
+        amount: median(&mut amounts),
+        last,
+        occurrences: by_day.len(),
+    })
+}
+
+impl Pattern {
+    /// Dates the pattern falls on after `today` up to `until`. An occurrence
+    /// that is late but not yet overdue is expected tomorrow.
+    pub fn dates(&self, today: NaiveDate, until: NaiveDate) -> Vec<NaiveDate> {
+        let mut dates = vec![];
+        for n in 1.. {
+            let date = self.cadence.nth(self.last, n);
+            if date > until {
+                break;
+            }
+            if date > today {
+                dates.push(date);
+            } else if dates.is_empty() && self.cadence.nth(self.last, n + 1) > today {
+                dates.push(today + Days::new(1));
+            }
+        }
+        dates.dedup();
+        dates
+    }
+}
+
+/// Leaves out the dates a scheduled transaction of the same pattern already
+/// covers. Each scheduled date takes the nearest date within the tolerance of
+/// the cadence.
+fn without_scheduled(
+    mut dates: Vec<NaiveDate>,
+    scheduled: &[NaiveDate],
+    cadence: Cadence,
+) -> Vec<NaiveDate> {
+    for &date in scheduled {
+        let nearest = dates
+            .iter()
+            .enumerate()
+            .map(|(index, d)| (index, (*d - date).num_days().abs()))
+            .filter(|&(_, days)| days <= cadence.tolerance())
+            .min_by_key(|&(_, days)| days);
+        if let Some((index, _)) = nearest {
+            dates.remove(index);
+        }
+    }
+    dates
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssumptionSource {
+    /// A recurring pattern found in past transactions.
+    Detected,
+    /// A transaction entered with a future date.
+    Scheduled,
+    /// The instalments of a loan.
+    LoanSchedule,
+}
+
+/// Something the projection expects to happen.
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct Assumption {
+    pub account_id: Uuid,
+    pub source: AssumptionSource,
+    pub title: String,
+    pub cadence: Option<Cadence>,
+    /// Amount of each occurrence, in minor units.
+    pub amount: i64,
+    /// Past occurrences the pattern was found from.
+    pub occurrences: usize,
+    pub dates: Vec<NaiveDate>,
+}
+
+#[derive(Debug, Clone, Copy, ToSchema, Serialize)]
+pub struct ProjectedBalance {
+    pub date: NaiveDate,
+    pub balance: i64,
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct AccountForecast {
+    pub account_id: Uuid,
+    pub name: String,
+    pub currency_code: String,
+    /// Balance at the end of each day, starting today.
+    pub series: Vec<ProjectedBalance>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ToSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    BelowZero,
+    BelowThreshold,
+}
+
+/// The first day a balance is projected to drop below zero or below the
+/// threshold.
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct ForecastWarning {
+    pub account_id: Uuid,
+    pub kind: WarningKind,
+    pub date: NaiveDate,
+    pub balance: i64,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct Forecast {
+    pub accounts: Vec<AccountForecast>,
+    pub assumptions: Vec<Assumption>,
+    pub warnings: Vec<ForecastWarning>,
+}
+
+/// The first day of `series` below zero and the first below a positive
+/// `threshold`.
+fn first_drops(
+    series: &[ProjectedBalance],
+    threshold: Option<i64>,
+) -> Vec<(WarningKind, ProjectedBalance)> {
+    [
+        (WarningKind::BelowZero, Some(0)),
+        (WarningKind::BelowThreshold, threshold.filter(|t| *t > 0)),
+    ]
+    .into_iter()
+    .filter_map(|(kind, limit)| {
+        let limit = limit?;
+        series
+            .iter()
+            .find(|p| p.balance < limit)
+            .map(|p| (kind, *p))
+    })
+    .collect()
+}
+
+impl Forecast {
+    pub async fn build(
+        db: &DbConn,
+        tz: &TimeZone,
+        days: u32,
+        history_days: u32,
+        threshold: Option<i64>,
+    ) -> anyhow::Result<Self> {
+        let now = Utc::now();
+        let today = period::today(tz);
+        let until = today + Days::new(u64::from(days));
+        let history_start = today - Days::new(u64::from(history_days));
+        let accounts = AccountReq::find_all_with_currency(db).await?;
+        let cash_flow = accounts
+            .iter()
+            .filter(|a| a.account.is_cash_flow)
+            .map(|a| a.account.id)
+            .collect::<HashSet<_>>();
+        let mut balances = accounts
+            .iter()
+            .map(|a| (a.account.id, a.account.starting_balance))
+            .collect::<HashMap<_, _>>();
+
+        // Past amounts by account and title, and which account the money
+        // went to or came from when it was not spent on a category.
+        let mut history = HashMap::<(Uuid, String, bool), Vec<(NaiveDate, i64)>>::new();
+        let mut titles = HashMap::<(Uuid, String, bool), String>::new();
+        let mut counterparts = HashMap::<(Uuid, String, bool), Uuid>::new();
+        let mut loan_payers = HashMap::<Uuid, (DateTime<Utc>, Uuid)>::new();
+        // Dates of future transactions, keyed like the history.
+        let mut scheduled = HashMap::<(Uuid, String, bool), Vec<NaiveDate>>::new();
+        let mut assumptions = vec![];
+        let mut events = HashMap::<(Uuid, NaiveDate), i64>::new();
+        let end = period::start_of_day(tz, until + Days::new(1))?;
+        for t in TransactionReq::find_by_month(db, DateTime::<Utc>::MIN_UTC, end).await? {
+            let transaction = t.transaction.0;
+            let date = period::local_date(tz, transaction.timestamp);
+            let items = t
+                .items
+                .iter()
+                .map(|i| (i.0.account_id, i.0.category_id, i.0.amount))
+                .collect::<Vec<_>>();
+            let title = transaction.title.trim().to_lowercase();
+            for item in &items {
+                let (account_id, _, amount) = *item;
+                if transaction.timestamp > now {
+                    if is_cash_flow_item(&items, &cash_flow, item) {
+                        *events.entry((account_id, date)).or_default() += amount;
+                        scheduled
+                            .entry((account_id, title.clone(), amount > 0))
+                            .or_default()
+                            .push(date);
+                        assumptions.push(Assumption {
+                            account_id,
+                            source: AssumptionSource::Scheduled,
+                            title: transaction.title.clone(),
+                            cadence: None,
+                            amount,
+                            occurrences: 0,
+                            dates: vec![date],
+                        });
+                    }
+                    continue;
+                }
+                if let Some(balance) = balances.get_mut(&account_id) {
+                    *balance += amount;
+                }
+                if !is_cash_flow_item(&items, &cash_flow, item) {
+                    continue;
+                }
+                let counterpart = items
+                    .iter()
+                    .find(|(other, _, _)| !cash_flow.contains(other))
+                    .map(|(other, _, _)| *other);
+                if let Some(counterpart) = counterpart {
+                    let payer = loan_payers
+                        .entry(counterpart)
+                        .or_insert((transaction.timestamp, account_id));
+                    if payer.0 <= transaction.timestamp {
+                        *payer = (transaction.timestamp, account_id);
+                    }
+                }
+                if date < history_start || title.is_empty() {
+                    continue;
+                }
+                let key = (account_id, title.clone(), amount > 0);
+                history.entry(key.clone()).or_default().push((date, amount));
+                // Titles are grouped without case, newest first, so the
+                // latest spelling is kept.
+                titles
+                    .entry(key.clone())
+                    .or_insert_with(|| transaction.title.trim().to_owned());
+                if let Some(counterpart) = counterpart {
+                    counterparts.insert(key, counterpart);
+                }
+            }
+        }
+
+        // Loans with a schedule replace the patterns paying into them.
+        let mut scheduled_loans = HashSet::new();
+        for account in &accounts {
+            let Some(AccountExtra::Loan(loan)) = &account.account.account_extra else {
+                continue;
+            };
+            let Some(&(_, payer)) = loan_payers.get(&account.account.id) else {
+                continue;
+            };
+            let instalments = loan
+                .schedule(account.account.starting_balance.abs())
+                .into_iter()
+                .filter(|i| i.due_date > today && i.due_date <= until)
+                .collect::<Vec<_>>();
+            scheduled_loans.insert(account.account.id);
+            for instalment in &instalments {
+                *events.entry((payer, instalment.due_date)).or_default() -= instalment.amount;
+            }
+            if let Some(first) = instalments.first() {
+                assumptions.push(Assumption {
+                    account_id: payer,
+                    source: AssumptionSource::LoanSchedule,
+                    title: account.account.name.clone(),
+                    cadence: Some(Cadence::Monthly),
+                    amount: -first.amount,
+                    occurrences: 0,
+                    dates: instalments.iter().map(|i| i.due_date).collect(),
+                });
+            }
+        }
+
+        for (key, occurrences) in history {
+            if counterparts
+                .get(&key)
+                .is_some_and(|c| scheduled_loans.contains(c))
+            {
+                continue;
+            }
+            let Some(pattern) = detect_pattern(&occurrences, today) else {
+                continue;
+            };
+            let dates = without_scheduled(
+                pattern.dates(today, until),
+                scheduled.get(&key).map_or(&[], Vec::as_slice),
+                pattern.cadence,
+            );
+            if dates.is_empty() {
+                continue;
+            }
+            for date in &dates {
+                *events.entry((key.0, *date)).or_default() += pattern.amount;
+            }
+            assumptions.push(Assumption {
+                account_id: key.0,
+                source: AssumptionSource::Detected,
+                title: titles.remove(&key).unwrap_or(key.1),
+                cadence: Some(pattern.cadence),
+                amount: pattern.amount,
+                occurrences: pattern.occurrences,
+                dates,
+            });
+        }
+        assumptions
+            .sort_by(|a, b| (a.account_id, a.dates.first()).cmp(&(b.account_id, b.dates.first())));
+
+        let mut forecasts = vec![];
+        let mut warnings = vec![];
+        for account in accounts.iter().filter(|a| a.account.is_cash_flow) {
+            let account_id = account.account.id;
+            let code = &account.currency.0.code;
+            let mut balance = balances.get(&account_id).copied().unwrap_or_default();
+            let mut series = vec![];
+            for offset in 0..=days {
+                let date = today + Days::new(u64::from(offset));
+                balance += events.get(&(account_id, date)).copied().unwrap_or_default();
+                series.push(ProjectedBalance { date, balance });
+            }
+            for (kind, ProjectedBalance { date, balance }) in first_drops(&series, threshold) {
+                let digits = account.currency.0.decimal_digits;
+                warnings.push(ForecastWarning {
+                    account_id,
+                    kind,
+                    date,
+                    balance,
+                    message: format!(
+                        "{} is projected to drop to {} on {date}",
+                        account.account.name,
+                        crate::notification::format_amount(balance, digits, code),
+                    ),
+                });
+            }
+            forecasts.push(AccountForecast {
+                account_id,
+                name: account.account.name.clone(),
+                currency_code: code.clone(),
+                series,
+            });
+        }
+        warnings.sort_by_key(|w| w.date);
+        Ok(Self {
+            accounts: forecasts,
+            assumptions,
+            warnings,
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use migration::AccountType;
+
+    use super::*;
+    use crate::test_ledger;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    /// Occurrences of `amount` on each date.
+    fn on(dates: &[NaiveDate], amount: i64) -> Vec<(NaiveDate, i64)> {
+        dates.iter().map(|&d| (d, amount)).collect()
+    }
+
+    fn every(start: NaiveDate, days: u64, count: u64) -> Vec<NaiveDate> {
+        (0..count).map(|n| start + Days::new(days * n)).collect()
+    }
+
+    #[test]
+    fn cadence_follows_the_median_interval() {
+        let today = date(2026, 10, 19);
+        let cases = [
+            (every(date(2026, 9, 21), 7, 4), Cadence::Weekly),
+            (every(date(2026, 8, 24), 14, 4), Cadence::Biweekly),
+            (
+                vec![
+                    date(2026, 7, 1),
+                    date(2026, 8, 1),
+                    date(2026, 9, 1),
+                    date(2026, 10, 1),
+                ],
+                Cadence::Monthly,
+            ),
+            (
+                vec![
+                    date(2026, 1, 5),
+                    date(2026, 4, 5),
+                    date(2026, 7, 5),
+                    date(2026, 10, 5),
+                ],
+                Cadence::Quarterly,
+            ),
+            (vec![date(2025, 10, 1), date(2026, 10, 1)], Cadence::Yearly),
+        ];
+        for (dates, cadence) in cases {
+            let pattern = detect_pattern(&on(&dates, -500), today).unwrap();
+            assert_eq!(pattern.cadence, cadence, "{dates:?}");
+            assert_eq!(pattern.amount, -500);
+            assert_eq!(pattern.last, *dates.last().unwrap());
+            assert_eq!(pattern.occurrences, dates.len());
+        }
+        // Twenty days apart is no cadence.
+        assert_eq!(
+            detect_pattern(&on(&every(date(2026, 8, 10), 20, 4), -500), today),
+            None
+        );
+        // Two monthly occurrences are not enough.
+        assert_eq!(
+            detect_pattern(&on(&[date(2026, 9, 1), date(2026, 10, 1)], -500), today),
+            None
+        );
+    }
+
+    #[test]
+    fn three_in_four_intervals_must_be_regular() {
+        let today = date(2026, 10, 19);
+        // Intervals of 7, 7, 7 and 12 days: three in four are weekly.
+        let mut dates = every(date(2026, 9, 14), 7, 4);
+        dates.push(date(2026, 10, 17));
+        let pattern = detect_pattern(&on(&dates, -100), today).unwrap();
+        assert_eq!(pattern.cadence, Cadence::Weekly);
+
+        // Intervals of 7, 7, 12 and 12 days: only half are weekly.
+        let dates = [
+            date(2026, 9, 13),
+            date(2026, 9, 20),
+            date(2026, 9, 27),
+            date(2026, 10, 9),
+            date(2026, 10, 21),
+        ];
+        assert_eq!(detect_pattern(&on(&dates, -100), today), None);
+
+        // Amounts on the same day add up to one occurrence.
+        let mut occurrences = on(&every(date(2026, 9, 28), 7, 4), -100);
+        occurrences.push((date(2026, 10, 19), -50));
+        let pattern = detect_pattern(&occurrences, today).unwrap();
+        assert_eq!(pattern.occurrences, 4);
+        assert_eq!(pattern.amount, -100);
+    }
+
+    #[test]
+    fn long_overdue_patterns_are_dropped() {
+        let dates = [date(2026, 6, 1), date(2026, 7, 1), date(2026, 8, 1)];
+        let occurrences = on(&dates, 100_000);
+        // Monthly patterns may be 45 + 4 days late.
+        assert!(detect_pattern(&occurrences, date(2026, 9, 19)).is_some());
+        assert_eq!(detect_pattern(&occurrences, date(2026, 9, 20)), None);
+    }
+
+    #[test]
+    fn a_late_occurrence_is_expected_tomorrow() {
+        let pattern = Pattern {
+            cadence: Cadence::Monthly,
+            amount: 100_000,
+            last: date(2026, 9, 1),
+            occurrences: 3,
+        };
+        // Due on 2026-10-01 and not seen yet.
+        assert_eq!(
+            pattern.dates(date(2026, 10, 5), date(2026, 11, 30)),
+            [date(2026, 10, 6), date(2026, 11, 1)]
+        );
+        // On time it keeps its own dates.
+        assert_eq!(
+            pattern.dates(date(2026, 9, 20), date(2026, 11, 30)),
+            [date(2026, 10, 1), date(2026, 11, 1)]
+        );
+        // Of missed occurrences only the latest is moved to tomorrow.
+        let weekly = Pattern {
+            cadence: Cadence::Weekly,
+            amount: -100,
+            last: date(2026, 10, 1),
+            occurrences: 4,
+        };
+        assert_eq!(
+            weekly.dates(date(2026, 10, 16), date(2026, 10, 30)),
+            [date(2026, 10, 17), date(2026, 10, 22), date(2026, 10, 29)]
+        );
+    }
+
+    #[test]
+    fn monthly_dates_clamp_without_drifting() {
+        let pattern = Pattern {
+            cadence: Cadence::Monthly,
+            amount: -1_500_000,
+            last: date(2026, 1, 31),
+            occurrences: 6,
+        };
+        assert_eq!(
+            pattern.dates(date(2026, 2, 1), date(2026, 5, 31)),
+            [
+                date(2026, 2, 28),
+                date(2026, 3, 31),
+                date(2026, 4, 30),
+                date(2026, 5, 31)
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_on_the_first_drop_below_zero_and_the_threshold() {
+        let balances = [5_000, 900, 1_200, -300, -100, 800];
+        let series = balances
+            .iter()
+            .enumerate()
+            .map(|(n, &balance)| ProjectedBalance {
+                date: date(2026, 10, 19) + Days::new(n as u64),
+                balance,
+            })
+            .collect::<Vec<_>>();
+        let drops = first_drops(&series, Some(1_000))
+            .into_iter()
+            .map(|(kind, p)| (kind, p.date, p.balance))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            drops,
+            [
+                (WarningKind::BelowZero, date(2026, 10, 22), -300),
+                (WarningKind::BelowThreshold, date(2026, 10, 20), 900),
+            ]
+        );
+        // No threshold, or one that is not positive, only warns below zero.
+        assert_eq!(first_drops(&series, None).len(), 1);
+        assert_eq!(first_drops(&series, Some(0)).len(), 1);
+        assert!(first_drops(&series[..3], None).is_empty());
+    }
+
+    #[test]
+    fn scheduled_transactions_replace_the_nearest_occurrence() {
+        let dates = vec![date(2026, 11, 1), date(2026, 12, 1), date(2027, 1, 1)];
+        // Paid two days late and once more out of the blue.
+        let scheduled = [date(2026, 12, 3), date(2026, 11, 15)];
+        assert_eq!(
+            without_scheduled(dates.clone(), &scheduled, Cadence::Monthly),
+            [date(2026, 11, 1), date(2027, 1, 1)]
+        );
+        // Weekly patterns only allow a day.
+        assert_eq!(
+            without_scheduled(dates, &[date(2026, 11, 3)], Cadence::Weekly).len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn scheduled_transactions_are_not_counted_twice() {
+        let db = test_ledger::ledger().await;
+        let bank = test_ledger::account(&db, "Bank", AccountType::Bank, "INR", true).await;
+        let rent = test_ledger::category(&db, "Rent", "Living").await;
+        let tz = TimeZone::UTC;
+        let today = period::today(&tz);
+        let paid = [90, 60, 30].map(|days| today - Days::new(days));
+        for day in paid {
+            test_ledger::transaction(&db, "Rent", day, &[(bank, Some(rent), -1_000)]).await;
+        }
+        let pattern = detect_pattern(&on(&paid, -1_000), today).unwrap();
+        let next = pattern.dates(today, today + Days::new(60))[0];
+        // Entered ahead, two days after the pattern expects it.
+        let due = next + Days::new(2);
+        test_ledger::transaction(&db, "rent", due, &[(bank, Some(rent), -1_000)]).await;
+
+        let forecast = Forecast::build(&db, &tz, 60, 365, None).await.unwrap();
+        let detected = forecast
+            .assumptions
+            .iter()
+            .find(|a| a.source == AssumptionSource::Detected)
+            .unwrap();
+        assert!(!detected.dates.contains(&next));
+        assert!(
+            forecast
+                .assumptions
+                .iter()
+                .any(|a| { a.source == AssumptionSource::Scheduled && a.dates == [due] })
+        );
+        let balance = |date: NaiveDate| {
+            forecast.accounts[0]
+                .series
+                .iter()
+                .find(|p| p.date == date)
+                .unwrap()
+                .balance
+        };
+        assert_eq!(balance(next), -3_000);
+        assert_eq!(balance(due), -4_000);
+        let expected = -4_000 - 1_000 * i64::try_from(detected.dates.len()).unwrap();
+        assert_eq!(
+            forecast.accounts[0].series.last().unwrap().balance,
+            expected
+        );
+    }
+}
@@ -0,0 +1,450 @@
+//! Flags unusual spending from the history of the ledger.
+//!
+//! Everything is computed here from the transaction items, nothing leaves
+//! the server. Spending is every categorized item that takes money out of
+//! an account, so transfers are left out. Two kinds of anomalies are found:
+//!
+//! - a category whose spending this month is well above its median month,
+//! - a recent transaction far above the usual amount at the same payee.
+
+use std::collections::HashMap;
+
+use chrono::{Days, NaiveDate};
+use sea_orm::DbConn;
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    model::{account::AccountReq, category::CategoryReq, transaction::TransactionReq},
+    notification::format_amount,
+    period::{self, Period},
+};
+
+/// How much higher than the median month a category has to be.
+const CATEGORY_RATIO: f64 = 1.5;
+/// How much higher than the usual amount a transaction has to be.
+const PAYEE_RATIO: f64 = 2.0;
+/// How many robust standard deviations above the usual amount a transaction
+/// has to be, when the amounts at the payee vary at all.
+const PAYEE_SCORE: f64 = 3.0;
+/// Earlier transactions needed before a payee has a usual amount.
+const PAYEE_MIN_HISTORY: usize = 3;
+/// Transactions this recent are checked against the ones before.
+const RECENT_DAYS: u64 = 30;
+
+/// An item that took money out of an account, as a positive amount in minor
+/// units.
+#[derive(Debug, Clone)]
+pub struct Spending {
+    pub transaction_id: Uuid,
+    /// Local date of the transaction.
+    pub date: NaiveDate,
+    pub account_id: Uuid,
+    /// Only missing when [`load`] was asked for uncategorized items.
+    pub category_id: Option<Uuid>,
+    /// Title of the transaction.
+    pub payee: String,
+    pub currency_code: String,
+    pub amount: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    /// Spending in a category this month against its median month.
+    CategorySpike,
+    /// A recent transaction against the median transaction at the payee.
+    PayeeOutlier,
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct Anomaly {
+    pub kind: AnomalyKind,
+    pub category_id: Option<Uuid>,
+    pub payee: Option<String>,
+    pub transaction_id: Option<Uuid>,
+    pub currency_code: String,
+    /// The unusual amount, in minor units.
+    pub amount: i64,
+    /// The median it is compared to, in minor units.
+    pub typical: i64,
+    /// `amount` divided by `typical`, anomalies are ranked by it.
+    pub ratio: f64,
+    pub explanation: String,
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn median(values: &mut [i64]) -> f64 {
+    if values.is_empty() {
+        return 0.0;
+    }
+    values.sort_unstable();
+    let lower = values[(values.len() - 1) / 2];
+    let upper = values[values.len() / 2];
+    f64::midpoint(lower as f64, upper as f64)
+}
+
+/// Median absolute deviation from the median.
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+fn median_deviation(values: &[i64], median_value: f64) -> f64 {
+    let mut deviations = values
+        .iter()
+        .map(|&v| (v as f64 - median_value).abs().round() as i64)
+        .collect::<Vec<_>>();
+    median(&mut deviations)
+}
+
+/// Finds unusual spending up to `today`, comparing with `months` full months
+/// of history. Anomalies are ranked by how far off they are, largest first.
+pub fn detect(
+    spending: &[Spending],
+    categories: &HashMap<Uuid, String>,
+    decimal_digits: &HashMap<String, i32>,
+    today: NaiveDate,
+    months: u32,
+) -> Vec<Anomaly> {
+    let format = |amount: i64, code: &str| {
+        format_amount(
+            amount,
+            decimal_digits.get(code).copied().unwrap_or_default(),
+            code,
+        )
+    };
+    let mut anomalies = vec![];
+
+    let current = Period::month_of(today);
+    let history = (1..=i32::try_from(months).unwrap_or(i32::MAX))
+        .map(|back| current.shift_months(-back))
+        .collect::<Vec<_>>();
+    // Totals by category and currency, this month first.
+    let mut monthly = HashMap::<(Uuid, &str), Vec<i64>>::new();
+    for s in spending {
+        let Some(category_id) = s.category_id else {
+            continue;
+        };
+        let index = if current.start <= s.date && s.date <= today {
+            0
+        } else if let Some(index) = history
+            .iter()
+            .position(|p| p.start <= s.date && s.date < p.end)
+        {
+            index + 1
+        } else {
+            continue;
+        };
+        monthly
+            .entry((category_id, s.currency_code.as_str()))
+            .or_insert_with(|| vec![0; history.len() + 1])[index] += s.amount;
+    }
+    for ((category_id, code), totals) in monthly {
+        let Some((&amount, earlier)) = totals.split_first() else {
+            continue;
+        };
+        let typical = median(&mut earlier.to_vec());
+        if typical <= 0.0 {
+            continue;
+        }
+        #[allow(clippy::cast_precision_loss)]
+        let ratio = amount as f64 / typical;
+        if ratio < CATEGORY_RATIO {
+            continue;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        let typical = typical.round() as i64;
+        let name = categories
+            .get(&category_id)
+            .map_or("Uncategorized", String::as_str);
+        anomalies.push(Anomaly {
+            kind: AnomalyKind::CategorySpike,
+            category_id: Some(category_id),
+            payee: None,
+            transaction_id: None,
+            currency_code: code.to_string(),
+            amount,
+            typical,
+            ratio,
+            explanation: format!(
+                "{name} this month is {ratio:.1}× your {months}-month median ({} against {})",
+                format(amount, code),
+                format(typical, code),
+            ),
+        });
+    }
+
+    let recent_start = today - Days::new(RECENT_DAYS);
+    let history_start = history.last().map_or(current.start, |p| p.start);
+    // Amounts per transaction by payee and currency.
+    let mut by_payee = HashMap::<(String, &str), HashMap<Uuid, (NaiveDate, &str, i64)>>::new();
+    for s in spending {
+        if s.date < history_start || s.date > today {
+            continue;
+        }
+        let key = (s.payee.trim().to_lowercase(), s.currency_code.as_str());
+        if key.0.is_empty() {
+            continue;
+        }
+        by_payee
+            .entry(key)
+            .or_default()
+            .entry(s.transaction_id)
+            .or_insert_with(|| (s.date, s.payee.trim(), 0))
+            .2 += s.amount;
+    }
+    for ((_, code), transactions) in by_payee {
+        let (recent, earlier): (Vec<_>, Vec<_>) = transactions
+            .into_iter()
+            .partition(|(_, (date, _, _))| *date >= recent_start);
+        if earlier.len() < PAYEE_MIN_HISTORY {
+            continue;
+        }
+        let mut amounts = earlier.iter().map(|(_, (_, _, a))| *a).collect::<Vec<_>>();
+        let typical = median(&mut amounts);
+        if typical <= 0.0 {
+            continue;
+        }
+        // Scaled to match the standard deviation of normally distributed amounts.
+        let deviation = median_deviation(&amounts, typical) * 1.4826;
+        for (transaction_id, (date, payee, amount)) in recent {
+            #[allow(clippy::cast_precision_loss)]
+            let amount_f = amount as f64;
+            let ratio = amount_f / typical;
+            if ratio < PAYEE_RATIO
+                || (deviation > 0.0 && (amount_f - typical) / deviation < PAYEE_SCORE)
+            {
+                continue;
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            let typical = typical.round() as i64;
+            anomalies.push(Anomaly {
+                kind: AnomalyKind::PayeeOutlier,
+                category_id: None,
+                payee: Some(payee.to_string()),
+                transaction_id: Some(transaction_id),
+                currency_code: code.to_string(),
+                amount,
+                typical,
+                ratio,
+                explanation: format!(
+                    "{payee} charged {} on {date}, {ratio:.1}× the usual {}",
+                    format(amount, code),
+                    format(typical, code),
+                ),
+            });
+        }
+    }
+
+    anomalies.sort_by(|a, b| b.ratio.total_cmp(&a.ratio));
+    anomalies
+}
+
+/// The spending of the ledger from the start of the month `months` months
+/// ago, with local dates. Uncategorized outflows are only included when
+/// asked for, and never when the money went to another account.
+pub async fn load(
+    db: &DbConn,
+    tz: &jiff::tz::TimeZone,
+    today: NaiveDate,
+    months: u32,
+    uncategorized: bool,
+) -> anyhow::Result<Vec<Spending>> {
+    let current = Period::month_of(today);
+    let first = current.shift_months(-i32::try_from(months).unwrap_or(i32::MAX));
+    let (start, _) = first.bounds(tz)?;
+    let (_, end) = current.bounds(tz)?;
+    let accounts = AccountReq::find_all_with_currency(db)
+        .await?
+        .into_iter()
+        .map(|a| (a.account.id, a.currency.0.code))
+        .collect::<HashMap<_, _>>();
+    let mut spending = vec![];
+    for t in TransactionReq::find_by_month(db, start, end).await? {
+        let transaction = t.transaction.0;
+        let date = period::local_date(tz, transaction.timestamp);
+        // Money that comes back into an account was moved, not spent.
+        let transfer = t
+            .items
+            .iter()
+            .any(|i| i.0.category_id.is_none() && i.0.amount > 0);
+        for i in t.items {
+            let Some(code) = accounts.get(&i.0.account_id) else {
+                continue;
+            };
+            if i.0.amount >= 0 || (i.0.category_id.is_none() && (!uncategorized || transfer)) {
+                continue;
+            }
+            spending.push(Spending {
+                transaction_id: transaction.id,
+                date,
+                account_id: i.0.account_id,
+                category_id: i.0.category_id,
+                payee: transaction.title.clone(),
+                currency_code: code.clone(),
+                amount: -i.0.amount,
+            });
+        }
+    }
+    Ok(spending)
+}
+
+/// Names of the categories of the ledger, for explanations.
+pub async fn category_names(db: &DbConn) -> anyhow::Result<HashMap<Uuid, String>> {
+    Ok(CategoryReq::find_all(db)
+        .await?
+        .into_iter()
+        .map(|c| (c.0.id, c.0.name))
+        .collect())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    struct Ledger {
+        spending: Vec<Spending>,
+        restaurants: Uuid,
+        groceries: Uuid,
+    }
+
+    impl Ledger {
+        fn new() -> Self {
+            Self {
+                spending: vec![],
+                restaurants: Uuid::now_v7(),
+                groceries: Uuid::now_v7(),
+            }
+        }
+
+        fn spend(&mut self, date: NaiveDate, category_id: Uuid, payee: &str, amount: i64) -> Uuid {
+            let transaction_id = Uuid::now_v7();
+            self.spending.push(Spending {
+                transaction_id,
+                date,
+                account_id: Uuid::nil(),
+                category_id: Some(category_id),
+                payee: payee.to_string(),
+                currency_code: "INR".to_string(),
+                amount,
+            });
+            transaction_id
+        }
+
+        fn detect(&self, today: NaiveDate) -> Vec<Anomaly> {
+            let categories = HashMap::from([
+                (self.restaurants, "Restaurants".to_string()),
+                (self.groceries, "Groceries".to_string()),
+            ]);
+            let digits = HashMap::from([("INR".to_string(), 2)]);
+            detect(&self.spending, &categories, &digits, today, 6)
+        }
+    }
+
+    /// Six months of steady spending before October 2026.
+    fn steady() -> Ledger {
+        let mut ledger = Ledger::new();
+        for month in 4..=9 {
+            let (restaurants, groceries) = (ledger.restaurants, ledger.groceries);
+            ledger.spend(date(2026, month, 5), restaurants, "Cafe", 50_000);
+            ledger.spend(date(2026, month, 20), restaurants, "Cafe", 50_000);
+            ledger.spend(date(2026, month, 3), groceries, "Market", 300_000);
+        }
+        ledger
+    }
+
+    #[test]
+    fn steady_spending_is_not_flagged() {
+        let mut ledger = steady();
+        let (restaurants, groceries) = (ledger.restaurants, ledger.groceries);
+        ledger.spend(date(2026, 10, 5), restaurants, "Cafe", 52_000);
+        ledger.spend(date(2026, 10, 3), groceries, "Market", 290_000);
+        assert!(ledger.detect(date(2026, 10, 19)).is_empty());
+    }
+
+    #[test]
+    fn category_above_its_median_month_is_flagged() {
+        let mut ledger = steady();
+        let restaurants = ledger.restaurants;
+        ledger.spend(date(2026, 10, 2), restaurants, "Cafe", 50_000);
+        ledger.spend(date(2026, 10, 9), restaurants, "Cafe", 50_000);
+        ledger.spend(date(2026, 10, 16), restaurants, "Cafe", 50_000);
+        ledger.spend(date(2026, 10, 18), restaurants, "Cafe", 80_000);
+        let anomalies = ledger.detect(date(2026, 10, 19));
+        assert_eq!(anomalies.len(), 1);
+        let anomaly = &anomalies[0];
+        assert_eq!(anomaly.kind, AnomalyKind::CategorySpike);
+        assert_eq!(anomaly.category_id, Some(restaurants));
+        assert_eq!(anomaly.amount, 230_000);
+        assert_eq!(anomaly.typical, 100_000);
+        assert_eq!(
+            anomaly.explanation,
+            "Restaurants this month is 2.3× your 6-month median (2300.00 INR against 1000.00 INR)"
+        );
+    }
+
+    #[test]
+    fn category_without_history_is_not_flagged() {
+        let mut ledger = Ledger::new();
+        let restaurants = ledger.restaurants;
+        ledger.spend(date(2026, 10, 2), restaurants, "Cafe", 500_000);
+        assert!(ledger.detect(date(2026, 10, 19)).is_empty());
+    }
+
+    #[test]
+    fn transaction_far_above_the_usual_amount_is_flagged() {
+        let mut ledger = Ledger::new();
+        let restaurants = ledger.restaurants;
+        for (month, amount) in [(6, 58_000), (7, 61_000), (8, 59_000), (9, 60_000)] {
+            ledger.spend(date(2026, month, 12), restaurants, "Swiggy", amount);
+        }
+        let normal = ledger.spend(date(2026, 10, 1), restaurants, "swiggy ", 62_000);
+        let outlier = ledger.spend(date(2026, 10, 12), restaurants, "Swiggy", 240_000);
+        let anomalies = ledger
+            .detect(date(2026, 10, 19))
+            .into_iter()
+            .filter(|a| a.kind == AnomalyKind::PayeeOutlier)
+            .collect::<Vec<_>>();
+        assert_eq!(anomalies.len(), 1);
+        assert_eq!(anomalies[0].transaction_id, Some(outlier));
+        assert_ne!(anomalies[0].transaction_id, Some(normal));
+        assert_eq!(anomalies[0].typical, 59_500);
+        assert_eq!(
+            anomalies[0].explanation,
+            "Swiggy charged 2400.00 INR on 2026-10-12, 4.0× the usual 595.00 INR"
+        );
+    }
+
+    #[test]
+    fn payee_with_little_history_is_not_flagged() {
+        let mut ledger = Ledger::new();
+        let restaurants = ledger.restaurants;
+        ledger.spend(date(2026, 8, 12), restaurants, "Diner", 40_000);
+        ledger.spend(date(2026, 9, 12), restaurants, "Diner", 40_000);
+        ledger.spend(date(2026, 10, 12), restaurants, "Diner", 400_000);
+        assert!(
+            ledger
+                .detect(date(2026, 10, 19))
+                .iter()
+                .all(|a| a.kind != AnomalyKind::PayeeOutlier)
+        );
+    }
+
+    #[test]
+    fn anomalies_are_ranked_by_ratio() {
+        let mut ledger = steady();
+        let (restaurants, groceries) = (ledger.restaurants, ledger.groceries);
+        ledger.spend(date(2026, 10, 2), restaurants, "Cafe", 200_000);
+        ledger.spend(date(2026, 10, 3), groceries, "Market", 600_000);
+        let ratios = ledger
+            .detect(date(2026, 10, 19))
+            .iter()
+            .map(|a| a.ratio)
+            .collect::<Vec<_>>();
+        assert!(ratios.len() >= 2);
+        assert!(ratios.windows(2).all(|w| w[0] >= w[1]));
+    }
+}
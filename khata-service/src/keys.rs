@@ -1,22 +1,65 @@
+use aes_gcm::{
+    Aes256Gcm, Key, KeyInit, Nonce,
+    aead::{Aead, AeadCore, OsRng},
+};
 use chrono::Utc;
+use hmac::{Hmac, Mac};
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use secrecy::ExposeSecret;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
-use crate::KEYS;
+use crate::{API_URL, KEYS, model::user::User};
 
 pub struct Keys {
     encoding: EncodingKey,
     decoding: DecodingKey,
+    cipher: Aes256Gcm,
+    mac_key: Vec<u8>,
 }
 
 impl Keys {
     pub fn new(secret: &[u8]) -> Self {
+        let key = Sha256::digest(secret);
         Self {
             encoding: EncodingKey::from_secret(secret),
             decoding: DecodingKey::from_secret(secret),
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+            mac_key: Sha256::digest([b"khata-mac:", secret].concat()).to_vec(),
         }
     }
+
+    /// HMAC-SHA256 of `data` in hex, keyed with a key derived from
+    /// `KHATA_SECRET_KEY`, for secrets that are stored only to be compared.
+    pub fn mac(&self, data: &[u8]) -> String {
+        let Ok(mut mac) = <Hmac<Sha256> as Mac>::new_from_slice(&self.mac_key) else {
+            unreachable!("HMAC takes keys of any length");
+        };
+        mac.update(data);
+        format!("{:x}", mac.finalize().into_bytes())
+    }
+
+    /// Encrypts data at rest with a key derived from `KHATA_SECRET_KEY`. The
+    /// random nonce is stored in front of the ciphertext.
+    pub fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| anyhow::anyhow!("Could not encrypt"))?;
+        Ok([nonce.as_slice(), &ciphertext].concat())
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if data.len() < 12 {
+            anyhow::bail!("Encrypted data is too short");
+        }
+        let (nonce, ciphertext) = data.split_at(12);
+        self.cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow::anyhow!("Could not decrypt"))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -34,7 +77,8 @@ pub async fn generate_verify_url(id: Uuid, email: &str) -> jsonwebtoken::errors:
     };
     let token = encode(&Header::default(), &claim, &KEYS.encoding)?;
     Ok(format!(
-        "http://localhost:8000/khata-api/api/verify-email?token={token}"
+        "{}/khata-api/api/verify-email?token={token}",
+        *API_URL
     ))
 }
 
@@ -42,3 +86,38 @@ pub async fn verify_email(token: String) -> jsonwebtoken::errors::Result<Uuid> {
     let claim = decode::<VerifyEmailClaim>(&token, &KEYS.decoding, &Validation::default())?;
     Ok(claim.claims.id)
 }
+
+/// Reset tokens carry a fingerprint of the password hash they were issued
+/// for, so they stop working once the password has been changed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasswordResetClaim {
+    pub id: Uuid,
+    fingerprint: String,
+    exp: i64,
+}
+
+impl PasswordResetClaim {
+    pub fn matches(&self, user: &User) -> bool {
+        self.id == user.id && self.fingerprint == password_fingerprint(user)
+    }
+}
+
+fn password_fingerprint(user: &User) -> String {
+    let digest = Sha256::digest(user.password_hash.expose_secret().as_bytes());
+    format!("{digest:x}")
+}
+
+pub async fn generate_reset_url(user: &User) -> jsonwebtoken::errors::Result<String> {
+    let claim = PasswordResetClaim {
+        id: user.id,
+        fingerprint: password_fingerprint(user),
+        exp: Utc::now().timestamp() + 1800,
+    };
+    let token = encode(&Header::default(), &claim, &KEYS.encoding)?;
+    Ok(format!("{}/reset-password?token={token}", *API_URL))
+}
+
+pub async fn verify_reset_token(token: &str) -> jsonwebtoken::errors::Result<PasswordResetClaim> {
+    let claim = decode::<PasswordResetClaim>(token, &KEYS.decoding, &Validation::default())?;
+    Ok(claim.claims)
+}
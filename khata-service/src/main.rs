@@ -11,13 +11,28 @@ mod auth;
 mod cache;
 mod entity;
 mod error;
+mod forecast;
+mod insight;
 mod keys;
+mod mailer;
 mod model;
+mod notification;
+mod oidc;
+mod period;
+mod pivot;
+mod registration;
+mod report;
 mod routes;
+mod subscription;
+#[cfg(test)]
+mod test_ledger;
+mod totp;
+mod user_data;
 mod user_entity;
 
 use std::{
     collections::HashSet,
+    net::{IpAddr, SocketAddr},
     num::NonZeroUsize,
     path::PathBuf,
     sync::{Arc, LazyLock},
@@ -25,30 +40,55 @@ use std::{
 
 use anyhow::Context;
 use argon2::{
-    Argon2, PasswordHasher,
+    Argon2, PasswordHash, PasswordHasher, PasswordVerifier,
     password_hash::{SaltString, rand_core::OsRng},
 };
-use auth::{Backend, Credentials};
+use auth::{
+    AuthCredentials, AuthError, Backend, Credentials, OidcCredentials, OidcSecondFactorCredentials,
+};
 use axum::{
     Json,
-    extract::{FromRequest, FromRequestParts, Query, Request, rejection::JsonRejection},
-    http::StatusCode,
+    extract::{
+        ConnectInfo, FromRequest, FromRequestParts, Query, Request, rejection::JsonRejection,
+    },
+    http::{
+        HeaderMap, StatusCode,
+        header::{AUTHORIZATION, USER_AGENT},
+    },
+    response::Redirect,
 };
 use axum_login::{
     AuthManagerLayerBuilder, login_required,
-    tower_sessions::{ExpiredDeletion, SessionManagerLayer},
+    tower_sessions::{ExpiredDeletion, Expiry, SessionManagerLayer, cookie},
 };
 use cache::CacheManager;
+use chrono::{DateTime, Duration, Utc};
 use clap::{Parser, Subcommand};
 use error::{AppError, AppResult};
-use keys::{generate_verify_url, verify_email};
+use keys::{generate_reset_url, generate_verify_url, verify_email, verify_reset_token};
 use lru::LruCache;
+use mailer::Mail;
 use migration::{Migrator, MigratorTrait};
-use model::user::User;
-use sea_orm::{ConnectOptions, Database, DatabaseConnection, sqlx::SqlitePool};
-use serde::{Deserialize, de::DeserializeOwned};
+use model::{
+    api_token::{ApiToken, ApiTokenScope},
+    audit::AuditContext,
+    invite::Invite,
+    ledger::{Ledger, LedgerRole},
+    rate_limit::{AuthLockout, RateLimit},
+    user::User,
+    user_session::UserSession,
+    user_settings::UserSettings,
+};
+use notification::Notifier;
+use registration::{Registration, RegistrationMode};
+use sea_orm::{ConnectOptions, Database, DatabaseConnection, DbConn, sqlx::SqlitePool};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use tokio::{runtime::Handle, sync::Mutex};
-use tower_http::services::{ServeDir, ServeFile};
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    services::{ServeDir, ServeFile},
+};
 use tower_sessions_sqlx_store::SqliteStore;
 use user_migration::Migrator as UserMigrator;
 use utoipa::{IntoParams, OpenApi, ToSchema};
@@ -56,6 +96,7 @@ use utoipa_axum::{router::OpenApiRouter, routes};
 use utoipa_rapidoc::RapiDoc;
 use utoipa_scalar::{Scalar, Servable as ScalarServable};
 use utoipa_swagger_ui::SwaggerUi;
+use uuid::Uuid;
 use validator::Validate;
 
 static DATA_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
@@ -66,6 +107,54 @@ static DATA_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
     PathBuf::from(dir)
 });
 
+/// Public base URL of the service, used for links in emails.
+static API_URL: LazyLock<String> = LazyLock::new(|| {
+    std::env::var("KHATA_API_URL")
+        .unwrap_or_else(|_| "http://localhost:8000".to_string())
+        .trim_end_matches('/')
+        .to_string()
+});
+
+static MAILER: LazyLock<mailer::Mailer> = LazyLock::new(|| {
+    #[allow(clippy::unwrap_used)]
+    mailer::Mailer::from_env()
+        .context("Invalid mail configuration")
+        .unwrap()
+});
+
+static REGISTRATION: LazyLock<registration::Registration> = LazyLock::new(|| {
+    #[allow(clippy::unwrap_used)]
+    registration::Registration::from_env()
+        .context("Invalid registration configuration")
+        .unwrap()
+});
+
+/// Whether the service runs behind a reverse proxy, in which case the client
+/// address is taken from `X-Forwarded-For`.
+static TRUST_PROXY: LazyLock<bool> = LazyLock::new(|| {
+    std::env::var("KHATA_TRUST_PROXY").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+});
+
+fn env_minutes(name: &str, default: i64) -> i64 {
+    #[allow(clippy::unwrap_used)]
+    std::env::var(name)
+        .map_or(Ok(default), |v| v.parse())
+        .with_context(|| format!("{name} must be a number of minutes"))
+        .unwrap()
+}
+
+/// Sessions end after this many minutes without a request, a week by default.
+static SESSION_IDLE_MINUTES: LazyLock<i64> =
+    LazyLock::new(|| env_minutes("KHATA_SESSION_IDLE_MINUTES", 7 * 24 * 60));
+
+/// Sessions end this many minutes after sign in no matter how active they
+/// are, 30 days by default.
+static SESSION_ABSOLUTE_MINUTES: LazyLock<i64> =
+    LazyLock::new(|| env_minutes("KHATA_SESSION_ABSOLUTE_MINUTES", 30 * 24 * 60));
+
+/// When the server started, for the admin health check.
+static STARTED_AT: LazyLock<DateTime<Utc>> = LazyLock::new(Utc::now);
+
 static KEYS: LazyLock<keys::Keys> = LazyLock::new(|| {
     #[allow(clippy::unwrap_used)]
     let secret_key = std::env::var("KHATA_SECRET_KEY")
@@ -87,6 +176,11 @@ enum Command {
         /// Output file
         output: PathBuf,
     },
+    /// Make an existing user an administrator
+    MakeAdmin {
+        /// Email of the user
+        email: String,
+    },
 }
 
 #[allow(clippy::needless_for_each)]
@@ -110,6 +204,11 @@ async fn main() -> anyhow::Result<()> {
     if !tokio::fs::try_exists(DATA_DIR.as_path()).await? {
         tokio::fs::create_dir_all(DATA_DIR.as_path()).await?;
     }
+    if let Some(Command::MakeAdmin { email }) = &cli.command {
+        let user = make_admin(&auth_database().await?, email).await?;
+        tracing::info!("{} is now an administrator", user.email);
+        return Ok(());
+    }
     let data_dir = DATA_DIR.join("currency");
     let api_key = std::env::var("CURRENCY_API_KEY").context("CURRENCY_API_KEY env var not set")?;
 
@@ -124,15 +223,24 @@ async fn main() -> anyhow::Result<()> {
     let deletion_task = tokio::task::spawn(
         session_store
             .clone()
-            .continuously_delete_expired(tokio::time::Duration::from_secs(60)),
+            .continuously_delete_expired(tokio::time::Duration::from_mins(1)),
     );
 
-    let session_layer = SessionManagerLayer::new(session_store);
+    let session_layer = SessionManagerLayer::new(session_store.clone()).with_expiry(
+        Expiry::OnInactivity(cookie::time::Duration::minutes(*SESSION_IDLE_MINUTES)),
+    );
 
-    let backend = Backend::new(auth_database().await?);
+    let oidc = oidc::OidcClient::from_env()
+        .context("Invalid OIDC configuration")?
+        .map(Arc::new);
+    let backend = Backend::new(auth_database().await?, oidc, session_store);
+    let reaper = user_data::AccountReaper::new(backend.clone());
     let auth_layer = AuthManagerLayerBuilder::new(backend, session_layer).build();
 
     LazyLock::force(&KEYS);
+    LazyLock::force(&MAILER);
+    LazyLock::force(&STARTED_AT);
+    LazyLock::force(&REGISTRATION);
 
     let serve_dir = ServeDir::new("../khata-ui/dist");
     let serve_file = ServeFile::new("../khata-ui/dist/index.html");
@@ -154,29 +262,52 @@ async fn main() -> anyhow::Result<()> {
                 )
                 .nest(
                     "/currency-cache",
-                    routes::currency_cache::router().with_state(cache),
+                    routes::currency_cache::router().with_state(cache.clone()),
                 )
                 .nest("/account", routes::account::router())
                 .nest("/category", routes::category::router())
                 .nest("/transaction", routes::transaction::router())
                 .nest("/dashboard", routes::dashboard::router())
+                .nest("/report", routes::report::router().with_state(cache))
+                .nest("/insight", routes::insight::router())
+                .nest("/subscription", routes::subscription::router())
+                .nest("/audit", routes::audit::router())
+                .nest("/ledger", routes::ledger::router())
+                .nest("/group", routes::group::router())
+                .nest("/notification", routes::notification::router())
+                .nest("/totp", routes::totp::router())
+                .nest("/token", routes::api_token::router())
+                .nest("/admin", routes::admin::router())
+                .nest("/session", routes::session::router())
+                .nest("/me", routes::me::router())
+                .nest("/invite", routes::invite::router())
                 .nest(
                     "/api",
                     OpenApiRouter::new()
                         .routes(routes![user])
                         .routes(routes![send_verify_url])
+                        .routes(routes![change_password])
                         .route_layer(login_required!(Backend))
                         .routes(routes![get_verify_email])
                         .routes(routes![signin])
                         .routes(routes![signout])
                         .routes(routes![signup])
+                        .routes(routes![registration])
+                        .routes(routes![oidc_login])
+                        .routes(routes![oidc_callback])
+                        .routes(routes![oidc_second_factor])
+                        .routes(routes![forgot_password])
+                        .routes(routes![reset_password])
                         .routes(routes![reset_data]),
                 )
                 .layer(auth_layer),
         )
         .split_for_parts();
 
-    router = router.fallback_service(serve_dir);
+    router = router
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+        .fallback_service(serve_dir);
     for path in routes {
         tracing::info!("Khata Web Route: {}", path);
         router = router.route_service(path, serve_file.clone());
@@ -193,13 +324,20 @@ async fn main() -> anyhow::Result<()> {
         .merge(RapiDoc::new("/openapi.json").path("/rapidoc"))
         .merge(Scalar::with_url("/scalar", api));
 
+    tokio::spawn(Notifier::new(auth_database().await?).run());
+    tokio::spawn(reaper.run());
+
     let port = std::env::var("PORT")
         .unwrap_or_else(|_| "8000".to_string())
         .parse::<u16>()?;
     let addr = ("127.0.0.1", port);
     let listener = tokio::net::TcpListener::bind(&addr).await?;
     tracing::info!("Starting server on {}", listener.local_addr()?);
-    axum::serve(listener, router).await?;
+    axum::serve(
+        listener,
+        router.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .await?;
 
     deletion_task.await??;
     Ok(())
@@ -213,10 +351,11 @@ static DATABASE_LOCK: LazyLock<Mutex<LruCache<String, DatabaseConnection>>> = La
 });
 
 #[tracing::instrument]
-async fn database(id: &str) -> AppResult<DatabaseConnection> {
+async fn database(ledger_id: Uuid) -> AppResult<DatabaseConnection> {
+    let id = ledger_id.to_string();
     let mut lock = DATABASE_LOCK.lock().await;
     let db = lock
-        .try_get_or_insert(id.to_string(), || -> AppResult<DatabaseConnection> {
+        .try_get_or_insert(id.clone(), || -> AppResult<DatabaseConnection> {
             let db_dir = DATA_DIR.join("database");
             std::fs::create_dir_all(&db_dir)
                 .context("Could not create database directory")
@@ -239,6 +378,44 @@ async fn database(id: &str) -> AppResult<DatabaseConnection> {
     Ok(db)
 }
 
+/// Closes the ledger's connection and removes its database file.
+#[tracing::instrument]
+async fn remove_database(ledger_id: Uuid) -> AppResult<()> {
+    let id = ledger_id.to_string();
+    let db = DATABASE_LOCK.lock().await.pop(&id);
+    if let Some(db) = db {
+        db.close().await.map_err(AppError::DbErr)?;
+    }
+    let path = DATA_DIR.join("database").join(format!("{id}.db"));
+    if tokio::fs::try_exists(&path)
+        .await
+        .context("Could not check database file")
+        .map_err(AppError::Other)?
+    {
+        tokio::fs::remove_file(&path)
+            .await
+            .context("Could not remove database file")
+            .map_err(AppError::Other)?;
+    }
+    Ok(())
+}
+
+/// Size of the ledger's database file in bytes, 0 if it was never opened.
+async fn database_size(ledger_id: Uuid) -> u64 {
+    let path = DATA_DIR.join("database").join(format!("{ledger_id}.db"));
+    tokio::fs::metadata(&path).await.map_or(0, |m| m.len())
+}
+
+/// Lets the user with `email` use the admin routes, see
+/// [`Command::MakeAdmin`].
+async fn make_admin(db: &DbConn, email: &str) -> anyhow::Result<User> {
+    let user = User::find_by_email(db, email)
+        .await?
+        .with_context(|| format!("No user with email {email}"))?;
+    User::update_admin(db, user.id, true).await?;
+    Ok(user)
+}
+
 #[tracing::instrument]
 async fn auth_database() -> AppResult<DatabaseConnection> {
     let db_path = DATA_DIR.join("auth.db");
@@ -254,7 +431,7 @@ async fn auth_database() -> AppResult<DatabaseConnection> {
 type AuthSession = axum_login::AuthSession<Backend>;
 
 #[derive(Debug)]
-struct XUserId(String);
+struct XUserId(Uuid);
 
 impl<S> FromRequestParts<S> for XUserId
 where
@@ -266,73 +443,646 @@ where
         parts: &mut axum::http::request::Parts,
         state: &S,
     ) -> Result<Self, Self::Rejection> {
-        let session: AuthSession = axum_login::AuthSession::from_request_parts(parts, state)
+        let (_, user) = verified_user(parts, state).await?;
+        Ok(Self(user.id))
+    }
+}
+
+/// The ledger a request works on, chosen with the `X-Ledger-Id` header and
+/// defaulting to the user's personal ledger. Reading requires at least the
+/// viewer role and any other method requires the editor role.
+#[derive(Debug)]
+struct XLedger {
+    id: Uuid,
+    /// Settings of the signed in user, for dates and amounts in responses.
+    settings: UserSettings,
+}
+
+/// Like [`XLedger`], but only lets owners of the ledger through.
+#[derive(Debug)]
+struct XLedgerOwner(XLedger);
+
+/// Like [`XLedger`], but lets viewers through for any method, for queries
+/// that only read but need a request body.
+#[derive(Debug)]
+struct XLedgerViewer(XLedger);
+
+impl XLedger {
+    async fn from_request_parts_with_role<S: Send + Sync>(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+        required: LedgerRole,
+    ) -> AppResult<Self> {
+        let (session, user) = verified_user(parts, state).await?;
+        let id = match parts.headers.get("x-ledger-id") {
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(|v| Uuid::parse_str(v).ok())
+                .ok_or_else(|| AppError::BadRequest(anyhow::anyhow!("Invalid ledger id")))?,
+            None => user.id,
+        };
+        let db = session.backend.db();
+        let mut role = Ledger::role_of(db, id, user.id).await?;
+        if role.is_none() && id == user.id {
+            Ledger::ensure_personal(db, &user).await?;
+            role = Some(LedgerRole::Owner);
+        }
+        let role =
+            role.ok_or_else(|| AppError::Forbidden(anyhow::anyhow!("No access to ledger")))?;
+        if role < required {
+            return Err(AppError::Forbidden(anyhow::anyhow!(
+                "Ledger role {} required",
+                required.as_str()
+            )));
+        }
+        Ok(Self {
+            id,
+            settings: user.settings,
+        })
+    }
+}
+
+impl<S> FromRequestParts<S> for XLedger
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let required = if parts.method.is_safe() {
+            LedgerRole::Viewer
+        } else {
+            LedgerRole::Editor
+        };
+        Self::from_request_parts_with_role(parts, state, required).await
+    }
+}
+
+impl<S> FromRequestParts<S> for XLedgerOwner
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        XLedger::from_request_parts_with_role(parts, state, LedgerRole::Owner)
             .await
-            .map_err(|(_, e)| AppError::Other(anyhow::anyhow!(e)))?;
+            .map(Self)
+    }
+}
+
+impl<S> FromRequestParts<S> for XLedgerViewer
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        XLedger::from_request_parts_with_role(parts, state, LedgerRole::Viewer)
+            .await
+            .map(Self)
+    }
+}
+
+impl<S> FromRequestParts<S> for AuditContext
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let (_, user) = verified_user(parts, state).await?;
+        let request_id = parts
+            .headers
+            .get("x-request-id")
+            .and_then(|v| v.to_str().ok())
+            .map(ToString::to_string);
+        Ok(Self {
+            user_id: user.id,
+            request_id,
+        })
+    }
+}
+
+/// The signed in user, if an administrator. Only a session is accepted, so
+/// a leaked API token cannot be used to administer the instance.
+#[derive(Debug)]
+struct XAdmin(User);
+
+impl<S> FromRequestParts<S> for XAdmin
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        if parts.headers.contains_key(AUTHORIZATION) {
+            return Err(AppError::Forbidden(anyhow::anyhow!(
+                "Not allowed with an API token"
+            )));
+        }
+        let (_, user) = verified_user(parts, state).await?;
+        if user.is_admin {
+            Ok(Self(user))
+        } else {
+            Err(AppError::Forbidden(anyhow::anyhow!("Admin only")))
+        }
+    }
+}
+
+/// The address of the client, see [`TRUST_PROXY`].
+#[derive(Debug)]
+struct ClientIp(IpAddr);
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        _state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        let forwarded = TRUST_PROXY
+            .then(|| parts.headers.get("x-forwarded-for"))
+            .flatten()
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .and_then(|v| v.trim().parse().ok());
+        forwarded
+            .or_else(|| {
+                parts
+                    .extensions
+                    .get::<ConnectInfo<SocketAddr>>()
+                    .map(|ConnectInfo(addr)| addr.ip())
+            })
+            .map(Self)
+            .ok_or_else(|| AppError::Other(anyhow::anyhow!("Unknown client address")))
+    }
+}
+
+/// Like [`XUserId`], but refuses personal access tokens. Used for endpoints
+/// that manage credentials, so a leaked token cannot mint new ones.
+#[derive(Debug)]
+struct XSessionUserId(Uuid);
+
+impl<S> FromRequestParts<S> for XSessionUserId
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut axum::http::request::Parts,
+        state: &S,
+    ) -> Result<Self, Self::Rejection> {
+        if parts.headers.contains_key(AUTHORIZATION) {
+            return Err(AppError::Forbidden(anyhow::anyhow!(
+                "Not allowed with an API token"
+            )));
+        }
+        let (_, user) = verified_user(parts, state).await?;
+        Ok(Self(user.id))
+    }
+}
+
+/// The signed in user, either from the session cookie or from a personal
+/// access token in `Authorization: Bearer`. Read-only tokens are refused for
+/// anything but safe methods.
+async fn verified_user<S: Send + Sync>(
+    parts: &mut axum::http::request::Parts,
+    state: &S,
+) -> AppResult<(AuthSession, User)> {
+    let session: AuthSession = axum_login::AuthSession::from_request_parts(parts, state)
+        .await
+        .map_err(|(_, e)| AppError::Other(anyhow::anyhow!(e)))?;
+    let user = if let Some(value) = parts.headers.get(AUTHORIZATION) {
+        let invalid = || AppError::Unauthorized(anyhow::anyhow!("Invalid API token"));
+        let token = value
+            .to_str()
+            .ok()
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or_else(invalid)?;
+        let db = session.backend.db();
+        let token = ApiToken::authenticate(db, token.trim())
+            .await?
+            .ok_or_else(invalid)?;
+        if token.scope == ApiTokenScope::Read && !parts.method.is_safe() {
+            return Err(AppError::Forbidden(anyhow::anyhow!(
+                "API token is read-only"
+            )));
+        }
+        User::find_by_id(db, token.user_id)
+            .await?
+            .filter(|u| !u.disabled)
+            .ok_or_else(invalid)?
+    } else {
         let user = session
             .user
+            .clone()
             .ok_or_else(|| AppError::Unauthorized(anyhow::anyhow!("Not logged in")))?;
-        if user.email_verified {
-            Ok(Self(user.id.to_string()))
-        } else {
-            Err(AppError::Unauthorized(anyhow::anyhow!(
-                "Email not verified"
-            )))
+        check_device(&session, &user).await?;
+        user
+    };
+    if user.email_verified {
+        Ok((session, user))
+    } else {
+        Err(AppError::Unauthorized(anyhow::anyhow!(
+            "Email not verified"
+        )))
+    }
+}
+
+/// Session key of the [`UserSession`] id.
+const DEVICE_KEY: &str = "device.id";
+
+/// Starts tracking a session that was just signed into.
+async fn start_device(
+    auth_session: &AuthSession,
+    user: &User,
+    headers: &HeaderMap,
+    ip: IpAddr,
+) -> AppResult<()> {
+    let user_agent = headers
+        .get(USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .map(ToString::to_string);
+    let device = UserSession::create(
+        auth_session.backend.db(),
+        user.id,
+        user_agent,
+        Some(ip.to_string()),
+    )
+    .await?;
+    auth_session
+        .session
+        .insert(DEVICE_KEY, device.id)
+        .await
+        .map_err(|e| AppError::Other(anyhow::anyhow!(e)))
+}
+
+async fn current_device(auth_session: &AuthSession) -> AppResult<Option<Uuid>> {
+    auth_session
+        .session
+        .get(DEVICE_KEY)
+        .await
+        .map_err(|e| AppError::Other(anyhow::anyhow!(e)))
+}
+
+/// Refuses sessions that were revoked or are past the absolute expiry, and
+/// records when the session was last seen.
+async fn check_device(auth_session: &AuthSession, user: &User) -> AppResult<()> {
+    let db = auth_session.backend.db();
+    let Some(device_id) = current_device(auth_session).await? else {
+        // Sessions from before devices were tracked.
+        let device = UserSession::create(db, user.id, None, None).await?;
+        return auth_session
+            .session
+            .insert(DEVICE_KEY, device.id)
+            .await
+            .map_err(|e| AppError::Other(anyhow::anyhow!(e)));
+    };
+    let now = Utc::now();
+    let device = UserSession::find_by_id(db, device_id)
+        .await?
+        .filter(|d| d.user_id == user.id);
+    let error = match device {
+        Some(device) if device.created_at + Duration::minutes(*SESSION_ABSOLUTE_MINUTES) > now => {
+            let session_id = auth_session.session.id().map(|id| id.to_string());
+            if (session_id.is_some() && session_id != device.session_id)
+                || device.last_seen_at + Duration::minutes(1) < now
+            {
+                UserSession::touch(db, device.id, session_id).await?;
+            }
+            return Ok(());
+        }
+        Some(_) => {
+            UserSession::delete_for_user(db, user.id, Some(device_id), None).await?;
+            "Session expired"
+        }
+        None => "Session revoked",
+    };
+    auth_session
+        .session
+        .flush()
+        .await
+        .map_err(|e| AppError::Other(anyhow::anyhow!(e)))?;
+    Err(AppError::Unauthorized(anyhow::anyhow!(error)))
+}
+
+const SIGNIN_IP_LIMIT: RateLimit = RateLimit::new(30, 15 * 60);
+const SIGNIN_EMAIL_LIMIT: RateLimit = RateLimit::new(10, 15 * 60);
+const SIGNUP_IP_LIMIT: RateLimit = RateLimit::new(5, 60 * 60);
+const VERIFY_EMAIL_LIMIT: RateLimit = RateLimit::new(3, 60 * 60);
+const FORGOT_PASSWORD_IP_LIMIT: RateLimit = RateLimit::new(10, 60 * 60);
+const FORGOT_PASSWORD_EMAIL_LIMIT: RateLimit = RateLimit::new(3, 60 * 60);
+
+/// Records a hit on every limit and fails on the first one that is reached.
+async fn throttle(db: &DbConn, limits: &[(RateLimit, String)]) -> AppResult<()> {
+    for (limit, key) in limits {
+        if let Some(seconds) = limit.hit(db, key).await? {
+            return Err(AppError::TooManyRequests(seconds));
         }
     }
+    Ok(())
 }
 
-#[tracing::instrument(skip(auth_session, creds))]
+/// Signs in with email and password. Users with two-factor authentication get
+/// "Second factor required" until the credentials are posted again together
+/// with a TOTP or recovery code. Repeated wrong passwords lock the email out
+/// for exponentially longer.
+#[tracing::instrument(skip(auth_session, headers, creds))]
 #[utoipa::path(post, path = "/signin", responses(
     (status = OK, body = ()),
     AppError
 ))]
 async fn signin(
     mut auth_session: AuthSession,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
     Json(creds): Json<Credentials>,
-) -> Result<(), (StatusCode, String)> {
-    let user = match auth_session.authenticate(creds.clone()).await {
-        Ok(Some(user)) => {
-            if user.email_verified {
-                user
-            } else {
-                print_verify_url(&user).await.map_err(|e| {
-                    tracing::error!("Error generating verify url: {:?}", e);
-                    (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "Error generating verify url".to_string(),
-                    )
-                })?;
-                return Err((StatusCode::UNAUTHORIZED, "Email not verified".to_string()));
-            }
+) -> AppResult<()> {
+    let db = auth_session.backend.db().clone();
+    let email = creds.email().to_lowercase();
+    throttle(
+        &db,
+        &[
+            (SIGNIN_IP_LIMIT, format!("signin:ip:{ip}")),
+            (SIGNIN_EMAIL_LIMIT, format!("signin:email:{email}")),
+        ],
+    )
+    .await?;
+    let lockout_key = format!("email:{email}");
+    if let Some(seconds) = AuthLockout::check(&db, &lockout_key).await? {
+        return Err(AppError::TooManyRequests(seconds));
+    }
+    let user = match auth_session
+        .authenticate(AuthCredentials::Password(creds))
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            AuthLockout::record_failure(&db, &lockout_key).await?;
+            return Err(AppError::Unauthorized(anyhow::anyhow!(
+                "Invalid credentials"
+            )));
+        }
+        Err(axum_login::Error::Backend(AuthError::SecondFactorRequired)) => {
+            return Err(AppError::Unauthorized(anyhow::anyhow!(
+                "Second factor required"
+            )));
+        }
+        Err(axum_login::Error::Backend(AuthError::Disabled)) => {
+            return Err(AppError::Forbidden(anyhow::anyhow!("Account disabled")));
         }
-        Ok(None) => return Err((StatusCode::UNAUTHORIZED, "Invalid credentials".to_string())),
         Err(e) => {
             tracing::error!("Error : {:?}", e);
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Database error".to_string(),
-            ));
+            return Err(AppError::Other(anyhow::anyhow!("Database error")));
         }
     };
+    AuthLockout::clear(&db, &lockout_key).await?;
 
-    if let Err(e) = auth_session.login(&user).await {
-        tracing::error!("Error logging in: {:?}", e);
-        return Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Session error".to_string(),
-        ));
+    if !user.email_verified {
+        // The verification email is only re-sent within its own limit, the
+        // sign in is refused either way.
+        if VERIFY_EMAIL_LIMIT
+            .hit(&db, &format!("verify:email:{email}"))
+            .await?
+            .is_none()
+        {
+            send_verify_email(&user).await.map_err(|e| {
+                tracing::error!("Error sending verification email: {:?}", e);
+                AppError::Other(anyhow::anyhow!("Error sending verification email"))
+            })?;
+        }
+        return Err(AppError::Unauthorized(anyhow::anyhow!(
+            "Email not verified"
+        )));
     }
 
+    auth_session.login(&user).await.map_err(|e| {
+        tracing::error!("Error logging in: {:?}", e);
+        AppError::Other(anyhow::anyhow!("Session error"))
+    })?;
+    start_device(&auth_session, &user, &headers, ip).await?;
+
     Ok(())
 }
 
+const OIDC_PENDING_KEY: &str = "oidc.pending";
+const OIDC_SECOND_FACTOR_KEY: &str = "oidc.second_factor";
+
+/// A user the identity provider signed in who still has to post a second
+/// factor to [`oidc_second_factor`] before it expires.
+#[derive(Debug, Serialize, Deserialize)]
+struct OidcSecondFactorPending {
+    user_id: Uuid,
+    expires_at: DateTime<Utc>,
+}
+
+/// Starts single sign-on by redirecting to the identity provider.
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/oidc/login", responses(
+    (status = SEE_OTHER, body = ()),
+    AppError
+))]
+async fn oidc_login(auth_session: AuthSession) -> AppResult<Redirect> {
+    let oidc = auth_session
+        .backend
+        .oidc()
+        .ok_or_else(|| AppError::Forbidden(anyhow::anyhow!("Single sign-on is not configured")))?;
+    let (url, pending) = oidc.authorize_url().await.map_err(AppError::Other)?;
+    auth_session
+        .session
+        .insert(OIDC_PENDING_KEY, pending)
+        .await
+        .map_err(|e| AppError::Other(anyhow::anyhow!(e)))?;
+    Ok(Redirect::to(url.as_str()))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+struct OidcCallbackQuery {
+    code: String,
+    state: String,
+}
+
+/// The identity provider redirects back here. Signs in the linked user and
+/// redirects to the app. Users with two-factor authentication are sent to the
+/// sign in page instead, to post a code to [`oidc_second_factor`].
+#[tracing::instrument(skip(auth_session, headers, query))]
+#[utoipa::path(get, path = "/oidc/callback", params(OidcCallbackQuery), responses(
+    (status = SEE_OTHER, body = ()),
+    AppError
+))]
+async fn oidc_callback(
+    mut auth_session: AuthSession,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    Query(query): Query<OidcCallbackQuery>,
+) -> AppResult<Redirect> {
+    let pending: Option<oidc::OidcPending> = auth_session
+        .session
+        .remove(OIDC_PENDING_KEY)
+        .await
+        .map_err(|e| AppError::Other(anyhow::anyhow!(e)))?;
+    let pending = pending
+        .filter(|p| p.state == query.state)
+        .ok_or_else(|| AppError::Unauthorized(anyhow::anyhow!("Invalid sign-on state")))?;
+    let user = match auth_session
+        .authenticate(AuthCredentials::Oidc(OidcCredentials {
+            code: query.code,
+            pending,
+        }))
+        .await
+    {
+        Ok(user) => user.ok_or_else(|| {
+            AppError::Unauthorized(anyhow::anyhow!(
+                "This identity cannot be linked to an account"
+            ))
+        })?,
+        Err(axum_login::Error::Backend(AuthError::OidcSecondFactorRequired(user_id))) => {
+            let pending = OidcSecondFactorPending {
+                user_id,
+                expires_at: Utc::now() + Duration::minutes(5),
+            };
+            auth_session
+                .session
+                .insert(OIDC_SECOND_FACTOR_KEY, pending)
+                .await
+                .map_err(|e| AppError::Other(anyhow::anyhow!(e)))?;
+            return Ok(Redirect::to("/signin?second_factor=oidc"));
+        }
+        Err(axum_login::Error::Backend(AuthError::Disabled)) => {
+            return Err(AppError::Forbidden(anyhow::anyhow!("Account disabled")));
+        }
+        Err(e) => return Err(AppError::Other(anyhow::anyhow!(e))),
+    };
+    oidc_login_user(&mut auth_session, &user, &headers, ip).await?;
+    Ok(Redirect::to("/"))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct OidcSecondFactor {
+    /// TOTP or recovery code.
+    code: String,
+}
+
+/// Finishes single sign-on for a user with two-factor authentication.
+#[tracing::instrument(skip(auth_session, headers, req))]
+#[utoipa::path(post, path = "/oidc/second-factor", responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn oidc_second_factor(
+    mut auth_session: AuthSession,
+    ClientIp(ip): ClientIp,
+    headers: HeaderMap,
+    Json(req): Json<OidcSecondFactor>,
+) -> AppResult<()> {
+    let pending: Option<OidcSecondFactorPending> = auth_session
+        .session
+        .get(OIDC_SECOND_FACTOR_KEY)
+        .await
+        .map_err(|e| AppError::Other(anyhow::anyhow!(e)))?;
+    let pending = pending
+        .filter(|p| p.expires_at > Utc::now())
+        .ok_or_else(|| AppError::Unauthorized(anyhow::anyhow!("Sign in again")))?;
+    let db = auth_session.backend.db().clone();
+    throttle(
+        &db,
+        &[
+            (SIGNIN_IP_LIMIT, format!("signin:ip:{ip}")),
+            (
+                SIGNIN_EMAIL_LIMIT,
+                format!("signin:user:{}", pending.user_id),
+            ),
+        ],
+    )
+    .await?;
+    let user = match auth_session
+        .authenticate(AuthCredentials::OidcSecondFactor(
+            OidcSecondFactorCredentials {
+                user_id: pending.user_id,
+                code: req.code,
+            },
+        ))
+        .await
+    {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(AppError::Unauthorized(anyhow::anyhow!(
+                "Invalid credentials"
+            )));
+        }
+        Err(axum_login::Error::Backend(AuthError::Disabled)) => {
+            return Err(AppError::Forbidden(anyhow::anyhow!("Account disabled")));
+        }
+        Err(e) => return Err(AppError::Other(anyhow::anyhow!(e))),
+    };
+    auth_session
+        .session
+        .remove::<OidcSecondFactorPending>(OIDC_SECOND_FACTOR_KEY)
+        .await
+        .map_err(|e| AppError::Other(anyhow::anyhow!(e)))?;
+    oidc_login_user(&mut auth_session, &user, &headers, ip).await
+}
+
+/// Signs in a user that came through the identity provider, unless their
+/// email still has to be verified.
+async fn oidc_login_user(
+    auth_session: &mut AuthSession,
+    user: &User,
+    headers: &HeaderMap,
+    ip: IpAddr,
+) -> AppResult<()> {
+    if !user.email_verified {
+        send_verify_email(user).await.map_err(AppError::Other)?;
+        return Err(AppError::Unauthorized(anyhow::anyhow!(
+            "Email not verified"
+        )));
+    }
+    auth_session
+        .login(user)
+        .await
+        .map_err(|e| AppError::Other(anyhow::anyhow!(e)))?;
+    start_device(auth_session, user, headers, ip).await
+}
+
 #[tracing::instrument(skip(auth_session))]
 #[utoipa::path(post, path = "/signout", responses(
     (status = OK, body = ()),
     (status = INTERNAL_SERVER_ERROR, body = String)
 ))]
 async fn signout(mut auth_session: AuthSession) -> Result<(), (StatusCode, String)> {
+    if let (Some(user), Ok(Some(device_id))) = (
+        auth_session.user.as_ref(),
+        current_device(&auth_session).await,
+    ) {
+        let revoked = auth_session
+            .backend
+            .revoke_sessions(user.id, Some(device_id), None)
+            .await;
+        if let Err(e) = revoked {
+            tracing::error!("Error removing session: {:?}", e);
+        }
+    }
     match auth_session.logout().await {
         Err(e) => {
             tracing::error!("Error logging out: {:?}", e);
@@ -350,58 +1100,76 @@ struct NewSignup {
     name: String,
     email: String,
     password: String,
+    /// Needed while registration is invite-only.
+    #[serde(default)]
+    invite_code: Option<String>,
 }
 
 #[tracing::instrument(skip(auth_session, user))]
 #[utoipa::path(post, path = "/signup", responses(
     (status = OK, body = ()),
-    (status = INTERNAL_SERVER_ERROR, body = String)
+    AppError
 ))]
 async fn signup(
     auth_session: AuthSession,
+    ClientIp(ip): ClientIp,
     Json(user): Json<NewSignup>,
-) -> Result<(), (StatusCode, String)> {
-    let argon2 = Argon2::default();
-    let salt = SaltString::generate(&mut OsRng);
-    let user = match User::create_user(
-        auth_session.backend.db(),
-        &user.name,
-        &user.email,
-        &argon2
-            .hash_password(user.password.as_bytes(), &salt)
-            .map_err(|e| {
-                tracing::error!("Error hashing password: {:?}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Hashing error".to_string(),
-                )
-            })?
-            .to_string(),
-    )
-    .await
-    {
+) -> AppResult<()> {
+    let db = auth_session.backend.db();
+    throttle(db, &[(SIGNUP_IP_LIMIT, format!("signup:ip:{ip}"))]).await?;
+    if REGISTRATION.mode == RegistrationMode::Closed {
+        return Err(AppError::Forbidden(anyhow::anyhow!(
+            "Registration is closed"
+        )));
+    }
+    if !REGISTRATION.allows_domain(&user.email) {
+        return Err(AppError::Forbidden(anyhow::anyhow!(
+            "Sign ups from this email domain are not allowed"
+        )));
+    }
+    let password_hash = hash_password(&user.password).map_err(|e| {
+        tracing::error!("Error hashing password: {:?}", e);
+        AppError::Other(anyhow::anyhow!("Hashing error"))
+    })?;
+    let invite_id = if REGISTRATION.mode == RegistrationMode::InviteOnly {
+        let invalid = || AppError::Forbidden(anyhow::anyhow!("A valid invite code is required"));
+        let code = user.invite_code.as_deref().ok_or_else(invalid)?;
+        Some(Invite::claim(db, code).await?.ok_or_else(invalid)?)
+    } else {
+        None
+    };
+    let user = match User::create_user(db, &user.name, &user.email, &password_hash).await {
         Ok(user) => user,
         Err(e) => {
             tracing::error!("Error creating user: {:?}", e);
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                "Database error".to_string(),
-            ));
+            if let Some(invite_id) = invite_id {
+                Invite::release(db, invite_id).await?;
+            }
+            return Err(AppError::Other(anyhow::anyhow!("Database error")));
         }
     };
+    if let Some(invite_id) = invite_id {
+        Invite::set_used_by(db, invite_id, user.id).await?;
+    }
 
-    print_verify_url(&user).await.map_err(|e| {
-        tracing::error!("Error generating verify url: {:?}", e);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Error generating verify url".to_string(),
-        )
+    send_verify_email(&user).await.map_err(|e| {
+        tracing::error!("Error sending verification email: {:?}", e);
+        AppError::Other(anyhow::anyhow!("Error sending verification email"))
     })?;
 
-    Err((
-        StatusCode::UNAUTHORIZED,
-        "Please verify your email".to_string(),
-    ))
+    Err(AppError::Unauthorized(anyhow::anyhow!(
+        "Please verify your email"
+    )))
+}
+
+/// How new accounts can be created, so the sign up form can ask for an
+/// invite code.
+#[tracing::instrument]
+#[utoipa::path(get, path = "/registration", responses(
+    (status = OK, body = Registration),
+))]
+async fn registration() -> Json<Registration> {
+    Json(REGISTRATION.clone())
 }
 
 #[tracing::instrument(skip(auth_session))]
@@ -419,20 +1187,187 @@ async fn user(auth_session: AuthSession) -> Result<Json<User>, (StatusCode, Stri
 #[tracing::instrument(skip(auth_session))]
 #[utoipa::path(get, path = "/generate-verify-url", responses(
     (status = OK, body = ()),
-    (status = UNAUTHORIZED, body = ())
+    AppError
 ))]
-async fn send_verify_url(auth_session: AuthSession) -> Result<(), StatusCode> {
-    let user = auth_session.user.ok_or(StatusCode::UNAUTHORIZED)?;
-    print_verify_url(&user).await.map_err(|e| {
-        tracing::error!("Error generating verify url: {:?}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
+async fn send_verify_url(auth_session: AuthSession) -> AppResult<()> {
+    let user = auth_session
+        .user
+        .ok_or_else(|| AppError::Unauthorized(anyhow::anyhow!("Not logged in")))?;
+    throttle(
+        auth_session.backend.db(),
+        &[(
+            VERIFY_EMAIL_LIMIT,
+            format!("verify:email:{}", user.email.to_lowercase()),
+        )],
+    )
+    .await?;
+    send_verify_email(&user).await.map_err(|e| {
+        tracing::error!("Error sending verification email: {:?}", e);
+        AppError::Other(anyhow::anyhow!("Error sending verification email"))
     })?;
     Ok(())
 }
 
-async fn print_verify_url(user: &User) -> jsonwebtoken::errors::Result<()> {
+async fn send_verify_email(user: &User) -> anyhow::Result<()> {
     let url = generate_verify_url(user.id, &user.email).await?;
-    tracing::info!("Verify URL: {}", url);
+    MAILER
+        .send(&user.email, Mail::verify_email(&user.name, &url))
+        .await
+}
+
+async fn send_reset_email(user: &User) -> anyhow::Result<()> {
+    let url = generate_reset_url(user)
+        .await
+        .context("Could not generate reset url")?;
+    MAILER
+        .send(&user.email, Mail::reset_password(&user.name, &url))
+        .await
+}
+
+fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|h| h.to_string())
+        .map_err(|e| anyhow::anyhow!(e))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct ForgotPassword {
+    email: String,
+}
+
+/// Always succeeds unless rate limited, so the endpoint cannot be used to
+/// find out which emails have an account. The limits count every request,
+/// whether or not the email exists.
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(post, path = "/forgot-password", responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn forgot_password(
+    auth_session: AuthSession,
+    ClientIp(ip): ClientIp,
+    Json(ForgotPassword { email }): Json<ForgotPassword>,
+) -> AppResult<()> {
+    let db = auth_session.backend.db();
+    throttle(
+        db,
+        &[
+            (FORGOT_PASSWORD_IP_LIMIT, format!("forgot:ip:{ip}")),
+            (
+                FORGOT_PASSWORD_EMAIL_LIMIT,
+                format!("forgot:email:{}", email.to_lowercase()),
+            ),
+        ],
+    )
+    .await?;
+    let Some(user) = User::find_by_email(db, &email).await? else {
+        return Ok(());
+    };
+    if let Err(e) = send_reset_email(&user).await {
+        tracing::error!("Error sending password reset email: {:?}", e);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct ResetPassword {
+    token: String,
+    password: String,
+}
+
+/// Sets a new password with a token from [`forgot_password`]. Changing the
+/// password signs the user out of every session.
+#[tracing::instrument(skip(auth_session, req))]
+#[utoipa::path(post, path = "/reset-password", responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn reset_password(
+    auth_session: AuthSession,
+    Json(req): Json<ResetPassword>,
+) -> AppResult<()> {
+    check_new_password("password", &req.password)?;
+    let invalid = || AppError::Unauthorized(anyhow::anyhow!("Invalid or expired reset token"));
+    let claim = verify_reset_token(&req.token).await.map_err(|e| {
+        tracing::warn!("Error verifying reset token: {:?}", e);
+        invalid()
+    })?;
+    let db = auth_session.backend.db();
+    let user = User::find_by_id(db, claim.id).await?.ok_or_else(invalid)?;
+    if !claim.matches(&user) {
+        return Err(invalid());
+    }
+    let password_hash = hash_password(&req.password).map_err(AppError::Other)?;
+    User::update_password(db, user.id, &password_hash).await?;
+    auth_session
+        .backend
+        .revoke_sessions(user.id, None, None)
+        .await
+        .map_err(AppError::Other)?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct ChangePassword {
+    current_password: String,
+    new_password: String,
+}
+
+/// Checks the length by hand because validator errors carry the value, which
+/// would end up in the response and the log.
+fn check_new_password(field: &'static str, password: &str) -> AppResult<()> {
+    if password.chars().count() >= 8 {
+        return Ok(());
+    }
+    let mut errors = validator::ValidationErrors::new();
+    errors.add(
+        field,
+        validator::ValidationError::new("length")
+            .with_message("Password must be at least 8 characters long".into()),
+    );
+    Err(AppError::ValidationError(errors))
+}
+
+/// Changes the password of the signed in user. Other sessions are signed out
+/// because their auth hash no longer matches, the current one is renewed.
+#[tracing::instrument(skip(auth_session, req))]
+#[utoipa::path(post, path = "/change-password", responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn change_password(
+    mut auth_session: AuthSession,
+    Json(req): Json<ChangePassword>,
+) -> AppResult<()> {
+    check_new_password("new_password", &req.new_password)?;
+    let user = auth_session
+        .user
+        .clone()
+        .ok_or_else(|| AppError::Unauthorized(anyhow::anyhow!("Not logged in")))?;
+    let current = PasswordHash::new(user.password_hash.expose_secret())
+        .map_err(|e| AppError::Other(anyhow::anyhow!(e)))?;
+    if Argon2::default()
+        .verify_password(req.current_password.as_bytes(), &current)
+        .is_err()
+    {
+        return Err(AppError::Unauthorized(anyhow::anyhow!(
+            "Current password is wrong"
+        )));
+    }
+    let password_hash = hash_password(&req.new_password).map_err(AppError::Other)?;
+    let user = User::update_password(auth_session.backend.db(), user.id, &password_hash).await?;
+    auth_session
+        .login(&user)
+        .await
+        .map_err(|e| AppError::Other(anyhow::anyhow!(e)))?;
+    let device_id = current_device(&auth_session).await?;
+    auth_session
+        .backend
+        .revoke_sessions(user.id, None, device_id)
+        .await
+        .map_err(AppError::Other)?;
     Ok(())
 }
 
@@ -474,8 +1409,8 @@ async fn get_verify_email(
     (status = UNAUTHORIZED, body = String),
     (status = INTERNAL_SERVER_ERROR, body = String)
 ))]
-async fn reset_data(XUserId(id): XUserId) -> Result<(), (StatusCode, String)> {
-    let db = database(&id).await.map_err(|e| {
+async fn reset_data(XLedgerOwner(ledger): XLedgerOwner) -> Result<(), (StatusCode, String)> {
+    let db = database(ledger.id).await.map_err(|e| {
         tracing::error!("Error getting database: {:?}", e);
         (
             StatusCode::INTERNAL_SERVER_ERROR,
@@ -516,3 +1451,331 @@ where
         Ok(Self(value))
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use axum::{
+        Router,
+        extract::Path,
+        routing::{get, post},
+    };
+    use reqwest::StatusCode;
+    use user_migration::MigratorTrait;
+
+    use super::*;
+    use crate::model::{
+        api_token::{ApiTokenReq, ApiTokenScope},
+        ledger::{LedgerMemberReq, LedgerReq},
+    };
+
+    /// Serves `app` behind the same session and auth layers as the service,
+    /// on an in-memory user database.
+    async fn serve(app: Router) -> (String, DbConn) {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        UserMigrator::up(&db, None).await.unwrap();
+        let sessions = SqliteStore::new(db.get_sqlite_connection_pool().clone());
+        sessions.migrate().await.unwrap();
+        let backend = Backend::new(db.clone(), None, sessions.clone());
+        let auth_layer =
+            AuthManagerLayerBuilder::new(backend, SessionManagerLayer::new(sessions)).build();
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let app = app.layer(auth_layer);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, db)
+    }
+
+    /// A verified user and a personal access token for them.
+    async fn user_with_token(db: &DbConn, email: &str, scope: ApiTokenScope) -> (User, String) {
+        let user = User::create_user(db, email, email, "").await.unwrap();
+        User::update_email_verified(db, user.id, true)
+            .await
+            .unwrap();
+        let token = ApiToken::create(
+            db,
+            user.id,
+            ApiTokenReq {
+                name: "test".to_string(),
+                scope,
+                expires_at: None,
+            },
+        )
+        .await
+        .unwrap();
+        (user, token.token)
+    }
+
+    #[tokio::test]
+    async fn api_tokens_authenticate_within_their_scope() {
+        async fn user_id(XUserId(id): XUserId) -> String {
+            id.to_string()
+        }
+        async fn session_user_id(XSessionUserId(id): XSessionUserId) -> String {
+            id.to_string()
+        }
+        async fn admin(XAdmin(user): XAdmin) -> String {
+            user.id.to_string()
+        }
+        let app = Router::new()
+            .route("/user", get(user_id).post(user_id))
+            .route("/session", get(session_user_id))
+            .route("/admin", get(admin));
+        let (url, db) = serve(app).await;
+        let (user, read_write) =
+            user_with_token(&db, "ada@example.com", ApiTokenScope::ReadWrite).await;
+        User::update_admin(&db, user.id, true).await.unwrap();
+        let read = ApiToken::create(
+            &db,
+            user.id,
+            ApiTokenReq {
+                name: "read".to_string(),
+                scope: ApiTokenScope::Read,
+                expires_at: Some(Utc::now() + Duration::days(1)),
+            },
+        )
+        .await
+        .unwrap()
+        .token;
+        let expired = ApiTokenReq {
+            name: "expired".to_string(),
+            scope: ApiTokenScope::ReadWrite,
+            expires_at: Some(Utc::now() - Duration::seconds(1)),
+        };
+        assert!(expired.validate().is_err());
+        let expired = ApiToken::create(&db, user.id, expired).await.unwrap().token;
+
+        let client = reqwest::Client::new();
+        let status = |method: reqwest::Method, path: &str, token: &str| {
+            client
+                .request(method, format!("{url}{path}"))
+                .bearer_auth(token)
+                .send()
+        };
+        let response = status(reqwest::Method::GET, "/user", &read_write)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.text().await.unwrap(), user.id.to_string());
+        let cases = [
+            (reqwest::Method::POST, "/user", read_write.as_str(), 200),
+            (reqwest::Method::GET, "/user", read.as_str(), 200),
+            (reqwest::Method::POST, "/user", read.as_str(), 403),
+            (reqwest::Method::GET, "/user", expired.as_str(), 401),
+            (reqwest::Method::GET, "/user", "khata_pat_unknown", 401),
+            (reqwest::Method::GET, "/session", read_write.as_str(), 403),
+            (reqwest::Method::GET, "/admin", read_write.as_str(), 403),
+        ];
+        for (method, path, token, expected) in cases {
+            let response = status(method.clone(), path, token).await.unwrap();
+            assert_eq!(response.status().as_u16(), expected, "{method} {path}");
+        }
+
+        User::update_disabled(&db, user.id, true).await.unwrap();
+        let response = status(reqwest::Method::GET, "/user", &read_write)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn ledger_extractors_check_the_member_role() {
+        async fn any(ledger: XLedger) -> String {
+            ledger.id.to_string()
+        }
+        async fn owner(XLedgerOwner(ledger): XLedgerOwner) -> String {
+            ledger.id.to_string()
+        }
+        async fn viewer(XLedgerViewer(ledger): XLedgerViewer) -> String {
+            ledger.id.to_string()
+        }
+        let app = Router::new()
+            .route("/any", get(any).post(any))
+            .route("/owner", get(owner))
+            .route("/viewer", post(viewer));
+        let (url, db) = serve(app).await;
+
+        let (owner_user, owner_token) =
+            user_with_token(&db, "owner@example.com", ApiTokenScope::ReadWrite).await;
+        let ledger_id = Ledger::create(
+            &db,
+            owner_user.id,
+            LedgerReq {
+                name: "Home".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let mut tokens = vec![("owner", owner_token)];
+        for (name, role) in [
+            ("editor", LedgerRole::Editor),
+            ("viewer", LedgerRole::Viewer),
+        ] {
+            let email = format!("{name}@example.com");
+            let (_, token) = user_with_token(&db, &email, ApiTokenScope::ReadWrite).await;
+            Ledger::upsert_member(&db, ledger_id, LedgerMemberReq { email, role })
+                .await
+                .unwrap();
+            tokens.push((name, token));
+        }
+        let (_, stranger) =
+            user_with_token(&db, "stranger@example.com", ApiTokenScope::ReadWrite).await;
+        tokens.push(("stranger", stranger));
+
+        let client = reqwest::Client::new();
+        // Expected status of GET /any, POST /any, GET /owner and POST /viewer.
+        let matrix = [
+            ("owner", [200, 200, 200, 200]),
+            ("editor", [200, 200, 403, 200]),
+            ("viewer", [200, 403, 403, 200]),
+            ("stranger", [403, 403, 403, 403]),
+        ];
+        for (name, expected) in matrix {
+            let token = &tokens.iter().find(|(n, _)| *n == name).unwrap().1;
+            let requests = [
+                client.get(format!("{url}/any")),
+                client.post(format!("{url}/any")),
+                client.get(format!("{url}/owner")),
+                client.post(format!("{url}/viewer")),
+            ];
+            for (request, expected) in requests.into_iter().zip(expected) {
+                let response = request
+                    .bearer_auth(token)
+                    .header("x-ledger-id", ledger_id.to_string())
+                    .send()
+                    .await
+                    .unwrap();
+                assert_eq!(response.status().as_u16(), expected, "{name}: {response:?}");
+                if response.status() == StatusCode::OK {
+                    assert_eq!(response.text().await.unwrap(), ledger_id.to_string());
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn admin_routes_manage_users() {
+        /// Signs in as the user the way the login routes do, without the
+        /// password.
+        async fn login(
+            mut auth_session: AuthSession,
+            headers: HeaderMap,
+            Path(user_id): Path<Uuid>,
+        ) -> AppResult<()> {
+            let user = User::find_by_id(auth_session.backend.db(), user_id)
+                .await?
+                .unwrap();
+            auth_session.login(&user).await.unwrap();
+            start_device(&auth_session, &user, &headers, IpAddr::from([127, 0, 0, 1])).await
+        }
+        let (admin_routes, _) = routes::admin::router().split_for_parts();
+        let app = Router::new()
+            .route("/login/{user_id}", post(login))
+            .nest("/admin", admin_routes);
+        let (url, db) = serve(app).await;
+        let (ada, _) = user_with_token(&db, "ada@example.com", ApiTokenScope::ReadWrite).await;
+        let (bob, _) = user_with_token(&db, "bob@example.com", ApiTokenScope::ReadWrite).await;
+
+        assert!(make_admin(&db, "nobody@example.com").await.is_err());
+        make_admin(&db, &ada.email).await.unwrap();
+        let ada = User::find_by_id(&db, ada.id).await.unwrap().unwrap();
+        assert!(ada.is_admin);
+
+        let client = reqwest::Client::new();
+        let mut cookies = vec![];
+        for user in [&ada, &bob] {
+            let response = client
+                .post(format!("{url}/login/{}", user.id))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let cookie = response.headers()[reqwest::header::SET_COOKIE]
+                .to_str()
+                .unwrap();
+            cookies.push(cookie.split(';').next().unwrap().to_string());
+        }
+        let (ada_cookie, bob_cookie) = (&cookies[0], &cookies[1]);
+        let send = |method: reqwest::Method, path: String, cookie: &str| {
+            client
+                .request(method, format!("{url}/admin{path}"))
+                .header(reqwest::header::COOKIE, cookie)
+                .send()
+        };
+
+        let cases = [
+            (reqwest::Method::GET, "/user".to_string(), "", 401),
+            (
+                reqwest::Method::GET,
+                "/user".to_string(),
+                bob_cookie.as_str(),
+                403,
+            ),
+            (
+                reqwest::Method::GET,
+                "/user".to_string(),
+                ada_cookie.as_str(),
+                200,
+            ),
+            (
+                reqwest::Method::POST,
+                format!("/user/{}/disable", ada.id),
+                ada_cookie.as_str(),
+                403,
+            ),
+            (
+                reqwest::Method::POST,
+                format!("/user/{}/disable", Uuid::now_v7()),
+                ada_cookie.as_str(),
+                404,
+            ),
+            (
+                reqwest::Method::POST,
+                format!("/user/{}/enable", Uuid::now_v7()),
+                ada_cookie.as_str(),
+                404,
+            ),
+        ];
+        for (method, path, cookie, expected) in cases {
+            let response = send(method.clone(), path.clone(), cookie).await.unwrap();
+            assert_eq!(response.status().as_u16(), expected, "{method} {path}");
+        }
+
+        let response = send(
+            reqwest::Method::POST,
+            format!("/user/{}/disable", bob.id),
+            ada_cookie,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            User::find_by_id(&db, bob.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .disabled
+        );
+        // Disabling signs the user out.
+        let response = send(reqwest::Method::GET, "/user".to_string(), bob_cookie)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = send(
+            reqwest::Method::POST,
+            format!("/user/{}/enable", bob.id),
+            ada_cookie,
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            !User::find_by_id(&db, bob.id)
+                .await
+                .unwrap()
+                .unwrap()
+                .disabled
+        );
+    }
+}
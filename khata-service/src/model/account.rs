@@ -1,12 +1,21 @@
+use jiff::tz::TimeZone;
 use migration::{AccountType, OnConflict};
-use sea_orm::{ActiveValue, DbConn, DbErr, EntityTrait, QueryOrder};
+use sea_orm::{
+    ActiveValue, ColumnTrait, ConnectionTrait, DbConn, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use uuid::Uuid;
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
-use super::currency::{CurrencyEntity, CurrencyModel};
-use crate::entity::account;
+use super::{
+    account_extra::{AccountExtra, AccountSummary, LoanInstalment},
+    audit::{AuditContext, AuditEntityType, AuditEventModel},
+    currency::{CurrencyEntity, CurrencyModel},
+    transaction::{TransactionEntity, TransactionItemColumn, TransactionItemEntity},
+};
+use crate::{entity::account, period};
 
 #[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
 pub struct AccountModel {
@@ -18,7 +27,7 @@ pub struct AccountModel {
     pub created_at: chrono::DateTime<chrono::Utc>,
     pub is_cash_flow: bool,
     pub is_active: bool,
-    pub account_extra: Option<serde_json::Value>,
+    pub account_extra: Option<AccountExtra>,
 }
 pub type AccountEntity = account::Entity;
 pub type AccountActiveModel = account::ActiveModel;
@@ -36,12 +45,16 @@ impl AccountModel {
             created_at: model.created_at,
             is_cash_flow: model.is_cash_flow,
             is_active: model.is_active,
-            account_extra: model.account_extra,
+            account_extra: model
+                .account_extra
+                .map(serde_json::from_value)
+                .transpose()?,
         })
     }
 }
 
 #[derive(Debug, Clone, ToSchema, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_account_extra"))]
 pub struct AccountReq {
     pub id: Option<Uuid>,
     #[validate(length(min = 1, max = 100))]
@@ -53,6 +66,23 @@ pub struct AccountReq {
     is_cash_flow: bool,
     is_active: bool,
     created_at: chrono::DateTime<chrono::Utc>,
+    /// Loan or card details. When left out, the stored details are kept.
+    #[validate(nested)]
+    #[serde(default)]
+    account_extra: Option<AccountExtra>,
+    /// Removes the stored details, for when `account_extra` is left out.
+    #[serde(default)]
+    clear_account_extra: bool,
+}
+
+fn validate_account_extra(account: &AccountReq) -> Result<(), ValidationError> {
+    match &account.account_extra {
+        Some(extra) if !extra.fits(&account.account_type) => {
+            Err(ValidationError::new("account_extra")
+                .with_message("account_extra does not match the account type".into()))
+        }
+        _ => Ok(()),
+    }
 }
 
 #[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
@@ -100,84 +130,286 @@ impl AccountReq {
             })
     }
 
-    pub async fn upsert(db: &DbConn, account: Self) -> Result<Uuid, DbErr> {
-        let result = AccountEntity::insert(AccountActiveModel {
-            id: ActiveValue::Set(account.id.unwrap_or_else(|| {
-                Uuid::new_v7(uuid::Timestamp::from_unix(
-                    uuid::timestamp::context::NoContext,
-                    account.created_at.timestamp() as u64,
-                    0,
-                ))
-            })),
-            name: ActiveValue::Set(account.name),
+    /// Amortisation schedule of a `Loan` account.
+    pub async fn schedule(db: &DbConn, id: Uuid) -> Result<Vec<LoanInstalment>, DbErr> {
+        let account = Self::find_model(db, id).await?;
+        match account.account_extra {
+            Some(AccountExtra::Loan(loan)) => Ok(loan.schedule(account.starting_balance.abs())),
+            _ => Err(DbErr::Custom(format!("account: {id} has no loan details"))),
+        }
+    }
+
+    /// Repayment position of a `Loan` or `CreditCard` account as of today in
+    /// the time zone.
+    pub async fn summary(db: &DbConn, id: Uuid, tz: &TimeZone) -> Result<AccountSummary, DbErr> {
+        let account = Self::find_model(db, id).await?;
+        let items = TransactionItemEntity::find()
+            .filter(TransactionItemColumn::AccountId.eq(id))
+            .find_also_related(TransactionEntity::default())
+            .all(db)
+            .await?
+            .into_iter()
+            .filter_map(|(item, transaction)| Some((transaction?.timestamp, item.amount)))
+            .collect::<Vec<_>>();
+        let balance = account.starting_balance + items.iter().map(|(_, a)| a).sum::<i64>();
+        let today = period::today(tz);
+        match account.account_extra {
+            Some(AccountExtra::Loan(loan)) => Ok(AccountSummary::Loan(loan.summary(
+                account.starting_balance.abs(),
+                balance,
+                items.iter().map(|(_, a)| a).filter(|a| **a > 0).sum(),
+                today,
+            ))),
+            Some(AccountExtra::CreditCard(card)) => {
+                let statement_date = card.last_statement_date(today);
+                let (before, after): (Vec<_>, Vec<_>) = items.iter().partition(|(timestamp, _)| {
+                    period::local_date(tz, *timestamp) <= statement_date
+                });
+                let statement_balance =
+                    account.starting_balance + before.iter().map(|(_, a)| a).sum::<i64>();
+                let paid_since = after.iter().map(|(_, a)| a).filter(|a| **a > 0).sum();
+                Ok(AccountSummary::CreditCard(card.summary(
+                    balance,
+                    statement_balance,
+                    paid_since,
+                    today,
+                )))
+            }
+            None => Err(DbErr::Custom(format!(
+                "account: {id} has no loan or credit card details"
+            ))),
+        }
+    }
+
+    async fn find_model(db: &DbConn, id: Uuid) -> Result<AccountModel, DbErr> {
+        let account = AccountEntity::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or_else(|| DbErr::RecordNotFound(format!("account: {id}")))?;
+        AccountModel::from_entity(account).map_err(|e| DbErr::Json(e.to_string()))
+    }
+
+    /// Keeps the stored details when the request leaves them out, which
+    /// clients that do not know about them always do.
+    async fn keep_account_extra<C: ConnectionTrait>(&mut self, db: &C) -> Result<(), DbErr> {
+        if self.account_extra.is_some() || self.clear_account_extra {
+            return Ok(());
+        }
+        let Some(id) = self.id else {
+            return Ok(());
+        };
+        let stored = AccountEntity::find_by_id(id)
+            .one(db)
+            .await?
+            .and_then(|account| account.account_extra);
+        self.account_extra = stored
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(|e| DbErr::Json(e.to_string()))?;
+        Ok(())
+    }
+
+    fn into_active_model(self) -> Result<(Uuid, AccountActiveModel), DbErr> {
+        let id = self.id.unwrap_or_else(|| {
+            Uuid::new_v7(uuid::Timestamp::from_unix(
+                uuid::timestamp::context::NoContext,
+                self.created_at.timestamp() as u64,
+                0,
+            ))
+        });
+        let model = AccountActiveModel {
+            id: ActiveValue::Set(id),
+            name: ActiveValue::Set(self.name),
             #[allow(clippy::unwrap_used)]
-            account_type: ActiveValue::Set(serde_json::to_string(&account.account_type).unwrap()),
-            currency_code: ActiveValue::Set(account.currency_code),
-            starting_balance: ActiveValue::Set(account.starting_balance),
-            is_cash_flow: ActiveValue::Set(account.is_cash_flow),
-            is_active: ActiveValue::Set(account.is_active),
-            created_at: ActiveValue::Set(account.created_at),
-            account_extra: ActiveValue::NotSet,
+            account_type: ActiveValue::Set(serde_json::to_string(&self.account_type).unwrap()),
+            currency_code: ActiveValue::Set(self.currency_code),
+            starting_balance: ActiveValue::Set(self.starting_balance),
+            is_cash_flow: ActiveValue::Set(self.is_cash_flow),
+            is_active: ActiveValue::Set(self.is_active),
+            created_at: ActiveValue::Set(self.created_at),
+            account_extra: ActiveValue::Set(
+                self.account_extra
+                    .map(serde_json::to_value)
+                    .transpose()
+                    .map_err(|e| DbErr::Json(e.to_string()))?,
+            ),
+        };
+        Ok((id, model))
+    }
+
+    fn on_conflict() -> OnConflict {
+        OnConflict::column(AccountColumn::Id)
+            .update_columns([
+                AccountColumn::Name,
+                AccountColumn::AccountType,
+                AccountColumn::CurrencyCode,
+                AccountColumn::StartingBalance,
+                AccountColumn::IsCashFlow,
+                AccountColumn::IsActive,
+                AccountColumn::CreatedAt,
+                AccountColumn::AccountExtra,
+            ])
+            .to_owned()
+    }
+
+    pub async fn upsert(db: &DbConn, ctx: &AuditContext, mut account: Self) -> Result<Uuid, DbErr> {
+        let ctx = ctx.clone();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move {
+                account.keep_account_extra(txn).await?;
+                let (id, model) = account.into_active_model()?;
+                let before = AuditEventModel::snapshot::<AccountEntity, _>(txn, id).await?;
+                AccountEntity::insert(model)
+                    .on_conflict(Self::on_conflict())
+                    .exec(txn)
+                    .await?;
+                let after = AuditEventModel::snapshot::<AccountEntity, _>(txn, id).await?;
+                AuditEventModel::record(txn, &ctx, AuditEntityType::Account, id, before, after)
+                    .await?;
+                Ok(id)
+            })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
         })
-        .on_conflict(
-            OnConflict::column(AccountColumn::Id)
-                .update_columns([
-                    AccountColumn::Name,
-                    AccountColumn::AccountType,
-                    AccountColumn::CurrencyCode,
-                    AccountColumn::StartingBalance,
-                    AccountColumn::IsCashFlow,
-                    AccountColumn::IsActive,
-                    AccountColumn::CreatedAt,
-                    AccountColumn::AccountExtra,
-                ])
-                .to_owned(),
-        )
-        .exec(db)
-        .await?;
-        Ok(result.last_insert_id)
-    }
-
-    pub async fn upsert_many(db: &DbConn, accounts: Vec<Self>) -> Result<(), DbErr> {
-        AccountEntity::insert_many(accounts.into_iter().map(|a| AccountActiveModel {
-            id: ActiveValue::Set(a.id.unwrap_or_else(|| {
-                Uuid::new_v7(uuid::Timestamp::from_unix(
-                    uuid::timestamp::context::NoContext,
-                    a.created_at.timestamp() as u64,
-                    0,
-                ))
-            })),
-            name: ActiveValue::Set(a.name),
-            #[allow(clippy::unwrap_used)]
-            account_type: ActiveValue::Set(serde_json::to_string(&a.account_type).unwrap()),
-            currency_code: ActiveValue::Set(a.currency_code),
-            starting_balance: ActiveValue::Set(a.starting_balance),
-            is_cash_flow: ActiveValue::Set(a.is_cash_flow),
-            is_active: ActiveValue::Set(a.is_active),
-            created_at: ActiveValue::Set(a.created_at),
-            account_extra: ActiveValue::NotSet,
-        }))
-        .on_conflict(
-            OnConflict::column(AccountColumn::Id)
-                .update_columns([
-                    AccountColumn::Name,
-                    AccountColumn::AccountType,
-                    AccountColumn::CurrencyCode,
-                    AccountColumn::StartingBalance,
-                    AccountColumn::IsCashFlow,
-                    AccountColumn::IsActive,
-                    AccountColumn::CreatedAt,
-                    AccountColumn::AccountExtra,
-                ])
-                .to_owned(),
-        )
-        .exec(db)
-        .await?;
-        Ok(())
     }
 
-    pub async fn delete(db: &DbConn, id: Uuid) -> Result<(), DbErr> {
+    pub async fn upsert_many(
+        db: &DbConn,
+        ctx: &AuditContext,
+        accounts: Vec<Self>,
+    ) -> Result<(), DbErr> {
+        let ctx = ctx.clone();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move {
+                let mut ids = Vec::with_capacity(accounts.len());
+                let mut models = Vec::with_capacity(accounts.len());
+                for mut account in accounts {
+                    account.keep_account_extra(txn).await?;
+                    let (id, model) = account.into_active_model()?;
+                    ids.push(id);
+                    models.push(model);
+                }
+                let mut befores = Vec::with_capacity(ids.len());
+                for id in &ids {
+                    befores.push(AuditEventModel::snapshot::<AccountEntity, _>(txn, *id).await?);
+                }
+                AccountEntity::insert_many(models)
+                    .on_conflict(Self::on_conflict())
+                    .exec(txn)
+                    .await?;
+                for (id, before) in ids.into_iter().zip(befores) {
+                    let after = AuditEventModel::snapshot::<AccountEntity, _>(txn, id).await?;
+                    AuditEventModel::record(txn, &ctx, AuditEntityType::Account, id, before, after)
+                        .await?;
+                }
+                Ok(())
+            })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
+    }
+
+    pub async fn delete(db: &DbConn, ctx: &AuditContext, id: Uuid) -> Result<(), DbErr> {
+        let ctx = ctx.clone();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move { Self::delete_in(txn, &ctx, id).await })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
+    }
+
+    /// Deletes the account together with its transaction items, which go
+    /// away through the cascading foreign key.
+    pub(crate) async fn delete_in<C: ConnectionTrait>(
+        db: &C,
+        ctx: &AuditContext,
+        id: Uuid,
+    ) -> Result<(), DbErr> {
+        let items = TransactionItemEntity::find()
+            .filter(TransactionItemColumn::AccountId.eq(id))
+            .all(db)
+            .await?;
+        let before = AuditEventModel::snapshot::<AccountEntity, _>(db, id).await?;
         AccountEntity::delete_by_id(id).exec(db).await?;
-        Ok(())
+        for item in items {
+            let item_id = item.id;
+            let item = serde_json::to_value(item).map_err(|e| DbErr::Json(e.to_string()))?;
+            AuditEventModel::record(
+                db,
+                ctx,
+                AuditEntityType::TransactionItem,
+                item_id,
+                Some(item),
+                None,
+            )
+            .await?;
+        }
+        AuditEventModel::record(db, ctx, AuditEntityType::Account, id, before, None).await
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::test_ledger;
+
+    fn card(id: Uuid, extra: &serde_json::Value, clear: bool) -> AccountReq {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": "Card",
+            "account_type": AccountType::CreditCard,
+            "currency_code": "INR",
+            "starting_balance": 0,
+            "is_cash_flow": true,
+            "is_active": true,
+            "created_at": "2026-10-01T00:00:00Z",
+            "account_extra": extra,
+            "clear_account_extra": clear,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn details_are_kept_unless_cleared() {
+        let db = test_ledger::ledger().await;
+        let ctx = AuditContext {
+            user_id: Uuid::now_v7(),
+            request_id: None,
+        };
+        let id = Uuid::now_v7();
+        let details = serde_json::json!({
+            "type": "credit_card",
+            "credit_limit": 100_000,
+            "statement_day": 5,
+            "due_day": 25,
+        });
+        AccountReq::upsert(&db, &ctx, card(id, &details, false))
+            .await
+            .unwrap();
+
+        AccountReq::upsert(&db, &ctx, card(id, &serde_json::Value::Null, false))
+            .await
+            .unwrap();
+        let account = AccountReq::find_model(&db, id).await.unwrap();
+        assert!(matches!(
+            account.account_extra,
+            Some(AccountExtra::CreditCard(card)) if card.credit_limit == 100_000
+        ));
+
+        AccountReq::upsert(&db, &ctx, card(id, &serde_json::Value::Null, true))
+            .await
+            .unwrap();
+        let account = AccountReq::find_model(&db, id).await.unwrap();
+        assert!(account.account_extra.is_none());
     }
 }
@@ -0,0 +1,359 @@
+use chrono::{Datelike, Months, NaiveDate};
+use migration::AccountType;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::{Validate, ValidationErrors};
+
+/// Share of the statement balance that has to be paid by the due date.
+const MINIMUM_DUE_PERCENT: i64 = 5;
+
+/// Type specific data kept in `account.account_extra`.
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AccountExtra {
+    Loan(LoanDetails),
+    CreditCard(CreditCardDetails),
+}
+
+impl AccountExtra {
+    /// Whether this kind of extra data belongs to an account of `account_type`.
+    pub const fn fits(&self, account_type: &AccountType) -> bool {
+        matches!(
+            (self, account_type),
+            (Self::Loan(_), AccountType::Loan) | (Self::CreditCard(_), AccountType::CreditCard)
+        )
+    }
+}
+
+impl Validate for AccountExtra {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        match self {
+            Self::Loan(loan) => loan.validate(),
+            Self::CreditCard(card) => card.validate(),
+        }
+    }
+}
+
+/// A loan is repaid in `tenure_months` equal monthly instalments, the first
+/// one falling a month after `start_date`. The principal is the account's
+/// starting balance.
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize, Validate)]
+pub struct LoanDetails {
+    /// Yearly interest rate in percent.
+    #[validate(range(min = 0.0, max = 100.0))]
+    pub interest_rate: f64,
+    #[validate(range(min = 1))]
+    pub emi_amount: i64,
+    #[validate(range(min = 1, max = 600))]
+    pub tenure_months: u32,
+    pub start_date: NaiveDate,
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize, Validate)]
+pub struct CreditCardDetails {
+    #[validate(range(min = 1))]
+    pub credit_limit: i64,
+    /// Day of the month the statement is generated on.
+    #[validate(range(min = 1, max = 28))]
+    pub statement_day: u32,
+    /// Day of the month the statement has to be paid by.
+    #[validate(range(min = 1, max = 28))]
+    pub due_day: u32,
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct LoanInstalment {
+    pub number: u32,
+    pub due_date: NaiveDate,
+    pub amount: i64,
+    pub principal: i64,
+    pub interest: i64,
+    /// Principal left once this instalment is paid.
+    pub balance: i64,
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct LoanSummary {
+    /// Current account balance from the starting balance and transactions.
+    pub balance: i64,
+    pub principal: i64,
+    pub principal_paid: i64,
+    pub interest_paid: i64,
+    pub outstanding_principal: i64,
+    pub outstanding_interest: i64,
+    pub instalments_paid: u32,
+    pub instalments_left: u32,
+    /// Instalments that are due but not paid in full.
+    pub instalments_overdue: u32,
+    pub next_due_date: Option<NaiveDate>,
+    pub next_due_amount: i64,
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct CreditCardSummary {
+    /// Current account balance from the starting balance and transactions.
+    pub balance: i64,
+    pub credit_limit: i64,
+    pub outstanding: i64,
+    pub available_credit: i64,
+    pub utilisation_percent: f64,
+    pub last_statement_date: NaiveDate,
+    /// Amount owed when the last statement was generated.
+    pub statement_balance: i64,
+    /// Part of the minimum due of the last statement that is still unpaid.
+    pub minimum_due: i64,
+    pub next_due_date: NaiveDate,
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AccountSummary {
+    Loan(LoanSummary),
+    CreditCard(CreditCardSummary),
+}
+
+impl LoanDetails {
+    /// Splits every instalment into interest on the remaining principal and
+    /// repaid principal. The last instalment clears whatever is left, so it
+    /// can differ from the EMI.
+    pub fn schedule(&self, principal: i64) -> Vec<LoanInstalment> {
+        let monthly_rate = self.interest_rate / 12.0 / 100.0;
+        let mut balance = principal;
+        let mut schedule = Vec::with_capacity(self.tenure_months as usize);
+        for number in 1..=self.tenure_months {
+            if balance <= 0 {
+                break;
+            }
+            #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+            let interest = (balance as f64 * monthly_rate).round() as i64;
+            let principal = if number == self.tenure_months {
+                balance
+            } else {
+                (self.emi_amount - interest).clamp(0, balance)
+            };
+            balance -= principal;
+            schedule.push(LoanInstalment {
+                number,
+                due_date: add_months(self.start_date, number),
+                amount: principal + interest,
+                principal,
+                interest,
+                balance,
+            });
+        }
+        schedule
+    }
+
+    /// `paid` is what was actually paid into the loan account. Payments
+    /// clear instalments in order, interest before principal, so a partly
+    /// paid instalment is still the next one due. Unpaid instalments due on
+    /// or before `today` are overdue.
+    pub fn summary(
+        &self,
+        principal: i64,
+        balance: i64,
+        paid: i64,
+        today: NaiveDate,
+    ) -> LoanSummary {
+        let schedule = self.schedule(principal);
+        let mut left_to_apply = paid.max(0);
+        let (mut principal_paid, mut interest_paid) = (0, 0);
+        let (mut instalments_paid, mut instalments_overdue) = (0, 0);
+        let mut next = None;
+        for instalment in &schedule {
+            let interest = instalment.interest.min(left_to_apply);
+            let principal = instalment.principal.min(left_to_apply - interest);
+            left_to_apply -= interest + principal;
+            interest_paid += interest;
+            principal_paid += principal;
+            if interest + principal == instalment.amount {
+                instalments_paid += 1;
+                continue;
+            }
+            if instalment.due_date <= today {
+                instalments_overdue += 1;
+            }
+            next.get_or_insert((
+                instalment.due_date,
+                instalment.amount - interest - principal,
+            ));
+        }
+        let scheduled_interest = schedule.iter().map(|i| i.interest).sum::<i64>();
+        LoanSummary {
+            balance,
+            principal,
+            principal_paid,
+            interest_paid,
+            outstanding_principal: principal - principal_paid,
+            outstanding_interest: scheduled_interest - interest_paid,
+            instalments_paid,
+            instalments_left: u32::try_from(schedule.len()).unwrap_or(u32::MAX) - instalments_paid,
+            instalments_overdue,
+            next_due_date: next.map(|(date, _)| date),
+            next_due_amount: next.map_or(0, |(_, amount)| amount),
+        }
+    }
+}
+
+impl CreditCardDetails {
+    /// The statement generated most recently, on or before `today`.
+    pub fn last_statement_date(&self, today: NaiveDate) -> NaiveDate {
+        let this_month = today.with_day(self.statement_day).unwrap_or(today);
+        if this_month <= today {
+            this_month
+        } else {
+            sub_month(this_month)
+        }
+    }
+
+    /// The day a statement generated on `statement_date` has to be paid by.
+    pub fn due_date(&self, statement_date: NaiveDate) -> NaiveDate {
+        let due = statement_date
+            .with_day(self.due_day)
+            .unwrap_or(statement_date);
+        if self.due_day > self.statement_day {
+            due
+        } else {
+            add_months(due, 1)
+        }
+    }
+
+    /// `statement_balance` is the account balance at the end of the last
+    /// statement day and `paid_since` what was paid into the card after it.
+    pub fn summary(
+        &self,
+        balance: i64,
+        statement_balance: i64,
+        paid_since: i64,
+        today: NaiveDate,
+    ) -> CreditCardSummary {
+        let last_statement_date = self.last_statement_date(today);
+        let due_date = self.due_date(last_statement_date);
+        let next_due_date = if due_date >= today {
+            due_date
+        } else {
+            self.due_date(add_months(last_statement_date, 1))
+        };
+        let outstanding = (-balance).max(0);
+        let statement_balance = (-statement_balance).max(0);
+        let minimum = (statement_balance * MINIMUM_DUE_PERCENT + 99) / 100;
+        #[allow(clippy::cast_precision_loss)]
+        let utilisation_percent = outstanding as f64 / self.credit_limit as f64 * 100.0;
+        CreditCardSummary {
+            balance,
+            credit_limit: self.credit_limit,
+            outstanding,
+            available_credit: (self.credit_limit - outstanding).max(0),
+            utilisation_percent,
+            last_statement_date,
+            statement_balance,
+            minimum_due: (minimum - paid_since).max(0),
+            next_due_date,
+        }
+    }
+}
+
+fn add_months(date: NaiveDate, months: u32) -> NaiveDate {
+    date.checked_add_months(Months::new(months))
+        .unwrap_or(NaiveDate::MAX)
+}
+
+fn sub_month(date: NaiveDate) -> NaiveDate {
+    date.checked_sub_months(Months::new(1))
+        .unwrap_or(NaiveDate::MIN)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    /// 1,20,000.00 at 12% a year over a year, the first instalment due on
+    /// 2026-02-15.
+    fn loan() -> LoanDetails {
+        LoanDetails {
+            interest_rate: 12.0,
+            emi_amount: 1_066_185,
+            tenure_months: 12,
+            start_date: date(2026, 1, 15),
+        }
+    }
+
+    #[test]
+    fn schedule_repays_the_principal() {
+        let schedule = loan().schedule(12_000_000);
+        assert_eq!(schedule.len(), 12);
+        assert_eq!(schedule[0].due_date, date(2026, 2, 15));
+        assert_eq!(schedule[0].interest, 120_000);
+        assert_eq!(schedule[0].principal, 946_185);
+        assert_eq!(schedule[0].balance, 12_000_000 - 946_185);
+        assert_eq!(
+            schedule.iter().map(|i| i.principal).sum::<i64>(),
+            12_000_000
+        );
+        assert_eq!(schedule.last().unwrap().balance, 0);
+        assert!(
+            schedule
+                .iter()
+                .all(|i| i.amount == i.principal + i.interest)
+        );
+    }
+
+    #[test]
+    fn summary_follows_actual_payments() {
+        let loan = loan();
+        let schedule = loan.schedule(12_000_000);
+        // Two instalments are due, but only the first one and part of the
+        // second were paid.
+        let paid = schedule[0].amount + 500_000;
+        let summary = loan.summary(12_000_000, -12_000_000 + paid, paid, date(2026, 3, 20));
+        assert_eq!(summary.instalments_paid, 1);
+        assert_eq!(summary.instalments_left, 11);
+        assert_eq!(summary.instalments_overdue, 1);
+        assert_eq!(
+            summary.interest_paid,
+            schedule[0].interest + schedule[1].interest
+        );
+        assert_eq!(
+            summary.principal_paid,
+            schedule[0].principal + 500_000 - schedule[1].interest
+        );
+        assert_eq!(
+            summary.outstanding_principal,
+            12_000_000 - summary.principal_paid
+        );
+        assert_eq!(summary.next_due_date, Some(date(2026, 3, 15)));
+        assert_eq!(summary.next_due_amount, schedule[1].amount - 500_000);
+    }
+
+    #[test]
+    fn summary_without_payments_counts_due_instalments_overdue() {
+        let summary = loan().summary(12_000_000, -12_000_000, 0, date(2026, 4, 15));
+        assert_eq!(summary.instalments_paid, 0);
+        assert_eq!(summary.instalments_overdue, 3);
+        assert_eq!(summary.principal_paid, 0);
+        assert_eq!(summary.outstanding_principal, 12_000_000);
+        assert_eq!(summary.next_due_date, Some(date(2026, 2, 15)));
+    }
+
+    #[test]
+    fn credit_card_due_date_after_statement() {
+        let card = CreditCardDetails {
+            credit_limit: 10_000_000,
+            statement_day: 20,
+            due_day: 10,
+        };
+        let today = date(2026, 10, 5);
+        assert_eq!(card.last_statement_date(today), date(2026, 9, 20));
+        let summary = card.summary(-300_000, -200_000, 4_000, today);
+        assert_eq!(summary.next_due_date, date(2026, 10, 10));
+        assert_eq!(summary.outstanding, 300_000);
+        assert_eq!(summary.statement_balance, 200_000);
+        assert_eq!(summary.minimum_due, 6_000);
+        assert_eq!(summary.available_credit, 9_700_000);
+    }
+}
@@ -0,0 +1,171 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Utc};
+use sea_orm::{ActiveValue, ColumnTrait, DbConn, DbErr, EntityTrait, QueryFilter, QueryOrder};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::user_entity::{api_token, prelude::ApiToken as ApiTokenEntity};
+
+/// Prefix of every personal access token, so leaked tokens are easy to spot.
+const TOKEN_PREFIX: &str = "khata_pat_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiTokenScope {
+    /// Only safe methods (`GET`, `HEAD`, `OPTIONS`).
+    Read,
+    ReadWrite,
+}
+
+impl ApiTokenScope {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Read => "read",
+            Self::ReadWrite => "read_write",
+        }
+    }
+
+    fn from_str(value: &str) -> Result<Self, DbErr> {
+        serde_json::from_value(serde_json::Value::String(value.to_string()))
+            .map_err(|e| DbErr::Type(e.to_string()))
+    }
+}
+
+/// A personal access token. The token itself is only returned once, on
+/// creation, and only its hash is stored.
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub scope: ApiTokenScope,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, ToSchema, Deserialize, Validate)]
+pub struct ApiTokenReq {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    pub scope: ApiTokenScope,
+    #[validate(custom(function = "validate_expires_at"))]
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+fn validate_expires_at(expires_at: &DateTime<Utc>) -> Result<(), ValidationError> {
+    if *expires_at <= Utc::now() {
+        return Err(ValidationError::new("expires_at")
+            .with_message("expires_at must be in the future".into()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct NewApiToken {
+    #[serde(flatten)]
+    pub info: ApiToken,
+    pub token: String,
+}
+
+fn hash_token(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+impl ApiToken {
+    fn from_model(model: api_token::Model) -> Result<Self, DbErr> {
+        Ok(Self {
+            id: model.id,
+            user_id: model.user_id,
+            name: model.name,
+            scope: ApiTokenScope::from_str(&model.scope)?,
+            expires_at: model.expires_at,
+            last_used_at: model.last_used_at,
+            created_at: model.created_at,
+        })
+    }
+
+    pub async fn create(
+        db: &DbConn,
+        user_id: Uuid,
+        req: ApiTokenReq,
+    ) -> Result<NewApiToken, DbErr> {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let token: String = std::iter::once(TOKEN_PREFIX.to_string())
+            .chain(bytes.iter().map(|b| format!("{b:02x}")))
+            .collect();
+        let info = Self {
+            id: Uuid::now_v7(),
+            user_id,
+            name: req.name,
+            scope: req.scope,
+            expires_at: req.expires_at,
+            last_used_at: None,
+            created_at: Utc::now(),
+        };
+        ApiTokenEntity::insert(api_token::ActiveModel {
+            id: ActiveValue::Set(info.id),
+            user_id: ActiveValue::Set(info.user_id),
+            name: ActiveValue::Set(info.name.clone()),
+            token_hash: ActiveValue::Set(hash_token(&token)),
+            scope: ActiveValue::Set(info.scope.as_str().to_string()),
+            expires_at: ActiveValue::Set(info.expires_at),
+            last_used_at: ActiveValue::Set(None),
+            created_at: ActiveValue::Set(info.created_at),
+        })
+        .exec_without_returning(db)
+        .await?;
+        Ok(NewApiToken { info, token })
+    }
+
+    pub async fn find_for_user(db: &DbConn, user_id: Uuid) -> Result<Vec<Self>, DbErr> {
+        ApiTokenEntity::find()
+            .filter(api_token::Column::UserId.eq(user_id))
+            .order_by_asc(api_token::Column::CreatedAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(Self::from_model)
+            .collect()
+    }
+
+    pub async fn revoke(db: &DbConn, user_id: Uuid, id: Uuid) -> Result<(), DbErr> {
+        ApiTokenEntity::delete_many()
+            .filter(api_token::Column::UserId.eq(user_id))
+            .filter(api_token::Column::Id.eq(id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    /// Looks up an unexpired token and records that it was used.
+    pub async fn authenticate(db: &DbConn, token: &str) -> Result<Option<Self>, DbErr> {
+        if !token.starts_with(TOKEN_PREFIX) {
+            return Ok(None);
+        }
+        let Some(model) = ApiTokenEntity::find()
+            .filter(api_token::Column::TokenHash.eq(hash_token(token)))
+            .one(db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let now = Utc::now();
+        if model.expires_at.is_some_and(|e| e <= now) {
+            return Ok(None);
+        }
+        ApiTokenEntity::update_many()
+            .col_expr(
+                api_token::Column::LastUsedAt,
+                sea_orm::sea_query::Expr::value(now),
+            )
+            .filter(api_token::Column::Id.eq(model.id))
+            .exec(db)
+            .await?;
+        Self::from_model(model).map(Some)
+    }
+}
@@ -0,0 +1,288 @@
+use migration::OnConflict;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DbConn, DbErr, EntityTrait,
+    IntoActiveModel, Iterable, PrimaryKeyTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::{
+    account::{AccountColumn, AccountEntity, AccountReq},
+    category::{CategoryColumn, CategoryEntity, CategoryReq},
+    currency::{CurrencyColumn, CurrencyEntity, CurrencyReq},
+    subscription::{SubscriptionColumn, SubscriptionEntity, SubscriptionReq},
+    transaction::{
+        TransactionColumn, TransactionEntity, TransactionItemColumn, TransactionItemEntity,
+        TransactionReq,
+    },
+};
+use crate::entity::audit_event;
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct AuditEventModel(#[schema(inline)] pub audit_event::Model);
+pub type AuditEventEntity = audit_event::Entity;
+pub type AuditEventActiveModel = audit_event::ActiveModel;
+pub type AuditEventColumn = audit_event::Column;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEntityType {
+    Account,
+    Category,
+    Currency,
+    Subscription,
+    Transaction,
+    TransactionItem,
+}
+
+impl AuditEntityType {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Account => "account",
+            Self::Category => "category",
+            Self::Currency => "currency",
+            Self::Subscription => "subscription",
+            Self::Transaction => "transaction",
+            Self::TransactionItem => "transaction_item",
+        }
+    }
+
+    fn from_str(value: &str) -> Result<Self, DbErr> {
+        serde_json::from_value(serde_json::Value::String(value.to_string()))
+            .map_err(|e| DbErr::Type(e.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+}
+
+impl AuditAction {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Create => "create",
+            Self::Update => "update",
+            Self::Delete => "delete",
+        }
+    }
+}
+
+/// Who performed a mutation and as part of which request.
+#[derive(Debug, Clone)]
+pub struct AuditContext {
+    pub user_id: Uuid,
+    pub request_id: Option<String>,
+}
+
+impl AuditEventModel {
+    /// Serialized state of the row with the given primary key, if it exists.
+    pub async fn snapshot<E, C>(
+        db: &C,
+        id: impl Into<<E::PrimaryKey as PrimaryKeyTrait>::ValueType>,
+    ) -> Result<Option<serde_json::Value>, DbErr>
+    where
+        E: EntityTrait,
+        E::Model: Serialize,
+        C: ConnectionTrait,
+    {
+        E::find_by_id(id)
+            .one(db)
+            .await?
+            .map(|model| serde_json::to_value(model).map_err(|e| DbErr::Json(e.to_string())))
+            .transpose()
+    }
+
+    /// Appends an event describing the change from `before` to `after`.
+    /// Nothing is written when the row did not change.
+    pub async fn record<C: ConnectionTrait>(
+        db: &C,
+        ctx: &AuditContext,
+        entity_type: AuditEntityType,
+        entity_id: impl ToString,
+        before: Option<serde_json::Value>,
+        after: Option<serde_json::Value>,
+    ) -> Result<(), DbErr> {
+        let action = match (&before, &after) {
+            (None, None) => return Ok(()),
+            (Some(before), Some(after)) if before == after => return Ok(()),
+            (None, Some(_)) => AuditAction::Create,
+            (Some(_), Some(_)) => AuditAction::Update,
+            (Some(_), None) => AuditAction::Delete,
+        };
+        AuditEventEntity::insert(AuditEventActiveModel {
+            id: ActiveValue::Set(Uuid::now_v7()),
+            entity_type: ActiveValue::Set(entity_type.as_str().to_string()),
+            entity_id: ActiveValue::Set(entity_id.to_string()),
+            action: ActiveValue::Set(action.as_str().to_string()),
+            before: ActiveValue::Set(before),
+            after: ActiveValue::Set(after),
+            user_id: ActiveValue::Set(ctx.user_id),
+            request_id: ActiveValue::Set(ctx.request_id.clone()),
+            created_at: ActiveValue::Set(chrono::Utc::now()),
+        })
+        .exec(db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn history(
+        db: &DbConn,
+        entity_type: AuditEntityType,
+        entity_id: &str,
+    ) -> Result<Vec<Self>, DbErr> {
+        AuditEventEntity::find()
+            .filter(AuditEventColumn::EntityType.eq(entity_type.as_str()))
+            .filter(AuditEventColumn::EntityId.eq(entity_id))
+            .order_by_asc(AuditEventColumn::CreatedAt)
+            .order_by_asc(AuditEventColumn::Id)
+            .all(db)
+            .await
+            .map(|v| v.into_iter().map(Self).collect())
+    }
+
+    /// Puts the entity of the given event back into the state it had right
+    /// after that event. The revert is itself recorded as a new event.
+    pub async fn revert(db: &DbConn, ctx: &AuditContext, event_id: Uuid) -> Result<(), DbErr> {
+        let ctx = ctx.clone();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move {
+                let event = AuditEventEntity::find_by_id(event_id)
+                    .one(txn)
+                    .await?
+                    .ok_or_else(|| DbErr::RecordNotFound(format!("audit_event: {event_id}")))?;
+                let entity_type = AuditEntityType::from_str(&event.entity_type)?;
+                let entity_id = event.entity_id;
+                let state = event.after;
+                match (entity_type, state) {
+                    (AuditEntityType::Account, Some(state)) => {
+                        restore::<AccountEntity, _>(
+                            txn,
+                            &ctx,
+                            entity_type,
+                            parse_uuid(&entity_id)?,
+                            AccountColumn::Id,
+                            state,
+                        )
+                        .await
+                    }
+                    (AuditEntityType::Account, None) => {
+                        AccountReq::delete_in(txn, &ctx, parse_uuid(&entity_id)?).await
+                    }
+                    (AuditEntityType::Category, Some(state)) => {
+                        restore::<CategoryEntity, _>(
+                            txn,
+                            &ctx,
+                            entity_type,
+                            parse_uuid(&entity_id)?,
+                            CategoryColumn::Id,
+                            state,
+                        )
+                        .await
+                    }
+                    (AuditEntityType::Category, None) => {
+                        CategoryReq::delete_in(txn, &ctx, parse_uuid(&entity_id)?).await
+                    }
+                    (AuditEntityType::Currency, Some(state)) => {
+                        restore::<CurrencyEntity, _>(
+                            txn,
+                            &ctx,
+                            entity_type,
+                            entity_id,
+                            CurrencyColumn::Code,
+                            state,
+                        )
+                        .await
+                    }
+                    (AuditEntityType::Currency, None) => {
+                        CurrencyReq::delete_in(txn, &ctx, &entity_id).await
+                    }
+                    (AuditEntityType::Subscription, Some(state)) => {
+                        restore::<SubscriptionEntity, _>(
+                            txn,
+                            &ctx,
+                            entity_type,
+                            parse_uuid(&entity_id)?,
+                            SubscriptionColumn::Id,
+                            state,
+                        )
+                        .await
+                    }
+                    (AuditEntityType::Subscription, None) => {
+                        SubscriptionReq::delete_in(txn, &ctx, parse_uuid(&entity_id)?).await
+                    }
+                    (AuditEntityType::Transaction, Some(state)) => {
+                        restore::<TransactionEntity, _>(
+                            txn,
+                            &ctx,
+                            entity_type,
+                            parse_uuid(&entity_id)?,
+                            TransactionColumn::Id,
+                            state,
+                        )
+                        .await
+                    }
+                    (AuditEntityType::Transaction, None) => {
+                        TransactionReq::delete_in(txn, &ctx, parse_uuid(&entity_id)?).await
+                    }
+                    (AuditEntityType::TransactionItem, Some(state)) => {
+                        restore::<TransactionItemEntity, _>(
+                            txn,
+                            &ctx,
+                            entity_type,
+                            parse_uuid(&entity_id)?,
+                            TransactionItemColumn::Id,
+                            state,
+                        )
+                        .await
+                    }
+                    (AuditEntityType::TransactionItem, None) => {
+                        TransactionReq::delete_item_in(txn, &ctx, parse_uuid(&entity_id)?).await
+                    }
+                }
+            })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
+    }
+}
+
+fn parse_uuid(value: &str) -> Result<Uuid, DbErr> {
+    Uuid::parse_str(value).map_err(|e| DbErr::Type(e.to_string()))
+}
+
+async fn restore<E, C>(
+    db: &C,
+    ctx: &AuditContext,
+    entity_type: AuditEntityType,
+    id: <E::PrimaryKey as PrimaryKeyTrait>::ValueType,
+    primary_key: E::Column,
+    state: serde_json::Value,
+) -> Result<(), DbErr>
+where
+    E: EntityTrait,
+    E::Model: Serialize + DeserializeOwned + IntoActiveModel<E::ActiveModel>,
+    E::ActiveModel: ActiveModelTrait<Entity = E> + Send,
+    <E::PrimaryKey as PrimaryKeyTrait>::ValueType: Clone + ToString,
+    C: ConnectionTrait,
+{
+    let before = AuditEventModel::snapshot::<E, _>(db, id.clone()).await?;
+    let model = E::ActiveModel::from_json(state)?;
+    E::insert(model)
+        .on_conflict(
+            OnConflict::column(primary_key)
+                .update_columns(E::Column::iter())
+                .to_owned(),
+        )
+        .exec(db)
+        .await?;
+    let after = AuditEventModel::snapshot::<E, _>(db, id.clone()).await?;
+    AuditEventModel::record(db, ctx, entity_type, id.to_string(), before, after).await
+}
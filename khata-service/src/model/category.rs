@@ -1,10 +1,17 @@
 use migration::OnConflict;
-use sea_orm::{ActiveValue, DbConn, DbErr, EntityTrait, QueryOrder};
+use sea_orm::{
+    ActiveValue, ColumnTrait, ConnectionTrait, DbConn, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use uuid::Uuid;
 use validator::Validate;
 
+use super::{
+    audit::{AuditContext, AuditEntityType, AuditEventModel},
+    transaction::{TransactionItemColumn, TransactionItemEntity},
+};
 use crate::entity::category;
 
 #[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
@@ -33,29 +40,88 @@ impl CategoryReq {
             .map(|v| v.into_iter().map(CategoryModel).collect())
     }
 
-    pub async fn upsert(db: &DbConn, category: Self) -> Result<Uuid, DbErr> {
-        CategoryEntity::insert(CategoryActiveModel {
-            id: ActiveValue::Set(category.id.unwrap_or_else(Uuid::now_v7)),
-            name: ActiveValue::Set(category.name),
-            group: ActiveValue::Set(category.group),
-            icon: ActiveValue::Set(category.icon),
+    pub async fn upsert(db: &DbConn, ctx: &AuditContext, category: Self) -> Result<Uuid, DbErr> {
+        let ctx = ctx.clone();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move {
+                let id = category.id.unwrap_or_else(Uuid::now_v7);
+                let before = AuditEventModel::snapshot::<CategoryEntity, _>(txn, id).await?;
+                CategoryEntity::insert(CategoryActiveModel {
+                    id: ActiveValue::Set(id),
+                    name: ActiveValue::Set(category.name),
+                    group: ActiveValue::Set(category.group),
+                    icon: ActiveValue::Set(category.icon),
+                })
+                .on_conflict(
+                    OnConflict::column(CategoryColumn::Id)
+                        .update_columns([
+                            CategoryColumn::Name,
+                            CategoryColumn::Group,
+                            CategoryColumn::Icon,
+                        ])
+                        .to_owned(),
+                )
+                .exec(txn)
+                .await?;
+                let after = AuditEventModel::snapshot::<CategoryEntity, _>(txn, id).await?;
+                AuditEventModel::record(txn, &ctx, AuditEntityType::Category, id, before, after)
+                    .await?;
+                Ok(id)
+            })
         })
-        .on_conflict(
-            OnConflict::column(CategoryColumn::Id)
-                .update_columns([
-                    CategoryColumn::Name,
-                    CategoryColumn::Group,
-                    CategoryColumn::Icon,
-                ])
-                .to_owned(),
-        )
-        .exec(db)
         .await
-        .map(|c| c.last_insert_id)
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
     }
 
-    pub async fn delete(db: &DbConn, id: Uuid) -> Result<(), DbErr> {
+    pub async fn delete(db: &DbConn, ctx: &AuditContext, id: Uuid) -> Result<(), DbErr> {
+        let ctx = ctx.clone();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move { Self::delete_in(txn, &ctx, id).await })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
+    }
+
+    /// Deletes the category. Items that used it lose their category through
+    /// the foreign key, which is recorded as an update of each item.
+    pub(crate) async fn delete_in<C: ConnectionTrait>(
+        db: &C,
+        ctx: &AuditContext,
+        id: Uuid,
+    ) -> Result<(), DbErr> {
+        let item_ids = TransactionItemEntity::find()
+            .filter(TransactionItemColumn::CategoryId.eq(id))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|item| item.id)
+            .collect::<Vec<_>>();
+        let mut item_befores = Vec::with_capacity(item_ids.len());
+        for item_id in &item_ids {
+            item_befores
+                .push(AuditEventModel::snapshot::<TransactionItemEntity, _>(db, *item_id).await?);
+        }
+        let before = AuditEventModel::snapshot::<CategoryEntity, _>(db, id).await?;
         CategoryEntity::delete_by_id(id).exec(db).await?;
-        Ok(())
+        for (item_id, item_before) in item_ids.into_iter().zip(item_befores) {
+            let item_after =
+                AuditEventModel::snapshot::<TransactionItemEntity, _>(db, item_id).await?;
+            AuditEventModel::record(
+                db,
+                ctx,
+                AuditEntityType::TransactionItem,
+                item_id,
+                item_before,
+                item_after,
+            )
+            .await?;
+        }
+        AuditEventModel::record(db, ctx, AuditEntityType::Category, id, before, None).await
     }
 }
@@ -1,9 +1,12 @@
 use migration::OnConflict;
-use sea_orm::{ActiveValue, DbConn, DbErr, EntityTrait, QueryOrder};
+use sea_orm::{
+    ActiveValue, ConnectionTrait, DbConn, DbErr, EntityTrait, QueryOrder, TransactionTrait,
+};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use validator::Validate;
 
+use super::audit::{AuditContext, AuditEntityType, AuditEventModel};
 use crate::entity::currency;
 
 #[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
@@ -38,40 +41,107 @@ impl CurrencyReq {
             .map(|c| c.map(CurrencyModel))
     }
 
-    pub async fn upsert(db: &DbConn, currency: Self) -> Result<String, DbErr> {
-        CurrencyEntity::insert(CurrencyActiveModel {
-            code: ActiveValue::Set(currency.code),
-            name: ActiveValue::Set(currency.name),
-            decimal_digits: ActiveValue::Set(currency.decimal_digits),
+    fn into_active_model(self) -> CurrencyActiveModel {
+        CurrencyActiveModel {
+            code: ActiveValue::Set(self.code),
+            name: ActiveValue::Set(self.name),
+            decimal_digits: ActiveValue::Set(self.decimal_digits),
+        }
+    }
+
+    fn on_conflict() -> OnConflict {
+        OnConflict::column(CurrencyColumn::Code)
+            .update_columns([CurrencyColumn::Name, CurrencyColumn::DecimalDigits])
+            .to_owned()
+    }
+
+    pub async fn upsert(db: &DbConn, ctx: &AuditContext, currency: Self) -> Result<String, DbErr> {
+        let ctx = ctx.clone();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move {
+                let code = currency.code.clone();
+                let before =
+                    AuditEventModel::snapshot::<CurrencyEntity, _>(txn, code.clone()).await?;
+                CurrencyEntity::insert(currency.into_active_model())
+                    .on_conflict(Self::on_conflict())
+                    .exec(txn)
+                    .await?;
+                let after =
+                    AuditEventModel::snapshot::<CurrencyEntity, _>(txn, code.clone()).await?;
+                AuditEventModel::record(txn, &ctx, AuditEntityType::Currency, &code, before, after)
+                    .await?;
+                Ok(code)
+            })
         })
-        .on_conflict(
-            OnConflict::column(CurrencyColumn::Code)
-                .update_columns([CurrencyColumn::Name, CurrencyColumn::DecimalDigits])
-                .to_owned(),
-        )
-        .exec(db)
         .await
-        .map(|c| c.last_insert_id)
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
     }
 
-    pub async fn delete(db: &DbConn, code: &str) -> Result<(), DbErr> {
+    pub async fn delete(db: &DbConn, ctx: &AuditContext, code: &str) -> Result<(), DbErr> {
+        let ctx = ctx.clone();
+        let code = code.to_string();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move { Self::delete_in(txn, &ctx, &code).await })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
+    }
+
+    pub(crate) async fn delete_in<C: ConnectionTrait>(
+        db: &C,
+        ctx: &AuditContext,
+        code: &str,
+    ) -> Result<(), DbErr> {
+        let before = AuditEventModel::snapshot::<CurrencyEntity, _>(db, code).await?;
         CurrencyEntity::delete_by_id(code).exec(db).await?;
-        Ok(())
+        AuditEventModel::record(db, ctx, AuditEntityType::Currency, code, before, None).await
     }
 
-    pub async fn upsert_many(db: &DbConn, list: Vec<Self>) -> Result<(), DbErr> {
-        CurrencyEntity::insert_many(list.into_iter().map(|item| CurrencyActiveModel {
-            code: ActiveValue::Set(item.code),
-            name: ActiveValue::Set(item.name),
-            decimal_digits: ActiveValue::Set(item.decimal_digits),
-        }))
-        .on_conflict(
-            OnConflict::column(CurrencyColumn::Code)
-                .update_columns([CurrencyColumn::Name, CurrencyColumn::DecimalDigits])
-                .to_owned(),
-        )
-        .exec(db)
-        .await?;
-        Ok(())
+    pub async fn upsert_many(
+        db: &DbConn,
+        ctx: &AuditContext,
+        list: Vec<Self>,
+    ) -> Result<(), DbErr> {
+        let ctx = ctx.clone();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move {
+                let codes = list.iter().map(|c| c.code.clone()).collect::<Vec<_>>();
+                let mut befores = Vec::with_capacity(codes.len());
+                for code in &codes {
+                    befores.push(
+                        AuditEventModel::snapshot::<CurrencyEntity, _>(txn, code.clone()).await?,
+                    );
+                }
+                CurrencyEntity::insert_many(list.into_iter().map(Self::into_active_model))
+                    .on_conflict(Self::on_conflict())
+                    .exec(txn)
+                    .await?;
+                for (code, before) in codes.into_iter().zip(befores) {
+                    let after =
+                        AuditEventModel::snapshot::<CurrencyEntity, _>(txn, code.clone()).await?;
+                    AuditEventModel::record(
+                        txn,
+                        &ctx,
+                        AuditEntityType::Currency,
+                        code,
+                        before,
+                        after,
+                    )
+                    .await?;
+                }
+                Ok(())
+            })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
     }
 }
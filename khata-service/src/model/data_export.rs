@@ -0,0 +1,227 @@
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveValue, ColumnTrait, ConnectionTrait, DbConn, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    sea_query::{Expr, Query},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::user_entity::{data_export, prelude::DataExport as DataExportEntity};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataExportStatus {
+    Pending,
+    Ready,
+    Failed,
+}
+
+impl DataExportStatus {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Ready => "ready",
+            Self::Failed => "failed",
+        }
+    }
+
+    fn from_str(value: &str) -> Result<Self, DbErr> {
+        serde_json::from_value(serde_json::Value::String(value.to_string()))
+            .map_err(|e| DbErr::Type(e.to_string()))
+    }
+}
+
+/// A zip of everything stored about a user, built in the background.
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct DataExport {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub status: DataExportStatus,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+impl DataExport {
+    fn from_model(model: data_export::Model) -> Result<Self, DbErr> {
+        Ok(Self {
+            id: model.id,
+            user_id: model.user_id,
+            status: DataExportStatus::from_str(&model.status)?,
+            error: model.error,
+            created_at: model.created_at,
+            completed_at: model.completed_at,
+        })
+    }
+
+    /// Creates a pending export unless the user already has one. The check is
+    /// part of the insert, so two requests cannot both start an export.
+    pub async fn create_unless_pending(db: &DbConn, user_id: Uuid) -> Result<Option<Self>, DbErr> {
+        let export = Self {
+            id: Uuid::now_v7(),
+            user_id,
+            status: DataExportStatus::Pending,
+            error: None,
+            created_at: Utc::now(),
+            completed_at: None,
+        };
+        let pending = Query::select()
+            .expr(Expr::val(1))
+            .from(DataExportEntity)
+            .and_where(data_export::Column::UserId.eq(user_id))
+            .and_where(data_export::Column::Status.eq(DataExportStatus::Pending.as_str()))
+            .to_owned();
+        let insert = Query::insert()
+            .into_table(DataExportEntity)
+            .columns([
+                data_export::Column::Id,
+                data_export::Column::UserId,
+                data_export::Column::Status,
+                data_export::Column::CreatedAt,
+            ])
+            .select_from(
+                Query::select()
+                    .exprs([
+                        Expr::val(export.id),
+                        Expr::val(export.user_id),
+                        Expr::val(export.status.as_str()),
+                        Expr::val(export.created_at),
+                    ])
+                    .and_where(Expr::exists(pending).not())
+                    .to_owned(),
+            )
+            .map_err(|e| DbErr::Custom(e.to_string()))?
+            .to_owned();
+        let result = db.execute(db.get_database_backend().build(&insert)).await?;
+        Ok((result.rows_affected() == 1).then_some(export))
+    }
+
+    pub async fn find(db: &DbConn, user_id: Uuid, id: Uuid) -> Result<Option<Self>, DbErr> {
+        DataExportEntity::find_by_id(id)
+            .filter(data_export::Column::UserId.eq(user_id))
+            .one(db)
+            .await?
+            .map(Self::from_model)
+            .transpose()
+    }
+
+    /// The exports of a user, latest first.
+    pub async fn find_for_user(db: &DbConn, user_id: Uuid) -> Result<Vec<Self>, DbErr> {
+        DataExportEntity::find()
+            .filter(data_export::Column::UserId.eq(user_id))
+            .order_by_desc(data_export::Column::CreatedAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(Self::from_model)
+            .collect()
+    }
+
+    /// Records the outcome of building the export, an error if it failed.
+    pub async fn complete(db: &DbConn, id: Uuid, error: Option<String>) -> Result<(), DbErr> {
+        let status = if error.is_some() {
+            DataExportStatus::Failed
+        } else {
+            DataExportStatus::Ready
+        };
+        DataExportEntity::update(data_export::ActiveModel {
+            id: ActiveValue::Set(id),
+            user_id: ActiveValue::NotSet,
+            status: ActiveValue::Set(status.as_str().to_string()),
+            error: ActiveValue::Set(error),
+            created_at: ActiveValue::NotSet,
+            completed_at: ActiveValue::Set(Some(Utc::now())),
+        })
+        .exec(db)
+        .await?;
+        Ok(())
+    }
+
+    /// Fails the exports still pending that were created before `before`.
+    /// Exports are built by a task that does not survive a restart, so these
+    /// would otherwise stay pending forever and block new exports.
+    pub async fn fail_pending_before(
+        db: &DbConn,
+        before: DateTime<Utc>,
+        error: &str,
+    ) -> Result<u64, DbErr> {
+        let result = DataExportEntity::update_many()
+            .col_expr(
+                data_export::Column::Status,
+                Expr::value(DataExportStatus::Failed.as_str()),
+            )
+            .col_expr(data_export::Column::Error, Expr::value(error))
+            .col_expr(data_export::Column::CompletedAt, Expr::value(Utc::now()))
+            .filter(data_export::Column::Status.eq(DataExportStatus::Pending.as_str()))
+            .filter(data_export::Column::CreatedAt.lt(before))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected)
+    }
+
+    /// Deletes the exports created before `before` and returns their ids, so
+    /// their files can be removed too.
+    pub async fn delete_older_than(db: &DbConn, before: DateTime<Utc>) -> Result<Vec<Uuid>, DbErr> {
+        let ids: Vec<Uuid> = DataExportEntity::find()
+            .filter(data_export::Column::CreatedAt.lt(before))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|m| m.id)
+            .collect();
+        DataExportEntity::delete_many()
+            .filter(data_export::Column::Id.is_in(ids.clone()))
+            .exec(db)
+            .await?;
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use user_migration::MigratorTrait;
+
+    use super::*;
+    use crate::model::user::User;
+
+    #[tokio::test]
+    async fn only_one_pending_export_until_it_is_failed() {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        user_migration::Migrator::up(&db, None).await.unwrap();
+        let user = User::create_user(&db, "Ada", "ada@example.com", "hash")
+            .await
+            .unwrap();
+
+        let export = DataExport::create_unless_pending(&db, user.id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(
+            DataExport::create_unless_pending(&db, user.id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+
+        let failed = DataExport::fail_pending_before(&db, export.created_at, "timed out")
+            .await
+            .unwrap();
+        assert_eq!(failed, 0);
+        let later = Utc::now() + chrono::Duration::seconds(1);
+        let failed = DataExport::fail_pending_before(&db, later, "timed out")
+            .await
+            .unwrap();
+        assert_eq!(failed, 1);
+        let exports = DataExport::find_for_user(&db, user.id).await.unwrap();
+        assert_eq!(exports[0].status, DataExportStatus::Failed);
+        assert_eq!(exports[0].error.as_deref(), Some("timed out"));
+        assert!(
+            DataExport::create_unless_pending(&db, user.id)
+                .await
+                .unwrap()
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,933 @@
+use std::collections::{BTreeMap, HashMap};
+
+use migration::{AccountType, OnConflict};
+use sea_orm::{
+    ActiveValue, ColumnTrait, ConnectionTrait, DbConn, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use super::{
+    ModelError,
+    account::AccountEntity,
+    audit::AuditContext,
+    transaction::{
+        TransactionExpandedModel, TransactionItemModel, TransactionModel, TransactionReq,
+    },
+};
+use crate::entity::{
+    expense_group, group_expense, group_expense_share, group_member, group_settlement, transaction,
+    transaction_item,
+};
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct ExpenseGroupModel(#[schema(inline)] pub expense_group::Model);
+pub type ExpenseGroupEntity = expense_group::Entity;
+pub type ExpenseGroupActiveModel = expense_group::ActiveModel;
+pub type ExpenseGroupColumn = expense_group::Column;
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct GroupMemberModel(#[schema(inline)] pub group_member::Model);
+pub type GroupMemberEntity = group_member::Entity;
+pub type GroupMemberActiveModel = group_member::ActiveModel;
+pub type GroupMemberColumn = group_member::Column;
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct GroupExpenseModel(#[schema(inline)] pub group_expense::Model);
+pub type GroupExpenseEntity = group_expense::Entity;
+pub type GroupExpenseActiveModel = group_expense::ActiveModel;
+pub type GroupExpenseColumn = group_expense::Column;
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct GroupExpenseShareModel(#[schema(inline)] pub group_expense_share::Model);
+pub type GroupExpenseShareEntity = group_expense_share::Entity;
+pub type GroupExpenseShareActiveModel = group_expense_share::ActiveModel;
+pub type GroupExpenseShareColumn = group_expense_share::Column;
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct GroupSettlementModel(#[schema(inline)] pub group_settlement::Model);
+pub type GroupSettlementEntity = group_settlement::Entity;
+pub type GroupSettlementActiveModel = group_settlement::ActiveModel;
+pub type GroupSettlementColumn = group_settlement::Column;
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize, Validate)]
+pub struct ExpenseGroupReq {
+    pub id: Option<Uuid>,
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+}
+
+/// A member is either one of the ledger's `Person` accounts or a user of
+/// this instance.
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_member"))]
+pub struct GroupMemberReq {
+    pub id: Option<Uuid>,
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    pub account_id: Option<Uuid>,
+    pub user_id: Option<Uuid>,
+}
+
+fn validate_member(member: &GroupMemberReq) -> Result<(), ValidationError> {
+    if member.account_id.is_some() == member.user_id.is_some() {
+        return Err(ValidationError::new("member")
+            .with_message("Exactly one of account_id and user_id must be set".into()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct MemberWeight {
+    pub member_id: Uuid,
+    pub weight: u32,
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct MemberAmount {
+    pub member_id: Uuid,
+    pub amount: i64,
+}
+
+/// How the amount of an expense is divided between members.
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum ExpenseSplit {
+    Equal { member_ids: Vec<Uuid> },
+    Shares { shares: Vec<MemberWeight> },
+    Exact { amounts: Vec<MemberAmount> },
+}
+
+impl ExpenseSplit {
+    /// Divides `total` into the amount owed by each member. Amounts that do
+    /// not divide evenly are rounded down and the leftover units go to the
+    /// first members, so the result always adds up to `total`.
+    pub fn owed(&self, total: i64) -> Result<Vec<(Uuid, i64)>, &'static str> {
+        let weights = match self {
+            Self::Equal { member_ids } => member_ids.iter().map(|id| (*id, 1)).collect(),
+            Self::Shares { shares } => shares
+                .iter()
+                .map(|s| (s.member_id, i64::from(s.weight)))
+                .collect::<Vec<_>>(),
+            Self::Exact { amounts } => {
+                if amounts.iter().any(|a| a.amount < 0) {
+                    return Err("Exact amounts cannot be negative");
+                }
+                if amounts.iter().map(|a| a.amount).sum::<i64>() != total {
+                    return Err("Exact amounts must add up to the expense amount");
+                }
+                return Ok(amounts.iter().map(|a| (a.member_id, a.amount)).collect());
+            }
+        };
+        let weight_sum = weights.iter().map(|(_, w)| w).sum::<i64>();
+        if weight_sum == 0 {
+            return Err("At least one member must share the expense");
+        }
+        // The product can overflow i64 for large amounts and weights, the
+        // quotient never exceeds `total`.
+        let mut owed = weights
+            .iter()
+            .map(|(id, w)| {
+                let share = i128::from(total) * i128::from(*w) / i128::from(weight_sum);
+                (*id, i64::try_from(share).unwrap_or(total))
+            })
+            .collect::<Vec<_>>();
+        let mut leftover = total - owed.iter().map(|(_, a)| a).sum::<i64>();
+        for (i, (_, amount)) in owed.iter_mut().enumerate() {
+            if leftover == 0 {
+                break;
+            }
+            if weights[i].1 > 0 {
+                *amount += 1;
+                leftover -= 1;
+            }
+        }
+        Ok(owed)
+    }
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_expense"))]
+pub struct GroupExpenseReq {
+    pub id: Option<Uuid>,
+    #[validate(length(min = 1, max = 100))]
+    pub title: String,
+    pub paid_by: Uuid,
+    #[validate(range(min = 1))]
+    pub amount: i64,
+    #[validate(length(min = 3, max = 3))]
+    pub currency_code: String,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    pub split: ExpenseSplit,
+}
+
+fn validate_expense(expense: &GroupExpenseReq) -> Result<(), ValidationError> {
+    expense
+        .split
+        .owed(expense.amount)
+        .map(|_| ())
+        .map_err(|message| ValidationError::new("split").with_message(message.into()))
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct GroupExpenseExpandedModel {
+    pub expense: GroupExpenseModel,
+    pub shares: Vec<GroupExpenseShareModel>,
+}
+
+/// Net position of a member in one currency. A positive balance means the
+/// member is owed money, a negative one means they owe money.
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct MemberBalance {
+    pub member_id: Uuid,
+    pub currency_code: String,
+    pub balance: i64,
+}
+
+/// A payment of `amount` from one member to another.
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct SettleUp {
+    pub from_member_id: Uuid,
+    pub to_member_id: Uuid,
+    pub currency_code: String,
+    pub amount: i64,
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_settlement"))]
+pub struct GroupSettlementReq {
+    pub from_member_id: Uuid,
+    pub to_member_id: Uuid,
+    #[validate(length(min = 3, max = 3))]
+    pub currency_code: String,
+    #[validate(range(min = 1))]
+    pub amount: i64,
+    pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Account that the current user pays from or receives into, when the
+    /// current user is one side of the settlement.
+    pub account_id: Option<Uuid>,
+}
+
+fn validate_settlement(settlement: &GroupSettlementReq) -> Result<(), ValidationError> {
+    if settlement.from_member_id == settlement.to_member_id {
+        return Err(ValidationError::new("settlement")
+            .with_message("A member cannot settle with themselves".into()));
+    }
+    Ok(())
+}
+
+impl ExpenseGroupReq {
+    pub async fn find_all(db: &DbConn) -> Result<Vec<ExpenseGroupModel>, DbErr> {
+        ExpenseGroupEntity::find()
+            .order_by_asc(ExpenseGroupColumn::Name)
+            .all(db)
+            .await
+            .map(|v| v.into_iter().map(ExpenseGroupModel).collect())
+    }
+
+    pub async fn upsert(db: &DbConn, group: Self) -> Result<Uuid, DbErr> {
+        ExpenseGroupEntity::insert(ExpenseGroupActiveModel {
+            id: ActiveValue::Set(group.id.unwrap_or_else(Uuid::now_v7)),
+            name: ActiveValue::Set(group.name),
+            created_at: ActiveValue::Set(chrono::Utc::now()),
+        })
+        .on_conflict(
+            OnConflict::column(ExpenseGroupColumn::Id)
+                .update_column(ExpenseGroupColumn::Name)
+                .to_owned(),
+        )
+        .exec(db)
+        .await
+        .map(|g| g.last_insert_id)
+    }
+
+    pub async fn delete(db: &DbConn, id: Uuid) -> Result<(), DbErr> {
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move {
+                GroupExpenseEntity::delete_many()
+                    .filter(GroupExpenseColumn::GroupId.eq(id))
+                    .exec(txn)
+                    .await?;
+                GroupSettlementEntity::delete_many()
+                    .filter(GroupSettlementColumn::GroupId.eq(id))
+                    .exec(txn)
+                    .await?;
+                ExpenseGroupEntity::delete_by_id(id).exec(txn).await?;
+                Ok(())
+            })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
+    }
+
+    pub async fn members(db: &DbConn, group_id: Uuid) -> Result<Vec<GroupMemberModel>, DbErr> {
+        GroupMemberEntity::find()
+            .filter(GroupMemberColumn::GroupId.eq(group_id))
+            .order_by_asc(GroupMemberColumn::Name)
+            .all(db)
+            .await
+            .map(|v| v.into_iter().map(GroupMemberModel).collect())
+    }
+
+    pub async fn upsert_member(
+        db: &DbConn,
+        group_id: Uuid,
+        member: GroupMemberReq,
+    ) -> Result<Uuid, DbErr> {
+        if let Some(account_id) = member.account_id {
+            let account = AccountEntity::find_by_id(account_id)
+                .one(db)
+                .await?
+                .ok_or_else(|| DbErr::RecordNotFound(format!("account: {account_id}")))?;
+            let account_type: AccountType = serde_json::from_str(&account.account_type)
+                .map_err(|e| DbErr::Json(e.to_string()))?;
+            if !matches!(account_type, AccountType::Person) {
+                return Err(DbErr::Custom(format!(
+                    "account: {account_id} is not a Person account"
+                )));
+            }
+        }
+        if let Some(id) = member.id {
+            let existing = GroupMemberEntity::find_by_id(id).one(db).await?;
+            if existing.is_some_and(|m| m.group_id != group_id) {
+                return Err(DbErr::RecordNotFound(format!("group_member: {id}")));
+            }
+        }
+        GroupMemberEntity::insert(GroupMemberActiveModel {
+            id: ActiveValue::Set(member.id.unwrap_or_else(Uuid::now_v7)),
+            group_id: ActiveValue::Set(group_id),
+            name: ActiveValue::Set(member.name),
+            account_id: ActiveValue::Set(member.account_id),
+            user_id: ActiveValue::Set(member.user_id),
+        })
+        .on_conflict(
+            OnConflict::column(GroupMemberColumn::Id)
+                .update_columns([
+                    GroupMemberColumn::Name,
+                    GroupMemberColumn::AccountId,
+                    GroupMemberColumn::UserId,
+                ])
+                .to_owned(),
+        )
+        .exec(db)
+        .await
+        .map(|m| m.last_insert_id)
+    }
+
+    /// Whether the member paid for or shares in an expense, or took part in
+    /// a settlement.
+    async fn member_in_use<C: ConnectionTrait>(db: &C, id: Uuid) -> Result<bool, DbErr> {
+        let paid = GroupExpenseEntity::find()
+            .filter(GroupExpenseColumn::PaidBy.eq(id))
+            .one(db)
+            .await?;
+        let shared = GroupExpenseShareEntity::find()
+            .filter(GroupExpenseShareColumn::MemberId.eq(id))
+            .one(db)
+            .await?;
+        let settled = GroupSettlementEntity::find()
+            .filter(
+                GroupSettlementColumn::FromMemberId
+                    .eq(id)
+                    .or(GroupSettlementColumn::ToMemberId.eq(id)),
+            )
+            .one(db)
+            .await?;
+        Ok(paid.is_some() || shared.is_some() || settled.is_some())
+    }
+
+    /// Members in use, see [`Self::member_in_use`], cannot be removed.
+    pub async fn delete_member(db: &DbConn, group_id: Uuid, id: Uuid) -> Result<(), ModelError> {
+        db.transaction::<_, _, ModelError>(|txn| {
+            Box::pin(async move {
+                if Self::member_in_use(txn, id).await? {
+                    return Err(ModelError::Conflict(
+                        "The member has expenses or settlements and cannot be removed".to_string(),
+                    ));
+                }
+                GroupMemberEntity::delete_many()
+                    .filter(GroupMemberColumn::GroupId.eq(group_id))
+                    .filter(GroupMemberColumn::Id.eq(id))
+                    .exec(txn)
+                    .await?;
+                Ok(())
+            })
+        })
+        .await
+        .map_err(ModelError::from)
+    }
+
+    pub async fn expenses(
+        db: &DbConn,
+        group_id: Uuid,
+    ) -> Result<Vec<GroupExpenseExpandedModel>, DbErr> {
+        GroupExpenseEntity::find()
+            .filter(GroupExpenseColumn::GroupId.eq(group_id))
+            .order_by_desc(GroupExpenseColumn::Timestamp)
+            .find_with_related(GroupExpenseShareEntity::default())
+            .all(db)
+            .await
+            .map(|expenses| {
+                expenses
+                    .into_iter()
+                    .map(|(expense, shares)| GroupExpenseExpandedModel {
+                        expense: GroupExpenseModel(expense),
+                        shares: shares.into_iter().map(GroupExpenseShareModel).collect(),
+                    })
+                    .collect()
+            })
+    }
+
+    pub async fn upsert_expense(
+        db: &DbConn,
+        group_id: Uuid,
+        expense: GroupExpenseReq,
+    ) -> Result<Uuid, DbErr> {
+        let owed = expense
+            .split
+            .owed(expense.amount)
+            .map_err(|e| DbErr::Custom(e.to_string()))?;
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move {
+                let members = GroupMemberEntity::find()
+                    .filter(GroupMemberColumn::GroupId.eq(group_id))
+                    .all(txn)
+                    .await?
+                    .into_iter()
+                    .map(|m| m.id)
+                    .collect::<Vec<_>>();
+                for member_id in owed.iter().map(|(id, _)| id).chain([&expense.paid_by]) {
+                    if !members.contains(member_id) {
+                        return Err(DbErr::RecordNotFound(format!("group_member: {member_id}")));
+                    }
+                }
+                if let Some(id) = expense.id {
+                    let existing = GroupExpenseEntity::find_by_id(id).one(txn).await?;
+                    if existing.is_some_and(|e| e.group_id != group_id) {
+                        return Err(DbErr::RecordNotFound(format!("group_expense: {id}")));
+                    }
+                }
+                let id = expense.id.unwrap_or_else(Uuid::now_v7);
+                GroupExpenseEntity::insert(GroupExpenseActiveModel {
+                    id: ActiveValue::Set(id),
+                    group_id: ActiveValue::Set(group_id),
+                    title: ActiveValue::Set(expense.title.trim().to_owned()),
+                    paid_by: ActiveValue::Set(expense.paid_by),
+                    amount: ActiveValue::Set(expense.amount),
+                    currency_code: ActiveValue::Set(expense.currency_code),
+                    split: ActiveValue::Set(
+                        serde_json::to_value(&expense.split)
+                            .map_err(|e| DbErr::Json(e.to_string()))?,
+                    ),
+                    timestamp: ActiveValue::Set(expense.timestamp),
+                })
+                .on_conflict(
+                    OnConflict::column(GroupExpenseColumn::Id)
+                        .update_columns([
+                            GroupExpenseColumn::Title,
+                            GroupExpenseColumn::PaidBy,
+                            GroupExpenseColumn::Amount,
+                            GroupExpenseColumn::CurrencyCode,
+                            GroupExpenseColumn::Split,
+                            GroupExpenseColumn::Timestamp,
+                        ])
+                        .to_owned(),
+                )
+                .exec(txn)
+                .await?;
+                GroupExpenseShareEntity::delete_many()
+                    .filter(GroupExpenseShareColumn::ExpenseId.eq(id))
+                    .exec(txn)
+                    .await?;
+                for (member_id, amount) in owed {
+                    GroupExpenseShareEntity::insert(GroupExpenseShareActiveModel {
+                        id: ActiveValue::Set(Uuid::now_v7()),
+                        expense_id: ActiveValue::Set(id),
+                        member_id: ActiveValue::Set(member_id),
+                        amount: ActiveValue::Set(amount),
+                    })
+                    .exec(txn)
+                    .await?;
+                }
+                Ok(id)
+            })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
+    }
+
+    pub async fn delete_expense(db: &DbConn, group_id: Uuid, id: Uuid) -> Result<(), DbErr> {
+        GroupExpenseEntity::delete_many()
+            .filter(GroupExpenseColumn::GroupId.eq(group_id))
+            .filter(GroupExpenseColumn::Id.eq(id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn settlements(
+        db: &DbConn,
+        group_id: Uuid,
+    ) -> Result<Vec<GroupSettlementModel>, DbErr> {
+        GroupSettlementEntity::find()
+            .filter(GroupSettlementColumn::GroupId.eq(group_id))
+            .order_by_desc(GroupSettlementColumn::Timestamp)
+            .all(db)
+            .await
+            .map(|v| v.into_iter().map(GroupSettlementModel).collect())
+    }
+
+    pub async fn balances(db: &DbConn, group_id: Uuid) -> Result<Vec<MemberBalance>, DbErr> {
+        let expenses = Self::expenses(db, group_id).await?;
+        let settlements = Self::settlements(db, group_id).await?;
+        Ok(net_balances(&expenses, &settlements))
+    }
+
+    pub async fn settle_up(db: &DbConn, group_id: Uuid) -> Result<Vec<SettleUp>, DbErr> {
+        Ok(settle_up(&Self::balances(db, group_id).await?))
+    }
+
+    /// Records a payment between two members. When both sides map to
+    /// accounts of this ledger, a transfer transaction is booked as well:
+    /// `Person` members use their account and the current user uses
+    /// `account_id`.
+    pub async fn settle(
+        db: &DbConn,
+        ctx: &AuditContext,
+        group_id: Uuid,
+        settlement: GroupSettlementReq,
+    ) -> Result<Uuid, DbErr> {
+        let ctx = ctx.clone();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move {
+                let members = GroupMemberEntity::find()
+                    .filter(GroupMemberColumn::GroupId.eq(group_id))
+                    .all(txn)
+                    .await?
+                    .into_iter()
+                    .map(|m| (m.id, m))
+                    .collect::<HashMap<_, _>>();
+                let member_account = |member_id: Uuid| -> Result<Option<Uuid>, DbErr> {
+                    let member = members.get(&member_id).ok_or_else(|| {
+                        DbErr::RecordNotFound(format!("group_member: {member_id}"))
+                    })?;
+                    Ok(member.account_id.or_else(|| {
+                        (member.user_id == Some(ctx.user_id))
+                            .then_some(settlement.account_id)
+                            .flatten()
+                    }))
+                };
+                let from_account = member_account(settlement.from_member_id)?;
+                let to_account = member_account(settlement.to_member_id)?;
+
+                let id = Uuid::now_v7();
+                let transaction_id =
+                    if let (Some(from_account), Some(to_account)) = (from_account, to_account) {
+                        for account_id in [from_account, to_account] {
+                            let account = AccountEntity::find_by_id(account_id)
+                                .one(txn)
+                                .await?
+                                .ok_or_else(|| {
+                                    DbErr::RecordNotFound(format!("account: {account_id}"))
+                                })?;
+                            if account.currency_code != settlement.currency_code {
+                                return Err(DbErr::Custom(format!(
+                                    "account: {account_id} is not in {}",
+                                    settlement.currency_code
+                                )));
+                            }
+                        }
+                        let transaction_id = Uuid::now_v7();
+                        let item = |account_id: Uuid, amount: i64| {
+                            TransactionItemModel(transaction_item::Model {
+                                id: Uuid::now_v7(),
+                                notes: String::new(),
+                                transaction_id,
+                                account_id,
+                                category_id: None,
+                                amount,
+                            })
+                        };
+                        let title = format!(
+                            "Settle up: {} to {}",
+                            members[&settlement.from_member_id].name,
+                            members[&settlement.to_member_id].name
+                        );
+                        TransactionReq::write_in(
+                            txn,
+                            &ctx,
+                            TransactionExpandedModel {
+                                transaction: TransactionModel(transaction::Model {
+                                    id: transaction_id,
+                                    title,
+                                    timestamp: settlement.timestamp,
+                                    import_batch_id: None,
+                                }),
+                                items: vec![
+                                    item(from_account, -settlement.amount),
+                                    item(to_account, settlement.amount),
+                                ],
+                            },
+                        )
+                        .await?;
+                        Some(transaction_id)
+                    } else {
+                        None
+                    };
+
+                GroupSettlementEntity::insert(GroupSettlementActiveModel {
+                    id: ActiveValue::Set(id),
+                    group_id: ActiveValue::Set(group_id),
+                    from_member_id: ActiveValue::Set(settlement.from_member_id),
+                    to_member_id: ActiveValue::Set(settlement.to_member_id),
+                    amount: ActiveValue::Set(settlement.amount),
+                    currency_code: ActiveValue::Set(settlement.currency_code),
+                    transaction_id: ActiveValue::Set(transaction_id),
+                    timestamp: ActiveValue::Set(settlement.timestamp),
+                })
+                .exec(txn)
+                .await?;
+                Ok(id)
+            })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
+    }
+}
+
+/// Whoever paid an expense is owed the full amount and every member owes
+/// their share. A settlement moves the payer's balance up and the
+/// receiver's balance down.
+fn net_balances(
+    expenses: &[GroupExpenseExpandedModel],
+    settlements: &[GroupSettlementModel],
+) -> Vec<MemberBalance> {
+    let mut balances = BTreeMap::<(String, Uuid), i64>::new();
+    for GroupExpenseExpandedModel { expense, shares } in expenses {
+        let currency = &expense.0.currency_code;
+        *balances
+            .entry((currency.clone(), expense.0.paid_by))
+            .or_default() += expense.0.amount;
+        for share in shares {
+            *balances
+                .entry((currency.clone(), share.0.member_id))
+                .or_default() -= share.0.amount;
+        }
+    }
+    for settlement in settlements {
+        let currency = &settlement.0.currency_code;
+        *balances
+            .entry((currency.clone(), settlement.0.from_member_id))
+            .or_default() += settlement.0.amount;
+        *balances
+            .entry((currency.clone(), settlement.0.to_member_id))
+            .or_default() -= settlement.0.amount;
+    }
+    balances
+        .into_iter()
+        .map(|((currency_code, member_id), balance)| MemberBalance {
+            member_id,
+            currency_code,
+            balance,
+        })
+        .collect()
+}
+
+/// Most members with a non-zero balance in one currency for which the
+/// fewest payments are searched exactly, larger groups are paired greedily.
+const EXACT_SETTLE_UP_MEMBERS: usize = 16;
+
+/// Finds the fewest payments that settle everyone. A set of `k` members
+/// whose balances add up to zero always needs `k - 1` payments, so the
+/// members of each currency are split into as many such sets as possible.
+/// Within a set the largest debtor pays the largest creditor until everyone
+/// is settled.
+fn settle_up(balances: &[MemberBalance]) -> Vec<SettleUp> {
+    let mut by_currency = BTreeMap::<&str, Vec<(Uuid, i64)>>::new();
+    for balance in balances.iter().filter(|b| b.balance != 0) {
+        by_currency
+            .entry(&balance.currency_code)
+            .or_default()
+            .push((balance.member_id, balance.balance));
+    }
+    let mut payments = vec![];
+    for (currency_code, members) in by_currency {
+        for members in zero_sum_sets(members) {
+            let (mut creditors, mut debtors): (Vec<_>, Vec<_>) =
+                members.into_iter().partition(|(_, b)| *b > 0);
+            creditors.sort_by_key(|(_, b)| -b);
+            debtors.sort_by_key(|(_, b)| *b);
+            let (mut c, mut d) = (0, 0);
+            while c < creditors.len() && d < debtors.len() {
+                let amount = creditors[c].1.min(-debtors[d].1);
+                payments.push(SettleUp {
+                    from_member_id: debtors[d].0,
+                    to_member_id: creditors[c].0,
+                    currency_code: currency_code.to_string(),
+                    amount,
+                });
+                creditors[c].1 -= amount;
+                debtors[d].1 += amount;
+                if creditors[c].1 == 0 {
+                    c += 1;
+                }
+                if debtors[d].1 == 0 {
+                    d += 1;
+                }
+            }
+        }
+    }
+    payments
+}
+
+/// Splits balances into the most sets that each add up to zero.
+///
+/// `sets[mask]` is the most zero-sum sets the members in `mask` can be
+/// taken apart into one member at a time, counting `mask` itself when it
+/// adds up to zero. Taking the members out in that order and cutting
+/// wherever the rest adds up to zero gives the sets.
+fn zero_sum_sets(members: Vec<(Uuid, i64)>) -> Vec<Vec<(Uuid, i64)>> {
+    let n = members.len();
+    if n == 0 || n > EXACT_SETTLE_UP_MEMBERS {
+        return vec![members];
+    }
+    let full = (1_usize << n) - 1;
+    let mut sum = vec![0_i64; full + 1];
+    let mut sets = vec![0_u32; full + 1];
+    let mut take = vec![0_usize; full + 1];
+    for mask in 1..=full {
+        let low = mask & mask.wrapping_neg();
+        sum[mask] = sum[mask ^ low] + members[low.trailing_zeros() as usize].1;
+        take[mask] = (0..n)
+            .map(|i| 1 << i)
+            .filter(|bit| mask & bit != 0)
+            .max_by_key(|bit| sets[mask ^ bit])
+            .unwrap_or(low);
+        sets[mask] = sets[mask ^ take[mask]] + u32::from(sum[mask] == 0);
+    }
+    let mut result = vec![];
+    let mut current = vec![];
+    let mut mask = full;
+    while mask != 0 {
+        let bit = take[mask];
+        current.push(members[bit.trailing_zeros() as usize]);
+        mask ^= bit;
+        if sum[mask] == 0 {
+            result.push(std::mem::take(&mut current));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn member_balances(amounts: &[i64]) -> (Vec<Uuid>, Vec<MemberBalance>) {
+        let ids = amounts.iter().map(|_| Uuid::now_v7()).collect::<Vec<_>>();
+        let balances = ids
+            .iter()
+            .zip(amounts)
+            .map(|(id, balance)| MemberBalance {
+                member_id: *id,
+                currency_code: "INR".to_string(),
+                balance: *balance,
+            })
+            .collect();
+        (ids, balances)
+    }
+
+    /// Applies the payments and checks that everyone ends up settled.
+    fn assert_settles(balances: &[MemberBalance], payments: &[SettleUp]) {
+        let mut left = balances
+            .iter()
+            .map(|b| (b.member_id, b.balance))
+            .collect::<HashMap<_, _>>();
+        for payment in payments {
+            assert!(payment.amount > 0);
+            *left.get_mut(&payment.from_member_id).unwrap() += payment.amount;
+            *left.get_mut(&payment.to_member_id).unwrap() -= payment.amount;
+        }
+        assert!(left.values().all(|b| *b == 0), "{left:?}");
+    }
+
+    #[test]
+    fn equal_split_gives_leftover_to_first_members() {
+        let ids = [Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7()];
+        let split = ExpenseSplit::Equal {
+            member_ids: ids.to_vec(),
+        };
+        let owed = split.owed(100).unwrap();
+        assert_eq!(owed, vec![(ids[0], 34), (ids[1], 33), (ids[2], 33)]);
+    }
+
+    #[test]
+    fn share_split_follows_weights_without_overflow() {
+        let ids = [Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7()];
+        let split = ExpenseSplit::Shares {
+            shares: vec![
+                MemberWeight {
+                    member_id: ids[0],
+                    weight: 2,
+                },
+                MemberWeight {
+                    member_id: ids[1],
+                    weight: 1,
+                },
+                MemberWeight {
+                    member_id: ids[2],
+                    weight: 0,
+                },
+            ],
+        };
+        assert_eq!(
+            split.owed(301).unwrap(),
+            vec![(ids[0], 201), (ids[1], 100), (ids[2], 0)]
+        );
+
+        let total = i64::MAX / 2;
+        let split = ExpenseSplit::Shares {
+            shares: vec![
+                MemberWeight {
+                    member_id: ids[0],
+                    weight: u32::MAX,
+                },
+                MemberWeight {
+                    member_id: ids[1],
+                    weight: u32::MAX,
+                },
+            ],
+        };
+        let owed = split.owed(total).unwrap();
+        assert_eq!(owed.iter().map(|(_, a)| a).sum::<i64>(), total);
+        assert_eq!(owed[0].1 - owed[1].1, 1);
+    }
+
+    #[test]
+    fn invalid_splits_are_refused() {
+        let id = Uuid::now_v7();
+        let exact = |amount| ExpenseSplit::Exact {
+            amounts: vec![MemberAmount {
+                member_id: id,
+                amount,
+            }],
+        };
+        assert!(exact(90).owed(100).is_err());
+        assert!(exact(-100).owed(-100).is_err());
+        assert_eq!(exact(100).owed(100).unwrap(), vec![(id, 100)]);
+        let nobody = ExpenseSplit::Equal { member_ids: vec![] };
+        assert!(nobody.owed(100).is_err());
+    }
+
+    #[test]
+    fn settle_up_finds_fewest_payments() {
+        // Pairing the largest debtor with the largest creditor takes four
+        // payments here, while {+4, -4} and {+3, +3, -6} need three.
+        let (ids, balances) = member_balances(&[4, 3, 3, -6, -4]);
+        let payments = settle_up(&balances);
+        assert_settles(&balances, &payments);
+        assert_eq!(payments.len(), 3);
+        assert!(
+            payments
+                .iter()
+                .any(|p| p.from_member_id == ids[4] && p.to_member_id == ids[0] && p.amount == 4)
+        );
+    }
+
+    #[test]
+    fn settle_up_keeps_currencies_apart() {
+        let (_, mut balances) = member_balances(&[5, -5]);
+        let (_, usd) = member_balances(&[-2, 2]);
+        balances.extend(usd.into_iter().map(|b| MemberBalance {
+            currency_code: "USD".to_string(),
+            ..b
+        }));
+        let payments = settle_up(&balances);
+        assert_settles(&balances, &payments);
+        assert_eq!(payments.len(), 2);
+        assert!(settle_up(&[]).is_empty());
+    }
+
+    #[test]
+    fn settling_with_oneself_is_refused() {
+        let id = Uuid::now_v7();
+        let settlement = |to_member_id| GroupSettlementReq {
+            from_member_id: id,
+            to_member_id,
+            currency_code: "INR".to_string(),
+            amount: 100,
+            timestamp: chrono::Utc::now(),
+            account_id: None,
+        };
+        assert!(settlement(id).validate().is_err());
+        assert!(settlement(Uuid::now_v7()).validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn members_with_expenses_cannot_be_removed() {
+        let db = crate::test_ledger::ledger().await;
+        let group_id = ExpenseGroupReq::upsert(
+            &db,
+            ExpenseGroupReq {
+                id: None,
+                name: "Trip".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let mut ids = vec![];
+        for name in ["Asha", "Ravi", "Meera"] {
+            let member = GroupMemberReq {
+                id: None,
+                name: name.to_string(),
+                account_id: None,
+                user_id: Some(Uuid::now_v7()),
+            };
+            ids.push(
+                ExpenseGroupReq::upsert_member(&db, group_id, member)
+                    .await
+                    .unwrap(),
+            );
+        }
+        ExpenseGroupReq::upsert_expense(
+            &db,
+            group_id,
+            GroupExpenseReq {
+                id: None,
+                title: "Dinner".to_string(),
+                paid_by: ids[0],
+                amount: 100,
+                currency_code: "INR".to_string(),
+                timestamp: chrono::Utc::now(),
+                split: ExpenseSplit::Equal {
+                    member_ids: ids[..2].to_vec(),
+                },
+            },
+        )
+        .await
+        .unwrap();
+
+        for id in &ids[..2] {
+            let deleted = ExpenseGroupReq::delete_member(&db, group_id, *id).await;
+            assert!(matches!(deleted, Err(ModelError::Conflict(_))));
+        }
+        ExpenseGroupReq::delete_member(&db, group_id, ids[2])
+            .await
+            .unwrap();
+        let left = ExpenseGroupReq::members(&db, group_id).await.unwrap();
+        assert_eq!(left.len(), 2);
+    }
+}
@@ -0,0 +1,352 @@
+use std::collections::HashSet;
+
+use sea_orm::{
+    ActiveValue, ColumnTrait, ConnectionTrait, DbConn, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{
+    ModelError,
+    audit::AuditContext,
+    transaction::{TransactionExpandedModel, TransactionReq},
+};
+use crate::entity::{import_batch, import_batch_entry};
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct ImportBatchModel(#[schema(inline)] pub import_batch::Model);
+pub type ImportBatchEntity = import_batch::Entity;
+pub type ImportBatchActiveModel = import_batch::ActiveModel;
+pub type ImportBatchColumn = import_batch::Column;
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct ImportBatchEntryModel(#[schema(inline)] pub import_batch_entry::Model);
+pub type ImportBatchEntryEntity = import_batch_entry::Entity;
+pub type ImportBatchEntryActiveModel = import_batch_entry::ActiveModel;
+pub type ImportBatchEntryColumn = import_batch_entry::Column;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportBatchAction {
+    Created,
+    Updated,
+}
+
+impl ImportBatchAction {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Created => "created",
+            Self::Updated => "updated",
+        }
+    }
+}
+
+/// Metadata describing where an import came from.
+#[derive(Debug, Clone, Deserialize, IntoParams, Validate)]
+pub struct ImportBatchReq {
+    #[validate(length(min = 0, max = 255))]
+    #[serde(default)]
+    pub file_name: String,
+    #[validate(length(min = 1, max = 20))]
+    #[serde(default = "default_format")]
+    pub format: String,
+}
+
+fn default_format() -> String {
+    "json".to_string()
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+pub struct ImportBatchExpandedModel {
+    pub batch: ImportBatchModel,
+    pub entries: Vec<ImportBatchEntryModel>,
+}
+
+impl ImportBatchReq {
+    pub async fn find_all(db: &DbConn) -> Result<Vec<ImportBatchModel>, DbErr> {
+        ImportBatchEntity::find()
+            .order_by_desc(ImportBatchColumn::CreatedAt)
+            .all(db)
+            .await
+            .map(|v| v.into_iter().map(ImportBatchModel).collect())
+    }
+
+    pub async fn find_one_with_entries(
+        db: &DbConn,
+        id: Uuid,
+    ) -> Result<Option<ImportBatchExpandedModel>, DbErr> {
+        ImportBatchEntity::find_by_id(id)
+            .find_with_related(ImportBatchEntryEntity::default())
+            .all(db)
+            .await
+            .map(|batches| {
+                batches
+                    .into_iter()
+                    .next()
+                    .map(|(batch, entries)| ImportBatchExpandedModel {
+                        batch: ImportBatchModel(batch),
+                        entries: entries.into_iter().map(ImportBatchEntryModel).collect(),
+                    })
+            })
+    }
+
+    pub(crate) async fn create_in<C: ConnectionTrait>(
+        self,
+        db: &C,
+        row_count: usize,
+    ) -> Result<Uuid, DbErr> {
+        let id = Uuid::now_v7();
+        ImportBatchEntity::insert(ImportBatchActiveModel {
+            id: ActiveValue::Set(id),
+            file_name: ActiveValue::Set(self.file_name),
+            format: ActiveValue::Set(self.format),
+            row_count: ActiveValue::Set(
+                i32::try_from(row_count).map_err(|e| DbErr::Type(e.to_string()))?,
+            ),
+            created_at: ActiveValue::Set(chrono::Utc::now()),
+            rolled_back_at: ActiveValue::Set(None),
+        })
+        .exec(db)
+        .await?;
+        Ok(id)
+    }
+
+    /// Remembers that the batch touched a transaction. `before` is the state
+    /// the transaction had before the import, or `None` if it was created,
+    /// and `after` the state the import left it in.
+    pub(crate) async fn add_entry_in<C: ConnectionTrait>(
+        db: &C,
+        batch_id: Uuid,
+        transaction_id: Uuid,
+        before: Option<TransactionExpandedModel>,
+        after: Option<TransactionExpandedModel>,
+    ) -> Result<(), DbErr> {
+        let action = if before.is_some() {
+            ImportBatchAction::Updated
+        } else {
+            ImportBatchAction::Created
+        };
+        ImportBatchEntryEntity::insert(ImportBatchEntryActiveModel {
+            id: ActiveValue::Set(Uuid::now_v7()),
+            batch_id: ActiveValue::Set(batch_id),
+            transaction_id: ActiveValue::Set(transaction_id),
+            action: ActiveValue::Set(action.as_str().to_string()),
+            before: ActiveValue::Set(state(before)?),
+            after: ActiveValue::Set(state(after)?),
+        })
+        .exec(db)
+        .await?;
+        Ok(())
+    }
+
+    /// Undoes everything the batch did, newest change first: created
+    /// transactions are deleted and updated ones get their prior state back.
+    /// Nothing is undone if a transaction changed after the import, by the
+    /// user or by a later import, and the conflict names those transactions.
+    pub async fn rollback(db: &DbConn, ctx: &AuditContext, id: Uuid) -> Result<(), ModelError> {
+        let ctx = ctx.clone();
+        db.transaction::<_, _, ModelError>(|txn| {
+            Box::pin(async move {
+                let batch = ImportBatchEntity::find_by_id(id)
+                    .one(txn)
+                    .await?
+                    .ok_or_else(|| ModelError::NotFound(format!("Import batch {id} not found")))?;
+                if batch.rolled_back_at.is_some() {
+                    return Err(ModelError::Conflict(format!(
+                        "Import batch {id} is already rolled back"
+                    )));
+                }
+                let entries = ImportBatchEntryEntity::find()
+                    .filter(ImportBatchEntryColumn::BatchId.eq(id))
+                    .order_by_desc(ImportBatchEntryColumn::Id)
+                    .all(txn)
+                    .await?;
+                // Only the newest entry of a transaction describes the state
+                // the import left it in.
+                let mut checked = HashSet::new();
+                let mut diverged = vec![];
+                for entry in &entries {
+                    if !checked.insert(entry.transaction_id) {
+                        continue;
+                    }
+                    let current =
+                        TransactionReq::find_one_with_items_in(txn, entry.transaction_id).await?;
+                    let unchanged = match &entry.after {
+                        Some(after) => state(current)?.as_ref() == Some(after),
+                        // Entries from before the state was kept can only be
+                        // checked for a later import.
+                        None => {
+                            current.is_some_and(|c| c.transaction.0.import_batch_id == Some(id))
+                        }
+                    };
+                    if !unchanged {
+                        diverged.push(entry.transaction_id.to_string());
+                    }
+                }
+                if !diverged.is_empty() {
+                    return Err(ModelError::Conflict(format!(
+                        "Transactions changed after the import: {}",
+                        diverged.join(", ")
+                    )));
+                }
+                for entry in entries {
+                    match entry.before {
+                        Some(before) => {
+                            let before = serde_json::from_value(before)
+                                .map_err(|e| DbErr::Json(e.to_string()))?;
+                            TransactionReq::write_in(txn, &ctx, before).await?;
+                        }
+                        None => {
+                            TransactionReq::delete_in(txn, &ctx, entry.transaction_id).await?;
+                        }
+                    }
+                }
+                ImportBatchEntity::update(ImportBatchActiveModel {
+                    id: ActiveValue::Set(id),
+                    file_name: ActiveValue::NotSet,
+                    format: ActiveValue::NotSet,
+                    row_count: ActiveValue::NotSet,
+                    created_at: ActiveValue::NotSet,
+                    rolled_back_at: ActiveValue::Set(Some(chrono::Utc::now())),
+                })
+                .exec(txn)
+                .await?;
+                Ok(())
+            })
+        })
+        .await
+        .map_err(ModelError::from)
+    }
+}
+
+/// A transaction as stored in an entry, with its items in a fixed order so
+/// that states can be compared.
+fn state(tx: Option<TransactionExpandedModel>) -> Result<Option<serde_json::Value>, DbErr> {
+    tx.map(|mut tx| {
+        tx.items.sort_by_key(|item| item.0.id);
+        serde_json::to_value(tx).map_err(|e| DbErr::Json(e.to_string()))
+    })
+    .transpose()
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use chrono::{DateTime, Utc};
+    use migration::AccountType;
+
+    use super::*;
+    use crate::test_ledger;
+
+    fn tx(id: Option<Uuid>, title: &str, amount: i64) -> TransactionReq {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "title": title,
+            "timestamp": "2026-10-01T12:00:00Z",
+            "items": [{
+                "id": null,
+                "notes": "",
+                "account_name": "Bank",
+                "category_name": null,
+                "amount": amount,
+            }],
+        }))
+        .unwrap()
+    }
+
+    fn batch() -> ImportBatchReq {
+        ImportBatchReq {
+            file_name: "bank.csv".to_string(),
+            format: "csv".to_string(),
+        }
+    }
+
+    async fn setup() -> (DbConn, AuditContext, Uuid) {
+        let db = test_ledger::ledger().await;
+        test_ledger::account(&db, "Bank", AccountType::Bank, "INR", true).await;
+        let ctx = AuditContext {
+            user_id: Uuid::now_v7(),
+            request_id: None,
+        };
+        let existing = TransactionReq::upsert(&db, &ctx, tx(None, "Rent", -100))
+            .await
+            .unwrap();
+        (db, ctx, existing)
+    }
+
+    async fn titles(db: &DbConn) -> Vec<(String, DateTime<Utc>)> {
+        let mut titles: Vec<_> = crate::entity::transaction::Entity::find()
+            .all(db)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.title, t.timestamp))
+            .collect();
+        titles.sort();
+        titles
+    }
+
+    #[tokio::test]
+    async fn rollback_restores_updated_and_deletes_created() {
+        let (db, ctx, existing) = setup().await;
+        let before = titles(&db).await;
+        let batch_id = TransactionReq::upsert_many(
+            &db,
+            &ctx,
+            batch(),
+            vec![
+                tx(Some(existing), "Rent (bank)", -100),
+                tx(None, "Salary", 500),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(titles(&db).await.len(), 2);
+
+        ImportBatchReq::rollback(&db, &ctx, batch_id).await.unwrap();
+        assert_eq!(titles(&db).await, before);
+        let restored = TransactionReq::find_one_with_items_in(&db, existing)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(restored.transaction.0.import_batch_id, None);
+        assert_eq!(restored.items.len(), 1);
+
+        let again = ImportBatchReq::rollback(&db, &ctx, batch_id).await;
+        assert!(matches!(again, Err(ModelError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn rollback_refuses_transactions_changed_after_the_import() {
+        let (db, ctx, existing) = setup().await;
+        let batch_id = TransactionReq::upsert_many(
+            &db,
+            &ctx,
+            batch(),
+            vec![tx(Some(existing), "Rent (bank)", -100)],
+        )
+        .await
+        .unwrap();
+        TransactionReq::upsert(&db, &ctx, tx(Some(existing), "Rent (edited)", -120))
+            .await
+            .unwrap();
+
+        let Err(ModelError::Conflict(message)) =
+            ImportBatchReq::rollback(&db, &ctx, batch_id).await
+        else {
+            panic!("rollback should conflict");
+        };
+        assert!(message.contains(&existing.to_string()));
+        assert_eq!(titles(&db).await[0].0, "Rent (edited)");
+    }
+
+    #[tokio::test]
+    async fn rollback_of_a_missing_batch_is_not_found() {
+        let (db, ctx, _) = setup().await;
+        let missing = ImportBatchReq::rollback(&db, &ctx, Uuid::now_v7()).await;
+        assert!(matches!(missing, Err(ModelError::NotFound(_))));
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt::Write;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::{
+    ActiveValue, ColumnTrait, DbConn, DbErr, EntityTrait, QueryFilter, QueryOrder, sea_query::Expr,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::user_entity::{invite, prelude::Invite as InviteEntity};
+
+/// A single-use code that lets someone sign up while registration is
+/// invite-only. Like API tokens only the hash of the code is stored.
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct Invite {
+    pub id: Uuid,
+    pub created_by: Uuid,
+    pub expires_at: DateTime<Utc>,
+    pub used_by: Option<Uuid>,
+    pub used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, ToSchema, Deserialize, Validate)]
+pub struct InviteReq {
+    /// Days until the code expires, a week by default.
+    #[validate(range(min = 1, max = 30))]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct NewInvite {
+    #[serde(flatten)]
+    pub info: Invite,
+    pub code: String,
+}
+
+fn hash_code(code: &str) -> String {
+    format!("{:x}", Sha256::digest(code.trim().as_bytes()))
+}
+
+impl Invite {
+    const fn from_model(model: &invite::Model) -> Self {
+        Self {
+            id: model.id,
+            created_by: model.created_by,
+            expires_at: model.expires_at,
+            used_by: model.used_by,
+            used_at: model.used_at,
+            created_at: model.created_at,
+        }
+    }
+
+    pub async fn create(
+        db: &DbConn,
+        created_by: Uuid,
+        req: &InviteReq,
+    ) -> Result<NewInvite, DbErr> {
+        let mut bytes = [0u8; 12];
+        OsRng.fill_bytes(&mut bytes);
+        let code = bytes.iter().fold(String::new(), |mut code, b| {
+            let _ = write!(code, "{b:02x}");
+            code
+        });
+        let now = Utc::now();
+        let info = Self {
+            id: Uuid::now_v7(),
+            created_by,
+            expires_at: now + Duration::days(req.expires_in_days.unwrap_or(7)),
+            used_by: None,
+            used_at: None,
+            created_at: now,
+        };
+        InviteEntity::insert(invite::ActiveModel {
+            id: ActiveValue::Set(info.id),
+            code_hash: ActiveValue::Set(hash_code(&code)),
+            created_by: ActiveValue::Set(info.created_by),
+            expires_at: ActiveValue::Set(info.expires_at),
+            used_by: ActiveValue::Set(None),
+            used_at: ActiveValue::Set(None),
+            created_at: ActiveValue::Set(info.created_at),
+        })
+        .exec_without_returning(db)
+        .await?;
+        Ok(NewInvite { info, code })
+    }
+
+    /// The invites created by a user, or every invite for `None`.
+    pub async fn find_created_by(
+        db: &DbConn,
+        created_by: Option<Uuid>,
+    ) -> Result<Vec<Self>, DbErr> {
+        let mut query = InviteEntity::find();
+        if let Some(created_by) = created_by {
+            query = query.filter(invite::Column::CreatedBy.eq(created_by));
+        }
+        Ok(query
+            .order_by_desc(invite::Column::CreatedAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|model| Self::from_model(&model))
+            .collect())
+    }
+
+    /// Deletes an unused invite, only one created by `created_by` if given.
+    pub async fn revoke(db: &DbConn, id: Uuid, created_by: Option<Uuid>) -> Result<(), DbErr> {
+        let mut query = InviteEntity::delete_many()
+            .filter(invite::Column::Id.eq(id))
+            .filter(invite::Column::UsedAt.is_null());
+        if let Some(created_by) = created_by {
+            query = query.filter(invite::Column::CreatedBy.eq(created_by));
+        }
+        query.exec(db).await?;
+        Ok(())
+    }
+
+    /// Marks an unused and unexpired invite as used and returns its id. The
+    /// update is conditional, so a code can only be claimed once.
+    pub async fn claim(db: &DbConn, code: &str) -> Result<Option<Uuid>, DbErr> {
+        let now = Utc::now();
+        let Some(model) = InviteEntity::find()
+            .filter(invite::Column::CodeHash.eq(hash_code(code)))
+            .one(db)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let result = InviteEntity::update_many()
+            .col_expr(invite::Column::UsedAt, Expr::value(now))
+            .filter(invite::Column::Id.eq(model.id))
+            .filter(invite::Column::UsedAt.is_null())
+            .filter(invite::Column::ExpiresAt.gt(now))
+            .exec(db)
+            .await?;
+        Ok((result.rows_affected == 1).then_some(model.id))
+    }
+
+    /// Gives a claimed invite back when the sign up failed after all.
+    pub async fn release(db: &DbConn, id: Uuid) -> Result<(), DbErr> {
+        InviteEntity::update_many()
+            .col_expr(invite::Column::UsedAt, Expr::value(None::<DateTime<Utc>>))
+            .filter(invite::Column::Id.eq(id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_used_by(db: &DbConn, id: Uuid, user_id: Uuid) -> Result<(), DbErr> {
+        InviteEntity::update_many()
+            .col_expr(invite::Column::UsedBy, Expr::value(user_id))
+            .filter(invite::Column::Id.eq(id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+}
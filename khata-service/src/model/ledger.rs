@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use migration::OnConflict;
+use sea_orm::{
+    ActiveValue, ColumnTrait, DbConn, DbErr, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+use super::{ModelError, user::User};
+use crate::user_entity::{
+    ledger, ledger_member,
+    prelude::{Ledger as LedgerEntity, LedgerMember as LedgerMemberEntity, User as UserEntity},
+};
+
+/// What a member may do in a ledger. Roles are ordered, so a higher role
+/// includes everything a lower one allows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LedgerRole {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl LedgerRole {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Viewer => "viewer",
+            Self::Editor => "editor",
+            Self::Owner => "owner",
+        }
+    }
+
+    fn from_str(value: &str) -> Result<Self, DbErr> {
+        serde_json::from_value(serde_json::Value::String(value.to_string()))
+            .map_err(|e| DbErr::Type(e.to_string()))
+    }
+}
+
+/// A ledger as seen by one of its members.
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct Ledger {
+    pub id: Uuid,
+    pub name: String,
+    pub owner_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub role: LedgerRole,
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct LedgerMember {
+    pub user_id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub role: LedgerRole,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, ToSchema, Deserialize, Validate)]
+pub struct LedgerReq {
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, ToSchema, Deserialize, Validate)]
+pub struct LedgerMemberReq {
+    #[validate(email)]
+    pub email: String,
+    pub role: LedgerRole,
+}
+
+impl Ledger {
+    pub async fn find_for_user(db: &DbConn, user_id: Uuid) -> Result<Vec<Self>, DbErr> {
+        let memberships = LedgerMemberEntity::find()
+            .filter(ledger_member::Column::UserId.eq(user_id))
+            .find_also_related(LedgerEntity)
+            .order_by_asc(ledger_member::Column::CreatedAt)
+            .all(db)
+            .await?;
+        memberships
+            .into_iter()
+            .filter_map(|(member, ledger)| Some((member, ledger?)))
+            .map(|(member, ledger)| {
+                Ok(Self {
+                    id: ledger.id,
+                    name: ledger.name,
+                    owner_id: ledger.owner_id,
+                    created_at: ledger.created_at,
+                    role: LedgerRole::from_str(&member.role)?,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn role_of(
+        db: &DbConn,
+        ledger_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<Option<LedgerRole>, DbErr> {
+        LedgerMemberEntity::find_by_id((ledger_id, user_id))
+            .one(db)
+            .await?
+            .map(|member| LedgerRole::from_str(&member.role))
+            .transpose()
+    }
+
+    /// Every user owns a personal ledger whose id is their user id, which
+    /// keeps the ledger file of accounts created before ledgers existed.
+    pub async fn ensure_personal(db: &DbConn, user: &User) -> Result<(), DbErr> {
+        LedgerEntity::insert(ledger::ActiveModel {
+            id: ActiveValue::Set(user.id),
+            name: ActiveValue::Set(format!("{}'s ledger", user.name)),
+            owner_id: ActiveValue::Set(user.id),
+            created_at: ActiveValue::Set(user.created_at),
+        })
+        .on_conflict(
+            OnConflict::column(ledger::Column::Id)
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+        LedgerMemberEntity::insert(ledger_member::ActiveModel {
+            ledger_id: ActiveValue::Set(user.id),
+            user_id: ActiveValue::Set(user.id),
+            role: ActiveValue::Set(LedgerRole::Owner.as_str().to_string()),
+            created_at: ActiveValue::Set(user.created_at),
+        })
+        .on_conflict(
+            OnConflict::columns([
+                ledger_member::Column::LedgerId,
+                ledger_member::Column::UserId,
+            ])
+            .do_nothing()
+            .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn create(db: &DbConn, owner_id: Uuid, ledger: LedgerReq) -> Result<Uuid, DbErr> {
+        let id = Uuid::now_v7();
+        let now = Utc::now();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move {
+                LedgerEntity::insert(ledger::ActiveModel {
+                    id: ActiveValue::Set(id),
+                    name: ActiveValue::Set(ledger.name),
+                    owner_id: ActiveValue::Set(owner_id),
+                    created_at: ActiveValue::Set(now),
+                })
+                .exec(txn)
+                .await?;
+                LedgerMemberEntity::insert(ledger_member::ActiveModel {
+                    ledger_id: ActiveValue::Set(id),
+                    user_id: ActiveValue::Set(owner_id),
+                    role: ActiveValue::Set(LedgerRole::Owner.as_str().to_string()),
+                    created_at: ActiveValue::Set(now),
+                })
+                .exec_without_returning(txn)
+                .await?;
+                Ok(())
+            })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })?;
+        Ok(id)
+    }
+
+    /// The ids of the ledgers each user owns.
+    pub async fn ids_by_owner(db: &DbConn) -> Result<HashMap<Uuid, Vec<Uuid>>, DbErr> {
+        let ledgers: Vec<(Uuid, Uuid)> = LedgerEntity::find()
+            .select_only()
+            .column(ledger::Column::OwnerId)
+            .column(ledger::Column::Id)
+            .into_tuple()
+            .all(db)
+            .await?;
+        let mut owned: HashMap<Uuid, Vec<Uuid>> = HashMap::new();
+        for (owner_id, id) in ledgers {
+            owned.entry(owner_id).or_default().push(id);
+        }
+        Ok(owned)
+    }
+
+    /// The ledgers a user owns, personal one included. They are deleted
+    /// along with the user.
+    pub async fn find_owned_by(db: &DbConn, owner_id: Uuid) -> Result<Vec<ledger::Model>, DbErr> {
+        LedgerEntity::find()
+            .filter(ledger::Column::OwnerId.eq(owner_id))
+            .order_by_asc(ledger::Column::CreatedAt)
+            .all(db)
+            .await
+    }
+
+    /// Removes a shared ledger. Personal ledgers cannot be deleted.
+    pub async fn delete(db: &DbConn, id: Uuid) -> Result<(), ModelError> {
+        let ledger = LedgerEntity::find_by_id(id)
+            .one(db)
+            .await?
+            .ok_or_else(|| ModelError::NotFound(format!("Ledger {id} not found")))?;
+        if ledger.owner_id == ledger.id {
+            return Err(ModelError::Conflict(
+                "A personal ledger cannot be deleted".to_string(),
+            ));
+        }
+        LedgerEntity::delete_by_id(id).exec(db).await?;
+        Ok(())
+    }
+
+    pub async fn members(db: &DbConn, ledger_id: Uuid) -> Result<Vec<LedgerMember>, DbErr> {
+        let members = LedgerMemberEntity::find()
+            .filter(ledger_member::Column::LedgerId.eq(ledger_id))
+            .find_also_related(UserEntity)
+            .order_by_asc(ledger_member::Column::CreatedAt)
+            .all(db)
+            .await?;
+        members
+            .into_iter()
+            .filter_map(|(member, user)| Some((member, user?)))
+            .map(|(member, user)| {
+                Ok(LedgerMember {
+                    user_id: user.id,
+                    name: user.name,
+                    email: user.email,
+                    role: LedgerRole::from_str(&member.role)?,
+                    created_at: member.created_at,
+                })
+            })
+            .collect()
+    }
+
+    /// Adds an existing user to the ledger, or changes their role if they are
+    /// already a member.
+    pub async fn upsert_member(
+        db: &DbConn,
+        ledger_id: Uuid,
+        member: LedgerMemberReq,
+    ) -> Result<(), ModelError> {
+        let user = User::find_by_email(db, &member.email)
+            .await?
+            .ok_or_else(|| ModelError::NotFound(format!("No user with email {}", member.email)))?;
+        if member.role < LedgerRole::Owner {
+            Self::ensure_other_owner(db, ledger_id, user.id).await?;
+        }
+        LedgerMemberEntity::insert(ledger_member::ActiveModel {
+            ledger_id: ActiveValue::Set(ledger_id),
+            user_id: ActiveValue::Set(user.id),
+            role: ActiveValue::Set(member.role.as_str().to_string()),
+            created_at: ActiveValue::Set(Utc::now()),
+        })
+        .on_conflict(
+            OnConflict::columns([
+                ledger_member::Column::LedgerId,
+                ledger_member::Column::UserId,
+            ])
+            .update_column(ledger_member::Column::Role)
+            .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn remove_member(
+        db: &DbConn,
+        ledger_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<(), ModelError> {
+        Self::ensure_other_owner(db, ledger_id, user_id).await?;
+        LedgerMemberEntity::delete_by_id((ledger_id, user_id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    /// Whether `user_id` is the only owner of the ledger.
+    async fn is_last_owner(db: &DbConn, ledger_id: Uuid, user_id: Uuid) -> Result<bool, DbErr> {
+        let other_owner = LedgerMemberEntity::find()
+            .filter(ledger_member::Column::LedgerId.eq(ledger_id))
+            .filter(ledger_member::Column::UserId.ne(user_id))
+            .filter(ledger_member::Column::Role.eq(LedgerRole::Owner.as_str()))
+            .one(db)
+            .await?;
+        let is_owner = Self::role_of(db, ledger_id, user_id).await? == Some(LedgerRole::Owner);
+        Ok(is_owner && other_owner.is_none())
+    }
+
+    /// Fails if `user_id` is the only owner left, so that a ledger can never
+    /// end up without one.
+    async fn ensure_other_owner(
+        db: &DbConn,
+        ledger_id: Uuid,
+        user_id: Uuid,
+    ) -> Result<(), ModelError> {
+        if Self::is_last_owner(db, ledger_id, user_id).await? {
+            return Err(ModelError::Conflict(
+                "A ledger must keep at least one owner".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use user_migration::MigratorTrait;
+
+    use super::*;
+
+    async fn user(db: &DbConn, email: &str) -> User {
+        User::create_user(db, email, email, "").await.unwrap()
+    }
+
+    fn member(email: &str, role: LedgerRole) -> LedgerMemberReq {
+        LedgerMemberReq {
+            email: email.to_string(),
+            role,
+        }
+    }
+
+    #[tokio::test]
+    async fn the_last_owner_cannot_leave_or_be_demoted() {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        user_migration::Migrator::up(&db, None).await.unwrap();
+        let alice = user(&db, "alice@example.com").await;
+        let bob = user(&db, "bob@example.com").await;
+        let id = Ledger::create(
+            &db,
+            alice.id,
+            LedgerReq {
+                name: "Home".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+
+        let removed = Ledger::remove_member(&db, id, alice.id).await;
+        assert!(matches!(removed, Err(ModelError::Conflict(_))));
+        let demoted =
+            Ledger::upsert_member(&db, id, member(&alice.email, LedgerRole::Editor)).await;
+        assert!(matches!(demoted, Err(ModelError::Conflict(_))));
+
+        Ledger::upsert_member(&db, id, member(&bob.email, LedgerRole::Owner))
+            .await
+            .unwrap();
+        Ledger::upsert_member(&db, id, member(&alice.email, LedgerRole::Editor))
+            .await
+            .unwrap();
+        let removed = Ledger::remove_member(&db, id, bob.id).await;
+        assert!(matches!(removed, Err(ModelError::Conflict(_))));
+        Ledger::remove_member(&db, id, alice.id).await.unwrap();
+        assert_eq!(Ledger::role_of(&db, id, alice.id).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn personal_ledgers_cannot_be_deleted() {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        user_migration::Migrator::up(&db, None).await.unwrap();
+        let alice = user(&db, "alice@example.com").await;
+        Ledger::ensure_personal(&db, &alice).await.unwrap();
+
+        let deleted = Ledger::delete(&db, alice.id).await;
+        assert!(matches!(deleted, Err(ModelError::Conflict(_))));
+        let missing = Ledger::delete(&db, Uuid::now_v7()).await;
+        assert!(matches!(missing, Err(ModelError::NotFound(_))));
+    }
+}
@@ -1,5 +1,44 @@
 pub mod account;
+pub mod account_extra;
+pub mod api_token;
+pub mod audit;
 pub mod category;
 pub mod currency;
+pub mod data_export;
+pub mod group;
+pub mod import_batch;
+pub mod invite;
+pub mod ledger;
+pub mod notification;
+pub mod rate_limit;
+pub mod report_definition;
+pub mod subscription;
 pub mod transaction;
 pub mod user;
+pub mod user_identity;
+pub mod user_session;
+pub mod user_settings;
+
+use sea_orm::{DbErr, TransactionError};
+
+/// Why a model refused a change, so that routes can answer with the right
+/// status instead of a server error.
+#[derive(Debug, thiserror::Error)]
+pub enum ModelError {
+    #[error(transparent)]
+    Db(#[from] DbErr),
+    /// The change conflicts with the current state.
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    NotFound(String),
+}
+
+impl From<TransactionError<Self>> for ModelError {
+    fn from(e: TransactionError<Self>) -> Self {
+        match e {
+            TransactionError::Connection(e) => Self::Db(e),
+            TransactionError::Transaction(e) => e,
+        }
+    }
+}
@@ -0,0 +1,513 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use migration::OnConflict;
+use sea_orm::{
+    ActiveValue, ColumnTrait, DbConn, DbErr, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::{
+    period::PeriodKind,
+    user_entity::{
+        notification, notification_rule,
+        prelude::{Notification as NotificationEntity, NotificationRule as NotificationRuleEntity},
+    },
+};
+
+/// Delivery attempts after which an outbox entry is given up on.
+const MAX_ATTEMPTS: i32 = 5;
+
+/// Wait before retrying after the first failed attempt, doubled after every
+/// further failure.
+const RETRY_DELAY_MINUTES: i64 = 5;
+
+/// How long to wait after `attempts` failed attempts.
+pub fn retry_delay(attempts: i32) -> TimeDelta {
+    let doublings = u32::try_from(attempts - 1).unwrap_or(0).min(16);
+    TimeDelta::minutes(RETRY_DELAY_MINUTES << doublings)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationChannel {
+    InApp,
+    Email,
+    Webhook,
+}
+
+impl NotificationChannel {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::InApp => "in_app",
+            Self::Email => "email",
+            Self::Webhook => "webhook",
+        }
+    }
+
+    fn from_str(value: &str) -> Result<Self, DbErr> {
+        serde_json::from_value(serde_json::Value::String(value.to_string()))
+            .map_err(|e| DbErr::Type(e.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationStatus {
+    Pending,
+    Delivered,
+    Failed,
+    /// The channel is not set up on this server, e.g. email without SMTP.
+    Skipped,
+}
+
+impl NotificationStatus {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Delivered => "delivered",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
+
+    fn from_str(value: &str) -> Result<Self, DbErr> {
+        serde_json::from_value(serde_json::Value::String(value.to_string()))
+            .map_err(|e| DbErr::Type(e.to_string()))
+    }
+}
+
+/// What a rule watches for. Amounts are in the minor units of the currency
+/// they refer to.
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum NotificationCondition {
+    /// A credit card with an outstanding balance is due within `days_before`
+    /// days.
+    CardDue { account_id: Uuid, days_before: u32 },
+    /// Spending in a category this period reached `percent` of `limit`.
+    /// Only accounts in `currency_code` are counted.
+    BudgetUsed {
+        category_id: Uuid,
+        currency_code: String,
+        /// The limit for one period, a calendar month by default. Rules saved
+        /// before periods existed call it `monthly_limit`.
+        #[serde(alias = "monthly_limit")]
+        limit: i64,
+        percent: u32,
+        #[serde(default)]
+        period: PeriodKind,
+    },
+    /// A transaction moved at least `threshold` in or out of an account.
+    LargeTransaction { threshold: i64 },
+}
+
+impl Validate for NotificationCondition {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        match self {
+            Self::CardDue { days_before, .. } if *days_before > 60 => {
+                errors.add("days_before", ValidationError::new("range"));
+            }
+            Self::BudgetUsed {
+                currency_code,
+                limit,
+                percent,
+                ..
+            } => {
+                if currency_code.len() != 3 {
+                    errors.add("currency_code", ValidationError::new("length"));
+                }
+                if *limit < 1 {
+                    errors.add("limit", ValidationError::new("range"));
+                }
+                if !(1..=1000).contains(percent) {
+                    errors.add("percent", ValidationError::new("range"));
+                }
+            }
+            Self::LargeTransaction { threshold } if *threshold < 1 => {
+                errors.add("threshold", ValidationError::new("range"));
+            }
+            _ => {}
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct NotificationRule {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub ledger_id: Uuid,
+    pub condition: NotificationCondition,
+    pub channels: Vec<NotificationChannel>,
+    pub webhook_url: Option<String>,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, ToSchema, Deserialize, Validate)]
+#[validate(schema(function = "validate_rule"))]
+pub struct NotificationRuleReq {
+    pub id: Option<Uuid>,
+    #[validate(nested)]
+    pub condition: NotificationCondition,
+    #[validate(length(min = 1, max = 3))]
+    pub channels: Vec<NotificationChannel>,
+    #[validate(url)]
+    pub webhook_url: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+const fn default_enabled() -> bool {
+    true
+}
+
+fn validate_rule(rule: &NotificationRuleReq) -> Result<(), ValidationError> {
+    if rule.channels.contains(&NotificationChannel::Webhook) && rule.webhook_url.is_none() {
+        return Err(ValidationError::new("webhook_url")
+            .with_message("The webhook channel needs a webhook_url".into()));
+    }
+    let scheme = rule
+        .webhook_url
+        .as_deref()
+        .and_then(|url| reqwest::Url::parse(url).ok())
+        .map(|url| url.scheme().to_string());
+    if scheme.is_some_and(|scheme| scheme != "http" && scheme != "https") {
+        return Err(ValidationError::new("webhook_url")
+            .with_message("webhook_url must be an http or https URL".into()));
+    }
+    Ok(())
+}
+
+/// An entry of the outbox: one message on one channel.
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct Notification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub rule_id: Option<Uuid>,
+    pub channel: NotificationChannel,
+    pub title: String,
+    pub body: String,
+    pub status: NotificationStatus,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub read_at: Option<DateTime<Utc>>,
+}
+
+/// A message produced by a rule. The same `dedupe_key` is only ever queued
+/// once per user and channel, so rules can be evaluated over and over.
+#[derive(Debug, Clone)]
+pub struct NotificationMessage {
+    pub dedupe_key: String,
+    pub title: String,
+    pub body: String,
+}
+
+impl NotificationRule {
+    fn from_model(model: notification_rule::Model) -> Result<Self, DbErr> {
+        Ok(Self {
+            id: model.id,
+            user_id: model.user_id,
+            ledger_id: model.ledger_id,
+            condition: serde_json::from_value(model.condition)
+                .map_err(|e| DbErr::Json(e.to_string()))?,
+            channels: serde_json::from_value(model.channels)
+                .map_err(|e| DbErr::Json(e.to_string()))?,
+            webhook_url: model.webhook_url,
+            enabled: model.enabled,
+            created_at: model.created_at,
+        })
+    }
+
+    pub async fn find_for_user(
+        db: &DbConn,
+        user_id: Uuid,
+        ledger_id: Uuid,
+    ) -> Result<Vec<Self>, DbErr> {
+        NotificationRuleEntity::find()
+            .filter(notification_rule::Column::UserId.eq(user_id))
+            .filter(notification_rule::Column::LedgerId.eq(ledger_id))
+            .order_by_asc(notification_rule::Column::CreatedAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(Self::from_model)
+            .collect()
+    }
+
+    pub async fn find_enabled(db: &DbConn) -> Result<Vec<Self>, DbErr> {
+        NotificationRuleEntity::find()
+            .filter(notification_rule::Column::Enabled.eq(true))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(Self::from_model)
+            .collect()
+    }
+
+    pub async fn find_by_id(db: &DbConn, id: Uuid) -> Result<Option<Self>, DbErr> {
+        NotificationRuleEntity::find_by_id(id)
+            .one(db)
+            .await?
+            .map(Self::from_model)
+            .transpose()
+    }
+
+    pub async fn upsert(
+        db: &DbConn,
+        user_id: Uuid,
+        ledger_id: Uuid,
+        rule: NotificationRuleReq,
+    ) -> Result<Uuid, DbErr> {
+        if let Some(id) = rule.id {
+            let existing = NotificationRuleEntity::find_by_id(id).one(db).await?;
+            if existing.is_some_and(|r| r.user_id != user_id || r.ledger_id != ledger_id) {
+                return Err(DbErr::RecordNotFound(format!("notification_rule: {id}")));
+            }
+        }
+        let id = rule.id.unwrap_or_else(Uuid::now_v7);
+        NotificationRuleEntity::insert(notification_rule::ActiveModel {
+            id: ActiveValue::Set(id),
+            user_id: ActiveValue::Set(user_id),
+            ledger_id: ActiveValue::Set(ledger_id),
+            condition: ActiveValue::Set(
+                serde_json::to_value(rule.condition).map_err(|e| DbErr::Json(e.to_string()))?,
+            ),
+            channels: ActiveValue::Set(
+                serde_json::to_value(rule.channels).map_err(|e| DbErr::Json(e.to_string()))?,
+            ),
+            webhook_url: ActiveValue::Set(rule.webhook_url),
+            enabled: ActiveValue::Set(rule.enabled),
+            created_at: ActiveValue::Set(Utc::now()),
+        })
+        .on_conflict(
+            OnConflict::column(notification_rule::Column::Id)
+                .update_columns([
+                    notification_rule::Column::Condition,
+                    notification_rule::Column::Channels,
+                    notification_rule::Column::WebhookUrl,
+                    notification_rule::Column::Enabled,
+                ])
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+        Ok(id)
+    }
+
+    pub async fn delete(db: &DbConn, user_id: Uuid, id: Uuid) -> Result<(), DbErr> {
+        NotificationRuleEntity::delete_many()
+            .filter(notification_rule::Column::UserId.eq(user_id))
+            .filter(notification_rule::Column::Id.eq(id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+}
+
+impl Notification {
+    fn from_model(model: notification::Model) -> Result<Self, DbErr> {
+        Ok(Self {
+            id: model.id,
+            user_id: model.user_id,
+            rule_id: model.rule_id,
+            channel: NotificationChannel::from_str(&model.channel)?,
+            title: model.title,
+            body: model.body,
+            status: NotificationStatus::from_str(&model.status)?,
+            attempts: model.attempts,
+            last_error: model.last_error,
+            created_at: model.created_at,
+            delivered_at: model.delivered_at,
+            read_at: model.read_at,
+        })
+    }
+
+    /// The in-app notifications of a user, newest first.
+    pub async fn inbox(db: &DbConn, user_id: Uuid) -> Result<Vec<Self>, DbErr> {
+        Self::find(db, user_id, Some(NotificationChannel::InApp)).await
+    }
+
+    /// Everything queued for a user on any channel, newest first.
+    pub async fn outbox(db: &DbConn, user_id: Uuid) -> Result<Vec<Self>, DbErr> {
+        Self::find(db, user_id, None).await
+    }
+
+    async fn find(
+        db: &DbConn,
+        user_id: Uuid,
+        channel: Option<NotificationChannel>,
+    ) -> Result<Vec<Self>, DbErr> {
+        let mut query = NotificationEntity::find()
+            .filter(notification::Column::UserId.eq(user_id))
+            .order_by_desc(notification::Column::CreatedAt)
+            .limit(200);
+        if let Some(channel) = channel {
+            query = query.filter(notification::Column::Channel.eq(channel.as_str()));
+        }
+        query
+            .all(db)
+            .await?
+            .into_iter()
+            .map(Self::from_model)
+            .collect()
+    }
+
+    pub async fn mark_read(db: &DbConn, user_id: Uuid, id: Uuid) -> Result<(), DbErr> {
+        NotificationEntity::update_many()
+            .col_expr(
+                notification::Column::ReadAt,
+                sea_orm::sea_query::Expr::value(Utc::now()),
+            )
+            .filter(notification::Column::UserId.eq(user_id))
+            .filter(notification::Column::Id.eq(id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    /// Queues `message` on every channel of the rule. In-app notifications
+    /// need no delivery and are stored as delivered right away.
+    pub async fn enqueue(
+        db: &DbConn,
+        rule: &NotificationRule,
+        message: &NotificationMessage,
+    ) -> Result<(), DbErr> {
+        let now = Utc::now();
+        for channel in &rule.channels {
+            let (status, delivered_at) = match channel {
+                NotificationChannel::InApp => (NotificationStatus::Delivered, Some(now)),
+                _ => (NotificationStatus::Pending, None),
+            };
+            NotificationEntity::insert(notification::ActiveModel {
+                id: ActiveValue::Set(Uuid::now_v7()),
+                user_id: ActiveValue::Set(rule.user_id),
+                rule_id: ActiveValue::Set(Some(rule.id)),
+                channel: ActiveValue::Set(channel.as_str().to_string()),
+                dedupe_key: ActiveValue::Set(message.dedupe_key.clone()),
+                title: ActiveValue::Set(message.title.clone()),
+                body: ActiveValue::Set(message.body.clone()),
+                status: ActiveValue::Set(status.as_str().to_string()),
+                attempts: ActiveValue::Set(0),
+                last_error: ActiveValue::Set(None),
+                created_at: ActiveValue::Set(now),
+                delivered_at: ActiveValue::Set(delivered_at),
+                read_at: ActiveValue::Set(None),
+                next_attempt_at: ActiveValue::Set(None),
+            })
+            .on_conflict(
+                OnConflict::columns([
+                    notification::Column::UserId,
+                    notification::Column::Channel,
+                    notification::Column::DedupeKey,
+                ])
+                .do_nothing()
+                .to_owned(),
+            )
+            .exec_without_returning(db)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Pending entries that are due for a (next) delivery attempt.
+    pub async fn pending(db: &DbConn) -> Result<Vec<Self>, DbErr> {
+        NotificationEntity::find()
+            .filter(notification::Column::Status.eq(NotificationStatus::Pending.as_str()))
+            .filter(
+                notification::Column::NextAttemptAt
+                    .is_null()
+                    .or(notification::Column::NextAttemptAt.lte(Utc::now())),
+            )
+            .order_by_asc(notification::Column::CreatedAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(Self::from_model)
+            .collect()
+    }
+
+    pub async fn mark_delivered(db: &DbConn, id: Uuid) -> Result<(), DbErr> {
+        NotificationEntity::update(notification::ActiveModel {
+            id: ActiveValue::Set(id),
+            user_id: ActiveValue::NotSet,
+            rule_id: ActiveValue::NotSet,
+            channel: ActiveValue::NotSet,
+            dedupe_key: ActiveValue::NotSet,
+            title: ActiveValue::NotSet,
+            body: ActiveValue::NotSet,
+            status: ActiveValue::Set(NotificationStatus::Delivered.as_str().to_string()),
+            attempts: ActiveValue::NotSet,
+            last_error: ActiveValue::Set(None),
+            created_at: ActiveValue::NotSet,
+            delivered_at: ActiveValue::Set(Some(Utc::now())),
+            read_at: ActiveValue::NotSet,
+            next_attempt_at: ActiveValue::NotSet,
+        })
+        .exec(db)
+        .await?;
+        Ok(())
+    }
+
+    /// Gives up on an entry whose channel cannot deliver anything.
+    pub async fn mark_skipped(db: &DbConn, id: Uuid, reason: &str) -> Result<(), DbErr> {
+        NotificationEntity::update(notification::ActiveModel {
+            id: ActiveValue::Set(id),
+            user_id: ActiveValue::NotSet,
+            rule_id: ActiveValue::NotSet,
+            channel: ActiveValue::NotSet,
+            dedupe_key: ActiveValue::NotSet,
+            title: ActiveValue::NotSet,
+            body: ActiveValue::NotSet,
+            status: ActiveValue::Set(NotificationStatus::Skipped.as_str().to_string()),
+            attempts: ActiveValue::NotSet,
+            last_error: ActiveValue::Set(Some(reason.to_string())),
+            created_at: ActiveValue::NotSet,
+            delivered_at: ActiveValue::NotSet,
+            read_at: ActiveValue::NotSet,
+            next_attempt_at: ActiveValue::NotSet,
+        })
+        .exec(db)
+        .await?;
+        Ok(())
+    }
+
+    /// Records a failed attempt. The entry stays pending until it has failed
+    /// `MAX_ATTEMPTS` times.
+    pub async fn mark_attempt_failed(&self, db: &DbConn, error: &str) -> Result<(), DbErr> {
+        let attempts = self.attempts + 1;
+        let status = if attempts >= MAX_ATTEMPTS {
+            NotificationStatus::Failed
+        } else {
+            NotificationStatus::Pending
+        };
+        NotificationEntity::update(notification::ActiveModel {
+            id: ActiveValue::Set(self.id),
+            user_id: ActiveValue::NotSet,
+            rule_id: ActiveValue::NotSet,
+            channel: ActiveValue::NotSet,
+            dedupe_key: ActiveValue::NotSet,
+            title: ActiveValue::NotSet,
+            body: ActiveValue::NotSet,
+            status: ActiveValue::Set(status.as_str().to_string()),
+            attempts: ActiveValue::Set(attempts),
+            last_error: ActiveValue::Set(Some(error.to_string())),
+            created_at: ActiveValue::NotSet,
+            delivered_at: ActiveValue::NotSet,
+            read_at: ActiveValue::NotSet,
+            next_attempt_at: ActiveValue::Set(Some(Utc::now() + retry_delay(attempts))),
+        })
+        .exec(db)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,257 @@
+use chrono::{DateTime, Duration, Utc};
+use migration::OnConflict;
+use sea_orm::{
+    ActiveValue, ColumnTrait, DbConn, DbErr, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::user_entity::{
+    auth_lockout,
+    prelude::{AuthLockout as AuthLockoutEntity, RateLimitEvent as RateLimitEventEntity},
+    rate_limit_event,
+};
+
+/// Consecutive failures before an account is locked.
+const LOCKOUT_THRESHOLD: i32 = 5;
+/// Lockouts double from one minute up to a day.
+const MAX_LOCKOUT_MINUTES: i64 = 24 * 60;
+/// Longest window of any [`RateLimit`], after which hits are pruned.
+const MAX_WINDOW_SECONDS: i64 = 24 * 60 * 60;
+
+/// At most `max` hits per sliding window of `window_seconds`.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub max: u64,
+    pub window_seconds: i64,
+}
+
+impl RateLimit {
+    pub const fn new(max: u64, window_seconds: i64) -> Self {
+        Self {
+            max,
+            window_seconds,
+        }
+    }
+
+    /// Records a hit for `key`. Returns the seconds until the next hit is
+    /// allowed when the limit is reached, in which case nothing is recorded.
+    pub async fn hit(self, db: &DbConn, key: &str) -> Result<Option<i64>, DbErr> {
+        let key = key.to_string();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move {
+                let now = Utc::now();
+                let window = Duration::seconds(self.window_seconds);
+                // Deleting first takes the write lock, so concurrent hits for
+                // the key cannot both pass the count below.
+                RateLimitEventEntity::delete_many()
+                    .filter(rate_limit_event::Column::Key.eq(&key))
+                    .filter(rate_limit_event::Column::CreatedAt.lte(now - window))
+                    .exec(txn)
+                    .await?;
+                let query =
+                    RateLimitEventEntity::find().filter(rate_limit_event::Column::Key.eq(&key));
+                if query.clone().count(txn).await? >= self.max {
+                    let oldest = query
+                        .order_by_asc(rate_limit_event::Column::CreatedAt)
+                        .one(txn)
+                        .await?
+                        .map_or(now, |e| e.created_at);
+                    return Ok(Some(retry_after(oldest + window, now)));
+                }
+                RateLimitEventEntity::insert(rate_limit_event::ActiveModel {
+                    id: ActiveValue::Set(Uuid::now_v7()),
+                    key: ActiveValue::Set(key),
+                    created_at: ActiveValue::Set(now),
+                })
+                .exec_without_returning(txn)
+                .await?;
+                Ok(None)
+            })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
+    }
+
+    /// Removes the hits of every key that are older than any window, which
+    /// [`Self::hit`] only does for the key it is called with.
+    pub async fn prune(db: &DbConn) -> Result<(), DbErr> {
+        let since = Utc::now() - Duration::seconds(MAX_WINDOW_SECONDS);
+        RateLimitEventEntity::delete_many()
+            .filter(rate_limit_event::Column::CreatedAt.lte(since))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+}
+
+fn retry_after(until: DateTime<Utc>, now: DateTime<Utc>) -> i64 {
+    (until - now).num_seconds().max(1)
+}
+
+/// Failed password attempts for a key, locking it for exponentially longer
+/// once `LOCKOUT_THRESHOLD` is reached.
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct AuthLockout {
+    pub key: String,
+    pub failures: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl AuthLockout {
+    fn from_model(model: auth_lockout::Model) -> Self {
+        Self {
+            key: model.key,
+            failures: model.failures,
+            locked_until: model.locked_until,
+            updated_at: model.updated_at,
+        }
+    }
+
+    /// Returns the seconds the key is still locked for.
+    pub async fn check(db: &DbConn, key: &str) -> Result<Option<i64>, DbErr> {
+        let now = Utc::now();
+        Ok(AuthLockoutEntity::find_by_id(key)
+            .one(db)
+            .await?
+            .and_then(|m| m.locked_until)
+            .filter(|until| *until > now)
+            .map(|until| retry_after(until, now)))
+    }
+
+    pub async fn record_failure(db: &DbConn, key: &str) -> Result<(), DbErr> {
+        let now = Utc::now();
+        let failures = AuthLockoutEntity::find_by_id(key)
+            .one(db)
+            .await?
+            .map_or(0, |m| m.failures)
+            + 1;
+        let locked_until = (failures >= LOCKOUT_THRESHOLD).then(|| {
+            let doublings = (failures - LOCKOUT_THRESHOLD).min(20) as u32;
+            let minutes = (1i64 << doublings).min(MAX_LOCKOUT_MINUTES);
+            now + Duration::minutes(minutes)
+        });
+        AuthLockoutEntity::insert(auth_lockout::ActiveModel {
+            key: ActiveValue::Set(key.to_string()),
+            failures: ActiveValue::Set(failures),
+            locked_until: ActiveValue::Set(locked_until),
+            updated_at: ActiveValue::Set(now),
+        })
+        .on_conflict(
+            OnConflict::column(auth_lockout::Column::Key)
+                .update_columns([
+                    auth_lockout::Column::Failures,
+                    auth_lockout::Column::LockedUntil,
+                    auth_lockout::Column::UpdatedAt,
+                ])
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn clear(db: &DbConn, key: &str) -> Result<(), DbErr> {
+        AuthLockoutEntity::delete_by_id(key).exec(db).await?;
+        Ok(())
+    }
+
+    /// Keys that are locked right now.
+    pub async fn find_locked(db: &DbConn) -> Result<Vec<Self>, DbErr> {
+        Ok(AuthLockoutEntity::find()
+            .filter(auth_lockout::Column::LockedUntil.gt(Utc::now()))
+            .order_by_desc(auth_lockout::Column::UpdatedAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(Self::from_model)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use user_migration::MigratorTrait;
+
+    use super::*;
+
+    async fn db() -> DbConn {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        user_migration::Migrator::up(&db, None).await.unwrap();
+        db
+    }
+
+    async fn hit_at(db: &DbConn, key: &str, created_at: DateTime<Utc>) {
+        RateLimitEventEntity::insert(rate_limit_event::ActiveModel {
+            id: ActiveValue::Set(Uuid::now_v7()),
+            key: ActiveValue::Set(key.to_string()),
+            created_at: ActiveValue::Set(created_at),
+        })
+        .exec_without_returning(db)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn hits_are_limited_per_window() {
+        let db = db().await;
+        let limit = RateLimit::new(2, 60);
+        assert_eq!(limit.hit(&db, "ip:1").await.unwrap(), None);
+        assert_eq!(limit.hit(&db, "ip:1").await.unwrap(), None);
+        let retry = limit.hit(&db, "ip:1").await.unwrap().unwrap();
+        assert!((59..=60).contains(&retry), "{retry}");
+        // Other keys have their own window.
+        assert_eq!(limit.hit(&db, "ip:2").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn retry_after_counts_from_the_oldest_hit() {
+        let db = db().await;
+        let limit = RateLimit::new(1, 60);
+        hit_at(&db, "ip:1", Utc::now() - Duration::seconds(45)).await;
+        let retry = limit.hit(&db, "ip:1").await.unwrap().unwrap();
+        assert!((14..=15).contains(&retry), "{retry}");
+
+        // Hits outside the window no longer count.
+        hit_at(&db, "ip:2", Utc::now() - Duration::seconds(61)).await;
+        assert_eq!(limit.hit(&db, "ip:2").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn prune_removes_old_hits_of_every_key() {
+        let db = db().await;
+        hit_at(&db, "ip:1", Utc::now() - Duration::days(2)).await;
+        hit_at(&db, "ip:2", Utc::now()).await;
+        RateLimit::prune(&db).await.unwrap();
+        let left = RateLimitEventEntity::find().all(&db).await.unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].key, "ip:2");
+    }
+
+    #[tokio::test]
+    async fn lockouts_double_after_the_threshold() {
+        let db = db().await;
+        for _ in 1..LOCKOUT_THRESHOLD {
+            AuthLockout::record_failure(&db, "email:ada").await.unwrap();
+        }
+        assert_eq!(AuthLockout::check(&db, "email:ada").await.unwrap(), None);
+
+        let mut expected = 60;
+        for _ in 0..3 {
+            AuthLockout::record_failure(&db, "email:ada").await.unwrap();
+            let locked = AuthLockout::check(&db, "email:ada").await.unwrap().unwrap();
+            assert!((expected - 1..=expected).contains(&locked), "{locked}");
+            expected *= 2;
+        }
+
+        AuthLockout::clear(&db, "email:ada").await.unwrap();
+        assert_eq!(AuthLockout::check(&db, "email:ada").await.unwrap(), None);
+    }
+}
@@ -0,0 +1,198 @@
+use chrono::{DateTime, Utc};
+use migration::OnConflict;
+use sea_orm::{
+    ActiveValue, ColumnTrait, DbConn, DbErr, EntityTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+use super::ModelError;
+use crate::{
+    pivot::PivotQuery,
+    user_entity::{prelude::ReportDefinition as ReportDefinitionEntity, report_definition},
+};
+
+/// A saved [`PivotQuery`] of a user. It can be run on any ledger the user
+/// can read.
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct ReportDefinition {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub query: PivotQuery,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, ToSchema, Deserialize, Validate)]
+pub struct ReportDefinitionReq {
+    pub id: Option<Uuid>,
+    #[validate(length(min = 1, max = 100))]
+    pub name: String,
+    #[validate(nested)]
+    pub query: PivotQuery,
+}
+
+impl ReportDefinition {
+    fn from_model(model: report_definition::Model) -> Result<Self, DbErr> {
+        Ok(Self {
+            id: model.id,
+            user_id: model.user_id,
+            name: model.name,
+            query: serde_json::from_value(model.query).map_err(|e| DbErr::Json(e.to_string()))?,
+            created_at: model.created_at,
+            updated_at: model.updated_at,
+        })
+    }
+
+    pub async fn find_for_user(db: &DbConn, user_id: Uuid) -> Result<Vec<Self>, DbErr> {
+        ReportDefinitionEntity::find()
+            .filter(report_definition::Column::UserId.eq(user_id))
+            .order_by_asc(report_definition::Column::Name)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(Self::from_model)
+            .collect()
+    }
+
+    pub async fn find(db: &DbConn, user_id: Uuid, id: Uuid) -> Result<Option<Self>, DbErr> {
+        ReportDefinitionEntity::find_by_id(id)
+            .filter(report_definition::Column::UserId.eq(user_id))
+            .one(db)
+            .await?
+            .map(Self::from_model)
+            .transpose()
+    }
+
+    /// Saves the definition. An `id` of another user's definition is not
+    /// found, so it cannot be overwritten.
+    pub async fn upsert(
+        db: &DbConn,
+        user_id: Uuid,
+        definition: ReportDefinitionReq,
+    ) -> Result<Uuid, ModelError> {
+        let id = definition.id.unwrap_or_else(Uuid::now_v7);
+        let query =
+            serde_json::to_value(definition.query).map_err(|e| DbErr::Json(e.to_string()))?;
+        db.transaction::<_, _, ModelError>(|txn| {
+            Box::pin(async move {
+                let existing = ReportDefinitionEntity::find_by_id(id).one(txn).await?;
+                if existing.is_some_and(|d| d.user_id != user_id) {
+                    return Err(ModelError::NotFound(format!(
+                        "Report definition {id} not found"
+                    )));
+                }
+                let now = Utc::now();
+                ReportDefinitionEntity::insert(report_definition::ActiveModel {
+                    id: ActiveValue::Set(id),
+                    user_id: ActiveValue::Set(user_id),
+                    name: ActiveValue::Set(definition.name),
+                    query: ActiveValue::Set(query),
+                    created_at: ActiveValue::Set(now),
+                    updated_at: ActiveValue::Set(now),
+                })
+                .on_conflict(
+                    OnConflict::column(report_definition::Column::Id)
+                        .update_columns([
+                            report_definition::Column::Name,
+                            report_definition::Column::Query,
+                            report_definition::Column::UpdatedAt,
+                        ])
+                        .to_owned(),
+                )
+                .exec_without_returning(txn)
+                .await?;
+                Ok(())
+            })
+        })
+        .await
+        .map_err(ModelError::from)?;
+        Ok(id)
+    }
+
+    pub async fn delete(db: &DbConn, user_id: Uuid, id: Uuid) -> Result<(), ModelError> {
+        let deleted = ReportDefinitionEntity::delete_many()
+            .filter(report_definition::Column::UserId.eq(user_id))
+            .filter(report_definition::Column::Id.eq(id))
+            .exec(db)
+            .await?;
+        if deleted.rows_affected == 0 {
+            return Err(ModelError::NotFound(format!(
+                "Report definition {id} not found"
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use user_migration::MigratorTrait;
+
+    use super::*;
+    use crate::{
+        model::user::User,
+        pivot::{Dimension, Measure, PivotFilter},
+    };
+
+    fn definition(id: Option<Uuid>, name: &str) -> ReportDefinitionReq {
+        ReportDefinitionReq {
+            id,
+            name: name.to_string(),
+            query: PivotQuery {
+                dimensions: vec![Dimension::Category],
+                measures: vec![Measure::Sum],
+                filter: PivotFilter::default(),
+                convert_to: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn definitions_of_other_users_are_not_found() {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        user_migration::Migrator::up(&db, None).await.unwrap();
+        let alice = User::create_user(&db, "Alice", "alice@example.com", "")
+            .await
+            .unwrap();
+        let bob = User::create_user(&db, "Bob", "bob@example.com", "")
+            .await
+            .unwrap();
+
+        let id = ReportDefinition::upsert(&db, alice.id, definition(None, "Spending"))
+            .await
+            .unwrap();
+        let renamed = ReportDefinition::upsert(&db, alice.id, definition(Some(id), "Food"))
+            .await
+            .unwrap();
+        assert_eq!(renamed, id);
+
+        let taken = ReportDefinition::upsert(&db, bob.id, definition(Some(id), "Mine")).await;
+        assert!(matches!(taken, Err(ModelError::NotFound(_))));
+        assert!(
+            ReportDefinition::find(&db, bob.id, id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let deleted = ReportDefinition::delete(&db, bob.id, id).await;
+        assert!(matches!(deleted, Err(ModelError::NotFound(_))));
+
+        let kept = ReportDefinition::find(&db, alice.id, id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(kept.name, "Food");
+        ReportDefinition::delete(&db, alice.id, id).await.unwrap();
+        assert!(
+            ReportDefinition::find(&db, alice.id, id)
+                .await
+                .unwrap()
+                .is_none()
+        );
+    }
+}
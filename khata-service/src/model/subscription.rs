@@ -0,0 +1,213 @@
+use chrono::{DateTime, Utc};
+use migration::OnConflict;
+use sea_orm::{
+    ActiveValue, ColumnTrait, ConnectionTrait, DbConn, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+use super::audit::{AuditContext, AuditEntityType, AuditEventModel};
+use crate::entity::subscription;
+
+pub type SubscriptionEntity = subscription::Entity;
+pub type SubscriptionColumn = subscription::Column;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    Confirmed,
+    Dismissed,
+}
+
+impl SubscriptionStatus {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Confirmed => "confirmed",
+            Self::Dismissed => "dismissed",
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self, DbErr> {
+        match value {
+            "confirmed" => Ok(Self::Confirmed),
+            "dismissed" => Ok(Self::Dismissed),
+            _ => Err(DbErr::Custom(format!(
+                "subscription: unknown status {value}"
+            ))),
+        }
+    }
+}
+
+/// What the user decided about a detected subscription. Detections are
+/// matched by payee and currency, so a decision outlives new charges.
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct SubscriptionDecision {
+    pub id: Uuid,
+    pub payee: String,
+    pub currency_code: String,
+    pub status: SubscriptionStatus,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, ToSchema, Deserialize, Validate)]
+pub struct SubscriptionReq {
+    #[validate(length(min = 1, max = 255))]
+    pub payee: String,
+    #[validate(length(min = 1, max = 10))]
+    pub currency_code: String,
+    pub status: SubscriptionStatus,
+}
+
+/// Payees are transaction titles, compared without case or surrounding
+/// whitespace.
+pub fn normalize_payee(title: &str) -> String {
+    title.trim().to_lowercase()
+}
+
+impl SubscriptionReq {
+    pub async fn find_all(db: &DbConn) -> Result<Vec<SubscriptionDecision>, DbErr> {
+        SubscriptionEntity::find()
+            .order_by_asc(SubscriptionColumn::Payee)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(|model| {
+                Ok(SubscriptionDecision {
+                    id: model.id,
+                    payee: model.payee,
+                    currency_code: model.currency_code,
+                    status: SubscriptionStatus::parse(&model.status)?,
+                    updated_at: model.updated_at,
+                })
+            })
+            .collect()
+    }
+
+    /// Records the decision, replacing an earlier one for the same payee and
+    /// currency.
+    pub async fn upsert(db: &DbConn, ctx: &AuditContext, req: Self) -> Result<(), DbErr> {
+        let ctx = ctx.clone();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move {
+                let payee = normalize_payee(&req.payee);
+                // The row of an earlier decision is updated in place.
+                let id = SubscriptionEntity::find()
+                    .filter(SubscriptionColumn::Payee.eq(&payee))
+                    .filter(SubscriptionColumn::CurrencyCode.eq(&req.currency_code))
+                    .one(txn)
+                    .await?
+                    .map_or_else(Uuid::now_v7, |model| model.id);
+                let before = AuditEventModel::snapshot::<SubscriptionEntity, _>(txn, id).await?;
+                SubscriptionEntity::insert(subscription::ActiveModel {
+                    id: ActiveValue::Set(id),
+                    payee: ActiveValue::Set(payee),
+                    currency_code: ActiveValue::Set(req.currency_code),
+                    status: ActiveValue::Set(req.status.as_str().to_string()),
+                    updated_at: ActiveValue::Set(Utc::now()),
+                })
+                .on_conflict(
+                    OnConflict::columns([
+                        SubscriptionColumn::Payee,
+                        SubscriptionColumn::CurrencyCode,
+                    ])
+                    .update_columns([SubscriptionColumn::Status, SubscriptionColumn::UpdatedAt])
+                    .to_owned(),
+                )
+                .exec_without_returning(txn)
+                .await?;
+                let after = AuditEventModel::snapshot::<SubscriptionEntity, _>(txn, id).await?;
+                AuditEventModel::record(txn, &ctx, AuditEntityType::Subscription, id, before, after)
+                    .await
+            })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
+    }
+
+    /// Forgets a decision, so the subscription shows up as detected again.
+    pub async fn delete(db: &DbConn, ctx: &AuditContext, id: Uuid) -> Result<(), DbErr> {
+        let ctx = ctx.clone();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move { Self::delete_in(txn, &ctx, id).await })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
+    }
+
+    pub(crate) async fn delete_in<C: ConnectionTrait>(
+        db: &C,
+        ctx: &AuditContext,
+        id: Uuid,
+    ) -> Result<(), DbErr> {
+        let before = AuditEventModel::snapshot::<SubscriptionEntity, _>(db, id).await?;
+        SubscriptionEntity::delete_by_id(id).exec(db).await?;
+        AuditEventModel::record(db, ctx, AuditEntityType::Subscription, id, before, None).await
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::{model::audit::AuditEventModel, test_ledger};
+
+    #[tokio::test]
+    async fn decisions_are_audited_and_can_be_reverted() {
+        let db = test_ledger::ledger().await;
+        let ctx = AuditContext {
+            user_id: Uuid::now_v7(),
+            request_id: None,
+        };
+        let decision = |status| SubscriptionReq {
+            payee: " Streamflix".to_string(),
+            currency_code: "INR".to_string(),
+            status,
+        };
+        SubscriptionReq::upsert(&db, &ctx, decision(SubscriptionStatus::Confirmed))
+            .await
+            .unwrap();
+        SubscriptionReq::upsert(&db, &ctx, decision(SubscriptionStatus::Dismissed))
+            .await
+            .unwrap();
+        let [saved] = SubscriptionReq::find_all(&db)
+            .await
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(saved.payee, "streamflix");
+        assert_eq!(saved.status, SubscriptionStatus::Dismissed);
+        SubscriptionReq::delete(&db, &ctx, saved.id).await.unwrap();
+
+        let history =
+            AuditEventModel::history(&db, AuditEntityType::Subscription, &saved.id.to_string())
+                .await
+                .unwrap();
+        let actions = history
+            .iter()
+            .map(|e| e.0.action.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(actions, ["create", "update", "delete"]);
+        assert!(history.iter().all(|e| e.0.user_id == ctx.user_id));
+
+        // Reverting to the state after the update brings the decision back.
+        AuditEventModel::revert(&db, &ctx, history[1].0.id)
+            .await
+            .unwrap();
+        let [restored] = SubscriptionReq::find_all(&db)
+            .await
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(restored.id, saved.id);
+        assert_eq!(restored.status, SubscriptionStatus::Dismissed);
+    }
+}
@@ -1,6 +1,9 @@
+use std::collections::HashMap;
+
 use migration::OnConflict;
 use sea_orm::{
-    ActiveValue, ColumnTrait, DbConn, DbErr, EntityTrait, QueryFilter, QueryOrder, TransactionTrait,
+    ActiveValue, ColumnTrait, ConnectionTrait, DbConn, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
@@ -9,7 +12,9 @@ use validator::Validate;
 
 use super::{
     account::{AccountColumn, AccountEntity},
+    audit::{AuditContext, AuditEntityType, AuditEventModel},
     category::{CategoryActiveModel, CategoryColumn, CategoryEntity},
+    import_batch::ImportBatchReq,
 };
 use crate::entity::{transaction, transaction_item};
 
@@ -76,6 +81,13 @@ impl TransactionReq {
     pub async fn find_one_with_items(
         db: &DbConn,
         id: Uuid,
+    ) -> Result<Option<TransactionExpandedModel>, DbErr> {
+        Self::find_one_with_items_in(db, id).await
+    }
+
+    pub(crate) async fn find_one_with_items_in<C: ConnectionTrait>(
+        db: &C,
+        id: Uuid,
     ) -> Result<Option<TransactionExpandedModel>, DbErr> {
         TransactionEntity::find_by_id(id)
             .find_with_related(TransactionItemEntity::default())
@@ -119,120 +131,44 @@ impl TransactionReq {
             .unwrap_or_default())
     }
 
-    pub async fn upsert(db: &DbConn, tx: Self) -> Result<Uuid, DbErr> {
+    pub async fn upsert(db: &DbConn, ctx: &AuditContext, tx: Self) -> Result<Uuid, DbErr> {
+        let ctx = ctx.clone();
         let id = db
             .transaction::<_, _, DbErr>(|txn| {
-                Box::pin(async move {
-                    let tx_id = tx.id.unwrap_or_else(|| {
-                        Uuid::new_v7(uuid::Timestamp::from_unix(
-                            uuid::timestamp::context::NoContext,
-                            tx.timestamp.timestamp() as u64,
-                            0,
-                        ))
-                    });
-                    TransactionEntity::insert(TransactionActiveModel {
-                        id: ActiveValue::Set(tx_id),
-                        title: ActiveValue::Set(tx.title.trim().to_owned()),
-                        timestamp: ActiveValue::Set(tx.timestamp),
-                    })
-                    .on_conflict(
-                        OnConflict::column(TransactionColumn::Id)
-                            .update_columns([
-                                TransactionColumn::Title,
-                                TransactionColumn::Timestamp,
-                            ])
-                            .to_owned(),
-                    )
-                    .exec(txn)
-                    .await?;
-
-                    let old_items = TransactionItemEntity::find()
-                        .filter(transaction_item::Column::TransactionId.eq(tx_id))
-                        .all(txn)
-                        .await?;
-
-                    for item in old_items {
-                        TransactionItemEntity::delete_by_id(item.id)
-                            .exec(txn)
-                            .await?;
-                    }
+                Box::pin(async move { Ok(Self::upsert_in(txn, &ctx, tx, None).await?.0) })
+            })
+            .await
+            .map_err(|e| match e {
+                sea_orm::TransactionError::Connection(e)
+                | sea_orm::TransactionError::Transaction(e) => e,
+            })?;
 
-                    for item in &tx.items {
-                        let account = AccountEntity::find()
-                            .filter(AccountColumn::Name.eq(item.account_name.to_string()))
-                            .one(txn)
-                            .await?
-                            .ok_or_else(|| {
-                                DbErr::RecordNotFound(format!(
-                                    "account_name: {:?}",
-                                    item.account_name
-                                ))
-                            })?;
-                        let cat_id = if let Some(cat) = &item.category_name {
-                            let found = CategoryEntity::find()
-                                .filter(CategoryColumn::Name.eq(item.category_name.clone()))
-                                .one(txn)
-                                .await?;
-                            if let Some(found) = found {
-                                Some(found.id)
-                            } else {
-                                Some(
-                                    CategoryEntity::insert(CategoryActiveModel {
-                                        id: ActiveValue::Set(Uuid::new_v7(
-                                            uuid::Timestamp::from_unix(
-                                                uuid::timestamp::context::NoContext,
-                                                tx.timestamp.timestamp() as u64,
-                                                0,
-                                            ),
-                                        )),
-                                        name: ActiveValue::Set(cat.clone()),
-                                        group: ActiveValue::Set(String::new()),
-                                        icon: ActiveValue::Set(String::new()),
-                                    })
-                                    .on_conflict(
-                                        OnConflict::column(CategoryColumn::Name)
-                                            .do_nothing()
-                                            .to_owned(),
-                                    )
-                                    .exec(txn)
-                                    .await?
-                                    .last_insert_id,
-                                )
-                            }
-                        } else {
-                            None
-                        };
+        Ok(id)
+    }
 
-                        TransactionItemEntity::insert(TransactionItemActiveModel {
-                            id: ActiveValue::Set(item.id.unwrap_or_else(|| {
-                                Uuid::new_v7(uuid::Timestamp::from_unix(
-                                    uuid::timestamp::context::NoContext,
-                                    tx.timestamp.timestamp() as u64,
-                                    0,
-                                ))
-                            })),
-                            notes: ActiveValue::Set(item.notes.trim().to_owned()),
-                            transaction_id: ActiveValue::Set(tx_id),
-                            account_id: ActiveValue::Set(account.id),
-                            category_id: ActiveValue::Set(cat_id),
-                            amount: ActiveValue::Set(item.amount),
-                        })
-                        .on_conflict(
-                            OnConflict::column(TransactionItemColumn::Id)
-                                .update_columns([
-                                    TransactionItemColumn::Notes,
-                                    TransactionItemColumn::TransactionId,
-                                    TransactionItemColumn::AccountId,
-                                    TransactionItemColumn::CategoryId,
-                                    TransactionItemColumn::Amount,
-                                ])
-                                .to_owned(),
-                        )
-                        .exec(txn)
-                        .await?;
+    /// Imports the transactions as one batch and returns the batch id. Every
+    /// transaction the import creates or overwrites is tagged with the batch,
+    /// and the prior state of the overwritten ones is kept so that the batch
+    /// can be rolled back.
+    pub async fn upsert_many(
+        db: &DbConn,
+        ctx: &AuditContext,
+        batch: ImportBatchReq,
+        transactions: Vec<Self>,
+    ) -> Result<Uuid, DbErr> {
+        let ctx = ctx.clone();
+        let batch_id = db
+            .transaction::<_, _, DbErr>(|txn| {
+                Box::pin(async move {
+                    let batch_id = batch.create_in(txn, transactions.len()).await?;
+                    for tx in transactions {
+                        let (tx_id, before) =
+                            Self::upsert_in(txn, &ctx, tx, Some(batch_id)).await?;
+                        let after = Self::find_one_with_items_in(txn, tx_id).await?;
+                        ImportBatchReq::add_entry_in(txn, batch_id, tx_id, before, after).await?;
                     }
 
-                    Ok(tx_id)
+                    Ok(batch_id)
                 })
             })
             .await
@@ -241,144 +177,291 @@ impl TransactionReq {
                 | sea_orm::TransactionError::Transaction(e) => e,
             })?;
 
-        Ok(id)
+        Ok(batch_id)
     }
 
-    pub async fn upsert_many(db: &DbConn, transactions: Vec<Self>) -> Result<(), DbErr> {
-        db.transaction::<_, _, DbErr>(|txn| {
-            Box::pin(async move {
-                for tx in transactions {
-                    let tx_id = tx.id.unwrap_or_else(|| {
-                        Uuid::new_v7(uuid::Timestamp::from_unix(
+    /// Upserts the transaction and returns its id along with the state it had
+    /// before, if it already existed.
+    async fn upsert_in<C: ConnectionTrait>(
+        txn: &C,
+        ctx: &AuditContext,
+        tx: Self,
+        import_batch_id: Option<Uuid>,
+    ) -> Result<(Uuid, Option<TransactionExpandedModel>), DbErr> {
+        let tx_id = tx.id.unwrap_or_else(|| {
+            Uuid::new_v7(uuid::Timestamp::from_unix(
+                uuid::timestamp::context::NoContext,
+                tx.timestamp.timestamp() as u64,
+                0,
+            ))
+        });
+        let previous = Self::find_one_with_items_in(txn, tx_id).await?;
+        let before = AuditEventModel::snapshot::<TransactionEntity, _>(txn, tx_id).await?;
+        TransactionEntity::insert(TransactionActiveModel {
+            id: ActiveValue::Set(tx_id),
+            title: ActiveValue::Set(tx.title.trim().to_owned()),
+            timestamp: ActiveValue::Set(tx.timestamp),
+            import_batch_id: import_batch_id.map_or(ActiveValue::NotSet, |batch_id| {
+                ActiveValue::Set(Some(batch_id))
+            }),
+        })
+        .on_conflict(
+            OnConflict::column(TransactionColumn::Id)
+                .update_columns(
+                    [TransactionColumn::Title, TransactionColumn::Timestamp]
+                        .into_iter()
+                        .chain(import_batch_id.map(|_| TransactionColumn::ImportBatchId)),
+                )
+                .to_owned(),
+        )
+        .exec(txn)
+        .await?;
+        let after = AuditEventModel::snapshot::<TransactionEntity, _>(txn, tx_id).await?;
+        AuditEventModel::record(txn, ctx, AuditEntityType::Transaction, tx_id, before, after)
+            .await?;
+
+        let mut items = Vec::with_capacity(tx.items.len());
+        for item in &tx.items {
+            let account = AccountEntity::find()
+                .filter(AccountColumn::Name.eq(item.account_name.clone()))
+                .one(txn)
+                .await?
+                .ok_or_else(|| {
+                    DbErr::RecordNotFound(format!("account_name: {:?}", item.account_name))
+                })?;
+            let cat_id = if let Some(cat) = &item.category_name {
+                let found = CategoryEntity::find()
+                    .filter(CategoryColumn::Name.eq(item.category_name.clone()))
+                    .one(txn)
+                    .await?;
+                if let Some(found) = found {
+                    Some(found.id)
+                } else if cat.is_empty() && import_batch_id.is_some() {
+                    // Imports leave items without a category name
+                    // uncategorized.
+                    None
+                } else {
+                    let cat_id = CategoryEntity::insert(CategoryActiveModel {
+                        id: ActiveValue::Set(Uuid::new_v7(uuid::Timestamp::from_unix(
                             uuid::timestamp::context::NoContext,
                             tx.timestamp.timestamp() as u64,
                             0,
-                        ))
-                    });
-                    TransactionEntity::insert(TransactionActiveModel {
-                        id: ActiveValue::Set(tx_id),
-                        title: ActiveValue::Set(tx.title.trim().to_owned()),
-                        timestamp: ActiveValue::Set(tx.timestamp),
+                        ))),
+                        name: ActiveValue::Set(cat.clone()),
+                        group: ActiveValue::Set(String::new()),
+                        icon: ActiveValue::Set(String::new()),
                     })
                     .on_conflict(
-                        OnConflict::column(TransactionColumn::Id)
-                            .update_columns([
-                                TransactionColumn::Title,
-                                TransactionColumn::Timestamp,
-                            ])
+                        OnConflict::column(CategoryColumn::Name)
+                            .do_nothing()
                             .to_owned(),
                     )
                     .exec(txn)
+                    .await?
+                    .last_insert_id;
+                    let after = AuditEventModel::snapshot::<CategoryEntity, _>(txn, cat_id).await?;
+                    AuditEventModel::record(
+                        txn,
+                        ctx,
+                        AuditEntityType::Category,
+                        cat_id,
+                        None,
+                        after,
+                    )
                     .await?;
+                    Some(cat_id)
+                }
+            } else {
+                None
+            };
 
-                    let old_items = TransactionItemEntity::find()
-                        .filter(transaction_item::Column::TransactionId.eq(tx_id))
-                        .all(txn)
-                        .await?;
+            items.push(transaction_item::Model {
+                id: item.id.unwrap_or_else(|| {
+                    Uuid::new_v7(uuid::Timestamp::from_unix(
+                        uuid::timestamp::context::NoContext,
+                        tx.timestamp.timestamp() as u64,
+                        0,
+                    ))
+                }),
+                notes: item.notes.trim().to_owned(),
+                transaction_id: tx_id,
+                account_id: account.id,
+                category_id: cat_id,
+                amount: item.amount,
+            });
+        }
+        Self::replace_items_in(txn, ctx, tx_id, items).await?;
 
-                    for item in old_items {
-                        TransactionItemEntity::delete_by_id(item.id)
-                            .exec(txn)
-                            .await?;
-                    }
+        Ok((tx_id, previous))
+    }
 
-                    for item in &tx.items {
-                        let account = AccountEntity::find()
-                            .filter(AccountColumn::Name.eq(item.account_name.to_string()))
-                            .one(txn)
-                            .await?
-                            .ok_or_else(|| {
-                                DbErr::RecordNotFound(format!(
-                                    "account_name: {:?}",
-                                    item.account_name
-                                ))
-                            })?;
-                        let cat_id = if let Some(cat) = &item.category_name {
-                            let found = CategoryEntity::find()
-                                .filter(CategoryColumn::Name.eq(item.category_name.clone()))
-                                .one(txn)
-                                .await?;
-                            if let Some(found) = found {
-                                Some(found.id)
-                            } else if cat.is_empty() {
-                                None
-                            } else {
-                                Some(
-                                    CategoryEntity::insert(CategoryActiveModel {
-                                        id: ActiveValue::Set(Uuid::new_v7(
-                                            uuid::Timestamp::from_unix(
-                                                uuid::timestamp::context::NoContext,
-                                                tx.timestamp.timestamp() as u64,
-                                                0,
-                                            ),
-                                        )),
-                                        name: ActiveValue::Set(cat.clone()),
-                                        group: ActiveValue::Set(String::new()),
-                                        icon: ActiveValue::Set(String::new()),
-                                    })
-                                    .on_conflict(
-                                        OnConflict::column(CategoryColumn::Name)
-                                            .do_nothing()
-                                            .to_owned(),
-                                    )
-                                    .exec(txn)
-                                    .await?
-                                    .last_insert_id,
-                                )
-                            }
-                        } else {
-                            None
-                        };
+    /// Writes a transaction and its items exactly as given, e.g. to put back a
+    /// previously captured state.
+    pub(crate) async fn write_in<C: ConnectionTrait>(
+        txn: &C,
+        ctx: &AuditContext,
+        state: TransactionExpandedModel,
+    ) -> Result<(), DbErr> {
+        let tx = state.transaction.0;
+        let tx_id = tx.id;
+        let before = AuditEventModel::snapshot::<TransactionEntity, _>(txn, tx_id).await?;
+        TransactionEntity::insert(TransactionActiveModel {
+            id: ActiveValue::Set(tx_id),
+            title: ActiveValue::Set(tx.title),
+            timestamp: ActiveValue::Set(tx.timestamp),
+            import_batch_id: ActiveValue::Set(tx.import_batch_id),
+        })
+        .on_conflict(
+            OnConflict::column(TransactionColumn::Id)
+                .update_columns([
+                    TransactionColumn::Title,
+                    TransactionColumn::Timestamp,
+                    TransactionColumn::ImportBatchId,
+                ])
+                .to_owned(),
+        )
+        .exec(txn)
+        .await?;
+        let after = AuditEventModel::snapshot::<TransactionEntity, _>(txn, tx_id).await?;
+        AuditEventModel::record(txn, ctx, AuditEntityType::Transaction, tx_id, before, after)
+            .await?;
+        let items = state.items.into_iter().map(|item| item.0).collect();
+        Self::replace_items_in(txn, ctx, tx_id, items).await
+    }
 
-                        TransactionItemEntity::insert(TransactionItemActiveModel {
-                            id: ActiveValue::Set(item.id.unwrap_or_else(|| {
-                                Uuid::new_v7(uuid::Timestamp::from_unix(
-                                    uuid::timestamp::context::NoContext,
-                                    tx.timestamp.timestamp() as u64,
-                                    0,
-                                ))
-                            })),
-                            notes: ActiveValue::Set(item.notes.trim().to_owned()),
-                            transaction_id: ActiveValue::Set(tx_id),
-                            account_id: ActiveValue::Set(account.id),
-                            category_id: ActiveValue::Set(cat_id),
-                            amount: ActiveValue::Set(item.amount),
-                        })
-                        .on_conflict(
-                            OnConflict::column(TransactionItemColumn::Id)
-                                .update_columns([
-                                    TransactionItemColumn::Notes,
-                                    TransactionItemColumn::TransactionId,
-                                    TransactionItemColumn::AccountId,
-                                    TransactionItemColumn::CategoryId,
-                                    TransactionItemColumn::Amount,
-                                ])
-                                .to_owned(),
-                        )
-                        .exec(txn)
-                        .await?;
-                    }
-                }
+    /// Replaces all items of a transaction with the given ones.
+    async fn replace_items_in<C: ConnectionTrait>(
+        txn: &C,
+        ctx: &AuditContext,
+        tx_id: Uuid,
+        items: Vec<transaction_item::Model>,
+    ) -> Result<(), DbErr> {
+        let old_items = TransactionItemEntity::find()
+            .filter(transaction_item::Column::TransactionId.eq(tx_id))
+            .all(txn)
+            .await?;
+
+        let mut old_snapshots = HashMap::new();
+        for item in old_items {
+            TransactionItemEntity::delete_by_id(item.id)
+                .exec(txn)
+                .await?;
+            let item_id = item.id;
+            let item = serde_json::to_value(item).map_err(|e| DbErr::Json(e.to_string()))?;
+            old_snapshots.insert(item_id, item);
+        }
 
-                Ok(())
+        for item in items {
+            let item_id = item.id;
+            TransactionItemEntity::insert(TransactionItemActiveModel {
+                id: ActiveValue::Set(item.id),
+                notes: ActiveValue::Set(item.notes),
+                transaction_id: ActiveValue::Set(item.transaction_id),
+                account_id: ActiveValue::Set(item.account_id),
+                category_id: ActiveValue::Set(item.category_id),
+                amount: ActiveValue::Set(item.amount),
             })
+            .on_conflict(
+                OnConflict::column(TransactionItemColumn::Id)
+                    .update_columns([
+                        TransactionItemColumn::Notes,
+                        TransactionItemColumn::TransactionId,
+                        TransactionItemColumn::AccountId,
+                        TransactionItemColumn::CategoryId,
+                        TransactionItemColumn::Amount,
+                    ])
+                    .to_owned(),
+            )
+            .exec(txn)
+            .await?;
+            let before = old_snapshots.remove(&item_id);
+            let after = AuditEventModel::snapshot::<TransactionItemEntity, _>(txn, item_id).await?;
+            AuditEventModel::record(
+                txn,
+                ctx,
+                AuditEntityType::TransactionItem,
+                item_id,
+                before,
+                after,
+            )
+            .await?;
+        }
+
+        for (item_id, before) in old_snapshots {
+            AuditEventModel::record(
+                txn,
+                ctx,
+                AuditEntityType::TransactionItem,
+                item_id,
+                Some(before),
+                None,
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    pub async fn delete(db: &DbConn, ctx: &AuditContext, id: Uuid) -> Result<(), DbErr> {
+        let ctx = ctx.clone();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move { Self::delete_in(txn, &ctx, id).await })
         })
         .await
         .map_err(|e| match e {
             sea_orm::TransactionError::Connection(e)
             | sea_orm::TransactionError::Transaction(e) => e,
-        })?;
-
-        Ok(())
+        })
     }
 
-    pub async fn delete(db: &DbConn, id: Uuid) -> Result<(), DbErr> {
+    /// Deletes the transaction together with its items, which go away
+    /// through the cascading foreign key.
+    pub(crate) async fn delete_in<C: ConnectionTrait>(
+        db: &C,
+        ctx: &AuditContext,
+        id: Uuid,
+    ) -> Result<(), DbErr> {
+        let items = TransactionItemEntity::find()
+            .filter(TransactionItemColumn::TransactionId.eq(id))
+            .all(db)
+            .await?;
+        let before = AuditEventModel::snapshot::<TransactionEntity, _>(db, id).await?;
         TransactionEntity::delete_by_id(id).exec(db).await?;
-        Ok(())
+        for item in items {
+            let item_id = item.id;
+            let item = serde_json::to_value(item).map_err(|e| DbErr::Json(e.to_string()))?;
+            AuditEventModel::record(
+                db,
+                ctx,
+                AuditEntityType::TransactionItem,
+                item_id,
+                Some(item),
+                None,
+            )
+            .await?;
+        }
+        AuditEventModel::record(db, ctx, AuditEntityType::Transaction, id, before, None).await
+    }
+
+    pub async fn delete_item(db: &DbConn, ctx: &AuditContext, id: Uuid) -> Result<(), DbErr> {
+        let ctx = ctx.clone();
+        db.transaction::<_, _, DbErr>(|txn| {
+            Box::pin(async move { Self::delete_item_in(txn, &ctx, id).await })
+        })
+        .await
+        .map_err(|e| match e {
+            sea_orm::TransactionError::Connection(e)
+            | sea_orm::TransactionError::Transaction(e) => e,
+        })
     }
 
-    pub async fn delete_item(db: &DbConn, id: Uuid) -> Result<(), DbErr> {
+    pub(crate) async fn delete_item_in<C: ConnectionTrait>(
+        db: &C,
+        ctx: &AuditContext,
+        id: Uuid,
+    ) -> Result<(), DbErr> {
+        let before = AuditEventModel::snapshot::<TransactionItemEntity, _>(db, id).await?;
         TransactionItemEntity::delete_by_id(id).exec(db).await?;
-        Ok(())
+        AuditEventModel::record(db, ctx, AuditEntityType::TransactionItem, id, before, None).await
     }
 }
@@ -1,16 +1,19 @@
 use chrono::{DateTime, Utc};
-use sea_orm::{ActiveValue, ColumnTrait, DbConn, DbErr, EntityTrait, QueryFilter};
+use sea_orm::{ActiveValue, ColumnTrait, DbConn, DbErr, EntityTrait, QueryFilter, QueryOrder};
 use secrecy::{ExposeSecret, SecretString};
 use serde::Serialize;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
+use super::user_settings::UserSettings;
 use crate::user_entity::{
     self,
     prelude::User as UserEnitty,
     user::{ActiveModel, Model},
 };
 
+// The flags are independent of each other, so they do not fold into an enum.
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Clone, ToSchema, Serialize)]
 pub struct User {
     pub id: Uuid,
@@ -20,6 +23,20 @@ pub struct User {
     pub password_hash: SecretString,
     pub email_verified: bool,
     pub created_at: DateTime<Utc>,
+    /// TOTP secret encrypted with [`crate::keys::Keys::encrypt`]. It is set
+    /// during enrolment but only checked once `totp_enabled` is true.
+    #[serde(skip_serializing)]
+    pub totp_secret: Option<Vec<u8>>,
+    pub totp_enabled: bool,
+    /// SHA-256 hashes of the unused recovery codes.
+    #[serde(skip_serializing)]
+    pub recovery_codes: Vec<String>,
+    /// When the account will be deleted, if the user asked for it.
+    pub deletion_scheduled_at: Option<DateTime<Utc>>,
+    pub is_admin: bool,
+    /// Disabled users cannot sign in or use their tokens.
+    pub disabled: bool,
+    pub settings: UserSettings,
 }
 
 impl User {
@@ -31,6 +48,16 @@ impl User {
             password_hash: model.password_hash.into(),
             email_verified: model.email_verified,
             created_at: model.created_at,
+            totp_secret: model.totp_secret,
+            totp_enabled: model.totp_enabled,
+            recovery_codes: model
+                .recovery_codes
+                .and_then(|v| serde_json::from_value(v).ok())
+                .unwrap_or_default(),
+            deletion_scheduled_at: model.deletion_scheduled_at,
+            is_admin: model.is_admin,
+            disabled: model.disabled,
+            settings: UserSettings::from_json(model.settings),
         }
     }
 
@@ -49,6 +76,16 @@ impl User {
             .map(|r| r.map(Self::from_model))
     }
 
+    pub async fn find_all(db: &DbConn) -> Result<Vec<Self>, DbErr> {
+        Ok(UserEnitty::find()
+            .order_by_asc(user_entity::user::Column::CreatedAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(Self::from_model)
+            .collect())
+    }
+
     pub async fn create_user(
         db: &DbConn,
         name: &str,
@@ -62,6 +99,13 @@ impl User {
             password_hash: password_hash.into(),
             email_verified: false,
             created_at: Utc::now(),
+            totp_secret: None,
+            totp_enabled: false,
+            recovery_codes: vec![],
+            deletion_scheduled_at: None,
+            is_admin: false,
+            disabled: false,
+            settings: UserSettings::default(),
         };
         UserEnitty::insert(ActiveModel {
             id: ActiveValue::Set(model.id),
@@ -71,6 +115,13 @@ impl User {
             email_verified: ActiveValue::Set(model.email_verified),
             created_at: ActiveValue::Set(model.created_at),
             settings: ActiveValue::NotSet,
+            totp_secret: ActiveValue::NotSet,
+            totp_enabled: ActiveValue::NotSet,
+            recovery_codes: ActiveValue::NotSet,
+            totp_last_step: ActiveValue::NotSet,
+            deletion_scheduled_at: ActiveValue::NotSet,
+            is_admin: ActiveValue::NotSet,
+            disabled: ActiveValue::NotSet,
         })
         .exec(db)
         .await?;
@@ -90,9 +141,207 @@ impl User {
             email_verified: ActiveValue::Set(email_verified),
             created_at: ActiveValue::NotSet,
             settings: ActiveValue::NotSet,
+            totp_secret: ActiveValue::NotSet,
+            totp_enabled: ActiveValue::NotSet,
+            recovery_codes: ActiveValue::NotSet,
+            totp_last_step: ActiveValue::NotSet,
+            deletion_scheduled_at: ActiveValue::NotSet,
+            is_admin: ActiveValue::NotSet,
+            disabled: ActiveValue::NotSet,
+        })
+        .exec(db)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn update_password(
+        db: &DbConn,
+        id: Uuid,
+        password_hash: &str,
+    ) -> Result<Self, DbErr> {
+        let model = UserEnitty::update(ActiveModel {
+            id: ActiveValue::Set(id),
+            name: ActiveValue::NotSet,
+            email: ActiveValue::NotSet,
+            password_hash: ActiveValue::Set(password_hash.to_string()),
+            email_verified: ActiveValue::NotSet,
+            created_at: ActiveValue::NotSet,
+            settings: ActiveValue::NotSet,
+            totp_secret: ActiveValue::NotSet,
+            totp_enabled: ActiveValue::NotSet,
+            recovery_codes: ActiveValue::NotSet,
+            totp_last_step: ActiveValue::NotSet,
+            deletion_scheduled_at: ActiveValue::NotSet,
+            is_admin: ActiveValue::NotSet,
+            disabled: ActiveValue::NotSet,
+        })
+        .exec(db)
+        .await?;
+        Ok(Self::from_model(model))
+    }
+
+    /// Replaces the second factor of the user. Passing no secret turns it off.
+    pub async fn update_totp(
+        db: &DbConn,
+        id: Uuid,
+        totp_secret: Option<Vec<u8>>,
+        totp_enabled: bool,
+        recovery_codes: &[String],
+    ) -> Result<(), DbErr> {
+        let recovery_codes = if recovery_codes.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_value(recovery_codes).map_err(|e| DbErr::Json(e.to_string()))?)
+        };
+        UserEnitty::update(ActiveModel {
+            id: ActiveValue::Set(id),
+            name: ActiveValue::NotSet,
+            email: ActiveValue::NotSet,
+            password_hash: ActiveValue::NotSet,
+            email_verified: ActiveValue::NotSet,
+            created_at: ActiveValue::NotSet,
+            settings: ActiveValue::NotSet,
+            totp_secret: ActiveValue::Set(totp_secret),
+            totp_enabled: ActiveValue::Set(totp_enabled),
+            recovery_codes: ActiveValue::Set(recovery_codes),
+            totp_last_step: ActiveValue::NotSet,
+            deletion_scheduled_at: ActiveValue::NotSet,
+            is_admin: ActiveValue::NotSet,
+            disabled: ActiveValue::NotSet,
         })
         .exec(db)
         .await?;
         Ok(())
     }
+
+    /// Records `step` as the last TOTP time step used by the user. Returns
+    /// false without changing anything when a step at or after it was already
+    /// used, so a code can only be accepted once.
+    pub async fn use_totp_step(db: &DbConn, id: Uuid, step: i64) -> Result<bool, DbErr> {
+        let last_step = user_entity::user::Column::TotpLastStep;
+        let result = UserEnitty::update_many()
+            .col_expr(last_step, sea_orm::sea_query::Expr::value(step))
+            .filter(user_entity::user::Column::Id.eq(id))
+            .filter(last_step.is_null().or(last_step.lt(step)))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected == 1)
+    }
+
+    /// Removes the recovery code with `hash`. Returns false when the user has
+    /// no such code, or when another request changed the codes in between, so
+    /// a code can only be used once.
+    pub async fn use_recovery_code(db: &DbConn, id: Uuid, hash: &str) -> Result<bool, DbErr> {
+        let Some(user) = Self::find_by_id(db, id).await? else {
+            return Ok(false);
+        };
+        let Some(index) = user.recovery_codes.iter().position(|c| c == hash) else {
+            return Ok(false);
+        };
+        let stored =
+            serde_json::to_value(&user.recovery_codes).map_err(|e| DbErr::Json(e.to_string()))?;
+        let mut left = user.recovery_codes;
+        left.remove(index);
+        let left = if left.is_empty() {
+            None
+        } else {
+            Some(serde_json::to_value(left).map_err(|e| DbErr::Json(e.to_string()))?)
+        };
+        let recovery_codes = user_entity::user::Column::RecoveryCodes;
+        let result = UserEnitty::update_many()
+            .col_expr(recovery_codes, sea_orm::sea_query::Expr::value(left))
+            .filter(user_entity::user::Column::Id.eq(id))
+            .filter(recovery_codes.eq(stored))
+            .exec(db)
+            .await?;
+        Ok(result.rows_affected == 1)
+    }
+
+    pub async fn update_name(db: &DbConn, id: Uuid, name: &str) -> Result<(), DbErr> {
+        UserEnitty::update_many()
+            .col_expr(
+                user_entity::user::Column::Name,
+                sea_orm::sea_query::Expr::value(name),
+            )
+            .filter(user_entity::user::Column::Id.eq(id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_settings(
+        db: &DbConn,
+        id: Uuid,
+        settings: &UserSettings,
+    ) -> Result<(), DbErr> {
+        let settings = settings.to_json().map_err(|e| DbErr::Json(e.to_string()))?;
+        UserEnitty::update_many()
+            .col_expr(
+                user_entity::user::Column::Settings,
+                sea_orm::sea_query::Expr::value(settings),
+            )
+            .filter(user_entity::user::Column::Id.eq(id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_admin(db: &DbConn, id: Uuid, is_admin: bool) -> Result<(), DbErr> {
+        UserEnitty::update_many()
+            .col_expr(
+                user_entity::user::Column::IsAdmin,
+                sea_orm::sea_query::Expr::value(is_admin),
+            )
+            .filter(user_entity::user::Column::Id.eq(id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn update_disabled(db: &DbConn, id: Uuid, disabled: bool) -> Result<(), DbErr> {
+        UserEnitty::update_many()
+            .col_expr(
+                user_entity::user::Column::Disabled,
+                sea_orm::sea_query::Expr::value(disabled),
+            )
+            .filter(user_entity::user::Column::Id.eq(id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    /// Schedules the deletion of the account, or cancels it with `None`.
+    pub async fn schedule_deletion(
+        db: &DbConn,
+        id: Uuid,
+        deletion_scheduled_at: Option<DateTime<Utc>>,
+    ) -> Result<(), DbErr> {
+        UserEnitty::update_many()
+            .col_expr(
+                user_entity::user::Column::DeletionScheduledAt,
+                sea_orm::sea_query::Expr::value(deletion_scheduled_at),
+            )
+            .filter(user_entity::user::Column::Id.eq(id))
+            .exec(db)
+            .await?;
+        Ok(())
+    }
+
+    /// Users whose scheduled deletion is due.
+    pub async fn find_due_for_deletion(db: &DbConn) -> Result<Vec<Self>, DbErr> {
+        Ok(UserEnitty::find()
+            .filter(user_entity::user::Column::DeletionScheduledAt.lte(Utc::now()))
+            .all(db)
+            .await?
+            .into_iter()
+            .map(Self::from_model)
+            .collect())
+    }
+
+    /// Deletes the user row. Ledgers, tokens, sessions and the like go with it
+    /// through their foreign keys.
+    pub async fn delete(db: &DbConn, id: Uuid) -> Result<(), DbErr> {
+        UserEnitty::delete_by_id(id).exec(db).await?;
+        Ok(())
+    }
 }
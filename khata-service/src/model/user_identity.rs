@@ -0,0 +1,41 @@
+use chrono::Utc;
+use sea_orm::{ActiveValue, ColumnTrait, DbConn, DbErr, EntityTrait, QueryFilter};
+use uuid::Uuid;
+
+use crate::user_entity::{prelude::UserIdentity as UserIdentityEntity, user_identity};
+
+/// Links the subject id of an external identity provider to a user.
+pub struct UserIdentity;
+
+impl UserIdentity {
+    pub async fn find_user_id(
+        db: &DbConn,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<Option<Uuid>, DbErr> {
+        Ok(UserIdentityEntity::find()
+            .filter(user_identity::Column::Issuer.eq(issuer))
+            .filter(user_identity::Column::Subject.eq(subject))
+            .one(db)
+            .await?
+            .map(|m| m.user_id))
+    }
+
+    pub async fn link(
+        db: &DbConn,
+        user_id: Uuid,
+        issuer: &str,
+        subject: &str,
+    ) -> Result<(), DbErr> {
+        UserIdentityEntity::insert(user_identity::ActiveModel {
+            id: ActiveValue::Set(Uuid::now_v7()),
+            user_id: ActiveValue::Set(user_id),
+            issuer: ActiveValue::Set(issuer.to_string()),
+            subject: ActiveValue::Set(subject.to_string()),
+            created_at: ActiveValue::Set(Utc::now()),
+        })
+        .exec_without_returning(db)
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveValue, ColumnTrait, DbConn, DbErr, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::user_entity::{prelude::UserSession as UserSessionEntity, user_session};
+
+/// A signed in browser, tracked next to the session in the session store. The
+/// id is kept in the session data, while `session_id` is the id of the session
+/// in the store, known from the first request after sign in.
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct UserSession {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    #[serde(skip_serializing)]
+    pub session_id: Option<String>,
+    pub user_agent: Option<String>,
+    pub ip: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    /// Whether this is the session of the request.
+    pub current: bool,
+}
+
+impl UserSession {
+    fn from_model(model: user_session::Model) -> Self {
+        Self {
+            id: model.id,
+            user_id: model.user_id,
+            session_id: model.session_id,
+            user_agent: model.user_agent,
+            ip: model.ip,
+            created_at: model.created_at,
+            last_seen_at: model.last_seen_at,
+            current: false,
+        }
+    }
+
+    pub async fn create(
+        db: &DbConn,
+        user_id: Uuid,
+        user_agent: Option<String>,
+        ip: Option<String>,
+    ) -> Result<Self, DbErr> {
+        let now = Utc::now();
+        let session = Self {
+            id: Uuid::now_v7(),
+            user_id,
+            session_id: None,
+            user_agent,
+            ip,
+            created_at: now,
+            last_seen_at: now,
+            current: true,
+        };
+        UserSessionEntity::insert(user_session::ActiveModel {
+            id: ActiveValue::Set(session.id),
+            user_id: ActiveValue::Set(session.user_id),
+            session_id: ActiveValue::Set(None),
+            user_agent: ActiveValue::Set(session.user_agent.clone()),
+            ip: ActiveValue::Set(session.ip.clone()),
+            created_at: ActiveValue::Set(session.created_at),
+            last_seen_at: ActiveValue::Set(session.last_seen_at),
+        })
+        .exec_without_returning(db)
+        .await?;
+        Ok(session)
+    }
+
+    pub async fn find_by_id(db: &DbConn, id: Uuid) -> Result<Option<Self>, DbErr> {
+        Ok(UserSessionEntity::find_by_id(id)
+            .one(db)
+            .await?
+            .map(Self::from_model))
+    }
+
+    /// The sessions of a user that were seen after `since`, latest first.
+    pub async fn find_for_user(
+        db: &DbConn,
+        user_id: Uuid,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<Self>, DbErr> {
+        Ok(UserSessionEntity::find()
+            .filter(user_session::Column::UserId.eq(user_id))
+            .filter(user_session::Column::LastSeenAt.gt(since))
+            .order_by_desc(user_session::Column::LastSeenAt)
+            .all(db)
+            .await?
+            .into_iter()
+            .map(Self::from_model)
+            .collect())
+    }
+
+    pub async fn touch(db: &DbConn, id: Uuid, session_id: Option<String>) -> Result<(), DbErr> {
+        UserSessionEntity::update(user_session::ActiveModel {
+            id: ActiveValue::Set(id),
+            user_id: ActiveValue::NotSet,
+            session_id: session_id.map_or(ActiveValue::NotSet, |s| ActiveValue::Set(Some(s))),
+            user_agent: ActiveValue::NotSet,
+            ip: ActiveValue::NotSet,
+            created_at: ActiveValue::NotSet,
+            last_seen_at: ActiveValue::Set(Utc::now()),
+        })
+        .exec(db)
+        .await?;
+        Ok(())
+    }
+
+    /// Deletes the sessions of a user, only `only` if given and never `keep`.
+    /// Returns the ids of the deleted sessions in the session store.
+    pub async fn delete_for_user(
+        db: &DbConn,
+        user_id: Uuid,
+        only: Option<Uuid>,
+        keep: Option<Uuid>,
+    ) -> Result<Vec<String>, DbErr> {
+        let mut query = UserSessionEntity::find().filter(user_session::Column::UserId.eq(user_id));
+        if let Some(only) = only {
+            query = query.filter(user_session::Column::Id.eq(only));
+        }
+        if let Some(keep) = keep {
+            query = query.filter(user_session::Column::Id.ne(keep));
+        }
+        let sessions: Vec<(Uuid, Option<String>)> = query
+            .select_only()
+            .column(user_session::Column::Id)
+            .column(user_session::Column::SessionId)
+            .into_tuple()
+            .all(db)
+            .await?;
+        UserSessionEntity::delete_many()
+            .filter(user_session::Column::Id.is_in(sessions.iter().map(|(id, _)| *id)))
+            .exec(db)
+            .await?;
+        Ok(sessions.into_iter().filter_map(|(_, s)| s).collect())
+    }
+
+    /// When each user was last seen in any session.
+    pub async fn last_seen_by_user(db: &DbConn) -> Result<HashMap<Uuid, DateTime<Utc>>, DbErr> {
+        let rows: Vec<(Uuid, Option<DateTime<Utc>>)> = UserSessionEntity::find()
+            .select_only()
+            .column(user_session::Column::UserId)
+            .column_as(user_session::Column::LastSeenAt.max(), "last_seen_at")
+            .group_by(user_session::Column::UserId)
+            .into_tuple()
+            .all(db)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|(user_id, last_seen_at)| Some((user_id, last_seen_at?)))
+            .collect())
+    }
+
+    /// Number of sessions of all users seen after `since`.
+    pub async fn count_active(db: &DbConn, since: DateTime<Utc>) -> Result<u64, DbErr> {
+        UserSessionEntity::find()
+            .filter(user_session::Column::LastSeenAt.gt(since))
+            .count(db)
+            .await
+    }
+}
@@ -0,0 +1,293 @@
+use jiff::tz::TimeZone;
+use serde::{Deserialize, Deserializer, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+/// Version of the [`UserSettings`] layout written to `user.settings`. Older
+/// versions are upgraded when read. Newer ones, written by a later release,
+/// are read as far as this one understands them and kept as they are, see
+/// [`UserSettings::is_newer`].
+pub const SETTINGS_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl From<Weekday> for chrono::Weekday {
+    fn from(weekday: Weekday) -> Self {
+        match weekday {
+            Weekday::Monday => Self::Mon,
+            Weekday::Tuesday => Self::Tue,
+            Weekday::Wednesday => Self::Wed,
+            Weekday::Thursday => Self::Thu,
+            Weekday::Friday => Self::Fri,
+            Weekday::Saturday => Self::Sat,
+            Weekday::Sunday => Self::Sun,
+        }
+    }
+}
+
+/// How amounts are grouped and where the decimal separator goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NumberFormat {
+    /// 1,234,567.89
+    CommaPeriod,
+    /// 1.234.567,89
+    PeriodComma,
+    /// 1 234 567,89
+    SpaceComma,
+    /// 12,34,567.89
+    Indian,
+}
+
+fn is_locale(locale: &str) -> bool {
+    locale
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic())
+        && locale.split('-').all(|part| {
+            (1..=8).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+fn validate_settings(settings: &UserSettings) -> Result<(), ValidationError> {
+    if TimeZone::get(&settings.time_zone).is_err() {
+        return Err(ValidationError::new("time_zone").with_message("Unknown time zone".into()));
+    }
+    if !is_locale(&settings.locale) {
+        return Err(ValidationError::new("locale").with_message("Invalid locale".into()));
+    }
+    if settings
+        .base_currency
+        .as_deref()
+        .is_some_and(|code| code.len() != 3 || !code.chars().all(|c| c.is_ascii_uppercase()))
+    {
+        return Err(
+            ValidationError::new("base_currency").with_message("Invalid currency code".into())
+        );
+    }
+    Ok(())
+}
+
+/// Preferences of a user, stored as JSON in `user.settings`.
+#[derive(Debug, Clone, PartialEq, Eq, ToSchema, Serialize, Deserialize, Validate)]
+#[serde(default)]
+#[validate(schema(function = "validate_settings"))]
+pub struct UserSettings {
+    pub version: u32,
+    /// Currency code reports are converted to.
+    pub base_currency: Option<String>,
+    /// IANA time zone, e.g. `Asia/Kolkata`.
+    pub time_zone: String,
+    /// BCP 47 language tag, e.g. `en-IN`.
+    #[validate(length(max = 35))]
+    pub locale: String,
+    pub first_day_of_week: Weekday,
+    #[validate(range(min = 1, max = 12))]
+    pub fiscal_year_start_month: u8,
+    /// Day of the month cycle periods start on, e.g. a pay day.
+    #[validate(range(min = 1, max = 28))]
+    pub cycle_day: u8,
+    /// Account preselected for new transactions in the personal ledger.
+    pub default_account_id: Option<Uuid>,
+    pub number_format: NumberFormat,
+}
+
+impl Default for UserSettings {
+    fn default() -> Self {
+        Self {
+            version: SETTINGS_VERSION,
+            base_currency: None,
+            time_zone: "UTC".to_string(),
+            locale: "en-US".to_string(),
+            first_day_of_week: Weekday::Monday,
+            fiscal_year_start_month: 1,
+            cycle_day: 1,
+            default_account_id: None,
+            number_format: NumberFormat::CommaPeriod,
+        }
+    }
+}
+
+impl UserSettings {
+    /// Reads the stored settings. Missing fields take their defaults and
+    /// settings that cannot be read at all are replaced by the defaults, so a
+    /// broken value never locks a user out.
+    pub fn from_json(value: Option<serde_json::Value>) -> Self {
+        let Some(value) = value else {
+            return Self::default();
+        };
+        // Version 0 is the empty object written before settings existed. It
+        // has no version field, which would otherwise default to the current
+        // version.
+        let version = value
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .and_then(|v| u32::try_from(v).ok())
+            .unwrap_or(0);
+        match serde_json::from_value::<Self>(value) {
+            Ok(settings) if version > SETTINGS_VERSION => {
+                tracing::warn!("Reading user settings of newer version {}", version);
+                Self {
+                    version,
+                    ..settings
+                }
+            }
+            // Versions 0 and 1 share a layout.
+            Ok(settings) => Self {
+                version: SETTINGS_VERSION,
+                ..settings
+            },
+            Err(e) => {
+                tracing::warn!("Ignoring unreadable user settings: {:?}", e);
+                Self::default()
+            }
+        }
+    }
+
+    /// Whether the settings were written by a later release. Saving them
+    /// would drop what this release does not understand.
+    pub const fn is_newer(&self) -> bool {
+        self.version > SETTINGS_VERSION
+    }
+
+    pub fn to_json(&self) -> Result<serde_json::Value, serde_json::Error> {
+        serde_json::to_value(self)
+    }
+
+    /// The time zone of the user, UTC if it is no longer known.
+    pub fn tz(&self) -> TimeZone {
+        TimeZone::get(&self.time_zone).unwrap_or(TimeZone::UTC)
+    }
+
+    pub fn apply(&mut self, patch: UserSettingsPatch) {
+        patch.base_currency.apply_to(&mut self.base_currency);
+        if let Some(time_zone) = patch.time_zone {
+            self.time_zone = time_zone;
+        }
+        if let Some(locale) = patch.locale {
+            self.locale = locale;
+        }
+        if let Some(first_day_of_week) = patch.first_day_of_week {
+            self.first_day_of_week = first_day_of_week;
+        }
+        if let Some(fiscal_year_start_month) = patch.fiscal_year_start_month {
+            self.fiscal_year_start_month = fiscal_year_start_month;
+        }
+        if let Some(cycle_day) = patch.cycle_day {
+            self.cycle_day = cycle_day;
+        }
+        patch
+            .default_account_id
+            .apply_to(&mut self.default_account_id);
+        if let Some(number_format) = patch.number_format {
+            self.number_format = number_format;
+        }
+    }
+}
+
+/// A nullable field of a patch, which tells a missing field (keep the value)
+/// from `null` (clear the value).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Patch<T> {
+    #[default]
+    Missing,
+    Null,
+    Value(T),
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Option::<T>::deserialize(deserializer).map(|value| value.map_or(Self::Null, Self::Value))
+    }
+}
+
+impl<T> Patch<T> {
+    pub fn apply_to(self, target: &mut Option<T>) {
+        match self {
+            Self::Missing => {}
+            Self::Null => *target = None,
+            Self::Value(value) => *target = Some(value),
+        }
+    }
+}
+
+/// Changes to [`UserSettings`]. Fields that are left out keep their value.
+#[derive(Debug, Clone, Default, ToSchema, Deserialize)]
+pub struct UserSettingsPatch {
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub base_currency: Patch<String>,
+    pub time_zone: Option<String>,
+    pub locale: Option<String>,
+    pub first_day_of_week: Option<Weekday>,
+    pub fiscal_year_start_month: Option<u8>,
+    pub cycle_day: Option<u8>,
+    #[serde(default)]
+    #[schema(value_type = Option<Uuid>)]
+    pub default_account_id: Patch<Uuid>,
+    pub number_format: Option<NumberFormat>,
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patch_tells_missing_from_null() {
+        let mut settings = UserSettings {
+            base_currency: Some("INR".to_string()),
+            default_account_id: Some(Uuid::nil()),
+            ..UserSettings::default()
+        };
+        let patch: UserSettingsPatch =
+            serde_json::from_str(r#"{"base_currency": null, "locale": "en-IN"}"#).unwrap();
+        assert_eq!(patch.base_currency, Patch::Null);
+        assert_eq!(patch.default_account_id, Patch::Missing);
+        settings.apply(patch);
+        assert_eq!(settings.base_currency, None);
+        assert_eq!(settings.default_account_id, Some(Uuid::nil()));
+        assert_eq!(settings.locale, "en-IN");
+
+        let patch: UserSettingsPatch = serde_json::from_str(r#"{"base_currency": "EUR"}"#).unwrap();
+        settings.apply(patch);
+        assert_eq!(settings.base_currency.as_deref(), Some("EUR"));
+    }
+
+    #[test]
+    fn from_json_upgrades_older_versions_and_keeps_newer_ones() {
+        let settings = UserSettings::from_json(Some(serde_json::json!({})));
+        assert_eq!(settings, UserSettings::default());
+
+        let settings = UserSettings::from_json(Some(serde_json::json!({
+            "version": 1,
+            "locale": "en-IN",
+        })));
+        assert_eq!(settings.version, SETTINGS_VERSION);
+        assert_eq!(settings.locale, "en-IN");
+        assert!(!settings.is_newer());
+
+        let settings = UserSettings::from_json(Some(serde_json::json!({
+            "version": SETTINGS_VERSION + 1,
+            "locale": "en-IN",
+            "layout_of_the_future": true,
+        })));
+        assert_eq!(settings.version, SETTINGS_VERSION + 1);
+        assert_eq!(settings.locale, "en-IN");
+        assert!(settings.is_newer());
+
+        let settings = UserSettings::from_json(Some(serde_json::json!({"cycle_day": "first"})));
+        assert_eq!(settings, UserSettings::default());
+    }
+}
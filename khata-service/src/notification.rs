@@ -0,0 +1,732 @@
+//! Evaluates notification rules and delivers the resulting outbox entries.
+//!
+//! Rules are checked by a background job every
+//! `KHATA_NOTIFICATION_INTERVAL_SECS` seconds (default 300). Email goes
+//! through the mailer configured in [`crate::mailer`], and is skipped when no
+//! SMTP server is set up. Webhooks are only posted to public addresses unless
+//! `KHATA_WEBHOOK_ALLOW_PRIVATE` is `true`, for hooks on the local network.
+
+use std::{collections::HashMap, net::IpAddr, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use chrono::Utc;
+use jiff::tz::TimeZone;
+use reqwest::{
+    Client,
+    dns::{Addrs, Name, Resolve, Resolving},
+    redirect,
+};
+use sea_orm::{DatabaseConnection, DbErr};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    MAILER, database,
+    mailer::{Mail, Mailer},
+    model::{
+        account::AccountReq,
+        account_extra::AccountSummary,
+        ledger::Ledger,
+        notification::{
+            Notification, NotificationChannel, NotificationCondition, NotificationMessage,
+            NotificationRule,
+        },
+        transaction::TransactionReq,
+        user::User,
+    },
+    period::{self, Periods},
+};
+
+/// Where a notification goes on the channels that leave the app.
+pub struct Recipient {
+    pub email: String,
+    pub webhook_url: Option<String>,
+}
+
+/// A way of getting an outbox entry to its recipient.
+pub trait Channel {
+    fn deliver(
+        &self,
+        recipient: &Recipient,
+        notification: &Notification,
+    ) -> impl Future<Output = anyhow::Result<()>> + Send;
+}
+
+/// Sends notifications with a [`crate::mailer::Mailer`], the shared one
+/// outside of tests.
+pub struct EmailChannel {
+    mailer: &'static Mailer,
+}
+
+impl Channel for EmailChannel {
+    async fn deliver(
+        &self,
+        recipient: &Recipient,
+        notification: &Notification,
+    ) -> anyhow::Result<()> {
+        self.mailer
+            .send(
+                &recipient.email,
+                Mail {
+                    subject: notification.title.clone(),
+                    text: notification.body.clone(),
+                    html: None,
+                },
+            )
+            .await
+    }
+}
+
+/// Posts notifications as JSON. Redirects are not followed, and hosts are
+/// resolved by [`WebhookResolver`], so a rule cannot be used to reach the
+/// service's own network.
+pub struct WebhookChannel {
+    client: Client,
+    allow_private: bool,
+}
+
+impl WebhookChannel {
+    pub fn new(allow_private: bool) -> Self {
+        let client = Client::builder()
+            .redirect(redirect::Policy::none())
+            .no_proxy()
+            .dns_resolver(Arc::new(WebhookResolver { allow_private }))
+            .build()
+            .unwrap_or_default();
+        Self {
+            client,
+            allow_private,
+        }
+    }
+}
+
+/// Resolves webhook hosts and refuses any that point at a loopback,
+/// link-local or private address.
+struct WebhookResolver {
+    allow_private: bool,
+}
+
+impl Resolve for WebhookResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allow_private = self.allow_private;
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .collect::<Vec<_>>();
+            if !allow_private && addrs.iter().any(|addr| !is_public(addr.ip())) {
+                return Err(format!("{host} resolves to a non-public address").into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Whether the address can be reached from the internet at large.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            // 100.64.0.0/10 is shared address space for carrier-grade NAT.
+            let shared = a == 100 && (64..128).contains(&b);
+            !(ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || shared)
+        }
+        IpAddr::V6(ip) => ip.to_ipv4_mapped().map_or_else(
+            || {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local())
+            },
+            |ip| is_public(IpAddr::V4(ip)),
+        ),
+    }
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    id: Uuid,
+    rule_id: Option<Uuid>,
+    title: &'a str,
+    body: &'a str,
+    created_at: chrono::DateTime<Utc>,
+}
+
+impl Channel for WebhookChannel {
+    async fn deliver(
+        &self,
+        recipient: &Recipient,
+        notification: &Notification,
+    ) -> anyhow::Result<()> {
+        let url = recipient
+            .webhook_url
+            .as_deref()
+            .context("The rule has no webhook_url")?;
+        let url = reqwest::Url::parse(url).context("Invalid webhook_url")?;
+        if !matches!(url.scheme(), "http" | "https") {
+            anyhow::bail!("webhook_url must be an http or https URL");
+        }
+        // Addresses in the URL are never resolved, so check them here.
+        let literal = url
+            .host_str()
+            .map(|host| host.trim_start_matches('[').trim_end_matches(']'))
+            .and_then(|host| host.parse::<IpAddr>().ok());
+        if !self.allow_private && literal.is_some_and(|ip| !is_public(ip)) {
+            anyhow::bail!("webhook_url points at a non-public address");
+        }
+        let response = self
+            .client
+            .post(url)
+            .timeout(Duration::from_secs(10))
+            .json(&WebhookPayload {
+                id: notification.id,
+                rule_id: notification.rule_id,
+                title: &notification.title,
+                body: &notification.body,
+                created_at: notification.created_at,
+            })
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            anyhow::bail!("Webhook answered {}", response.status());
+        }
+        Ok(())
+    }
+}
+
+pub struct Notifier {
+    db: DatabaseConnection,
+    email: EmailChannel,
+    webhook: WebhookChannel,
+}
+
+impl Notifier {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self {
+            db,
+            email: EmailChannel { mailer: &MAILER },
+            webhook: WebhookChannel::new(
+                std::env::var("KHATA_WEBHOOK_ALLOW_PRIVATE").is_ok_and(|v| v == "true"),
+            ),
+        }
+    }
+
+    /// Runs [`Self::run_once`] forever.
+    pub async fn run(self) {
+        let interval = std::env::var("KHATA_NOTIFICATION_INTERVAL_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(300);
+        let mut interval = tokio::time::interval(Duration::from_secs(interval));
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.run_once().await {
+                tracing::error!("Error running notifications: {:?}", e);
+            }
+        }
+    }
+
+    pub async fn run_once(&self) -> anyhow::Result<()> {
+        for rule in NotificationRule::find_enabled(&self.db).await? {
+            if let Err(e) = self.evaluate(&rule).await {
+                tracing::error!("Error evaluating notification rule {}: {:?}", rule.id, e);
+            }
+        }
+        self.deliver_pending().await
+    }
+
+    async fn evaluate(&self, rule: &NotificationRule) -> anyhow::Result<()> {
+        if Ledger::role_of(&self.db, rule.ledger_id, rule.user_id)
+            .await?
+            .is_none()
+        {
+            return Ok(());
+        }
+        let db = database(rule.ledger_id).await?;
+        let settings = User::find_by_id(&self.db, rule.user_id)
+            .await?
+            .map(|u| u.settings)
+            .unwrap_or_default();
+        let tz = settings.tz();
+        let messages = match &rule.condition {
+            NotificationCondition::CardDue {
+                account_id,
+                days_before,
+            } => card_due(&db, *account_id, *days_before, &tz).await?,
+            NotificationCondition::BudgetUsed {
+                category_id,
+                currency_code,
+                limit,
+                percent,
+                period,
+            } => {
+                budget_used(
+                    &db,
+                    *category_id,
+                    currency_code,
+                    *limit,
+                    *percent,
+                    Periods::new(*period, &settings),
+                    &tz,
+                )
+                .await?
+            }
+            NotificationCondition::LargeTransaction { threshold } => {
+                large_transaction(&db, rule, *threshold, &tz).await?
+            }
+        };
+        for message in messages {
+            Notification::enqueue(&self.db, rule, &message).await?;
+        }
+        Ok(())
+    }
+
+    async fn deliver_pending(&self) -> anyhow::Result<()> {
+        for notification in Notification::pending(&self.db).await? {
+            if notification.channel == NotificationChannel::Email
+                && !self.email.mailer.is_configured()
+            {
+                Notification::mark_skipped(&self.db, notification.id, "SMTP is not configured")
+                    .await?;
+                continue;
+            }
+            let result = self.deliver(&notification).await;
+            match result {
+                Ok(()) => Notification::mark_delivered(&self.db, notification.id).await?,
+                Err(e) => {
+                    tracing::warn!("Could not deliver notification {}: {e:#}", notification.id);
+                    notification
+                        .mark_attempt_failed(&self.db, &format!("{e:#}"))
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn deliver(&self, notification: &Notification) -> anyhow::Result<()> {
+        let user = User::find_by_id(&self.db, notification.user_id)
+            .await?
+            .context("User not found")?;
+        let rule = match notification.rule_id {
+            Some(id) => NotificationRule::find_by_id(&self.db, id).await?,
+            None => None,
+        };
+        let recipient = Recipient {
+            email: user.email,
+            webhook_url: rule.and_then(|r| r.webhook_url),
+        };
+        match notification.channel {
+            NotificationChannel::InApp => Ok(()),
+            NotificationChannel::Email => self.email.deliver(&recipient, notification).await,
+            NotificationChannel::Webhook => self.webhook.deliver(&recipient, notification).await,
+        }
+    }
+}
+
+async fn card_due(
+    db: &DatabaseConnection,
+    account_id: Uuid,
+    days_before: u32,
+    tz: &TimeZone,
+) -> Result<Vec<NotificationMessage>, DbErr> {
+    let Some(account) = AccountReq::find_one_with_currency(db, account_id).await? else {
+        return Ok(vec![]);
+    };
+    let AccountSummary::CreditCard(summary) = AccountReq::summary(db, account_id, tz).await? else {
+        return Ok(vec![]);
+    };
+    let days_left = (summary.next_due_date - period::today(tz)).num_days();
+    if summary.outstanding == 0 || days_left < 0 || days_left > i64::from(days_before) {
+        return Ok(vec![]);
+    }
+    let digits = account.currency.0.decimal_digits;
+    let code = &account.currency.0.code;
+    Ok(vec![NotificationMessage {
+        dedupe_key: format!("card_due:{account_id}:{}", summary.next_due_date),
+        title: format!("{} payment due in {days_left} days", account.account.name),
+        body: format!(
+            "{} is due on {}. Statement balance {}, minimum due {}.",
+            account.account.name,
+            summary.next_due_date,
+            format_amount(summary.statement_balance, digits, code),
+            format_amount(summary.minimum_due, digits, code),
+        ),
+    }])
+}
+
+async fn budget_used(
+    db: &DatabaseConnection,
+    category_id: Uuid,
+    currency_code: &str,
+    limit: i64,
+    percent: u32,
+    periods: Periods,
+    tz: &TimeZone,
+) -> anyhow::Result<Vec<NotificationMessage>> {
+    let current = periods.containing(period::today(tz));
+    let (start, end) = current.bounds(tz)?;
+    let accounts = AccountReq::find_all_with_currency(db)
+        .await?
+        .into_iter()
+        .map(|a| (a.account.id, a))
+        .collect::<HashMap<_, _>>();
+    let transactions = TransactionReq::find_by_month(db, start, end).await?;
+    let spent = -transactions
+        .iter()
+        .flat_map(|t| &t.items)
+        .filter(|i| i.0.category_id == Some(category_id))
+        .filter(|i| {
+            accounts
+                .get(&i.0.account_id)
+                .is_some_and(|a| a.account.currency_code == currency_code)
+        })
+        .map(|i| i.0.amount)
+        .sum::<i64>();
+    if spent * 100 < limit * i64::from(percent) {
+        return Ok(vec![]);
+    }
+    let digits = accounts
+        .values()
+        .find(|a| a.currency.0.code == currency_code)
+        .map_or(0, |a| a.currency.0.decimal_digits);
+    let since = current.start;
+    Ok(vec![NotificationMessage {
+        dedupe_key: format!("budget_used:{category_id}:{currency_code}:{percent}:{since}"),
+        title: format!("Budget {percent}% used"),
+        body: format!(
+            "You spent {} of your {} budget since {since}.",
+            format_amount(spent, digits, currency_code),
+            format_amount(limit, digits, currency_code),
+        ),
+    }])
+}
+
+/// Only transactions dated after the rule was created are considered, so a
+/// new rule does not flood the outbox with old transactions.
+async fn large_transaction(
+    db: &DatabaseConnection,
+    rule: &NotificationRule,
+    threshold: i64,
+    tz: &TimeZone,
+) -> Result<Vec<NotificationMessage>, DbErr> {
+    let accounts = AccountReq::find_all_with_currency(db)
+        .await?
+        .into_iter()
+        .map(|a| (a.account.id, a))
+        .collect::<HashMap<_, _>>();
+    let until = Utc::now() + chrono::TimeDelta::days(366);
+    let transactions = TransactionReq::find_by_month(db, rule.created_at, until).await?;
+    Ok(transactions
+        .into_iter()
+        .filter_map(|t| {
+            let item = t
+                .items
+                .iter()
+                .max_by_key(|i| i.0.amount.abs())
+                .filter(|i| i.0.amount.abs() >= threshold)?;
+            let account = accounts.get(&item.0.account_id)?;
+            let transaction = t.transaction.0;
+            Some(NotificationMessage {
+                dedupe_key: format!("large_transaction:{}", transaction.id),
+                title: format!("Large transaction: {}", transaction.title),
+                body: format!(
+                    "{} moved {} on {}.",
+                    account.account.name,
+                    format_amount(
+                        item.0.amount,
+                        account.currency.0.decimal_digits,
+                        &account.currency.0.code
+                    ),
+                    period::local_date(tz, transaction.timestamp),
+                ),
+            })
+        })
+        .collect())
+}
+
+pub fn format_amount(amount: i64, decimal_digits: i32, currency_code: &str) -> String {
+    let digits = usize::try_from(decimal_digits).unwrap_or(0);
+    let scale = 10_i64.pow(u32::try_from(decimal_digits).unwrap_or(0));
+    let sign = if amount < 0 { "-" } else { "" };
+    let amount = amount.unsigned_abs();
+    let scale = scale.unsigned_abs();
+    if digits == 0 {
+        format!("{sign}{amount} {currency_code}")
+    } else {
+        format!(
+            "{sign}{}.{:0digits$} {currency_code}",
+            amount / scale,
+            amount % scale
+        )
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use axum::{Json, Router, http::StatusCode, routing::post};
+    use sea_orm::Database;
+    use tokio::{net::TcpListener, sync::mpsc};
+    use user_migration::MigratorTrait;
+
+    use super::*;
+    use crate::{
+        mailer::{MailerConfig, smtp_capture},
+        model::notification::{NotificationRuleReq, NotificationStatus, retry_delay},
+    };
+
+    fn notification(channel: NotificationChannel) -> Notification {
+        Notification {
+            id: Uuid::now_v7(),
+            user_id: Uuid::now_v7(),
+            rule_id: Some(Uuid::now_v7()),
+            channel,
+            title: "Card due".to_string(),
+            body: "Pay 1.00 INR by tomorrow".to_string(),
+            status: NotificationStatus::Pending,
+            attempts: 0,
+            last_error: None,
+            created_at: Utc::now(),
+            delivered_at: None,
+            read_at: None,
+        }
+    }
+
+    fn recipient(webhook_url: Option<String>) -> Recipient {
+        Recipient {
+            email: "ada@example.com".to_string(),
+            webhook_url,
+        }
+    }
+
+    fn mailer(smtp_url: Option<String>) -> &'static Mailer {
+        Box::leak(Box::new(
+            Mailer::from_config(MailerConfig {
+                smtp_url,
+                from: None,
+            })
+            .unwrap(),
+        ))
+    }
+
+    #[test]
+    fn budget_rules_saved_with_monthly_limit_still_load() {
+        let condition: NotificationCondition = serde_json::from_str(
+            r#"{"kind": "budget_used", "category_id": "00000000-0000-0000-0000-000000000000",
+                "currency_code": "INR", "monthly_limit": 500000, "percent": 80}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            condition,
+            NotificationCondition::BudgetUsed {
+                limit: 500_000,
+                period: period::PeriodKind::Month,
+                ..
+            }
+        ));
+        let json = serde_json::to_value(&condition).unwrap();
+        assert_eq!(json["limit"], 500_000);
+    }
+
+    #[tokio::test]
+    async fn webhook_posts_payload() {
+        let (sender, mut received) = mpsc::unbounded_channel();
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(move |Json(body): Json<serde_json::Value>| async move {
+                    sender.send(body).unwrap();
+                }),
+            )
+            .route(
+                "/broken",
+                post(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+            )
+            .route(
+                "/moved",
+                post(|| async { axum::response::Redirect::temporary("/hook") }),
+            );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let channel = WebhookChannel::new(true);
+        let sent = notification(NotificationChannel::Webhook);
+        channel
+            .deliver(&recipient(Some(format!("http://{address}/hook"))), &sent)
+            .await
+            .unwrap();
+        let body = received.recv().await.unwrap();
+        assert_eq!(body["id"], sent.id.to_string());
+        assert_eq!(body["rule_id"], sent.rule_id.unwrap().to_string());
+        assert_eq!(body["title"], "Card due");
+        assert_eq!(body["body"], "Pay 1.00 INR by tomorrow");
+
+        let broken = recipient(Some(format!("http://{address}/broken")));
+        assert!(channel.deliver(&broken, &sent).await.is_err());
+        assert!(channel.deliver(&recipient(None), &sent).await.is_err());
+
+        let moved = recipient(Some(format!("http://{address}/moved")));
+        assert!(channel.deliver(&moved, &sent).await.is_err());
+        assert!(received.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn webhook_refuses_private_addresses() {
+        let channel = WebhookChannel::new(false);
+        let sent = notification(NotificationChannel::Webhook);
+        for url in [
+            "http://127.0.0.1:9/hook",
+            "http://[::1]:9/hook",
+            "http://[::ffff:10.0.0.1]:9/hook",
+            "http://169.254.169.254/latest/meta-data",
+            "http://localhost:9/hook",
+            "ftp://example.com/hook",
+        ] {
+            let error = channel
+                .deliver(&recipient(Some(url.to_string())), &sent)
+                .await
+                .unwrap_err();
+            let error = format!("{error:#}");
+            assert!(
+                error.contains("non-public") || error.contains("http or https"),
+                "{url}: {error}"
+            );
+        }
+        assert!(is_public("93.184.215.14".parse().unwrap()));
+        assert!(!is_public("100.100.1.1".parse().unwrap()));
+        assert!(!is_public("fd00::1".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn failed_deliveries_back_off() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        user_migration::Migrator::up(&db, None).await.unwrap();
+        let user = User::create_user(&db, "Ada", "ada@example.com", "hash")
+            .await
+            .unwrap();
+        Ledger::ensure_personal(&db, &user).await.unwrap();
+        let rule_id = NotificationRule::upsert(
+            &db,
+            user.id,
+            user.id,
+            NotificationRuleReq {
+                id: None,
+                condition: NotificationCondition::LargeTransaction { threshold: 1 },
+                channels: vec![NotificationChannel::Webhook],
+                webhook_url: Some("http://127.0.0.1:9/hook".to_string()),
+                enabled: true,
+            },
+        )
+        .await
+        .unwrap();
+        let rule = NotificationRule::find_by_id(&db, rule_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let message = NotificationMessage {
+            dedupe_key: "large".to_string(),
+            title: "Large transaction".to_string(),
+            body: "Rent".to_string(),
+        };
+        Notification::enqueue(&db, &rule, &message).await.unwrap();
+        let notifier = Notifier {
+            db: db.clone(),
+            email: EmailChannel {
+                mailer: mailer(None),
+            },
+            webhook: WebhookChannel::new(false),
+        };
+
+        notifier.deliver_pending().await.unwrap();
+        let outbox = Notification::outbox(&db, user.id).await.unwrap();
+        assert_eq!(outbox[0].attempts, 1);
+        assert_eq!(outbox[0].status, NotificationStatus::Pending);
+        // Not retried before the delay is over.
+        assert!(Notification::pending(&db).await.unwrap().is_empty());
+        notifier.deliver_pending().await.unwrap();
+        let outbox = Notification::outbox(&db, user.id).await.unwrap();
+        assert_eq!(outbox[0].attempts, 1);
+
+        assert_eq!(retry_delay(1), chrono::TimeDelta::minutes(5));
+        assert_eq!(retry_delay(4), chrono::TimeDelta::minutes(40));
+    }
+
+    #[tokio::test]
+    async fn email_is_sent_over_smtp() {
+        let (url, mut received) = smtp_capture::start().await;
+        let channel = EmailChannel {
+            mailer: mailer(Some(url)),
+        };
+        channel
+            .deliver(&recipient(None), &notification(NotificationChannel::Email))
+            .await
+            .unwrap();
+        let message = received.recv().await.unwrap();
+        assert!(message.contains("To: ada@example.com"));
+        assert!(message.contains("Subject: Card due"));
+        assert!(message.contains("Pay 1.00 INR by tomorrow"));
+    }
+
+    #[tokio::test]
+    async fn email_without_smtp_is_skipped() {
+        let db = Database::connect("sqlite::memory:").await.unwrap();
+        user_migration::Migrator::up(&db, None).await.unwrap();
+        let user = User::create_user(&db, "Ada", "ada@example.com", "hash")
+            .await
+            .unwrap();
+        Ledger::ensure_personal(&db, &user).await.unwrap();
+        let rule_id = NotificationRule::upsert(
+            &db,
+            user.id,
+            user.id,
+            NotificationRuleReq {
+                id: None,
+                condition: NotificationCondition::CardDue {
+                    account_id: Uuid::now_v7(),
+                    days_before: 3,
+                },
+                channels: vec![NotificationChannel::Email],
+                webhook_url: None,
+                enabled: true,
+            },
+        )
+        .await
+        .unwrap();
+        let rule = NotificationRule::find_by_id(&db, rule_id)
+            .await
+            .unwrap()
+            .unwrap();
+        let message = NotificationMessage {
+            dedupe_key: "card_due".to_string(),
+            title: "Card due".to_string(),
+            body: "Pay 1.00 INR by tomorrow".to_string(),
+        };
+        Notification::enqueue(&db, &rule, &message).await.unwrap();
+
+        let notifier = Notifier {
+            db: db.clone(),
+            email: EmailChannel {
+                mailer: mailer(None),
+            },
+            webhook: WebhookChannel::new(false),
+        };
+        notifier.deliver_pending().await.unwrap();
+        let outbox = Notification::outbox(&db, user.id).await.unwrap();
+        assert_eq!(outbox.len(), 1);
+        assert_eq!(outbox[0].status, NotificationStatus::Skipped);
+        assert_eq!(
+            outbox[0].last_error.as_deref(),
+            Some("SMTP is not configured")
+        );
+        assert!(Notification::pending(&db).await.unwrap().is_empty());
+    }
+}
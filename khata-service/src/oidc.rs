@@ -0,0 +1,329 @@
+//! Single sign-on with an `OpenID` Connect provider using the authorization
+//! code flow with PKCE.
+//!
+//! The provider is configured with `KHATA_OIDC_ISSUER`,
+//! `KHATA_OIDC_CLIENT_ID` and `KHATA_OIDC_CLIENT_SECRET`. Its endpoints are
+//! read from `{issuer}/.well-known/openid-configuration`, so any issuer URL
+//! works, including a mock provider on `http://localhost`. Without an issuer
+//! single sign-on is turned off.
+
+use anyhow::Context;
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header,
+    jwk::{AlgorithmParameters, Jwk, JwkSet},
+};
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::{OnceCell, RwLock};
+
+use crate::API_URL;
+
+#[derive(Debug, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    #[serde(default = "default_signing_algs")]
+    id_token_signing_alg_values_supported: Vec<String>,
+}
+
+/// The ID token signing algorithm every provider has to support.
+fn default_signing_algs() -> Vec<String> {
+    vec!["RS256".to_string()]
+}
+
+impl ProviderMetadata {
+    /// The algorithms ID tokens may be signed with. Names this crate does not
+    /// know, including `none`, are left out.
+    fn signing_algs(&self) -> Vec<Algorithm> {
+        self.id_token_signing_alg_values_supported
+            .iter()
+            .filter_map(|alg| alg.parse().ok())
+            .collect()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// The claims of a verified ID token that are used to find or create the
+/// user.
+#[derive(Debug, Clone, Deserialize)]
+pub struct IdTokenClaims {
+    pub iss: String,
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub name: Option<String>,
+    nonce: Option<String>,
+}
+
+/// State of a sign in that was sent to the provider, kept in the session until
+/// the provider redirects back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OidcPending {
+    pub state: String,
+    nonce: String,
+    code_verifier: String,
+}
+
+pub struct OidcClient {
+    issuer: String,
+    client_id: String,
+    client_secret: SecretString,
+    redirect_uri: String,
+    http: Client,
+    metadata: OnceCell<ProviderMetadata>,
+    /// The provider's keys, fetched again when a token names an unknown key.
+    jwks: RwLock<Option<JwkSet>>,
+}
+
+fn random_string() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+impl OidcClient {
+    pub fn from_env() -> anyhow::Result<Option<Self>> {
+        let Ok(issuer) = std::env::var("KHATA_OIDC_ISSUER") else {
+            return Ok(None);
+        };
+        let client_id = std::env::var("KHATA_OIDC_CLIENT_ID")
+            .context("KHATA_OIDC_CLIENT_ID env var not set")?;
+        let client_secret = std::env::var("KHATA_OIDC_CLIENT_SECRET")
+            .context("KHATA_OIDC_CLIENT_SECRET env var not set")?;
+        Ok(Some(Self::new(
+            &issuer,
+            client_id,
+            client_secret.into(),
+            format!("{}/khata-api/api/oidc/callback", *API_URL),
+        )))
+    }
+
+    pub fn new(
+        issuer: &str,
+        client_id: String,
+        client_secret: SecretString,
+        redirect_uri: String,
+    ) -> Self {
+        Self {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            client_id,
+            client_secret,
+            redirect_uri,
+            http: Client::new(),
+            metadata: OnceCell::new(),
+            jwks: RwLock::new(None),
+        }
+    }
+
+    async fn metadata(&self) -> anyhow::Result<&ProviderMetadata> {
+        self.metadata
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", self.issuer);
+                let metadata: ProviderMetadata = self
+                    .http
+                    .get(&url)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .json()
+                    .await
+                    .context("Invalid OIDC provider metadata")?;
+                if metadata.issuer.trim_end_matches('/') != self.issuer {
+                    anyhow::bail!("OIDC issuer mismatch: {}", metadata.issuer);
+                }
+                Ok(metadata)
+            })
+            .await
+    }
+
+    /// Returns the URL to send the browser to and the state to keep until the
+    /// provider redirects back.
+    pub async fn authorize_url(&self) -> anyhow::Result<(Url, OidcPending)> {
+        let metadata = self.metadata().await?;
+        let pending = OidcPending {
+            state: random_string(),
+            nonce: random_string(),
+            code_verifier: random_string(),
+        };
+        let code_challenge =
+            URL_SAFE_NO_PAD.encode(Sha256::digest(pending.code_verifier.as_bytes()));
+        let url = Url::parse_with_params(
+            &metadata.authorization_endpoint,
+            &[
+                ("response_type", "code"),
+                ("client_id", self.client_id.as_str()),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("scope", "openid email profile"),
+                ("state", pending.state.as_str()),
+                ("nonce", pending.nonce.as_str()),
+                ("code_challenge", code_challenge.as_str()),
+                ("code_challenge_method", "S256"),
+            ],
+        )?;
+        Ok((url, pending))
+    }
+
+    /// Exchanges the authorization code for an ID token and verifies it.
+    pub async fn exchange(
+        &self,
+        code: &str,
+        pending: &OidcPending,
+    ) -> anyhow::Result<IdTokenClaims> {
+        let metadata = self.metadata().await?;
+        let token: TokenResponse = self
+            .http
+            .post(&metadata.token_endpoint)
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", self.redirect_uri.as_str()),
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.expose_secret()),
+                ("code_verifier", pending.code_verifier.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Invalid OIDC token response")?;
+
+        let header = decode_header(&token.id_token)?;
+        let algs = metadata.signing_algs();
+        if !algs.contains(&header.alg) {
+            anyhow::bail!("OIDC ID token signed with {:?}", header.alg);
+        }
+        let jwk = self
+            .signing_key(&metadata.jwks_uri, header.kid.as_deref())
+            .await?;
+        if !key_fits(&jwk, header.alg) {
+            anyhow::bail!("OIDC signing key does not fit {:?}", header.alg);
+        }
+        let mut validation = Validation::new(header.alg);
+        validation.algorithms = algs;
+        validation.set_issuer(&[&metadata.issuer]);
+        validation.set_audience(&[&self.client_id]);
+        let claims =
+            decode::<IdTokenClaims>(&token.id_token, &DecodingKey::from_jwk(&jwk)?, &validation)?
+                .claims;
+        if claims.nonce.as_deref() != Some(pending.nonce.as_str()) {
+            anyhow::bail!("OIDC nonce mismatch");
+        }
+        Ok(claims)
+    }
+
+    /// The provider's key named `kid`, or its first key if the token names
+    /// none. The key set is cached and only fetched again for an unknown key,
+    /// which is how providers roll over to new keys.
+    async fn signing_key(&self, jwks_uri: &str, kid: Option<&str>) -> anyhow::Result<Jwk> {
+        if let Some(jwk) = self
+            .jwks
+            .read()
+            .await
+            .as_ref()
+            .and_then(|j| find_key(j, kid))
+        {
+            return Ok(jwk.clone());
+        }
+        let jwks: JwkSet = self
+            .http
+            .get(jwks_uri)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .context("Invalid OIDC key set")?;
+        let jwk = find_key(&jwks, kid).cloned();
+        *self.jwks.write().await = Some(jwks);
+        jwk.context("Unknown OIDC signing key")
+    }
+}
+
+fn find_key<'a>(jwks: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    kid.map_or_else(|| jwks.keys.first(), |kid| jwks.find(kid))
+}
+
+/// Whether the key type fits `alg` and the key, if it names an algorithm,
+/// names the same one.
+fn key_fits(jwk: &Jwk, alg: Algorithm) -> bool {
+    let kty_fits = matches!(
+        (&jwk.algorithm, alg),
+        (
+            AlgorithmParameters::RSA(_),
+            Algorithm::RS256
+                | Algorithm::RS384
+                | Algorithm::RS512
+                | Algorithm::PS256
+                | Algorithm::PS384
+                | Algorithm::PS512
+        ) | (
+            AlgorithmParameters::EllipticCurve(_),
+            Algorithm::ES256 | Algorithm::ES384
+        ) | (
+            AlgorithmParameters::OctetKey(_),
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) | (AlgorithmParameters::OctetKeyPair(_), Algorithm::EdDSA)
+    );
+    let alg_fits = jwk
+        .common
+        .key_algorithm
+        .is_none_or(|key_alg| key_alg.to_string() == format!("{alg:?}"));
+    kty_fits && alg_fits
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn providers_sign_with_rs256_unless_they_say_otherwise() {
+        let metadata = |algs: Option<serde_json::Value>| {
+            let mut metadata = json!({
+                "issuer": "https://id.example.com",
+                "authorization_endpoint": "https://id.example.com/authorize",
+                "token_endpoint": "https://id.example.com/token",
+                "jwks_uri": "https://id.example.com/jwks",
+            });
+            if let Some(algs) = algs {
+                metadata["id_token_signing_alg_values_supported"] = algs;
+            }
+            serde_json::from_value::<ProviderMetadata>(metadata)
+                .unwrap()
+                .signing_algs()
+        };
+        assert_eq!(metadata(None), vec![Algorithm::RS256]);
+        assert_eq!(
+            metadata(Some(json!(["none", "ES256", "RS256"]))),
+            vec![Algorithm::ES256, Algorithm::RS256]
+        );
+    }
+
+    #[test]
+    fn keys_must_fit_the_algorithm() {
+        let key = |jwk: serde_json::Value| serde_json::from_value::<Jwk>(jwk).unwrap();
+        let oct = key(json!({ "kty": "oct", "k": "c2VjcmV0" }));
+        assert!(key_fits(&oct, Algorithm::HS256));
+        assert!(!key_fits(&oct, Algorithm::RS256));
+        let oct_hs512 = key(json!({ "kty": "oct", "alg": "HS512", "k": "c2VjcmV0" }));
+        assert!(!key_fits(&oct_hs512, Algorithm::HS256));
+        let rsa = key(json!({ "kty": "RSA", "alg": "RS256", "n": "AQAB", "e": "AQAB" }));
+        assert!(key_fits(&rsa, Algorithm::RS256));
+        assert!(!key_fits(&rsa, Algorithm::PS256));
+        assert!(!key_fits(&rsa, Algorithm::HS256));
+    }
+}
@@ -0,0 +1,310 @@
+//! Date bucketing in the user's time zone.
+//!
+//! Transactions are stored with UTC timestamps, but a day, a month or a
+//! budget period starts at local midnight. Local dates are turned into
+//! instants with `jiff`, so days that start inside a DST gap begin at the
+//! first instant that exists and days are 23 or 25 hours long around the
+//! transitions.
+
+use anyhow::Context;
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use jiff::{Timestamp, Zoned, civil, tz::TimeZone};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::model::user_settings::UserSettings;
+
+fn to_civil(date: NaiveDate) -> anyhow::Result<civil::Date> {
+    let year = i16::try_from(date.year()).context("Year out of range")?;
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    Ok(civil::Date::new(
+        year,
+        date.month() as i8,
+        date.day() as i8,
+    )?)
+}
+
+fn from_civil(date: civil::Date) -> NaiveDate {
+    NaiveDate::from_ymd_opt(
+        i32::from(date.year()),
+        date.month() as u32,
+        date.day() as u32,
+    )
+    .unwrap_or(NaiveDate::MIN)
+}
+
+/// The current date in the time zone.
+pub fn today(tz: &TimeZone) -> NaiveDate {
+    from_civil(Zoned::now().with_time_zone(tz.clone()).date())
+}
+
+/// The local date of an instant.
+pub fn local_date(tz: &TimeZone, at: DateTime<Utc>) -> NaiveDate {
+    Timestamp::from_second(at.timestamp()).map_or_else(
+        |_| at.date_naive(),
+        |t| from_civil(t.to_zoned(tz.clone()).date()),
+    )
+}
+
+/// The instant the local date starts at.
+pub fn start_of_day(tz: &TimeZone, date: NaiveDate) -> anyhow::Result<DateTime<Utc>> {
+    let timestamp = to_civil(date)?.to_zoned(tz.clone())?.timestamp();
+    DateTime::from_timestamp(timestamp.as_second(), 0).context("Date out of range")
+}
+
+pub fn first_of_month(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).unwrap_or(date)
+}
+
+/// Moves by whole months, clamping the day to the end of shorter months.
+pub fn add_months(date: NaiveDate, months: i32) -> NaiveDate {
+    let shifted = if months >= 0 {
+        date.checked_add_months(Months::new(months.unsigned_abs()))
+    } else {
+        date.checked_sub_months(Months::new(months.unsigned_abs()))
+    };
+    shifted.unwrap_or(if months >= 0 {
+        NaiveDate::MAX
+    } else {
+        NaiveDate::MIN
+    })
+}
+
+/// Local dates from `start` up to, but not including, `end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize)]
+pub struct Period {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl Period {
+    /// The calendar month the date is in.
+    pub fn month_of(date: NaiveDate) -> Self {
+        let start = first_of_month(date);
+        Self {
+            start,
+            end: add_months(start, 1),
+        }
+    }
+
+    /// The period of the same length in months `months` away.
+    pub fn shift_months(self, months: i32) -> Self {
+        Self {
+            start: add_months(self.start, months),
+            end: add_months(self.end, months),
+        }
+    }
+
+    /// The instants the period starts and ends at in the time zone, for
+    /// queries on UTC timestamps.
+    pub fn bounds(self, tz: &TimeZone) -> anyhow::Result<(DateTime<Utc>, DateTime<Utc>)> {
+        Ok((start_of_day(tz, self.start)?, start_of_day(tz, self.end)?))
+    }
+}
+
+/// How reports and budgets cut time into periods.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PeriodKind {
+    /// Calendar months.
+    #[default]
+    Month,
+    /// Years starting in the fiscal year start month of the user.
+    FiscalYear,
+    /// Months starting on the cycle day of the user, e.g. a pay day.
+    Cycle,
+}
+
+/// A [`PeriodKind`] with the settings it depends on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Periods {
+    Month,
+    FiscalYear { start_month: u32 },
+    Cycle { day: u32 },
+}
+
+impl Periods {
+    pub fn new(kind: PeriodKind, settings: &UserSettings) -> Self {
+        match kind {
+            PeriodKind::Month => Self::Month,
+            PeriodKind::FiscalYear => Self::FiscalYear {
+                start_month: u32::from(settings.fiscal_year_start_month).clamp(1, 12),
+            },
+            PeriodKind::Cycle => Self::Cycle {
+                day: u32::from(settings.cycle_day).clamp(1, 28),
+            },
+        }
+    }
+
+    const fn months(self) -> i32 {
+        match self {
+            Self::Month | Self::Cycle { .. } => 1,
+            Self::FiscalYear { .. } => 12,
+        }
+    }
+
+    /// The period the date is in.
+    pub fn containing(self, date: NaiveDate) -> Period {
+        let start = match self {
+            Self::Month => first_of_month(date),
+            Self::FiscalYear { start_month } => {
+                let year = if date.month() >= start_month {
+                    date.year()
+                } else {
+                    date.year() - 1
+                };
+                NaiveDate::from_ymd_opt(year, start_month, 1).unwrap_or(date)
+            }
+            Self::Cycle { day } => {
+                let start = date.with_day(day).unwrap_or(date);
+                if date.day() >= day {
+                    start
+                } else {
+                    add_months(start, -1)
+                }
+            }
+        };
+        Period {
+            start,
+            end: add_months(start, self.months()),
+        }
+    }
+
+    /// The period before the given one.
+    pub fn previous(self, period: Period) -> Period {
+        period.shift_months(-self.months())
+    }
+
+    /// The last `count` periods up to and including the one the date is in,
+    /// oldest first.
+    pub fn last(self, date: NaiveDate, count: u32) -> Vec<Period> {
+        let current = self.containing(date);
+        (0..i32::try_from(count).unwrap_or(i32::MAX))
+            .rev()
+            .map(|back| current.shift_months(-back * self.months()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use chrono::TimeZone as _;
+
+    use super::*;
+
+    fn tz(name: &str) -> TimeZone {
+        TimeZone::get(name).unwrap()
+    }
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn month_starts_at_local_midnight() {
+        let ist = tz("Asia/Kolkata");
+        let (start, end) = Period::month_of(date(2026, 11, 15)).bounds(&ist).unwrap();
+        assert_eq!(start, utc(2026, 10, 31, 18, 30));
+        assert_eq!(end, utc(2026, 11, 30, 18, 30));
+    }
+
+    #[test]
+    fn early_morning_on_the_first_is_in_the_new_month() {
+        let ist = tz("Asia/Kolkata");
+        // 01:00 IST on 1 November.
+        let at = utc(2026, 10, 31, 19, 30);
+        assert_eq!(local_date(&ist, at), date(2026, 11, 1));
+        assert_eq!(at.date_naive(), date(2026, 10, 31));
+        let (start, _) = Period::month_of(local_date(&ist, at)).bounds(&ist).unwrap();
+        assert!(start <= at);
+    }
+
+    #[test]
+    fn month_spanning_spring_forward_is_an_hour_short() {
+        let new_york = tz("America/New_York");
+        let (start, end) = Period::month_of(date(2026, 3, 10))
+            .bounds(&new_york)
+            .unwrap();
+        assert_eq!(start, utc(2026, 3, 1, 5, 0));
+        assert_eq!(end, utc(2026, 4, 1, 4, 0));
+        assert_eq!((end - start).num_hours(), 31 * 24 - 1);
+    }
+
+    #[test]
+    fn month_spanning_fall_back_is_an_hour_long() {
+        let london = tz("Europe/London");
+        let (start, end) = Period::month_of(date(2026, 10, 1)).bounds(&london).unwrap();
+        assert_eq!(start, utc(2026, 9, 30, 23, 0));
+        assert_eq!(end, utc(2026, 11, 1, 0, 0));
+        assert_eq!((end - start).num_hours(), 31 * 24 + 1);
+    }
+
+    #[test]
+    fn day_starting_in_a_dst_gap_starts_at_the_first_valid_instant() {
+        // Clocks in São Paulo jumped from 00:00 to 01:00 on 4 November 2018.
+        let sao_paulo = tz("America/Sao_Paulo");
+        let start = start_of_day(&sao_paulo, date(2018, 11, 4)).unwrap();
+        assert_eq!(start, utc(2018, 11, 4, 3, 0));
+        assert_eq!(local_date(&sao_paulo, start), date(2018, 11, 4));
+        assert_eq!(
+            local_date(&sao_paulo, start - chrono::Duration::seconds(1)),
+            date(2018, 11, 3)
+        );
+    }
+
+    #[test]
+    fn months_clamp_to_shorter_months() {
+        assert_eq!(add_months(date(2026, 1, 31), 1), date(2026, 2, 28));
+        assert_eq!(add_months(date(2024, 3, 31), -1), date(2024, 2, 29));
+        let january = Period::month_of(date(2026, 1, 20));
+        assert_eq!(
+            january.shift_months(-1),
+            Period {
+                start: date(2025, 12, 1),
+                end: date(2026, 1, 1),
+            }
+        );
+    }
+
+    #[test]
+    fn fiscal_year_starts_in_april() {
+        let periods = Periods::FiscalYear { start_month: 4 };
+        let expected = Period {
+            start: date(2026, 4, 1),
+            end: date(2027, 4, 1),
+        };
+        assert_eq!(periods.containing(date(2026, 4, 1)), expected);
+        assert_eq!(periods.containing(date(2027, 3, 31)), expected);
+        assert_eq!(
+            periods.containing(date(2026, 3, 31)).start,
+            date(2025, 4, 1)
+        );
+    }
+
+    #[test]
+    fn cycle_starts_on_the_cycle_day() {
+        let periods = Periods::Cycle { day: 25 };
+        assert_eq!(
+            periods.containing(date(2026, 1, 10)),
+            Period {
+                start: date(2025, 12, 25),
+                end: date(2026, 1, 25),
+            }
+        );
+        assert_eq!(
+            periods.containing(date(2026, 1, 25)).start,
+            date(2026, 1, 25)
+        );
+        let last = periods.last(date(2026, 3, 1), 3);
+        assert_eq!(
+            last.iter().map(|p| p.start).collect::<Vec<_>>(),
+            [date(2025, 12, 25), date(2026, 1, 25), date(2026, 2, 25)]
+        );
+    }
+}
@@ -0,0 +1,824 @@
+//! Aggregates transaction items by the dimensions and measures a client asks
+//! for, so new charts do not each need a handler.
+//!
+//! A [`PivotQuery`] is compiled into one grouped query over the items joined
+//! with their transaction, account and category. Values only ever reach the
+//! database as bound parameters. Time buckets are computed here in the time
+//! zone of the user and passed in as UTC ranges, so they stay correct across
+//! DST transitions.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{Datelike, Days, NaiveDate};
+use migration::AccountType;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DbConn, DbErr, EntityTrait, JoinType, Order, QueryFilter,
+    QueryOrder, QueryResult, QuerySelect, QueryTrait, RelationTrait,
+    sea_query::{CaseStatement, Expr, SimpleExpr},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidationError, ValidationErrors};
+
+use crate::{
+    cache::CacheManager,
+    entity::{account, category, transaction, transaction_item},
+    model::{
+        currency::CurrencyReq, transaction::TransactionItemEntity, user_settings::UserSettings,
+    },
+    period,
+    report::Converter,
+};
+
+/// Most time buckets a query may produce.
+const MAX_BUCKETS: u64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dimension {
+    Category,
+    CategoryGroup,
+    Account,
+    AccountType,
+    Currency,
+    /// The title of the transaction.
+    Payee,
+    Day,
+    /// Weeks starting on the first day of the week of the user.
+    Week,
+    Month,
+    Year,
+}
+
+impl Dimension {
+    const fn is_time(self) -> bool {
+        matches!(self, Self::Day | Self::Week | Self::Month | Self::Year)
+    }
+
+    /// Whether rows are grouped by an id, with the name in a column of its
+    /// own, so clients can tell which category or account a row is.
+    const fn has_id(self) -> bool {
+        matches!(self, Self::Category | Self::Account)
+    }
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Category => "category",
+            Self::CategoryGroup => "category_group",
+            Self::Account => "account",
+            Self::AccountType => "account_type",
+            Self::Currency => "currency",
+            Self::Payee => "payee",
+            Self::Day => "day",
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::Year => "year",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Measure {
+    Sum,
+    Count,
+    Avg,
+    Min,
+    Max,
+}
+
+impl Measure {
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Sum => "sum",
+            Self::Count => "count",
+            Self::Avg => "avg",
+            Self::Min => "min",
+            Self::Max => "max",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Inflow,
+    Outflow,
+}
+
+/// Which items are aggregated. Empty lists do not filter.
+#[derive(Debug, Clone, Default, ToSchema, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PivotFilter {
+    /// First local date, needed for time dimensions.
+    pub from: Option<NaiveDate>,
+    /// Last local date, today by default.
+    pub to: Option<NaiveDate>,
+    pub account_ids: Vec<Uuid>,
+    pub account_types: Vec<AccountType>,
+    pub category_ids: Vec<Uuid>,
+    pub category_groups: Vec<String>,
+    pub currency_codes: Vec<String>,
+    /// Part of the transaction title.
+    pub payee: Option<String>,
+    pub direction: Option<Direction>,
+}
+
+fn validate_pivot(query: &PivotQuery) -> Result<(), ValidationError> {
+    let unique = query.dimensions.iter().collect::<HashSet<_>>();
+    if unique.len() != query.dimensions.len() {
+        return Err(ValidationError::new("dimensions").with_message("Duplicate dimension".into()));
+    }
+    let time = query
+        .dimensions
+        .iter()
+        .filter(|d| d.is_time())
+        .collect::<Vec<_>>();
+    if time.len() > 1 {
+        return Err(ValidationError::new("dimensions")
+            .with_message("Only one time dimension can be used".into()));
+    }
+    if query.filter.from.is_none() && !time.is_empty() {
+        return Err(
+            ValidationError::new("filter").with_message("Time dimensions need a from date".into())
+        );
+    }
+    if query
+        .convert_to
+        .as_deref()
+        .is_some_and(|code| code.len() != 3 || !code.chars().all(|c| c.is_ascii_uppercase()))
+    {
+        return Err(ValidationError::new("convert_to").with_message("Invalid currency code".into()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_pivot"))]
+pub struct PivotQuery {
+    #[validate(length(max = 4))]
+    #[serde(default)]
+    pub dimensions: Vec<Dimension>,
+    #[validate(length(min = 1, max = 5))]
+    pub measures: Vec<Measure>,
+    #[serde(default)]
+    pub filter: PivotFilter,
+    /// Currency code to convert amounts to at the rate of the last day.
+    /// Without it rows are always split by currency.
+    pub convert_to: Option<String>,
+}
+
+impl PivotQuery {
+    /// The last local date of the query, `today` unless the filter has one.
+    pub fn to(&self, today: NaiveDate) -> NaiveDate {
+        self.filter.to.unwrap_or(today)
+    }
+
+    /// Checks the date range against `today` of the user, which the
+    /// validation of the body cannot know.
+    pub fn validate_range(
+        &self,
+        settings: &UserSettings,
+        today: NaiveDate,
+    ) -> Result<(), ValidationErrors> {
+        let Some(from) = self.filter.from else {
+            return Ok(());
+        };
+        let to = self.to(today);
+        let error = if from > to {
+            Some(ValidationError::new("range").with_message("from is after to".into()))
+        } else {
+            self.dimensions
+                .iter()
+                .find(|d| d.is_time())
+                .filter(|&&d| {
+                    bucket_count(d, from, to, settings.first_day_of_week.into()) > MAX_BUCKETS
+                })
+                .map(|_| {
+                    ValidationError::new("range")
+                        .with_message(format!("More than {MAX_BUCKETS} time buckets").into())
+                })
+        };
+        error.map_or(Ok(()), |error| {
+            let mut errors = ValidationErrors::new();
+            errors.add("filter", error);
+            Err(errors)
+        })
+    }
+}
+
+/// One column per dimension and then one per measure. Categories and accounts
+/// take two columns, `category_id` and `category` with the name. Amounts are
+/// in major units; dimension values are `null` for items without a category.
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct PivotResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    /// The currency all amounts are in, if they were converted.
+    pub currency: Option<String>,
+}
+
+fn column_expr(dimension: Dimension) -> SimpleExpr {
+    match dimension {
+        Dimension::Category => Expr::col((category::Entity, category::Column::Id)).into(),
+        Dimension::CategoryGroup => Expr::col((category::Entity, category::Column::Group)).into(),
+        Dimension::Account => Expr::col((account::Entity, account::Column::Id)).into(),
+        Dimension::AccountType => Expr::col((account::Entity, account::Column::AccountType)).into(),
+        Dimension::Currency => Expr::col((account::Entity, account::Column::CurrencyCode)).into(),
+        Dimension::Payee => Expr::col((transaction::Entity, transaction::Column::Title)).into(),
+        Dimension::Day | Dimension::Week | Dimension::Month | Dimension::Year => {
+            Expr::val(None::<String>).into()
+        }
+    }
+}
+
+/// The name shown next to the id of a dimension that [`Dimension::has_id`].
+fn name_expr(dimension: Dimension) -> Option<SimpleExpr> {
+    match dimension {
+        Dimension::Category => Some(Expr::col((category::Entity, category::Column::Name)).into()),
+        Dimension::Account => Some(Expr::col((account::Entity, account::Column::Name)).into()),
+        _ => None,
+    }
+}
+
+fn bucket_start(dimension: Dimension, date: NaiveDate, first_day: chrono::Weekday) -> NaiveDate {
+    match dimension {
+        Dimension::Week => {
+            let back =
+                (7 + date.weekday().num_days_from_monday() - first_day.num_days_from_monday()) % 7;
+            date - Days::new(u64::from(back))
+        }
+        Dimension::Month => period::first_of_month(date),
+        Dimension::Year => NaiveDate::from_ymd_opt(date.year(), 1, 1).unwrap_or(date),
+        _ => date,
+    }
+}
+
+fn next_bucket(dimension: Dimension, start: NaiveDate) -> NaiveDate {
+    match dimension {
+        Dimension::Week => start + Days::new(7),
+        Dimension::Month => period::add_months(start, 1),
+        Dimension::Year => period::add_months(start, 12),
+        _ => start + Days::new(1),
+    }
+}
+
+/// How many buckets of `dimension` cover `from` to `to`, counting no further
+/// than one past [`MAX_BUCKETS`].
+fn bucket_count(
+    dimension: Dimension,
+    from: NaiveDate,
+    to: NaiveDate,
+    first_day: chrono::Weekday,
+) -> u64 {
+    let mut count = 0;
+    let mut start = bucket_start(dimension, from, first_day);
+    while start <= to && count <= MAX_BUCKETS {
+        count += 1;
+        start = next_bucket(dimension, start);
+    }
+    count
+}
+
+fn bucket_label(dimension: Dimension, start: NaiveDate) -> String {
+    match dimension {
+        Dimension::Month => start.format("%Y-%m").to_string(),
+        Dimension::Year => start.format("%Y").to_string(),
+        _ => start.to_string(),
+    }
+}
+
+/// Labels each item with the local time bucket its transaction falls in.
+fn time_expr(
+    dimension: Dimension,
+    settings: &UserSettings,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> anyhow::Result<SimpleExpr> {
+    let tz = settings.tz();
+    let timestamp = Expr::col((transaction::Entity, transaction::Column::Timestamp));
+    let mut case = CaseStatement::new();
+    let mut start = bucket_start(dimension, from, settings.first_day_of_week.into());
+    while start <= to {
+        let end = next_bucket(dimension, start);
+        case = case.case(
+            timestamp
+                .clone()
+                .gte(period::start_of_day(&tz, start)?)
+                .and(timestamp.clone().lt(period::start_of_day(&tz, end)?)),
+            Expr::val(bucket_label(dimension, start)),
+        );
+        start = end;
+    }
+    Ok(case.finally(Expr::val(None::<String>)).into())
+}
+
+/// Totals of one output row, in major units.
+#[derive(Debug, Default)]
+struct Totals {
+    sum: f64,
+    count: i64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Totals {
+    fn merge(&mut self, sum: f64, count: i64, min: f64, max: f64) {
+        self.sum += sum;
+        self.count += count;
+        self.min = Some(self.min.map_or(min, |m| m.min(min)));
+        self.max = Some(self.max.map_or(max, |m| m.max(max)));
+    }
+
+    fn value(&self, measure: Measure) -> Value {
+        match measure {
+            Measure::Sum => self.sum.into(),
+            Measure::Count => self.count.into(),
+            #[allow(clippy::cast_precision_loss)]
+            Measure::Avg if self.count > 0 => (self.sum / self.count as f64).into(),
+            Measure::Avg => Value::Null,
+            Measure::Min => self.min.map_or(Value::Null, Value::from),
+            Measure::Max => self.max.map_or(Value::Null, Value::from),
+        }
+    }
+}
+
+/// An aggregate of a row. Aggregates are read by type, as `into_json` leaves
+/// out columns of expressions, which have no declared type in the database.
+#[allow(clippy::cast_precision_loss)]
+fn number(row: &QueryResult, key: &str) -> Result<f64, DbErr> {
+    Ok(row.try_get::<Option<i64>>("", key)?.unwrap_or_default() as f64)
+}
+
+impl PivotQuery {
+    pub async fn run(
+        &self,
+        db: &DbConn,
+        cache: &Mutex<CacheManager>,
+        settings: &UserSettings,
+        today: NaiveDate,
+    ) -> anyhow::Result<PivotResult> {
+        let tz = settings.tz();
+        let to = self.to(today);
+        // Amounts in different currencies are never added up unconverted.
+        let mut dimensions = self.dimensions.clone();
+        if self.convert_to.is_none() && !dimensions.contains(&Dimension::Currency) {
+            dimensions.push(Dimension::Currency);
+        }
+
+        let mut query = TransactionItemEntity::find()
+            .select_only()
+            .join(
+                JoinType::InnerJoin,
+                transaction_item::Relation::Transaction.def(),
+            )
+            .join(
+                JoinType::InnerJoin,
+                transaction_item::Relation::Account.def(),
+            )
+            .join(
+                JoinType::LeftJoin,
+                transaction_item::Relation::Category.def(),
+            );
+        for (index, &dimension) in dimensions.iter().enumerate() {
+            let expr = match (dimension.is_time(), self.filter.from) {
+                (true, Some(from)) => time_expr(dimension, settings, from, to)?,
+                _ => column_expr(dimension),
+            };
+            query = query
+                .expr_as(expr.clone(), format!("d{index}"))
+                .group_by(expr.clone());
+            if let Some(name) = name_expr(dimension) {
+                query = query
+                    .expr_as(name.clone(), format!("n{index}"))
+                    .group_by(name.clone())
+                    .order_by(name, Order::Asc);
+            }
+            query = query.order_by(expr, Order::Asc);
+        }
+        let currency = column_expr(Dimension::Currency);
+        let amount = Expr::col((transaction_item::Entity, transaction_item::Column::Amount));
+        query = query
+            .expr_as(currency.clone(), "currency_code")
+            .group_by(currency)
+            .expr_as(amount.clone().sum(), "amount_sum")
+            .expr_as(amount.clone().count(), "amount_count")
+            .expr_as(amount.clone().min(), "amount_min")
+            .expr_as(amount.max(), "amount_max");
+
+        let filter = &self.filter;
+        if let Some(from) = filter.from {
+            query =
+                query.filter(transaction::Column::Timestamp.gte(period::start_of_day(&tz, from)?));
+        }
+        query = query.filter(
+            transaction::Column::Timestamp.lt(period::start_of_day(&tz, to + Days::new(1))?),
+        );
+        if !filter.account_ids.is_empty() {
+            query = query.filter(
+                transaction_item::Column::AccountId.is_in(filter.account_ids.iter().copied()),
+            );
+        }
+        if !filter.account_types.is_empty() {
+            let types = filter
+                .account_types
+                .iter()
+                .map(serde_json::to_string)
+                .collect::<Result<Vec<_>, _>>()?;
+            query = query.filter(account::Column::AccountType.is_in(types));
+        }
+        if !filter.category_ids.is_empty() {
+            query = query.filter(
+                transaction_item::Column::CategoryId.is_in(filter.category_ids.iter().copied()),
+            );
+        }
+        if !filter.category_groups.is_empty() {
+            query = query.filter(category::Column::Group.is_in(filter.category_groups.clone()));
+        }
+        if !filter.currency_codes.is_empty() {
+            query =
+                query.filter(account::Column::CurrencyCode.is_in(filter.currency_codes.clone()));
+        }
+        if let Some(payee) = &filter.payee {
+            query = query.filter(transaction::Column::Title.contains(payee));
+        }
+        match filter.direction {
+            Some(Direction::Inflow) => query = query.filter(transaction_item::Column::Amount.gt(0)),
+            Some(Direction::Outflow) => {
+                query = query.filter(transaction_item::Column::Amount.lt(0));
+            }
+            None => {}
+        }
+        let rows = db.query_all(query.build(db.get_database_backend())).await?;
+
+        let digits = CurrencyReq::find_all(db)
+            .await?
+            .into_iter()
+            .map(|c| (c.0.code, c.0.decimal_digits))
+            .collect::<HashMap<_, _>>();
+        let converter = match &self.convert_to {
+            Some(base) => {
+                let codes = digits.keys().cloned().collect::<HashSet<_>>();
+                Some(Converter::new(cache, base, &codes, to).await?)
+            }
+            None => None,
+        };
+
+        // Rows split only by the hidden currency are merged after conversion.
+        let mut merged = Vec::<(Vec<Value>, Totals)>::new();
+        let mut positions = HashMap::<String, usize>::new();
+        for row in rows {
+            let mut key = vec![];
+            for (index, &dimension) in dimensions.iter().enumerate() {
+                let column = format!("d{index}");
+                if dimension.has_id() {
+                    let id = row.try_get::<Option<Uuid>>("", &column)?;
+                    let name = row.try_get::<Option<String>>("", &format!("n{index}"))?;
+                    key.push(id.map_or(Value::Null, |id| Value::String(id.to_string())));
+                    key.push(name.map_or(Value::Null, Value::String));
+                    continue;
+                }
+                let value = row.try_get::<Option<String>>("", &column)?;
+                key.push(match (dimension, value) {
+                    // Account types are stored as JSON strings.
+                    (Dimension::AccountType, Some(value)) => {
+                        serde_json::from_str(&value).unwrap_or(Value::String(value))
+                    }
+                    (_, value) => value.map_or(Value::Null, Value::String),
+                });
+            }
+            let code = row
+                .try_get::<Option<String>>("", "currency_code")?
+                .unwrap_or_default();
+            let code = code.as_str();
+            let scale = 10_f64.powi(digits.get(code).copied().unwrap_or_default());
+            let convert = |amount: f64| {
+                converter.as_ref().map_or_else(
+                    || Ok(amount / scale),
+                    |converter| converter.convert_major(amount / scale, code),
+                )
+            };
+            let count = row.try_get::<i64>("", "amount_count")?;
+            let position = *positions
+                .entry(serde_json::to_string(&key)?)
+                .or_insert_with(|| {
+                    merged.push((key, Totals::default()));
+                    merged.len() - 1
+                });
+            merged[position].1.merge(
+                convert(number(&row, "amount_sum")?)?,
+                count,
+                convert(number(&row, "amount_min")?)?,
+                convert(number(&row, "amount_max")?)?,
+            );
+        }
+
+        let columns = dimensions
+            .iter()
+            .flat_map(|d| {
+                let id = d.has_id().then(|| format!("{}_id", d.name()));
+                id.into_iter().chain([d.name().to_string()])
+            })
+            .chain(self.measures.iter().map(|m| m.name().to_string()))
+            .collect();
+        let rows = merged
+            .into_iter()
+            .map(|(key, totals)| {
+                key.into_iter()
+                    .chain(self.measures.iter().map(|&m| totals.value(m)))
+                    .collect()
+            })
+            .collect();
+        Ok(PivotResult {
+            columns,
+            rows,
+            currency: self.convert_to.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::{DateTime, Utc, Weekday};
+    use serde_json::json;
+
+    use super::*;
+    use crate::{model::user_settings, test_ledger};
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn at(timestamp: &str) -> DateTime<Utc> {
+        timestamp.parse().unwrap()
+    }
+
+    fn query(dimensions: Vec<Dimension>, from: NaiveDate, to: NaiveDate) -> PivotQuery {
+        PivotQuery {
+            dimensions,
+            measures: vec![Measure::Sum, Measure::Count],
+            filter: PivotFilter {
+                from: Some(from),
+                to: Some(to),
+                ..PivotFilter::default()
+            },
+            convert_to: None,
+        }
+    }
+
+    /// A cache with the rates of `date` in a fresh directory, so nothing is
+    /// downloaded.
+    fn cache_with_rates(date: NaiveDate, rates: &[(&str, f64)]) -> Mutex<CacheManager> {
+        let dir = std::env::temp_dir().join(format!("khata-pivot-{}", Uuid::now_v7()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let data = rates
+            .iter()
+            .map(|&(code, value)| (code.to_string(), json!({ "code": code, "value": value })))
+            .collect::<serde_json::Map<_, _>>();
+        std::fs::write(
+            dir.join(format!("historical_{date}.json")),
+            json!({ "data": data }).to_string(),
+        )
+        .unwrap();
+        Mutex::new(CacheManager::new(dir, String::new()))
+    }
+
+    #[test]
+    fn week_starts_on_the_first_day_of_the_user() {
+        // 2026-10-15 is a Thursday.
+        let thursday = date(2026, 10, 15);
+        assert_eq!(
+            bucket_start(Dimension::Week, thursday, Weekday::Mon),
+            date(2026, 10, 12)
+        );
+        assert_eq!(
+            bucket_start(Dimension::Week, thursday, Weekday::Sun),
+            date(2026, 10, 11)
+        );
+        assert_eq!(
+            bucket_start(Dimension::Week, thursday, Weekday::Fri),
+            date(2026, 10, 9)
+        );
+        assert_eq!(
+            bucket_start(Dimension::Week, thursday, Weekday::Thu),
+            thursday
+        );
+        assert_eq!(
+            bucket_start(Dimension::Month, thursday, Weekday::Mon),
+            date(2026, 10, 1)
+        );
+        assert_eq!(
+            bucket_start(Dimension::Year, thursday, Weekday::Mon),
+            date(2026, 1, 1)
+        );
+    }
+
+    #[test]
+    fn every_time_dimension_is_capped() {
+        let settings = UserSettings::default();
+        let today = date(2026, 10, 19);
+        let mut long = query(vec![Dimension::Year], date(1, 1, 1), today);
+        assert!(long.validate_range(&settings, today).is_err());
+        long.dimensions = vec![Dimension::Month];
+        assert!(long.validate_range(&settings, today).is_err());
+        long.filter.from = Some(date(1990, 1, 1));
+        assert!(long.validate_range(&settings, today).is_ok());
+        // Without a time dimension the range is not bucketed.
+        long.dimensions = vec![Dimension::Category];
+        long.filter.from = Some(date(1, 1, 1));
+        assert!(long.validate_range(&settings, today).is_ok());
+
+        // The last allowed day.
+        let mut days = query(vec![Dimension::Day], date(2024, 1, 1), today);
+        days.filter.to = Some(date(2024, 1, 1) + Days::new(MAX_BUCKETS - 1));
+        assert!(days.validate_range(&settings, today).is_ok());
+        days.filter.to = Some(date(2024, 1, 1) + Days::new(MAX_BUCKETS));
+        assert!(days.validate_range(&settings, today).is_err());
+
+        // The range ends today of the user when it has no end.
+        let mut open = query(vec![Dimension::Day], date(2026, 10, 20), today);
+        open.filter.to = None;
+        assert!(open.validate_range(&settings, today).is_err());
+        assert!(open.validate_range(&settings, date(2026, 10, 20)).is_ok());
+    }
+
+    #[tokio::test]
+    async fn time_buckets_split_at_local_midnight() {
+        let db = test_ledger::ledger().await;
+        let bank = test_ledger::account(&db, "Bank", AccountType::Bank, "INR", true).await;
+        // New York moves to summer time on 2026-03-08, so that day ends at
+        // 04:00 UTC instead of 05:00.
+        for timestamp in [
+            "2026-03-08T04:59:59Z",
+            "2026-03-08T05:00:00Z",
+            "2026-03-09T03:59:59Z",
+            "2026-03-09T04:00:00Z",
+        ] {
+            test_ledger::transaction_at(&db, "Coffee", at(timestamp), &[(bank, None, -100)]).await;
+        }
+        let settings = UserSettings {
+            time_zone: "America/New_York".to_string(),
+            first_day_of_week: user_settings::Weekday::Sunday,
+            ..UserSettings::default()
+        };
+        let cache = Mutex::new(CacheManager::new(PathBuf::new(), String::new()));
+        let today = date(2026, 10, 19);
+
+        let days = query(vec![Dimension::Day], date(2026, 3, 7), date(2026, 3, 9))
+            .run(&db, &cache, &settings, today)
+            .await
+            .unwrap();
+        assert_eq!(days.columns, ["day", "currency", "sum", "count"]);
+        assert_eq!(
+            days.rows,
+            [
+                vec![json!("2026-03-07"), json!("INR"), json!(-1.0), json!(1)],
+                vec![json!("2026-03-08"), json!("INR"), json!(-2.0), json!(2)],
+                vec![json!("2026-03-09"), json!("INR"), json!(-1.0), json!(1)],
+            ]
+        );
+
+        // Sunday weeks: the Saturday falls in the week before.
+        let weeks = query(vec![Dimension::Week], date(2026, 3, 1), date(2026, 3, 14))
+            .run(&db, &cache, &settings, today)
+            .await
+            .unwrap();
+        assert_eq!(
+            weeks.rows,
+            [
+                vec![json!("2026-03-01"), json!("INR"), json!(-1.0), json!(1)],
+                vec![json!("2026-03-08"), json!("INR"), json!(-3.0), json!(3)],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn groups_measures_and_merges_currencies_after_conversion() {
+        let db = test_ledger::ledger().await;
+        let bank = test_ledger::account(&db, "Bank", AccountType::Bank, "INR", true).await;
+        let card = test_ledger::account(&db, "Card", AccountType::CreditCard, "USD", true).await;
+        let food = test_ledger::category(&db, "Food", "Living").await;
+        let rent = test_ledger::category(&db, "Rent", "Living").await;
+        let day = date(2025, 1, 10);
+        test_ledger::transaction(&db, "Lunch", day, &[(bank, Some(food), -40_000)]).await;
+        test_ledger::transaction(&db, "Dinner", day, &[(card, Some(food), -1_000)]).await;
+        test_ledger::transaction(&db, "Flat", day, &[(bank, Some(rent), -2_000_000)]).await;
+        test_ledger::transaction(&db, "Refund", day, &[(card, None, 500)]).await;
+        let to = date(2025, 1, 31);
+        let cache = cache_with_rates(to, &[("INR", 80.0), ("USD", 1.0)]);
+        let settings = UserSettings::default();
+
+        let mut pivot = query(vec![Dimension::Category], date(2025, 1, 1), to);
+        pivot.measures = vec![Measure::Sum, Measure::Count, Measure::Min, Measure::Max];
+        let split = pivot.run(&db, &cache, &settings, to).await.unwrap();
+        assert_eq!(
+            split.columns,
+            [
+                "category_id",
+                "category",
+                "currency",
+                "sum",
+                "count",
+                "min",
+                "max"
+            ]
+        );
+        assert_eq!(split.currency, None);
+        assert_eq!(split.rows.len(), 4);
+        assert!(split.rows.contains(&vec![
+            json!(food),
+            json!("Food"),
+            json!("USD"),
+            json!(-10.0),
+            json!(1),
+            json!(-10.0),
+            json!(-10.0)
+        ]));
+
+        pivot.convert_to = Some("INR".to_string());
+        let converted = pivot.run(&db, &cache, &settings, to).await.unwrap();
+        assert_eq!(
+            converted.columns,
+            ["category_id", "category", "sum", "count", "min", "max"]
+        );
+        assert_eq!(converted.currency.as_deref(), Some("INR"));
+        assert_eq!(
+            converted.rows,
+            [
+                vec![
+                    json!(null),
+                    json!(null),
+                    json!(400.0),
+                    json!(1),
+                    json!(400.0),
+                    json!(400.0)
+                ],
+                vec![
+                    json!(food),
+                    json!("Food"),
+                    json!(-1200.0),
+                    json!(2),
+                    json!(-800.0),
+                    json!(-400.0)
+                ],
+                vec![
+                    json!(rent),
+                    json!("Rent"),
+                    json!(-20000.0),
+                    json!(1),
+                    json!(-20000.0),
+                    json!(-20000.0)
+                ],
+            ]
+        );
+
+        pivot.filter.direction = Some(Direction::Outflow);
+        pivot.filter.category_groups = vec!["Living".to_string()];
+        pivot.dimensions = vec![Dimension::CategoryGroup];
+        let living = pivot.run(&db, &cache, &settings, to).await.unwrap();
+        assert_eq!(
+            living.rows,
+            [vec![
+                json!("Living"),
+                json!(-21200.0),
+                json!(3),
+                json!(-20000.0),
+                json!(-400.0)
+            ]]
+        );
+
+        pivot.dimensions = vec![Dimension::Account];
+        let accounts = pivot.run(&db, &cache, &settings, to).await.unwrap();
+        assert_eq!(
+            accounts.columns,
+            ["account_id", "account", "sum", "count", "min", "max"]
+        );
+        assert_eq!(
+            accounts.rows,
+            [
+                vec![
+                    json!(bank),
+                    json!("Bank"),
+                    json!(-20400.0),
+                    json!(2),
+                    json!(-20000.0),
+                    json!(-400.0)
+                ],
+                vec![
+                    json!(card),
+                    json!("Card"),
+                    json!(-800.0),
+                    json!(1),
+                    json!(-800.0),
+                    json!(-800.0)
+                ],
+            ]
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Who may create an account.
+//!
+//! `KHATA_REGISTRATION` is `open` (the default), `invite_only` or `closed`.
+//! `KHATA_SIGNUP_DOMAINS` optionally limits new accounts to a comma separated
+//! list of email domains. Both apply to sign ups with a password and to new
+//! accounts from single sign-on.
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationMode {
+    Open,
+    /// Sign ups need an invite code, see [`crate::model::invite::Invite`].
+    InviteOnly,
+    Closed,
+}
+
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct Registration {
+    pub mode: RegistrationMode,
+    /// Allowed email domains, any domain when empty.
+    pub domains: Vec<String>,
+}
+
+impl Registration {
+    pub fn from_env() -> anyhow::Result<Self> {
+        let mode = match std::env::var("KHATA_REGISTRATION") {
+            Ok(mode) => serde_json::from_value(serde_json::Value::String(mode.to_lowercase()))
+                .context("KHATA_REGISTRATION must be open, invite_only or closed")?,
+            Err(_) => RegistrationMode::Open,
+        };
+        let domains = std::env::var("KHATA_SIGNUP_DOMAINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|d| d.trim().trim_start_matches('@').to_lowercase())
+            .filter(|d| !d.is_empty())
+            .collect();
+        Ok(Self { mode, domains })
+    }
+
+    pub fn allows_domain(&self, email: &str) -> bool {
+        if self.domains.is_empty() {
+            return true;
+        }
+        email
+            .rsplit_once('@')
+            .is_some_and(|(_, domain)| self.domains.contains(&domain.to_lowercase()))
+    }
+}
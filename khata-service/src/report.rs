@@ -0,0 +1,919 @@
+//! Income statement and balance sheet in the base currency of the user.
+//!
+//! Amounts are converted with the daily rates of [`CacheManager`] at the end
+//! of each period, or at the balance sheet date. Ledgers that only hold the
+//! base currency never need a rate.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use migration::AccountType;
+use sea_orm::{DbConn, DbErr};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    cache::CacheManager,
+    entity::category,
+    model::{
+        account::{AccountExpandedModel, AccountReq},
+        category::CategoryReq,
+        transaction::TransactionReq,
+        user_settings::UserSettings,
+    },
+    period::{self, Period, Periods},
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ToSchema, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    #[default]
+    Json,
+    Csv,
+}
+
+/// Turns minor units into an amount in the currency of the account.
+fn major_units(amount: i64, account: &AccountExpandedModel) -> f64 {
+    #[allow(clippy::cast_precision_loss)]
+    let amount = amount as f64 / 10_f64.powi(account.currency.0.decimal_digits);
+    amount
+}
+
+/// A currency without an exchange rate, usually an unknown code.
+#[derive(Debug, thiserror::Error)]
+#[error("No exchange rate for {0}")]
+pub struct MissingRate(String);
+
+/// Converts amounts to the base currency.
+pub struct Converter {
+    base: String,
+    /// Units per US dollar by currency code, unless only the base currency
+    /// is involved.
+    rates: Option<HashMap<String, f64>>,
+}
+
+impl Converter {
+    pub async fn new(
+        cache: &Mutex<CacheManager>,
+        base: &str,
+        codes: &HashSet<String>,
+        date: NaiveDate,
+    ) -> anyhow::Result<Self> {
+        if codes.iter().all(|c| c == base) {
+            return Ok(Self {
+                base: base.to_string(),
+                rates: None,
+            });
+        }
+        // Rates of a day are published the day after.
+        let yesterday = Utc::now().date_naive() - Days::new(1);
+        let mut cache = cache.lock().await;
+        let rates = if date >= yesterday {
+            cache.latest().await?
+        } else {
+            cache.historical(&date.to_string()).await?
+        };
+        Ok(Self {
+            base: base.to_string(),
+            rates: Some(
+                rates
+                    .data
+                    .into_iter()
+                    .map(|(code, rate)| (code, rate.value))
+                    .collect(),
+            ),
+        })
+    }
+
+    fn convert(&self, amount: i64, account: &AccountExpandedModel) -> anyhow::Result<f64> {
+        self.convert_major(major_units(amount, account), &account.currency.0.code)
+    }
+
+    /// Converts an amount in the currency `code`, not in minor units.
+    pub fn convert_major(&self, amount: f64, code: &str) -> anyhow::Result<f64> {
+        if code == self.base {
+            return Ok(amount);
+        }
+        let rate = |code: &str| {
+            self.rates
+                .as_ref()
+                .and_then(|rates| rates.get(code))
+                .copied()
+                .filter(|rate| *rate > 0.0)
+                .ok_or_else(|| MissingRate(code.to_string()))
+        };
+        Ok(amount / rate(code)? * rate(&self.base)?)
+    }
+}
+
+async fn accounts_by_id(db: &DbConn) -> Result<HashMap<Uuid, AccountExpandedModel>, DbErr> {
+    Ok(AccountReq::find_all_with_currency(db)
+        .await?
+        .into_iter()
+        .map(|a| (a.account.id, a))
+        .collect())
+}
+
+fn currency_codes(accounts: &HashMap<Uuid, AccountExpandedModel>) -> HashSet<String> {
+    accounts
+        .values()
+        .map(|a| a.currency.0.code.clone())
+        .collect()
+}
+
+/// The last day of the period, for the exchange rate.
+fn last_day(period: Period) -> NaiveDate {
+    period.end.pred_opt().unwrap_or(period.start)
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_row(fields: &[String]) -> String {
+    let mut row = fields
+        .iter()
+        .map(|f| csv_field(f))
+        .collect::<Vec<_>>()
+        .join(",");
+    row.push('\n');
+    row
+}
+
+/// An amount in the period, the one before it and the same period a year
+/// earlier.
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+pub struct Comparison {
+    pub current: f64,
+    pub previous: f64,
+    pub last_year: f64,
+}
+
+impl Comparison {
+    fn add(&mut self, column: usize, amount: f64) {
+        match column {
+            0 => self.current += amount,
+            1 => self.previous += amount,
+            _ => self.last_year += amount,
+        }
+    }
+
+    fn total(&self) -> f64 {
+        self.current + self.previous + self.last_year
+    }
+
+    fn negated(&self) -> Self {
+        Self {
+            current: -self.current,
+            previous: -self.previous,
+            last_year: -self.last_year,
+        }
+    }
+
+    fn sum<'a>(values: impl Iterator<Item = &'a Self>) -> Self {
+        values.fold(Self::default(), |total, v| Self {
+            current: total.current + v.current,
+            previous: total.previous + v.previous,
+            last_year: total.last_year + v.last_year,
+        })
+    }
+
+    fn csv(&self) -> [String; 3] {
+        [
+            format!("{:.2}", self.current),
+            format!("{:.2}", self.previous),
+            format!("{:.2}", self.last_year),
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CategoryLine {
+    pub category_id: Uuid,
+    pub name: String,
+    pub amounts: Comparison,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CategoryGroup {
+    pub group: String,
+    pub total: Comparison,
+    pub categories: Vec<CategoryLine>,
+}
+
+/// Splits the net amounts of the categories into income and expense groups.
+fn sections(
+    lines: &HashMap<Uuid, Comparison>,
+    categories: &HashMap<Uuid, category::Model>,
+) -> (Vec<CategoryGroup>, Vec<CategoryGroup>) {
+    let mut income = BTreeMap::<String, Vec<CategoryLine>>::new();
+    let mut expense = BTreeMap::<String, Vec<CategoryLine>>::new();
+    for (&category_id, amounts) in lines {
+        let (groups, amounts) = if amounts.total() > 0.0 {
+            (&mut income, *amounts)
+        } else {
+            (&mut expense, amounts.negated())
+        };
+        let (group, name) = categories
+            .get(&category_id)
+            .map(|c| (c.group.clone(), c.name.clone()))
+            .unwrap_or_default();
+        groups.entry(group).or_default().push(CategoryLine {
+            category_id,
+            name,
+            amounts,
+        });
+    }
+    let sorted = |groups: BTreeMap<String, Vec<CategoryLine>>| {
+        groups
+            .into_iter()
+            .map(|(group, mut categories)| {
+                categories.sort_by(|a, b| a.name.cmp(&b.name));
+                CategoryGroup {
+                    group,
+                    total: Comparison::sum(categories.iter().map(|c| &c.amounts)),
+                    categories,
+                }
+            })
+            .collect::<Vec<_>>()
+    };
+    (sorted(income), sorted(expense))
+}
+
+/// Income and expense by category group, both as positive amounts. A
+/// category is income or expense by its net amount over the three periods,
+/// so a refund lowers the expense of its category. Uncategorized items, like
+/// transfers between accounts, are left out.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct IncomeStatement {
+    pub currency: String,
+    pub period: Period,
+    pub previous_period: Period,
+    pub last_year_period: Period,
+    pub income: Vec<CategoryGroup>,
+    pub expense: Vec<CategoryGroup>,
+    pub total_income: Comparison,
+    pub total_expense: Comparison,
+    pub net: Comparison,
+}
+
+impl IncomeStatement {
+    pub async fn build(
+        db: &DbConn,
+        cache: &Mutex<CacheManager>,
+        settings: &UserSettings,
+        base: &str,
+        periods: Periods,
+        date: NaiveDate,
+    ) -> anyhow::Result<Self> {
+        let tz = settings.tz();
+        let accounts = accounts_by_id(db).await?;
+        let categories = CategoryReq::find_all(db)
+            .await?
+            .into_iter()
+            .map(|c| (c.0.id, c.0))
+            .collect::<HashMap<_, _>>();
+        let codes = currency_codes(&accounts);
+        let current = periods.containing(date);
+        let columns = [
+            current,
+            periods.previous(current),
+            current.shift_months(-12),
+        ];
+        // The net amount of each category.
+        let mut lines = HashMap::<Uuid, Comparison>::new();
+        for (column, period) in columns.into_iter().enumerate() {
+            let converter = Converter::new(cache, base, &codes, last_day(period)).await?;
+            let (start, end) = period.bounds(&tz)?;
+            for t in TransactionReq::find_by_month(db, start, end).await? {
+                for i in &t.items {
+                    let Some(category_id) = i.0.category_id else {
+                        continue;
+                    };
+                    let Some(account) = accounts.get(&i.0.account_id) else {
+                        continue;
+                    };
+                    let amount = converter.convert(i.0.amount, account)?;
+                    lines.entry(category_id).or_default().add(column, amount);
+                }
+            }
+        }
+        let (income, expense) = sections(&lines, &categories);
+        let total_income = Comparison::sum(income.iter().map(|g| &g.total));
+        let total_expense = Comparison::sum(expense.iter().map(|g| &g.total));
+        Ok(Self {
+            currency: base.to_string(),
+            period: columns[0],
+            previous_period: columns[1],
+            last_year_period: columns[2],
+            income,
+            expense,
+            total_income,
+            total_expense,
+            net: Comparison {
+                current: total_income.current - total_expense.current,
+                previous: total_income.previous - total_expense.previous,
+                last_year: total_income.last_year - total_expense.last_year,
+            },
+        })
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = csv_row(&[
+            "section".into(),
+            "group".into(),
+            "category".into(),
+            format!("{} to {}", self.period.start, last_day(self.period)),
+            format!(
+                "{} to {}",
+                self.previous_period.start,
+                last_day(self.previous_period)
+            ),
+            format!(
+                "{} to {}",
+                self.last_year_period.start,
+                last_day(self.last_year_period)
+            ),
+        ]);
+        for (section, groups, total) in [
+            ("income", &self.income, &self.total_income),
+            ("expense", &self.expense, &self.total_expense),
+        ] {
+            for group in groups {
+                for category in &group.categories {
+                    let [current, previous, last_year] = category.amounts.csv();
+                    csv.push_str(&csv_row(&[
+                        section.into(),
+                        group.group.clone(),
+                        category.name.clone(),
+                        current,
+                        previous,
+                        last_year,
+                    ]));
+                }
+            }
+            let [current, previous, last_year] = total.csv();
+            csv.push_str(&csv_row(&[
+                section.into(),
+                "total".into(),
+                String::new(),
+                current,
+                previous,
+                last_year,
+            ]));
+        }
+        let [current, previous, last_year] = self.net.csv();
+        csv.push_str(&csv_row(&[
+            "net".into(),
+            String::new(),
+            String::new(),
+            current,
+            previous,
+            last_year,
+        ]));
+        csv
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AccountLine {
+    pub account_id: Uuid,
+    pub name: String,
+    pub currency_code: String,
+    /// Balance in the currency of the account.
+    pub balance: f64,
+    /// Balance in the base currency, positive for liabilities too.
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AccountTypeGroup {
+    pub account_type: AccountType,
+    pub total: f64,
+    pub accounts: Vec<AccountLine>,
+}
+
+/// What is owned and owed at the end of a day. Credit cards and loans are
+/// liabilities, people are assets or liabilities depending on who owes whom.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BalanceSheet {
+    pub currency: String,
+    pub date: NaiveDate,
+    pub assets: Vec<AccountTypeGroup>,
+    pub liabilities: Vec<AccountTypeGroup>,
+    pub total_assets: f64,
+    pub total_liabilities: f64,
+    pub net_worth: f64,
+}
+
+/// Whether an account with `balance` is a liability, and the amount it adds
+/// to its side of the sheet. Credit cards and loans are always liabilities,
+/// so an overpaid card lowers the liabilities; a person is one only while
+/// money is owed to them.
+fn balance_sheet_side(account_type: &AccountType, balance: i64, amount: f64) -> (bool, f64) {
+    let liability = match account_type {
+        AccountType::CreditCard | AccountType::Loan => true,
+        AccountType::Person => balance < 0,
+        AccountType::Cash | AccountType::Wallet | AccountType::Bank => false,
+    };
+    (liability, if liability { -amount } else { amount })
+}
+
+impl BalanceSheet {
+    pub async fn build(
+        db: &DbConn,
+        cache: &Mutex<CacheManager>,
+        settings: &UserSettings,
+        base: &str,
+        date: NaiveDate,
+    ) -> anyhow::Result<Self> {
+        let tz = settings.tz();
+        let accounts = accounts_by_id(db).await?;
+        let converter = Converter::new(cache, base, &currency_codes(&accounts), date).await?;
+        let end = period::start_of_day(&tz, date + Days::new(1))?;
+        let mut balances = accounts
+            .values()
+            .map(|a| (a.account.id, a.account.starting_balance))
+            .collect::<HashMap<_, _>>();
+        for t in TransactionReq::find_by_month(db, DateTime::<Utc>::MIN_UTC, end).await? {
+            for i in &t.items {
+                if let Some(balance) = balances.get_mut(&i.0.account_id) {
+                    *balance += i.0.amount;
+                }
+            }
+        }
+        let mut assets = BTreeMap::<String, AccountTypeGroup>::new();
+        let mut liabilities = BTreeMap::<String, AccountTypeGroup>::new();
+        for (account_id, balance) in balances {
+            let Some(account) = accounts.get(&account_id) else {
+                continue;
+            };
+            if balance == 0 && !account.account.is_active {
+                continue;
+            }
+            let (liability, amount) = balance_sheet_side(
+                &account.account.account_type,
+                balance,
+                converter.convert(balance, account)?,
+            );
+            let section = if liability {
+                &mut liabilities
+            } else {
+                &mut assets
+            };
+            let account_type = account.account.account_type.clone();
+            let group = section
+                .entry(format!("{account_type:?}"))
+                .or_insert_with(|| AccountTypeGroup {
+                    account_type,
+                    total: 0.0,
+                    accounts: vec![],
+                });
+            group.total += amount;
+            group.accounts.push(AccountLine {
+                account_id,
+                name: account.account.name.clone(),
+                currency_code: account.currency.0.code.clone(),
+                balance: major_units(balance, account),
+                amount,
+            });
+        }
+        let sorted = |groups: BTreeMap<String, AccountTypeGroup>| {
+            groups
+                .into_values()
+                .map(|mut g| {
+                    g.accounts.sort_by(|a, b| a.name.cmp(&b.name));
+                    g
+                })
+                .collect::<Vec<_>>()
+        };
+        let assets = sorted(assets);
+        let liabilities = sorted(liabilities);
+        let total_assets = assets.iter().map(|g| g.total).sum::<f64>();
+        let total_liabilities = liabilities.iter().map(|g| g.total).sum::<f64>();
+        Ok(Self {
+            currency: base.to_string(),
+            date,
+            assets,
+            liabilities,
+            total_assets,
+            total_liabilities,
+            net_worth: total_assets - total_liabilities,
+        })
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = csv_row(&[
+            "section".into(),
+            "account_type".into(),
+            "account".into(),
+            "currency".into(),
+            "balance".into(),
+            format!("amount_{}", self.currency),
+        ]);
+        for (section, groups, total) in [
+            ("assets", &self.assets, self.total_assets),
+            ("liabilities", &self.liabilities, self.total_liabilities),
+        ] {
+            for group in groups {
+                for account in &group.accounts {
+                    csv.push_str(&csv_row(&[
+                        section.into(),
+                        format!("{:?}", group.account_type),
+                        account.name.clone(),
+                        account.currency_code.clone(),
+                        account.balance.to_string(),
+                        format!("{:.2}", account.amount),
+                    ]));
+                }
+            }
+            csv.push_str(&csv_row(&[
+                section.into(),
+                "total".into(),
+                String::new(),
+                String::new(),
+                String::new(),
+                format!("{total:.2}"),
+            ]));
+        }
+        csv.push_str(&csv_row(&[
+            "net_worth".into(),
+            String::new(),
+            String::new(),
+            String::new(),
+            String::new(),
+            format!("{:.2}", self.net_worth),
+        ]));
+        csv
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CashFlowPeriod {
+    pub period: Period,
+    pub inflow: f64,
+    pub outflow: f64,
+    pub net: f64,
+    /// Net cash flow of this and all earlier periods in the report.
+    pub running_total: f64,
+}
+
+/// Money moving in and out of the accounts marked as cash flow.
+///
+/// Uncategorized items of a transaction are an internal transfer when all of
+/// them are on cash-flow accounts, and are left out. Money moved to or from
+/// any other account, like an investment or a loan, counts as outflow or
+/// inflow, and so does every categorized item on a cash-flow account.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CashFlow {
+    pub currency: String,
+    pub periods: Vec<CashFlowPeriod>,
+}
+
+/// Whether an item of a transaction, given as `(account_id, category_id,
+/// amount)`, belongs in its [`CashFlow`].
+pub fn is_cash_flow_item(
+    items: &[(Uuid, Option<Uuid>, i64)],
+    cash_flow: &HashSet<Uuid>,
+    item: &(Uuid, Option<Uuid>, i64),
+) -> bool {
+    let internal = items
+        .iter()
+        .filter(|(_, category_id, _)| category_id.is_none())
+        .all(|(account_id, _, _)| cash_flow.contains(account_id));
+    cash_flow.contains(&item.0) && !(internal && item.1.is_none())
+}
+
+impl CashFlow {
+    pub async fn build(
+        db: &DbConn,
+        cache: &Mutex<CacheManager>,
+        settings: &UserSettings,
+        base: &str,
+        periods: &[Period],
+    ) -> anyhow::Result<Self> {
+        let tz = settings.tz();
+        let accounts = accounts_by_id(db).await?;
+        let codes = currency_codes(&accounts);
+        let cash_flow = accounts
+            .values()
+            .filter(|a| a.account.is_cash_flow)
+            .map(|a| a.account.id)
+            .collect::<HashSet<_>>();
+        let mut running_total = 0.0;
+        let mut result = vec![];
+        for &period in periods {
+            let converter = Converter::new(cache, base, &codes, last_day(period)).await?;
+            let (start, end) = period.bounds(&tz)?;
+            let mut inflow = 0.0;
+            let mut outflow = 0.0;
+            for t in TransactionReq::find_by_month(db, start, end).await? {
+                let items = t
+                    .items
+                    .iter()
+                    .map(|i| (i.0.account_id, i.0.category_id, i.0.amount))
+                    .collect::<Vec<_>>();
+                for item in &items {
+                    if !is_cash_flow_item(&items, &cash_flow, item) {
+                        continue;
+                    }
+                    let Some(account) = accounts.get(&item.0) else {
+                        continue;
+                    };
+                    let amount = converter.convert(item.2, account)?;
+                    if amount > 0.0 {
+                        inflow += amount;
+                    } else {
+                        outflow -= amount;
+                    }
+                }
+            }
+            let net = inflow - outflow;
+            running_total += net;
+            result.push(CashFlowPeriod {
+                period,
+                inflow,
+                outflow,
+                net,
+                running_total,
+            });
+        }
+        Ok(Self {
+            currency: base.to_string(),
+            periods: result,
+        })
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = csv_row(&[
+            "start".into(),
+            "end".into(),
+            "inflow".into(),
+            "outflow".into(),
+            "net".into(),
+            "running_total".into(),
+        ]);
+        for p in &self.periods {
+            csv.push_str(&csv_row(&[
+                p.period.start.to_string(),
+                last_day(p.period).to_string(),
+                format!("{:.2}", p.inflow),
+                format!("{:.2}", p.outflow),
+                format!("{:.2}", p.net),
+                format!("{:.2}", p.running_total),
+            ]));
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::test_ledger;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn cache() -> Mutex<CacheManager> {
+        Mutex::new(CacheManager::new(PathBuf::new(), String::new()))
+    }
+
+    #[tokio::test]
+    async fn income_statement_nets_refunds_against_the_category() {
+        let db = test_ledger::ledger().await;
+        let bank = test_ledger::account(&db, "Bank", AccountType::Bank, "INR", true).await;
+        let card = test_ledger::account(&db, "Card", AccountType::CreditCard, "INR", true).await;
+        let salary = test_ledger::category(&db, "Salary", "Work").await;
+        let shopping = test_ledger::category(&db, "Shopping", "Living").await;
+        test_ledger::transaction(
+            &db,
+            "Pay",
+            date(2026, 9, 1),
+            &[(bank, Some(salary), 100_000)],
+        )
+        .await;
+        test_ledger::transaction(
+            &db,
+            "Shoes",
+            date(2026, 9, 5),
+            &[(card, Some(shopping), -8_000)],
+        )
+        .await;
+        test_ledger::transaction(
+            &db,
+            "Refund",
+            date(2026, 9, 9),
+            &[(card, Some(shopping), 3_000)],
+        )
+        .await;
+        test_ledger::transaction(
+            &db,
+            "Boots",
+            date(2026, 8, 20),
+            &[(card, Some(shopping), -2_000)],
+        )
+        .await;
+        // Uncategorized, like a card payment, is left out.
+        test_ledger::transaction(
+            &db,
+            "Card bill",
+            date(2026, 9, 10),
+            &[(bank, None, -5_000), (card, None, 5_000)],
+        )
+        .await;
+
+        let statement = IncomeStatement::build(
+            &db,
+            &cache(),
+            &UserSettings::default(),
+            "INR",
+            Periods::Month,
+            date(2026, 9, 15),
+        )
+        .await
+        .unwrap();
+        assert_eq!(statement.period.start, date(2026, 9, 1));
+        assert_eq!(statement.previous_period.start, date(2026, 8, 1));
+        assert_eq!(statement.income.len(), 1);
+        assert_eq!(statement.income[0].group, "Work");
+        assert!((statement.total_income.current - 1000.0).abs() < 1e-9);
+        assert_eq!(statement.expense.len(), 1);
+        let line = &statement.expense[0].categories[0];
+        assert_eq!(line.name, "Shopping");
+        assert!((line.amounts.current - 50.0).abs() < 1e-9);
+        assert!((line.amounts.previous - 20.0).abs() < 1e-9);
+        assert!((statement.net.current - 950.0).abs() < 1e-9);
+        assert!((statement.net.previous + 20.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn converter_reports_missing_rates() {
+        let converter = Converter {
+            base: "INR".to_string(),
+            rates: Some(HashMap::from([
+                ("USD".to_string(), 1.0),
+                ("INR".to_string(), 80.0),
+            ])),
+        };
+        assert!((converter.convert_major(2.0, "USD").unwrap() - 160.0).abs() < f64::EPSILON);
+        let error = converter.convert_major(2.0, "XYZ").unwrap_err();
+        assert!(error.is::<MissingRate>());
+        assert_eq!(error.to_string(), "No exchange rate for XYZ");
+    }
+
+    #[test]
+    fn income_statement_csv_quotes_names() {
+        let period = Period {
+            start: date(2026, 9, 1),
+            end: date(2026, 10, 1),
+        };
+        let amounts = Comparison {
+            current: 12.5,
+            previous: 0.0,
+            last_year: 1.0,
+        };
+        let statement = IncomeStatement {
+            currency: "INR".to_string(),
+            period,
+            previous_period: period.shift_months(-1),
+            last_year_period: period.shift_months(-12),
+            income: vec![],
+            expense: vec![CategoryGroup {
+                group: "Food, \"fun\"".to_string(),
+                total: amounts,
+                categories: vec![CategoryLine {
+                    category_id: Uuid::now_v7(),
+                    name: "Eating\nout".to_string(),
+                    amounts,
+                }],
+            }],
+            total_income: Comparison::default(),
+            total_expense: amounts,
+            net: Comparison {
+                current: -12.5,
+                previous: 0.0,
+                last_year: -1.0,
+            },
+        };
+        let csv = statement.to_csv();
+        let lines = csv.lines().collect::<Vec<_>>();
+        assert_eq!(
+            lines[0],
+            "section,group,category,2026-09-01 to 2026-09-30,2026-08-01 to 2026-08-31,2025-09-01 to 2025-09-30"
+        );
+        assert_eq!(lines[1], "income,total,,0.00,0.00,0.00");
+        assert_eq!(lines[2], "expense,\"Food, \"\"fun\"\"\",\"Eating");
+        assert_eq!(lines[3], "out\",12.50,0.00,1.00");
+        assert_eq!(lines[5], "net,,,-12.50,0.00,-1.00");
+    }
+
+    #[tokio::test]
+    async fn cash_flow_leaves_out_internal_transfers() {
+        let db = test_ledger::ledger().await;
+        let bank = test_ledger::account(&db, "Bank", AccountType::Bank, "INR", true).await;
+        let wallet = test_ledger::account(&db, "Wallet", AccountType::Wallet, "INR", true).await;
+        let fund = test_ledger::account(&db, "Fund", AccountType::Bank, "INR", false).await;
+        let salary = test_ledger::category(&db, "Salary", "Work").await;
+        let fees = test_ledger::category(&db, "Fees", "Bank").await;
+        test_ledger::transaction(
+            &db,
+            "Pay",
+            date(2026, 9, 1),
+            &[(bank, Some(salary), 100_000)],
+        )
+        .await;
+        // Between cash flow accounts, left out.
+        test_ledger::transaction(
+            &db,
+            "Cash",
+            date(2026, 9, 2),
+            &[(bank, None, -10_000), (wallet, None, 10_000)],
+        )
+        .await;
+        // To an investment, an outflow.
+        test_ledger::transaction(
+            &db,
+            "Invest",
+            date(2026, 9, 3),
+            &[(bank, None, -30_000), (fund, None, 30_000)],
+        )
+        .await;
+        // A transfer with a fee: the fee is an outflow, the transfer is not.
+        test_ledger::transaction(
+            &db,
+            "Top up",
+            date(2026, 10, 4),
+            &[
+                (bank, None, -20_000),
+                (wallet, None, 20_000),
+                (bank, Some(fees), -500),
+            ],
+        )
+        .await;
+
+        let periods = [
+            Period {
+                start: date(2026, 9, 1),
+                end: date(2026, 10, 1),
+            },
+            Period {
+                start: date(2026, 10, 1),
+                end: date(2026, 11, 1),
+            },
+        ];
+        let cash_flow = CashFlow::build(&db, &cache(), &UserSettings::default(), "INR", &periods)
+            .await
+            .unwrap();
+        let [september, october] = cash_flow.periods.as_slice() else {
+            panic!("{cash_flow:?}");
+        };
+        assert!((september.inflow - 1000.0).abs() < 1e-9);
+        assert!((september.outflow - 300.0).abs() < 1e-9);
+        assert!((september.net - 700.0).abs() < 1e-9);
+        assert!(october.inflow.abs() < 1e-9);
+        assert!((october.outflow - 5.0).abs() < 1e-9);
+        assert!((october.running_total - 695.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn balance_sheet_sides_follow_the_account_type() {
+        // A card in debt is a liability, an overpaid one lowers them.
+        assert_eq!(
+            balance_sheet_side(&AccountType::CreditCard, -500, -5.0),
+            (true, 5.0)
+        );
+        assert_eq!(
+            balance_sheet_side(&AccountType::CreditCard, 200, 2.0),
+            (true, -2.0)
+        );
+        assert_eq!(
+            balance_sheet_side(&AccountType::Loan, -10_000, -100.0),
+            (true, 100.0)
+        );
+        // A person is an asset while they owe money and a liability while
+        // money is owed to them.
+        assert_eq!(
+            balance_sheet_side(&AccountType::Person, 300, 3.0),
+            (false, 3.0)
+        );
+        assert_eq!(
+            balance_sheet_side(&AccountType::Person, -300, -3.0),
+            (true, 3.0)
+        );
+        assert_eq!(
+            balance_sheet_side(&AccountType::Bank, -100, -1.0),
+            (false, -1.0)
+        );
+    }
+}
@@ -8,8 +8,12 @@ use utoipa::IntoParams;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
-    AppError, AppResult, ValidatedJson, XUserId, database,
-    model::account::{AccountExpandedModel, AccountReq},
+    AppError, AppResult, ValidatedJson, XLedger, database,
+    model::{
+        account::{AccountExpandedModel, AccountReq},
+        account_extra::{AccountSummary, LoanInstalment},
+        audit::AuditContext,
+    },
 };
 
 pub fn router() -> OpenApiRouter<()> {
@@ -17,6 +21,8 @@ pub fn router() -> OpenApiRouter<()> {
         .routes(routes![account, put_account, delete_account])
         .routes(routes![put_accounts])
         .routes(routes![account_by_id])
+        .routes(routes![account_schedule])
+        .routes(routes![account_summary])
 }
 
 #[tracing::instrument]
@@ -24,8 +30,8 @@ pub fn router() -> OpenApiRouter<()> {
     (status = OK, body = Vec<AccountExpandedModel>),
     AppError
 ))]
-async fn account(id: XUserId) -> AppResult<Json<Vec<AccountExpandedModel>>> {
-    let db = database(&id.0).await?;
+async fn account(ledger: XLedger) -> AppResult<Json<Vec<AccountExpandedModel>>> {
+    let db = database(ledger.id).await?;
     Ok(Json(AccountReq::find_all_with_currency(&db).await?))
 }
 
@@ -35,15 +41,43 @@ async fn account(id: XUserId) -> AppResult<Json<Vec<AccountExpandedModel>>> {
     AppError
 ))]
 async fn account_by_id(
-    id: XUserId,
+    ledger: XLedger,
     Path(account_id): Path<Uuid>,
 ) -> AppResult<Json<Option<AccountExpandedModel>>> {
-    let db = database(&id.0).await?;
+    let db = database(ledger.id).await?;
     Ok(Json(
         AccountReq::find_one_with_currency(&db, account_id).await?,
     ))
 }
 
+#[tracing::instrument]
+#[utoipa::path(get, path = "/{account_id}/schedule", params(("account_id" = Uuid, Path)), responses(
+    (status = OK, body = Vec<LoanInstalment>),
+    AppError
+))]
+async fn account_schedule(
+    ledger: XLedger,
+    Path(account_id): Path<Uuid>,
+) -> AppResult<Json<Vec<LoanInstalment>>> {
+    let db = database(ledger.id).await?;
+    Ok(Json(AccountReq::schedule(&db, account_id).await?))
+}
+
+#[tracing::instrument]
+#[utoipa::path(get, path = "/{account_id}/summary", params(("account_id" = Uuid, Path)), responses(
+    (status = OK, body = AccountSummary),
+    AppError
+))]
+async fn account_summary(
+    ledger: XLedger,
+    Path(account_id): Path<Uuid>,
+) -> AppResult<Json<AccountSummary>> {
+    let db = database(ledger.id).await?;
+    Ok(Json(
+        AccountReq::summary(&db, account_id, &ledger.settings.tz()).await?,
+    ))
+}
+
 #[derive(Deserialize, IntoParams)]
 struct DeleteAccountParams {
     #[into_params(names("id"), parameter_in = Query)]
@@ -56,11 +90,12 @@ struct DeleteAccountParams {
     AppError
 ))]
 async fn delete_account(
-    id: XUserId,
+    ledger: XLedger,
+    audit: AuditContext,
     Query(DeleteAccountParams { id: account_id }): Query<DeleteAccountParams>,
 ) -> AppResult<()> {
-    let db = database(&id.0).await?;
-    AccountReq::delete(&db, account_id).await?;
+    let db = database(ledger.id).await?;
+    AccountReq::delete(&db, &audit, account_id).await?;
     Ok(())
 }
 
@@ -71,11 +106,12 @@ async fn delete_account(
     AppError
 ))]
 async fn put_account(
-    id: XUserId,
+    ledger: XLedger,
+    audit: AuditContext,
     ValidatedJson(account): ValidatedJson<AccountReq>,
 ) -> AppResult<Json<Uuid>> {
-    let db = database(&id.0).await?;
-    let id = AccountReq::upsert(&db, account).await?;
+    let db = database(ledger.id).await?;
+    let id = AccountReq::upsert(&db, &audit, account).await?;
     Ok(Json(id))
 }
 
@@ -86,10 +122,11 @@ async fn put_account(
     AppError
 ))]
 async fn put_accounts(
-    id: XUserId,
+    ledger: XLedger,
+    audit: AuditContext,
     ValidatedJson(accounts): ValidatedJson<Vec<AccountReq>>,
 ) -> AppResult<()> {
-    let db = database(&id.0).await?;
-    AccountReq::upsert_many(&db, accounts).await?;
+    let db = database(ledger.id).await?;
+    AccountReq::upsert_many(&db, &audit, accounts).await?;
     Ok(())
 }
@@ -0,0 +1,218 @@
+use axum::{
+    Json,
+    extract::{Path, Query},
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use crate::{
+    AppError, AppResult, AuthSession, DATABASE_LOCK, SESSION_IDLE_MINUTES, STARTED_AT, XAdmin,
+    model::{ledger::Ledger, rate_limit::AuthLockout, user::User, user_session::UserSession},
+    send_reset_email,
+};
+
+pub fn router() -> OpenApiRouter<()> {
+    OpenApiRouter::new()
+        .routes(routes![lockout, clear_lockout])
+        .routes(routes![users])
+        .routes(routes![disable_user])
+        .routes(routes![enable_user])
+        .routes(routes![verify_user_email])
+        .routes(routes![reset_user_password])
+        .routes(routes![health])
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/lockout", responses(
+    (status = OK, body = Vec<AuthLockout>),
+    AppError
+))]
+async fn lockout(auth_session: AuthSession, _: XAdmin) -> AppResult<Json<Vec<AuthLockout>>> {
+    Ok(Json(
+        AuthLockout::find_locked(auth_session.backend.db()).await?,
+    ))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct ClearLockoutParams {
+    /// The locked key, e.g. `email:someone@example.com`.
+    #[into_params(names("key"), parameter_in = Query)]
+    key: String,
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(delete, path = "/lockout", params(ClearLockoutParams), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn clear_lockout(
+    auth_session: AuthSession,
+    XAdmin(admin): XAdmin,
+    Query(ClearLockoutParams { key }): Query<ClearLockoutParams>,
+) -> AppResult<()> {
+    tracing::info!("Lockout of {key} cleared by {}", admin.email);
+    AuthLockout::clear(auth_session.backend.db(), &key).await?;
+    Ok(())
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct AdminUser {
+    #[serde(flatten)]
+    user: User,
+    /// Bytes on disk of the ledgers the user owns.
+    database_size: u64,
+    last_active_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/user", responses(
+    (status = OK, body = Vec<AdminUser>),
+    AppError
+))]
+async fn users(auth_session: AuthSession, _: XAdmin) -> AppResult<Json<Vec<AdminUser>>> {
+    let db = auth_session.backend.db();
+    let owned = Ledger::ids_by_owner(db).await?;
+    let last_seen = UserSession::last_seen_by_user(db).await?;
+    let mut users = vec![];
+    for user in User::find_all(db).await? {
+        let mut database_size = 0;
+        for ledger_id in owned.get(&user.id).into_iter().flatten() {
+            database_size += crate::database_size(*ledger_id).await;
+        }
+        users.push(AdminUser {
+            database_size,
+            last_active_at: last_seen.get(&user.id).copied(),
+            user,
+        });
+    }
+    Ok(Json(users))
+}
+
+async fn find_user(auth_session: &AuthSession, user_id: Uuid) -> AppResult<User> {
+    User::find_by_id(auth_session.backend.db(), user_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(anyhow::anyhow!("User {user_id} not found")))
+}
+
+/// Disables the account and signs it out everywhere. Its API tokens stop
+/// working until it is enabled again.
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(post, path = "/user/{user_id}/disable", params(("user_id" = Uuid, Path)), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn disable_user(
+    auth_session: AuthSession,
+    XAdmin(admin): XAdmin,
+    Path(user_id): Path<Uuid>,
+) -> AppResult<()> {
+    if user_id == admin.id {
+        return Err(AppError::Forbidden(anyhow::anyhow!(
+            "You cannot disable yourself"
+        )));
+    }
+    let user = find_user(&auth_session, user_id).await?;
+    User::update_disabled(auth_session.backend.db(), user.id, true).await?;
+    auth_session
+        .backend
+        .revoke_sessions(user.id, None, None)
+        .await
+        .map_err(AppError::Other)?;
+    tracing::info!("{} disabled by {}", user.email, admin.email);
+    Ok(())
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(post, path = "/user/{user_id}/enable", params(("user_id" = Uuid, Path)), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn enable_user(
+    auth_session: AuthSession,
+    XAdmin(admin): XAdmin,
+    Path(user_id): Path<Uuid>,
+) -> AppResult<()> {
+    let user = find_user(&auth_session, user_id).await?;
+    User::update_disabled(auth_session.backend.db(), user.id, false).await?;
+    tracing::info!("{} enabled by {}", user.email, admin.email);
+    Ok(())
+}
+
+/// Marks the email of the user as verified without the verification link.
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(post, path = "/user/{user_id}/verify-email", params(("user_id" = Uuid, Path)), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn verify_user_email(
+    auth_session: AuthSession,
+    XAdmin(admin): XAdmin,
+    Path(user_id): Path<Uuid>,
+) -> AppResult<()> {
+    let user = find_user(&auth_session, user_id).await?;
+    User::update_email_verified(auth_session.backend.db(), user.id, true).await?;
+    tracing::info!("Email of {} verified by {}", user.email, admin.email);
+    Ok(())
+}
+
+/// Emails the user a password reset link, like
+/// [`crate::forgot_password`] does.
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(post, path = "/user/{user_id}/reset-password", params(("user_id" = Uuid, Path)), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn reset_user_password(
+    auth_session: AuthSession,
+    XAdmin(admin): XAdmin,
+    Path(user_id): Path<Uuid>,
+) -> AppResult<()> {
+    let user = find_user(&auth_session, user_id).await?;
+    send_reset_email(&user).await.map_err(AppError::Other)?;
+    tracing::info!("Password reset of {} sent by {}", user.email, admin.email);
+    Ok(())
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct InstanceHealth {
+    version: String,
+    started_at: DateTime<Utc>,
+    /// Whether the auth database answers.
+    auth_database: bool,
+    users: usize,
+    disabled_users: usize,
+    active_sessions: u64,
+    /// Ledger databases with an open connection.
+    open_databases: usize,
+    /// Bytes on disk of all ledger databases.
+    database_size: u64,
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/health", responses(
+    (status = OK, body = InstanceHealth),
+    AppError
+))]
+async fn health(auth_session: AuthSession, _: XAdmin) -> AppResult<Json<InstanceHealth>> {
+    let db = auth_session.backend.db();
+    let auth_database = db.ping().await.is_ok();
+    let users = User::find_all(db).await?;
+    let since = Utc::now() - Duration::minutes(*SESSION_IDLE_MINUTES);
+    let mut database_size = 0;
+    for ledger_id in Ledger::ids_by_owner(db).await?.into_values().flatten() {
+        database_size += crate::database_size(ledger_id).await;
+    }
+    Ok(Json(InstanceHealth {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        started_at: *STARTED_AT,
+        auth_database,
+        users: users.len(),
+        disabled_users: users.iter().filter(|u| u.disabled).count(),
+        active_sessions: UserSession::count_active(db, since).await?,
+        open_databases: DATABASE_LOCK.lock().await.len(),
+        database_size,
+    }))
+}
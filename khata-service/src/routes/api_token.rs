@@ -0,0 +1,60 @@
+use axum::{Json, extract::Path};
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use crate::{
+    AppError, AppResult, AuthSession, ValidatedJson, XSessionUserId,
+    model::api_token::{ApiToken, ApiTokenReq, NewApiToken},
+};
+
+pub fn router() -> OpenApiRouter<()> {
+    OpenApiRouter::new()
+        .routes(routes![api_token, create_api_token])
+        .routes(routes![revoke_api_token])
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/", responses(
+    (status = OK, body = Vec<ApiToken>),
+    AppError
+))]
+async fn api_token(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+) -> AppResult<Json<Vec<ApiToken>>> {
+    Ok(Json(
+        ApiToken::find_for_user(auth_session.backend.db(), user_id).await?,
+    ))
+}
+
+/// Creates a token. The response holds the token itself, which cannot be
+/// retrieved again.
+#[tracing::instrument(skip(auth_session, req))]
+#[utoipa::path(post, path = "/",
+    request_body = ApiTokenReq, responses(
+    (status = OK, body = NewApiToken),
+    AppError
+))]
+async fn create_api_token(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+    ValidatedJson(req): ValidatedJson<ApiTokenReq>,
+) -> AppResult<Json<NewApiToken>> {
+    Ok(Json(
+        ApiToken::create(auth_session.backend.db(), user_id, req).await?,
+    ))
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(delete, path = "/{token_id}", params(("token_id" = Uuid, Path)), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn revoke_api_token(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+    Path(token_id): Path<Uuid>,
+) -> AppResult<()> {
+    ApiToken::revoke(auth_session.backend.db(), user_id, token_id).await?;
+    Ok(())
+}
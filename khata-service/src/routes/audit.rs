@@ -0,0 +1,42 @@
+use axum::{Json, extract::Path};
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use crate::{
+    AppError, AppResult, XLedger, database,
+    model::audit::{AuditContext, AuditEntityType, AuditEventModel},
+};
+
+pub fn router() -> OpenApiRouter<()> {
+    OpenApiRouter::new()
+        .routes(routes![history])
+        .routes(routes![revert])
+}
+
+#[tracing::instrument]
+#[utoipa::path(get, path = "/{entity_type}/{entity_id}",
+    params(("entity_type" = AuditEntityType, Path), ("entity_id" = String, Path)),
+    responses(
+    (status = OK, body = Vec<AuditEventModel>),
+    AppError
+))]
+async fn history(
+    ledger: XLedger,
+    Path((entity_type, entity_id)): Path<(AuditEntityType, String)>,
+) -> AppResult<Json<Vec<AuditEventModel>>> {
+    let db = database(ledger.id).await?;
+    Ok(Json(
+        AuditEventModel::history(&db, entity_type, &entity_id).await?,
+    ))
+}
+
+#[tracing::instrument]
+#[utoipa::path(post, path = "/revert/{event_id}", params(("event_id" = Uuid, Path)), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn revert(ledger: XLedger, audit: AuditContext, Path(event_id): Path<Uuid>) -> AppResult<()> {
+    let db = database(ledger.id).await?;
+    AuditEventModel::revert(&db, &audit, event_id).await?;
+    Ok(())
+}
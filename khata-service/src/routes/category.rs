@@ -5,8 +5,11 @@ use utoipa_axum::{router::OpenApiRouter, routes};
 use uuid::Uuid;
 
 use crate::{
-    AppError, AppResult, ValidatedJson, XUserId, database,
-    model::category::{CategoryModel, CategoryReq},
+    AppError, AppResult, ValidatedJson, XLedger, database,
+    model::{
+        audit::AuditContext,
+        category::{CategoryModel, CategoryReq},
+    },
 };
 
 pub fn router() -> OpenApiRouter<()> {
@@ -19,8 +22,8 @@ pub fn router() -> OpenApiRouter<()> {
     AppError
 ))]
 #[axum::debug_handler]
-async fn category(id: XUserId) -> AppResult<Json<Vec<CategoryModel>>> {
-    let db = database(&id.0).await?;
+async fn category(ledger: XLedger) -> AppResult<Json<Vec<CategoryModel>>> {
+    let db = database(ledger.id).await?;
     Ok(Json(CategoryReq::find_all(&db).await?))
 }
 
@@ -37,11 +40,12 @@ struct DeleteCategoryParams {
 ))]
 #[axum::debug_handler]
 async fn delete_category(
-    id: XUserId,
+    ledger: XLedger,
+    audit: AuditContext,
     Query(DeleteCategoryParams { id: category_id }): Query<DeleteCategoryParams>,
 ) -> AppResult<()> {
-    let db = database(&id.0).await?;
-    CategoryReq::delete(&db, category_id).await?;
+    let db = database(ledger.id).await?;
+    CategoryReq::delete(&db, &audit, category_id).await?;
     Ok(())
 }
 
@@ -53,10 +57,11 @@ async fn delete_category(
 ))]
 #[axum::debug_handler]
 async fn post_category(
-    id: XUserId,
+    ledger: XLedger,
+    audit: AuditContext,
     ValidatedJson(category): ValidatedJson<CategoryReq>,
 ) -> AppResult<()> {
-    let db = database(&id.0).await?;
-    CategoryReq::upsert(&db, category).await?;
+    let db = database(ledger.id).await?;
+    CategoryReq::upsert(&db, &audit, category).await?;
     Ok(())
 }
@@ -10,10 +10,13 @@ use utoipa::IntoParams;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
-    AppError, AppResult, ValidatedJson, XUserId,
+    AppError, AppResult, ValidatedJson, XLedger,
     cache::CacheManager,
     database,
-    model::currency::{CurrencyModel, CurrencyReq},
+    model::{
+        audit::AuditContext,
+        currency::{CurrencyModel, CurrencyReq},
+    },
 };
 
 pub fn router() -> OpenApiRouter<Arc<Mutex<CacheManager>>> {
@@ -32,8 +35,8 @@ pub fn router() -> OpenApiRouter<Arc<Mutex<CacheManager>>> {
     (status = OK, body = Vec<CurrencyModel>),
     AppError
 ))]
-async fn currency(id: XUserId) -> AppResult<Json<Vec<CurrencyModel>>> {
-    let db = database(&id.0).await?;
+async fn currency(ledger: XLedger) -> AppResult<Json<Vec<CurrencyModel>>> {
+    let db = database(ledger.id).await?;
     Ok(Json(CurrencyReq::find_all(&db).await?))
 }
 
@@ -43,10 +46,10 @@ async fn currency(id: XUserId) -> AppResult<Json<Vec<CurrencyModel>>> {
     AppError
 ))]
 async fn currency_by_id(
-    id: XUserId,
+    ledger: XLedger,
     Path(code): Path<String>,
 ) -> AppResult<Json<Option<CurrencyModel>>> {
-    let db = database(&id.0).await?;
+    let db = database(ledger.id).await?;
     Ok(Json(CurrencyReq::find_one(&db, &code).await?))
 }
 
@@ -62,11 +65,12 @@ struct DeleteCurrencyParams {
     AppError
 ))]
 async fn delete_currency(
-    id: XUserId,
+    ledger: XLedger,
+    audit: AuditContext,
     Query(DeleteCurrencyParams { code }): Query<DeleteCurrencyParams>,
 ) -> AppResult<()> {
-    let db = database(&id.0).await?;
-    CurrencyReq::delete(&db, &code).await?;
+    let db = database(ledger.id).await?;
+    CurrencyReq::delete(&db, &audit, &code).await?;
     Ok(())
 }
 
@@ -76,11 +80,12 @@ async fn delete_currency(
     (status = OK, body = ()), AppError
 ))]
 async fn post_currency(
-    id: XUserId,
+    ledger: XLedger,
+    audit: AuditContext,
     ValidatedJson(currency): ValidatedJson<CurrencyReq>,
 ) -> AppResult<()> {
-    let db = database(&id.0).await?;
-    CurrencyReq::upsert(&db, currency).await?;
+    let db = database(ledger.id).await?;
+    CurrencyReq::upsert(&db, &audit, currency).await?;
     Ok(())
 }
 
@@ -90,11 +95,12 @@ async fn post_currency(
     AppError
 ))]
 async fn sync_currency(
-    id: XUserId,
+    ledger: XLedger,
+    audit: AuditContext,
     State(cache): State<Arc<Mutex<CacheManager>>>,
 ) -> AppResult<()> {
-    let db = database(&id.0).await?;
-    tracing::info!("Syncing currency for {}", id.0);
+    let db = database(ledger.id).await?;
+    tracing::info!("Syncing currency for {}", ledger.id);
     let currencies = cache
         .lock()
         .await
@@ -111,6 +117,6 @@ async fn sync_currency(
             decimal_digits: c.decimal_digits,
         })
         .collect();
-    CurrencyReq::upsert_many(&db, currencies).await?;
+    CurrencyReq::upsert_many(&db, &audit, currencies).await?;
     Ok(())
 }
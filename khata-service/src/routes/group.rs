@@ -0,0 +1,234 @@
+use axum::{
+    Json,
+    extract::{Path, Query},
+};
+use sea_orm::prelude::Uuid;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    AppError, AppResult, AuthSession, ValidatedJson, XLedger, database,
+    model::{
+        audit::AuditContext,
+        group::{
+            ExpenseGroupModel, ExpenseGroupReq, GroupExpenseExpandedModel, GroupExpenseReq,
+            GroupMemberModel, GroupMemberReq, GroupSettlementModel, GroupSettlementReq,
+            MemberBalance, SettleUp,
+        },
+        user::User,
+    },
+};
+
+pub fn router() -> OpenApiRouter<()> {
+    OpenApiRouter::new()
+        .routes(routes![group, put_group, delete_group])
+        .routes(routes![member, put_member, delete_member])
+        .routes(routes![expense, put_expense, delete_expense])
+        .routes(routes![balance])
+        .routes(routes![settle_up, post_settle_up])
+        .routes(routes![settlement])
+}
+
+#[derive(Deserialize, IntoParams)]
+struct DeleteGroupParams {
+    #[into_params(names("id"), parameter_in = Query)]
+    id: Uuid,
+}
+
+#[tracing::instrument]
+#[utoipa::path(get, path = "/", responses(
+    (status = OK, body = Vec<ExpenseGroupModel>),
+    AppError
+))]
+async fn group(ledger: XLedger) -> AppResult<Json<Vec<ExpenseGroupModel>>> {
+    let db = database(ledger.id).await?;
+    Ok(Json(ExpenseGroupReq::find_all(&db).await?))
+}
+
+#[tracing::instrument(skip(group))]
+#[utoipa::path(put, path = "/",
+    request_body = ExpenseGroupReq, responses(
+    (status = OK, body = Uuid),
+    AppError
+))]
+async fn put_group(
+    ledger: XLedger,
+    ValidatedJson(group): ValidatedJson<ExpenseGroupReq>,
+) -> AppResult<Json<Uuid>> {
+    let db = database(ledger.id).await?;
+    Ok(Json(ExpenseGroupReq::upsert(&db, group).await?))
+}
+
+#[tracing::instrument]
+#[utoipa::path(delete, path = "/", params(DeleteGroupParams), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn delete_group(
+    ledger: XLedger,
+    Query(DeleteGroupParams { id }): Query<DeleteGroupParams>,
+) -> AppResult<()> {
+    let db = database(ledger.id).await?;
+    ExpenseGroupReq::delete(&db, id).await?;
+    Ok(())
+}
+
+#[tracing::instrument]
+#[utoipa::path(get, path = "/{group_id}/member", params(("group_id" = Uuid, Path)), responses(
+    (status = OK, body = Vec<GroupMemberModel>),
+    AppError
+))]
+async fn member(
+    ledger: XLedger,
+    Path(group_id): Path<Uuid>,
+) -> AppResult<Json<Vec<GroupMemberModel>>> {
+    let db = database(ledger.id).await?;
+    Ok(Json(ExpenseGroupReq::members(&db, group_id).await?))
+}
+
+#[tracing::instrument(skip(auth_session, member))]
+#[utoipa::path(put, path = "/{group_id}/member", params(("group_id" = Uuid, Path)),
+    request_body = GroupMemberReq, responses(
+    (status = OK, body = Uuid),
+    AppError
+))]
+async fn put_member(
+    auth_session: AuthSession,
+    ledger: XLedger,
+    Path(group_id): Path<Uuid>,
+    ValidatedJson(member): ValidatedJson<GroupMemberReq>,
+) -> AppResult<Json<Uuid>> {
+    if let Some(user_id) = member.user_id
+        && User::find_by_id(auth_session.backend.db(), user_id)
+            .await?
+            .is_none()
+    {
+        let mut errors = validator::ValidationErrors::new();
+        errors.add(
+            "user_id",
+            validator::ValidationError::new("user").with_message("No such user".into()),
+        );
+        return Err(AppError::ValidationError(errors));
+    }
+    let db = database(ledger.id).await?;
+    Ok(Json(
+        ExpenseGroupReq::upsert_member(&db, group_id, member).await?,
+    ))
+}
+
+#[tracing::instrument]
+#[utoipa::path(delete, path = "/{group_id}/member",
+    params(("group_id" = Uuid, Path), DeleteGroupParams), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn delete_member(
+    ledger: XLedger,
+    Path(group_id): Path<Uuid>,
+    Query(DeleteGroupParams { id }): Query<DeleteGroupParams>,
+) -> AppResult<()> {
+    let db = database(ledger.id).await?;
+    ExpenseGroupReq::delete_member(&db, group_id, id).await?;
+    Ok(())
+}
+
+#[tracing::instrument]
+#[utoipa::path(get, path = "/{group_id}/expense", params(("group_id" = Uuid, Path)), responses(
+    (status = OK, body = Vec<GroupExpenseExpandedModel>),
+    AppError
+))]
+async fn expense(
+    ledger: XLedger,
+    Path(group_id): Path<Uuid>,
+) -> AppResult<Json<Vec<GroupExpenseExpandedModel>>> {
+    let db = database(ledger.id).await?;
+    Ok(Json(ExpenseGroupReq::expenses(&db, group_id).await?))
+}
+
+#[tracing::instrument(skip(expense))]
+#[utoipa::path(put, path = "/{group_id}/expense", params(("group_id" = Uuid, Path)),
+    request_body = GroupExpenseReq, responses(
+    (status = OK, body = Uuid),
+    AppError
+))]
+async fn put_expense(
+    ledger: XLedger,
+    Path(group_id): Path<Uuid>,
+    ValidatedJson(expense): ValidatedJson<GroupExpenseReq>,
+) -> AppResult<Json<Uuid>> {
+    let db = database(ledger.id).await?;
+    Ok(Json(
+        ExpenseGroupReq::upsert_expense(&db, group_id, expense).await?,
+    ))
+}
+
+#[tracing::instrument]
+#[utoipa::path(delete, path = "/{group_id}/expense",
+    params(("group_id" = Uuid, Path), DeleteGroupParams), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn delete_expense(
+    ledger: XLedger,
+    Path(group_id): Path<Uuid>,
+    Query(DeleteGroupParams { id }): Query<DeleteGroupParams>,
+) -> AppResult<()> {
+    let db = database(ledger.id).await?;
+    ExpenseGroupReq::delete_expense(&db, group_id, id).await?;
+    Ok(())
+}
+
+#[tracing::instrument]
+#[utoipa::path(get, path = "/{group_id}/balance", params(("group_id" = Uuid, Path)), responses(
+    (status = OK, body = Vec<MemberBalance>),
+    AppError
+))]
+async fn balance(
+    ledger: XLedger,
+    Path(group_id): Path<Uuid>,
+) -> AppResult<Json<Vec<MemberBalance>>> {
+    let db = database(ledger.id).await?;
+    Ok(Json(ExpenseGroupReq::balances(&db, group_id).await?))
+}
+
+#[tracing::instrument]
+#[utoipa::path(get, path = "/{group_id}/settle-up", params(("group_id" = Uuid, Path)), responses(
+    (status = OK, body = Vec<SettleUp>),
+    AppError
+))]
+async fn settle_up(ledger: XLedger, Path(group_id): Path<Uuid>) -> AppResult<Json<Vec<SettleUp>>> {
+    let db = database(ledger.id).await?;
+    Ok(Json(ExpenseGroupReq::settle_up(&db, group_id).await?))
+}
+
+#[tracing::instrument(skip(settlement))]
+#[utoipa::path(post, path = "/{group_id}/settle-up", params(("group_id" = Uuid, Path)),
+    request_body = GroupSettlementReq, responses(
+    (status = OK, body = Uuid),
+    AppError
+))]
+async fn post_settle_up(
+    ledger: XLedger,
+    audit: AuditContext,
+    Path(group_id): Path<Uuid>,
+    ValidatedJson(settlement): ValidatedJson<GroupSettlementReq>,
+) -> AppResult<Json<Uuid>> {
+    let db = database(ledger.id).await?;
+    Ok(Json(
+        ExpenseGroupReq::settle(&db, &audit, group_id, settlement).await?,
+    ))
+}
+
+#[tracing::instrument]
+#[utoipa::path(get, path = "/{group_id}/settlement", params(("group_id" = Uuid, Path)), responses(
+    (status = OK, body = Vec<GroupSettlementModel>),
+    AppError
+))]
+async fn settlement(
+    ledger: XLedger,
+    Path(group_id): Path<Uuid>,
+) -> AppResult<Json<Vec<GroupSettlementModel>>> {
+    let db = database(ledger.id).await?;
+    Ok(Json(ExpenseGroupReq::settlements(&db, group_id).await?))
+}
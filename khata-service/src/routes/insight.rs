@@ -0,0 +1,56 @@
+use axum::{Json, extract::Query};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    XLedger, database,
+    error::{AppError, AppResult},
+    insight::{self, Anomaly},
+    model::currency::CurrencyReq,
+    period,
+};
+
+pub fn router() -> OpenApiRouter<()> {
+    OpenApiRouter::new().routes(routes![anomalies])
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct AnomalyParams {
+    /// Full months of history to compare with, 6 by default.
+    months: Option<u32>,
+    /// Most anomalies to return, 20 by default.
+    limit: Option<usize>,
+}
+
+/// Unusual spending in the ledger, most unusual first, each with an
+/// explanation.
+#[tracing::instrument]
+#[utoipa::path(get, path = "/anomaly", params(AnomalyParams), responses(
+    (status = OK, body = Vec<Anomaly>),
+    AppError
+))]
+async fn anomalies(
+    ledger: XLedger,
+    Query(params): Query<AnomalyParams>,
+) -> AppResult<Json<Vec<Anomaly>>> {
+    let db = database(ledger.id).await?;
+    let tz = ledger.settings.tz();
+    let today = period::today(&tz);
+    let months = params.months.unwrap_or(6).clamp(3, 24);
+    let spending = insight::load(&db, &tz, today, months, false)
+        .await
+        .map_err(AppError::Other)?;
+    let categories = insight::category_names(&db)
+        .await
+        .map_err(AppError::Other)?;
+    let digits = CurrencyReq::find_all(&db)
+        .await?
+        .into_iter()
+        .map(|c| (c.0.code, c.0.decimal_digits))
+        .collect();
+    let mut anomalies = insight::detect(&spending, &categories, &digits, today, months);
+    anomalies.truncate(params.limit.unwrap_or(20));
+    Ok(Json(anomalies))
+}
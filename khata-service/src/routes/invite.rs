@@ -0,0 +1,74 @@
+use axum::{Json, extract::Path};
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use crate::{
+    AppError, AppResult, AuthSession, ValidatedJson, XUserId,
+    model::{
+        invite::{Invite, InviteReq, NewInvite},
+        user::User,
+    },
+};
+
+pub fn router() -> OpenApiRouter<()> {
+    OpenApiRouter::new()
+        .routes(routes![invite, create_invite])
+        .routes(routes![revoke_invite])
+}
+
+/// Administrators see and manage every invite, others only their own.
+async fn invite_scope(auth_session: &AuthSession, user_id: Uuid) -> AppResult<Option<Uuid>> {
+    let user = User::find_by_id(auth_session.backend.db(), user_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized(anyhow::anyhow!("Not logged in")))?;
+    Ok((!user.is_admin).then_some(user.id))
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/", responses(
+    (status = OK, body = Vec<Invite>),
+    AppError
+))]
+async fn invite(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+) -> AppResult<Json<Vec<Invite>>> {
+    let created_by = invite_scope(&auth_session, user_id).await?;
+    Ok(Json(
+        Invite::find_created_by(auth_session.backend.db(), created_by).await?,
+    ))
+}
+
+/// Creates a single-use invite code. The response holds the code, which
+/// cannot be retrieved again.
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(post, path = "/",
+    request_body = InviteReq, responses(
+    (status = OK, body = NewInvite),
+    AppError
+))]
+async fn create_invite(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+    ValidatedJson(req): ValidatedJson<InviteReq>,
+) -> AppResult<Json<NewInvite>> {
+    Ok(Json(
+        Invite::create(auth_session.backend.db(), user_id, &req).await?,
+    ))
+}
+
+/// Deletes an invite that was not used yet.
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(delete, path = "/{invite_id}", params(("invite_id" = Uuid, Path)), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn revoke_invite(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+    Path(invite_id): Path<Uuid>,
+) -> AppResult<()> {
+    let created_by = invite_scope(&auth_session, user_id).await?;
+    Invite::revoke(auth_session.backend.db(), invite_id, created_by).await?;
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use axum::{Json, extract::Query};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use crate::{
+    AppError, AppResult, AuthSession, ValidatedJson, XLedger, XLedgerOwner, XUserId,
+    model::ledger::{Ledger, LedgerMember, LedgerMemberReq, LedgerReq},
+    remove_database,
+};
+
+pub fn router() -> OpenApiRouter<()> {
+    OpenApiRouter::new()
+        .routes(routes![ledger, post_ledger, delete_ledger])
+        .routes(routes![member, put_member, delete_member])
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/", responses(
+    (status = OK, body = Vec<Ledger>),
+    AppError
+))]
+async fn ledger(auth_session: AuthSession, XUserId(id): XUserId) -> AppResult<Json<Vec<Ledger>>> {
+    let db = auth_session.backend.db();
+    if let Some(user) = &auth_session.user {
+        Ledger::ensure_personal(db, user).await?;
+    }
+    Ok(Json(Ledger::find_for_user(db, id).await?))
+}
+
+#[tracing::instrument(skip(auth_session, ledger))]
+#[utoipa::path(post, path = "/",
+    request_body = LedgerReq, responses(
+    (status = OK, body = Uuid),
+    AppError
+))]
+async fn post_ledger(
+    auth_session: AuthSession,
+    XUserId(id): XUserId,
+    ValidatedJson(ledger): ValidatedJson<LedgerReq>,
+) -> AppResult<Json<Uuid>> {
+    Ok(Json(
+        Ledger::create(auth_session.backend.db(), id, ledger).await?,
+    ))
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(delete, path = "/", responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn delete_ledger(
+    auth_session: AuthSession,
+    XLedgerOwner(ledger): XLedgerOwner,
+) -> AppResult<()> {
+    let db = auth_session.backend.db();
+    Ledger::delete(db, ledger.id).await?;
+    remove_database(ledger.id).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/member", responses(
+    (status = OK, body = Vec<LedgerMember>),
+    AppError
+))]
+async fn member(auth_session: AuthSession, ledger: XLedger) -> AppResult<Json<Vec<LedgerMember>>> {
+    Ok(Json(
+        Ledger::members(auth_session.backend.db(), ledger.id).await?,
+    ))
+}
+
+#[tracing::instrument(skip(auth_session, member))]
+#[utoipa::path(put, path = "/member",
+    request_body = LedgerMemberReq, responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn put_member(
+    auth_session: AuthSession,
+    XLedgerOwner(ledger): XLedgerOwner,
+    ValidatedJson(member): ValidatedJson<LedgerMemberReq>,
+) -> AppResult<()> {
+    Ledger::upsert_member(auth_session.backend.db(), ledger.id, member).await?;
+    Ok(())
+}
+
+#[derive(Deserialize, IntoParams)]
+struct DeleteMemberParams {
+    #[into_params(names("user_id"), parameter_in = Query)]
+    user_id: Uuid,
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(delete, path = "/member", params(DeleteMemberParams), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn delete_member(
+    auth_session: AuthSession,
+    XLedgerOwner(ledger): XLedgerOwner,
+    Query(DeleteMemberParams { user_id }): Query<DeleteMemberParams>,
+) -> AppResult<()> {
+    Ledger::remove_member(auth_session.backend.db(), ledger.id, user_id).await?;
+    Ok(())
+}
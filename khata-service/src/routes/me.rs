@@ -0,0 +1,259 @@
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use axum::{
+    Json,
+    extract::Path,
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Duration, Utc};
+use secrecy::ExposeSecret;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    AppError, AppResult, AuthSession, ValidatedJson, XSessionUserId, XUserId, database,
+    model::{
+        account::AccountReq,
+        data_export::{DataExport, DataExportStatus},
+        ledger::Ledger,
+        user::User,
+        user_settings::{UserSettings, UserSettingsPatch},
+    },
+    user_data::{DELETION_GRACE_DAYS, build_export, export_path},
+};
+
+pub fn router() -> OpenApiRouter<()> {
+    OpenApiRouter::new()
+        .routes(routes![delete_account])
+        .routes(routes![cancel_delete_account])
+        .routes(routes![exports, create_export])
+        .routes(routes![download_export])
+        .routes(routes![settings, patch_settings])
+        .routes(routes![patch_profile])
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/settings", responses(
+    (status = OK, body = UserSettings),
+    AppError
+))]
+async fn settings(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+) -> AppResult<Json<UserSettings>> {
+    let user = current_user(&auth_session, user_id).await?;
+    Ok(Json(user.settings))
+}
+
+/// Changes the given settings and returns all of them.
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(patch, path = "/settings",
+    request_body = UserSettingsPatch, responses(
+    (status = OK, body = UserSettings),
+    AppError
+))]
+async fn patch_settings(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+    Json(patch): Json<UserSettingsPatch>,
+) -> AppResult<Json<UserSettings>> {
+    let user = current_user(&auth_session, user_id).await?;
+    let mut settings = user.settings;
+    if settings.is_newer() {
+        return Err(AppError::Conflict(anyhow::anyhow!(
+            "Settings were saved by a newer version of Khata"
+        )));
+    }
+    settings.apply(patch);
+    settings.validate()?;
+    if let Some(account_id) = settings.default_account_id {
+        let db = database(user.id).await?;
+        if AccountReq::find_one_with_currency(&db, account_id)
+            .await?
+            .is_none()
+        {
+            let mut errors = validator::ValidationErrors::new();
+            errors.add(
+                "default_account_id",
+                validator::ValidationError::new("default_account_id")
+                    .with_message("Default account not found in the personal ledger".into()),
+            );
+            return Err(AppError::ValidationError(errors));
+        }
+    }
+    User::update_settings(auth_session.backend.db(), user.id, &settings).await?;
+    Ok(Json(settings))
+}
+
+#[derive(Debug, Deserialize, ToSchema, Validate)]
+struct ProfileReq {
+    #[validate(length(min = 1, max = 100))]
+    name: String,
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(patch, path = "/profile",
+    request_body = ProfileReq, responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn patch_profile(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+    ValidatedJson(req): ValidatedJson<ProfileReq>,
+) -> AppResult<()> {
+    User::update_name(auth_session.backend.db(), user_id, req.name.trim()).await?;
+    Ok(())
+}
+
+async fn current_user(auth_session: &AuthSession, user_id: Uuid) -> AppResult<User> {
+    User::find_by_id(auth_session.backend.db(), user_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized(anyhow::anyhow!("Not logged in")))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct DeleteAccount {
+    password: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct AccountDeletion {
+    deletion_scheduled_at: DateTime<Utc>,
+    /// Ledgers the user owns that other members use. They are deleted with
+    /// the account.
+    shared_ledgers: Vec<SharedLedger>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct SharedLedger {
+    id: Uuid,
+    name: String,
+}
+
+/// Schedules the deletion of the account after a grace period. The account,
+/// its sessions and its ledgers are removed once it is over, unless the
+/// deletion is cancelled.
+#[tracing::instrument(skip(auth_session, req))]
+#[utoipa::path(post, path = "/delete", responses(
+    (status = OK, body = AccountDeletion),
+    AppError
+))]
+async fn delete_account(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+    Json(req): Json<DeleteAccount>,
+) -> AppResult<Json<AccountDeletion>> {
+    let db = auth_session.backend.db();
+    let user = current_user(&auth_session, user_id).await?;
+    let hash = PasswordHash::new(user.password_hash.expose_secret())
+        .map_err(|e| AppError::Other(anyhow::anyhow!(e)))?;
+    if Argon2::default()
+        .verify_password(req.password.as_bytes(), &hash)
+        .is_err()
+    {
+        return Err(AppError::Unauthorized(anyhow::anyhow!("Password is wrong")));
+    }
+    let deletion_scheduled_at = user
+        .deletion_scheduled_at
+        .unwrap_or_else(|| Utc::now() + Duration::days(DELETION_GRACE_DAYS));
+    User::schedule_deletion(db, user_id, Some(deletion_scheduled_at)).await?;
+    let shared_ledgers = Ledger::find_owned_by(db, user_id)
+        .await?
+        .into_iter()
+        .filter(|l| l.id != user_id)
+        .map(|l| SharedLedger {
+            id: l.id,
+            name: l.name,
+        })
+        .collect();
+    Ok(Json(AccountDeletion {
+        deletion_scheduled_at,
+        shared_ledgers,
+    }))
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(post, path = "/delete/cancel", responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn cancel_delete_account(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+) -> AppResult<()> {
+    User::schedule_deletion(auth_session.backend.db(), user_id, None).await?;
+    Ok(())
+}
+
+/// The data exports of the user, latest first. They can be downloaded for a
+/// day.
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/export", responses(
+    (status = OK, body = Vec<DataExport>),
+    AppError
+))]
+async fn exports(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+) -> AppResult<Json<Vec<DataExport>>> {
+    Ok(Json(
+        DataExport::find_for_user(auth_session.backend.db(), user_id).await?,
+    ))
+}
+
+/// Starts bundling the profile, settings and every ledger of the user into a
+/// zip. Poll [`exports`] until it is ready.
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(post, path = "/export", responses(
+    (status = OK, body = DataExport),
+    AppError
+))]
+async fn create_export(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+) -> AppResult<Json<DataExport>> {
+    let db = auth_session.backend.db();
+    let export = DataExport::create_unless_pending(db, user_id)
+        .await?
+        .ok_or_else(|| AppError::Conflict(anyhow::anyhow!("An export is already in progress")))?;
+    tokio::spawn(build_export(db.clone(), export.id, user_id));
+    Ok(Json(export))
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/export/{export_id}", params(("export_id" = Uuid, Path)), responses(
+    (status = OK, content_type = "application/zip", body = Vec<u8>),
+    AppError
+))]
+async fn download_export(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+    Path(export_id): Path<Uuid>,
+) -> AppResult<impl IntoResponse> {
+    let export = DataExport::find(auth_session.backend.db(), user_id, export_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(anyhow::anyhow!("Export {export_id} not found")))?;
+    if export.status != DataExportStatus::Ready {
+        return Err(AppError::Conflict(anyhow::anyhow!("Export is not ready")));
+    }
+    let content = tokio::fs::read(export_path(export.id))
+        .await
+        .map_err(|e| AppError::Other(anyhow::anyhow!(e)))?;
+    Ok((
+        [
+            (CONTENT_TYPE, "application/zip".to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!(
+                    "attachment; filename=\"khata-export-{}.zip\"",
+                    export.created_at.format("%Y-%m-%d")
+                ),
+            ),
+        ],
+        content,
+    ))
+}
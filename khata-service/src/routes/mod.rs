@@ -1,6 +1,19 @@
 pub mod account;
+pub mod admin;
+pub mod api_token;
+pub mod audit;
 pub mod category;
 pub mod currency;
 pub mod currency_cache;
 pub mod dashboard;
+pub mod group;
+pub mod insight;
+pub mod invite;
+pub mod ledger;
+pub mod me;
+pub mod notification;
+pub mod report;
+pub mod session;
+pub mod subscription;
+pub mod totp;
 pub mod transaction;
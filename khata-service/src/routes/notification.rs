@@ -0,0 +1,115 @@
+use axum::{
+    Json,
+    extract::{Path, Query},
+};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use crate::{
+    AppError, AppResult, AuthSession, ValidatedJson, XLedger, XUserId,
+    model::notification::{Notification, NotificationRule, NotificationRuleReq},
+};
+
+pub fn router() -> OpenApiRouter<()> {
+    OpenApiRouter::new()
+        .routes(routes![notification])
+        .routes(routes![read_notification])
+        .routes(routes![outbox])
+        .routes(routes![rule, put_rule, delete_rule])
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/", responses(
+    (status = OK, body = Vec<Notification>),
+    AppError
+))]
+async fn notification(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+) -> AppResult<Json<Vec<Notification>>> {
+    Ok(Json(
+        Notification::inbox(auth_session.backend.db(), user_id).await?,
+    ))
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(post, path = "/{notification_id}/read", params(("notification_id" = Uuid, Path)), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn read_notification(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+    Path(notification_id): Path<Uuid>,
+) -> AppResult<()> {
+    Notification::mark_read(auth_session.backend.db(), user_id, notification_id).await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/outbox", responses(
+    (status = OK, body = Vec<Notification>),
+    AppError
+))]
+async fn outbox(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+) -> AppResult<Json<Vec<Notification>>> {
+    Ok(Json(
+        Notification::outbox(auth_session.backend.db(), user_id).await?,
+    ))
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/rule", responses(
+    (status = OK, body = Vec<NotificationRule>),
+    AppError
+))]
+async fn rule(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+    ledger: XLedger,
+) -> AppResult<Json<Vec<NotificationRule>>> {
+    Ok(Json(
+        NotificationRule::find_for_user(auth_session.backend.db(), user_id, ledger.id).await?,
+    ))
+}
+
+#[tracing::instrument(skip(auth_session, rule))]
+#[utoipa::path(put, path = "/rule",
+    request_body = NotificationRuleReq, responses(
+    (status = OK, body = Uuid),
+    AppError
+))]
+async fn put_rule(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+    ledger: XLedger,
+    ValidatedJson(rule): ValidatedJson<NotificationRuleReq>,
+) -> AppResult<Json<Uuid>> {
+    Ok(Json(
+        NotificationRule::upsert(auth_session.backend.db(), user_id, ledger.id, rule).await?,
+    ))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct DeleteRuleParams {
+    #[into_params(names("id"), parameter_in = Query)]
+    id: Uuid,
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(delete, path = "/rule", params(DeleteRuleParams), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn delete_rule(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+    Query(DeleteRuleParams { id }): Query<DeleteRuleParams>,
+) -> AppResult<()> {
+    NotificationRule::delete(auth_session.backend.db(), user_id, id).await?;
+    Ok(())
+}
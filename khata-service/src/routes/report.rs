@@ -0,0 +1,450 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    response::{IntoResponse, Response},
+};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use utoipa::{IntoParams, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use crate::{
+    AuthSession, ValidatedJson, XLedger, XLedgerViewer, XUserId,
+    cache::CacheManager,
+    database,
+    error::{AppError, AppResult},
+    forecast::Forecast,
+    model::{
+        account::AccountReq,
+        report_definition::{ReportDefinition, ReportDefinitionReq},
+        transaction::TransactionReq,
+    },
+    period::{self, Period, PeriodKind, Periods},
+    pivot::{PivotQuery, PivotResult},
+    report::{BalanceSheet, CashFlow, IncomeStatement, MissingRate, ReportFormat},
+};
+
+pub fn router() -> OpenApiRouter<Arc<Mutex<CacheManager>>> {
+    OpenApiRouter::new()
+        .routes(routes![summary])
+        .routes(routes![income_statement])
+        .routes(routes![balance_sheet])
+        .routes(routes![cash_flow])
+        .routes(routes![forecast])
+        .routes(routes![pivot])
+        .routes(routes![definition, put_definition, delete_definition])
+        .routes(routes![run_definition])
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SummaryParams {
+    /// Calendar months by default.
+    period: Option<PeriodKind>,
+    /// Number of periods up to the current one, 12 by default.
+    count: Option<u32>,
+}
+
+/// Income and expense of one currency, in its minor units.
+#[derive(Debug, Default, Serialize, ToSchema)]
+struct IncomeExpense {
+    income: i64,
+    expense: i64,
+    /// Share of the income that was not spent, if there was any income.
+    savings_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct PeriodSummary {
+    period: Period,
+    /// By currency code.
+    currencies: HashMap<String, IncomeExpense>,
+}
+
+/// Income, expense and savings rate per period, oldest first. Only
+/// categorized items count, so transfers between accounts are left out.
+#[tracing::instrument]
+#[utoipa::path(get, path = "/summary", params(SummaryParams), responses(
+    (status = OK, body = Vec<PeriodSummary>),
+    AppError
+))]
+async fn summary(
+    ledger: XLedger,
+    Query(params): Query<SummaryParams>,
+) -> AppResult<Json<Vec<PeriodSummary>>> {
+    let db = database(ledger.id).await?;
+    let accounts = AccountReq::find_all_with_currency(&db)
+        .await?
+        .into_iter()
+        .map(|a| (a.account.id, a))
+        .collect::<HashMap<_, _>>();
+    let tz = ledger.settings.tz();
+    let periods = Periods::new(params.period.unwrap_or_default(), &ledger.settings)
+        .last(period::today(&tz), params.count.unwrap_or(12).clamp(1, 120));
+    let mut summaries = periods
+        .iter()
+        .map(|&period| PeriodSummary {
+            period,
+            currencies: HashMap::new(),
+        })
+        .collect::<Vec<_>>();
+    let (Some(first), Some(last)) = (periods.first(), periods.last()) else {
+        return Ok(Json(summaries));
+    };
+    let (start, _) = first.bounds(&tz).map_err(AppError::Other)?;
+    let (_, end) = last.bounds(&tz).map_err(AppError::Other)?;
+    for t in TransactionReq::find_by_month(&db, start, end).await? {
+        let date = period::local_date(&tz, t.transaction.0.timestamp);
+        let Some(summary) = summaries
+            .iter_mut()
+            .find(|s| s.period.start <= date && date < s.period.end)
+        else {
+            continue;
+        };
+        for i in &t.items {
+            if i.0.category_id.is_none() {
+                continue;
+            }
+            let Some(account) = accounts.get(&i.0.account_id) else {
+                continue;
+            };
+            let entry = summary
+                .currencies
+                .entry(account.currency.0.code.clone())
+                .or_default();
+            if i.0.amount > 0 {
+                entry.income += i.0.amount;
+            } else {
+                entry.expense -= i.0.amount;
+            }
+        }
+    }
+    for entry in summaries.iter_mut().flat_map(|s| s.currencies.values_mut()) {
+        if entry.income > 0 {
+            #[allow(clippy::cast_precision_loss)]
+            let rate = (entry.income - entry.expense) as f64 / entry.income as f64;
+            entry.savings_rate = Some(rate);
+        }
+    }
+    Ok(Json(summaries))
+}
+
+fn base_currency(ledger: &XLedger, currency: Option<String>) -> AppResult<String> {
+    let invalid = |code: &'static str, message: &'static str| {
+        let mut errors = validator::ValidationErrors::new();
+        errors.add(
+            "currency",
+            validator::ValidationError::new(code).with_message(message.into()),
+        );
+        AppError::ValidationError(errors)
+    };
+    let currency = currency
+        .or_else(|| ledger.settings.base_currency.clone())
+        .map(|c| c.to_uppercase())
+        .ok_or_else(|| {
+            invalid(
+                "required",
+                "Set a base currency in the settings or pass one",
+            )
+        })?;
+    if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_uppercase()) {
+        return Err(invalid("currency", "Invalid currency code"));
+    }
+    Ok(currency)
+}
+
+/// A report that needs a rate nobody has is a bad request, anything else
+/// went wrong on our side.
+fn report_error(e: anyhow::Error) -> AppError {
+    if e.is::<MissingRate>() {
+        AppError::BadRequest(e)
+    } else {
+        AppError::Other(e)
+    }
+}
+
+fn csv_response(name: &str, date: NaiveDate, csv: String) -> Response {
+    (
+        [
+            (CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{name}-{date}.csv\""),
+            ),
+        ],
+        csv,
+    )
+        .into_response()
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct IncomeStatementParams {
+    /// Calendar months by default.
+    period: Option<PeriodKind>,
+    /// A date in the period, today by default.
+    date: Option<NaiveDate>,
+    /// The base currency of the user by default.
+    currency: Option<String>,
+    format: Option<ReportFormat>,
+}
+
+/// Income and expense by category for a period, compared to the period
+/// before and the same period a year earlier.
+#[tracing::instrument(skip(cache))]
+#[utoipa::path(get, path = "/income-statement", params(IncomeStatementParams), responses(
+    (status = OK, content(
+        (IncomeStatement = "application/json"),
+        (String = "text/csv")
+    )),
+    AppError
+))]
+async fn income_statement(
+    State(cache): State<Arc<Mutex<CacheManager>>>,
+    ledger: XLedger,
+    Query(params): Query<IncomeStatementParams>,
+) -> AppResult<Response> {
+    let db = database(ledger.id).await?;
+    let base = base_currency(&ledger, params.currency)?;
+    let date = params
+        .date
+        .unwrap_or_else(|| period::today(&ledger.settings.tz()));
+    let periods = Periods::new(params.period.unwrap_or_default(), &ledger.settings);
+    let statement = IncomeStatement::build(&db, &cache, &ledger.settings, &base, periods, date)
+        .await
+        .map_err(report_error)?;
+    Ok(match params.format.unwrap_or_default() {
+        ReportFormat::Json => Json(statement).into_response(),
+        ReportFormat::Csv => csv_response(
+            "income-statement",
+            statement.period.start,
+            statement.to_csv(),
+        ),
+    })
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct BalanceSheetParams {
+    /// Balances at the end of this day, today by default.
+    date: Option<NaiveDate>,
+    /// The base currency of the user by default.
+    currency: Option<String>,
+    format: Option<ReportFormat>,
+}
+
+/// Assets and liabilities by account type at the end of a day.
+#[tracing::instrument(skip(cache))]
+#[utoipa::path(get, path = "/balance-sheet", params(BalanceSheetParams), responses(
+    (status = OK, content(
+        (BalanceSheet = "application/json"),
+        (String = "text/csv")
+    )),
+    AppError
+))]
+async fn balance_sheet(
+    State(cache): State<Arc<Mutex<CacheManager>>>,
+    ledger: XLedger,
+    Query(params): Query<BalanceSheetParams>,
+) -> AppResult<Response> {
+    let db = database(ledger.id).await?;
+    let base = base_currency(&ledger, params.currency)?;
+    let date = params
+        .date
+        .unwrap_or_else(|| period::today(&ledger.settings.tz()));
+    let sheet = BalanceSheet::build(&db, &cache, &ledger.settings, &base, date)
+        .await
+        .map_err(report_error)?;
+    Ok(match params.format.unwrap_or_default() {
+        ReportFormat::Json => Json(sheet).into_response(),
+        ReportFormat::Csv => csv_response("balance-sheet", sheet.date, sheet.to_csv()),
+    })
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct CashFlowParams {
+    /// Calendar months by default.
+    period: Option<PeriodKind>,
+    /// Number of periods up to the current one, 12 by default.
+    count: Option<u32>,
+    /// The base currency of the user by default.
+    currency: Option<String>,
+    format: Option<ReportFormat>,
+}
+
+/// Inflow, outflow and net cash flow of the cash-flow accounts per period,
+/// oldest first, with a running total.
+#[tracing::instrument(skip(cache))]
+#[utoipa::path(get, path = "/cash-flow", params(CashFlowParams), responses(
+    (status = OK, content(
+        (CashFlow = "application/json"),
+        (String = "text/csv")
+    )),
+    AppError
+))]
+async fn cash_flow(
+    State(cache): State<Arc<Mutex<CacheManager>>>,
+    ledger: XLedger,
+    Query(params): Query<CashFlowParams>,
+) -> AppResult<Response> {
+    let db = database(ledger.id).await?;
+    let base = base_currency(&ledger, params.currency)?;
+    let today = period::today(&ledger.settings.tz());
+    let periods = Periods::new(params.period.unwrap_or_default(), &ledger.settings)
+        .last(today, params.count.unwrap_or(12).clamp(1, 120));
+    let cash_flow = CashFlow::build(&db, &cache, &ledger.settings, &base, &periods)
+        .await
+        .map_err(report_error)?;
+    Ok(match params.format.unwrap_or_default() {
+        ReportFormat::Json => Json(cash_flow).into_response(),
+        ReportFormat::Csv => csv_response("cash-flow", today, cash_flow.to_csv()),
+    })
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct ForecastParams {
+    /// Days to project after today, 90 by default.
+    days: Option<u32>,
+    /// Days of history to look for recurring transactions in, 365 by default.
+    history_days: Option<u32>,
+    /// Warn when a balance drops below this, in minor units.
+    threshold: Option<i64>,
+}
+
+/// Projected daily balance of every cash-flow account, with the recurring
+/// and scheduled transactions it assumes and the days a balance drops below
+/// zero or the threshold.
+#[tracing::instrument]
+#[utoipa::path(get, path = "/forecast", params(ForecastParams), responses(
+    (status = OK, body = Forecast),
+    AppError
+))]
+async fn forecast(
+    ledger: XLedger,
+    Query(params): Query<ForecastParams>,
+) -> AppResult<Json<Forecast>> {
+    let db = database(ledger.id).await?;
+    let forecast = Forecast::build(
+        &db,
+        &ledger.settings.tz(),
+        params.days.unwrap_or(90).clamp(1, 366),
+        params.history_days.unwrap_or(365).clamp(90, 1095),
+        params.threshold,
+    )
+    .await
+    .map_err(AppError::Other)?;
+    Ok(Json(forecast))
+}
+
+/// Aggregates the items of the ledger by the requested dimensions and
+/// measures.
+#[tracing::instrument(skip(cache))]
+#[utoipa::path(post, path = "/pivot",
+    request_body = PivotQuery, responses(
+    (status = OK, body = PivotResult),
+    AppError
+))]
+async fn pivot(
+    State(cache): State<Arc<Mutex<CacheManager>>>,
+    XLedgerViewer(ledger): XLedgerViewer,
+    ValidatedJson(query): ValidatedJson<PivotQuery>,
+) -> AppResult<Json<PivotResult>> {
+    let today = period::today(&ledger.settings.tz());
+    query.validate_range(&ledger.settings, today)?;
+    let db = database(ledger.id).await?;
+    Ok(Json(
+        query
+            .run(&db, &cache, &ledger.settings, today)
+            .await
+            .map_err(report_error)?,
+    ))
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/definition", responses(
+    (status = OK, body = Vec<ReportDefinition>),
+    AppError
+))]
+async fn definition(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+) -> AppResult<Json<Vec<ReportDefinition>>> {
+    Ok(Json(
+        ReportDefinition::find_for_user(auth_session.backend.db(), user_id).await?,
+    ))
+}
+
+#[tracing::instrument(skip(auth_session, definition))]
+#[utoipa::path(put, path = "/definition",
+    request_body = ReportDefinitionReq, responses(
+    (status = OK, body = Uuid),
+    AppError
+))]
+async fn put_definition(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+    ValidatedJson(definition): ValidatedJson<ReportDefinitionReq>,
+) -> AppResult<Json<Uuid>> {
+    Ok(Json(
+        ReportDefinition::upsert(auth_session.backend.db(), user_id, definition).await?,
+    ))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct DeleteDefinitionParams {
+    #[into_params(names("id"), parameter_in = Query)]
+    id: Uuid,
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(delete, path = "/definition", params(DeleteDefinitionParams), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn delete_definition(
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+    Query(DeleteDefinitionParams { id }): Query<DeleteDefinitionParams>,
+) -> AppResult<()> {
+    ReportDefinition::delete(auth_session.backend.db(), user_id, id).await?;
+    Ok(())
+}
+
+/// Runs a saved report definition on the ledger.
+#[tracing::instrument(skip(auth_session, cache))]
+#[utoipa::path(get, path = "/definition/{definition_id}/run", params(("definition_id" = Uuid, Path)), responses(
+    (status = OK, body = PivotResult),
+    AppError
+))]
+async fn run_definition(
+    State(cache): State<Arc<Mutex<CacheManager>>>,
+    auth_session: AuthSession,
+    XUserId(user_id): XUserId,
+    ledger: XLedger,
+    Path(definition_id): Path<Uuid>,
+) -> AppResult<Json<PivotResult>> {
+    let definition = ReportDefinition::find(auth_session.backend.db(), user_id, definition_id)
+        .await?
+        .ok_or_else(|| {
+            AppError::NotFound(anyhow::anyhow!(
+                "Report definition {definition_id} not found"
+            ))
+        })?;
+    let today = period::today(&ledger.settings.tz());
+    definition.query.validate_range(&ledger.settings, today)?;
+    let db = database(ledger.id).await?;
+    Ok(Json(
+        definition
+            .query
+            .run(&db, &cache, &ledger.settings, today)
+            .await
+            .map_err(report_error)?,
+    ))
+}
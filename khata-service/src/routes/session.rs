@@ -0,0 +1,71 @@
+use axum::{Json, extract::Path};
+use chrono::{Duration, Utc};
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use crate::{
+    AppError, AppResult, AuthSession, SESSION_IDLE_MINUTES, XSessionUserId, current_device,
+    model::user_session::UserSession,
+};
+
+pub fn router() -> OpenApiRouter<()> {
+    OpenApiRouter::new()
+        .routes(routes![session])
+        .routes(routes![revoke_session])
+        .routes(routes![revoke_other_sessions])
+}
+
+/// The active sessions of the user, latest first.
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(get, path = "/", responses(
+    (status = OK, body = Vec<UserSession>),
+    AppError
+))]
+async fn session(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+) -> AppResult<Json<Vec<UserSession>>> {
+    let current = current_device(&auth_session).await?;
+    let since = Utc::now() - Duration::minutes(*SESSION_IDLE_MINUTES);
+    let mut sessions =
+        UserSession::find_for_user(auth_session.backend.db(), user_id, since).await?;
+    for session in &mut sessions {
+        session.current = Some(session.id) == current;
+    }
+    Ok(Json(sessions))
+}
+
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(delete, path = "/{session_id}", params(("session_id" = Uuid, Path)), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn revoke_session(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+    Path(session_id): Path<Uuid>,
+) -> AppResult<()> {
+    auth_session
+        .backend
+        .revoke_sessions(user_id, Some(session_id), None)
+        .await
+        .map_err(AppError::Other)
+}
+
+/// Signs out every session of the user except the current one.
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(post, path = "/revoke-others", responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn revoke_other_sessions(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+) -> AppResult<()> {
+    let current = current_device(&auth_session).await?;
+    auth_session
+        .backend
+        .revoke_sessions(user_id, None, current)
+        .await
+        .map_err(AppError::Other)
+}
@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use axum::{Json, extract::Query};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use crate::{
+    ValidatedJson, XLedger, database,
+    error::{AppError, AppResult},
+    insight,
+    model::{
+        audit::AuditContext,
+        subscription::{SubscriptionReq, SubscriptionStatus, normalize_payee},
+    },
+    period,
+    subscription::{self, Subscription},
+};
+
+pub fn router() -> OpenApiRouter<()> {
+    OpenApiRouter::new().routes(routes![
+        subscriptions,
+        put_subscription,
+        delete_subscription
+    ])
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct SubscriptionParams {
+    /// Full months of history to look for charges in, 24 by default.
+    months: Option<u32>,
+    /// Whether to list dismissed subscriptions too.
+    #[serde(default)]
+    include_dismissed: bool,
+}
+
+/// Payees charged at a regular interval for a stable amount, most expensive
+/// per year first, with what the user decided about each.
+#[tracing::instrument]
+#[utoipa::path(get, path = "/", params(SubscriptionParams), responses(
+    (status = OK, body = Vec<Subscription>),
+    AppError
+))]
+async fn subscriptions(
+    ledger: XLedger,
+    Query(params): Query<SubscriptionParams>,
+) -> AppResult<Json<Vec<Subscription>>> {
+    let db = database(ledger.id).await?;
+    let tz = ledger.settings.tz();
+    let today = period::today(&tz);
+    let months = params.months.unwrap_or(24).clamp(3, 60);
+    // Charges are often left uncategorized, so every outflow is searched.
+    let spending = insight::load(&db, &tz, today, months, true)
+        .await
+        .map_err(AppError::Other)?;
+    let mut decisions = SubscriptionReq::find_all(&db)
+        .await?
+        .into_iter()
+        .map(|d| ((d.payee.clone(), d.currency_code.clone()), d))
+        .collect::<HashMap<_, _>>();
+    let subscriptions = subscription::detect(&spending, today)
+        .into_iter()
+        .map(|mut s| {
+            s.decision = decisions.remove(&(normalize_payee(&s.payee), s.currency_code.clone()));
+            s
+        })
+        .filter(|s| {
+            params.include_dismissed
+                || s.decision
+                    .as_ref()
+                    .is_none_or(|d| d.status != SubscriptionStatus::Dismissed)
+        })
+        .collect();
+    Ok(Json(subscriptions))
+}
+
+/// Confirms or dismisses a detected subscription.
+#[tracing::instrument(skip(req))]
+#[utoipa::path(put, path = "/",
+    request_body = SubscriptionReq, responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn put_subscription(
+    ledger: XLedger,
+    audit: AuditContext,
+    ValidatedJson(req): ValidatedJson<SubscriptionReq>,
+) -> AppResult<()> {
+    let db = database(ledger.id).await?;
+    SubscriptionReq::upsert(&db, &audit, req).await?;
+    Ok(())
+}
+
+#[derive(Deserialize, IntoParams)]
+struct DeleteSubscriptionParams {
+    #[into_params(names("id"), parameter_in = Query)]
+    id: Uuid,
+}
+
+/// Forgets the decision about a subscription.
+#[tracing::instrument]
+#[utoipa::path(delete, path = "/", params(DeleteSubscriptionParams), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn delete_subscription(
+    ledger: XLedger,
+    audit: AuditContext,
+    Query(DeleteSubscriptionParams { id }): Query<DeleteSubscriptionParams>,
+) -> AppResult<()> {
+    let db = database(ledger.id).await?;
+    SubscriptionReq::delete(&db, &audit, id).await?;
+    Ok(())
+}
@@ -0,0 +1,167 @@
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use utoipa_axum::{router::OpenApiRouter, routes};
+use uuid::Uuid;
+
+use crate::{
+    AppError, AppResult, AuthSession, KEYS, XSessionUserId,
+    model::user::User,
+    totp::{generate_recovery_codes, generate_secret, totp, verify_second_factor, verify_totp},
+};
+
+pub fn router() -> OpenApiRouter<()> {
+    OpenApiRouter::new()
+        .routes(routes![enroll])
+        .routes(routes![confirm])
+        .routes(routes![recovery_codes])
+        .routes(routes![disable])
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+struct TotpEnrollment {
+    /// Base32 secret for authenticator apps that cannot scan the URI.
+    secret: String,
+    otpauth_uri: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+struct TotpCode {
+    code: String,
+}
+
+async fn current_user(auth_session: &AuthSession, user_id: Uuid) -> AppResult<User> {
+    User::find_by_id(auth_session.backend.db(), user_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized(anyhow::anyhow!("Not logged in")))
+}
+
+/// Starts enrolment with a new secret. Two-factor authentication stays off
+/// until a code from the secret is posted to [`confirm`].
+#[tracing::instrument(skip(auth_session))]
+#[utoipa::path(post, path = "/enroll", responses(
+    (status = OK, body = TotpEnrollment),
+    AppError
+))]
+async fn enroll(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+) -> AppResult<Json<TotpEnrollment>> {
+    let user = current_user(&auth_session, user_id).await?;
+    if user.totp_enabled {
+        return Err(AppError::Forbidden(anyhow::anyhow!(
+            "Two-factor authentication is already enabled"
+        )));
+    }
+    let secret = generate_secret().map_err(AppError::Other)?;
+    let encrypted = KEYS.encrypt(&secret).map_err(AppError::Other)?;
+    let totp = totp(secret, &user.email).map_err(AppError::Other)?;
+    User::update_totp(
+        auth_session.backend.db(),
+        user.id,
+        Some(encrypted),
+        false,
+        &[],
+    )
+    .await?;
+    Ok(Json(TotpEnrollment {
+        secret: totp.get_secret_base32(),
+        otpauth_uri: totp.get_url(),
+    }))
+}
+
+/// Turns two-factor authentication on with the first code from the enrolled
+/// secret and returns the recovery codes. They are only shown this once.
+#[tracing::instrument(skip(auth_session, req))]
+#[utoipa::path(post, path = "/confirm", responses(
+    (status = OK, body = Vec<String>),
+    AppError
+))]
+async fn confirm(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+    Json(req): Json<TotpCode>,
+) -> AppResult<Json<Vec<String>>> {
+    let user = current_user(&auth_session, user_id).await?;
+    if user.totp_enabled {
+        return Err(AppError::Forbidden(anyhow::anyhow!(
+            "Two-factor authentication is already enabled"
+        )));
+    }
+    if user.totp_secret.is_none() {
+        return Err(AppError::Forbidden(anyhow::anyhow!(
+            "No TOTP enrolment in progress"
+        )));
+    }
+    if !verify_totp(auth_session.backend.db(), &user, &req.code)
+        .await
+        .map_err(AppError::Other)?
+    {
+        return Err(AppError::Unauthorized(anyhow::anyhow!("Invalid code")));
+    }
+    let (codes, hashes) = generate_recovery_codes();
+    User::update_totp(
+        auth_session.backend.db(),
+        user.id,
+        user.totp_secret,
+        true,
+        &hashes,
+    )
+    .await?;
+    Ok(Json(codes))
+}
+
+/// Replaces the recovery codes, invalidating the old ones.
+#[tracing::instrument(skip(auth_session, req))]
+#[utoipa::path(post, path = "/recovery-codes", responses(
+    (status = OK, body = Vec<String>),
+    AppError
+))]
+async fn recovery_codes(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+    Json(req): Json<TotpCode>,
+) -> AppResult<Json<Vec<String>>> {
+    let db = auth_session.backend.db();
+    let user = check_enabled_user(&auth_session, user_id, &req.code).await?;
+    let (codes, hashes) = generate_recovery_codes();
+    User::update_totp(db, user.id, user.totp_secret, true, &hashes).await?;
+    Ok(Json(codes))
+}
+
+/// Turns two-factor authentication off. Needs a TOTP or recovery code.
+#[tracing::instrument(skip(auth_session, req))]
+#[utoipa::path(post, path = "/disable", responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn disable(
+    auth_session: AuthSession,
+    XSessionUserId(user_id): XSessionUserId,
+    Json(req): Json<TotpCode>,
+) -> AppResult<()> {
+    let user = check_enabled_user(&auth_session, user_id, &req.code).await?;
+    User::update_totp(auth_session.backend.db(), user.id, None, false, &[]).await?;
+    Ok(())
+}
+
+async fn check_enabled_user(
+    auth_session: &AuthSession,
+    user_id: Uuid,
+    code: &str,
+) -> AppResult<User> {
+    let user = current_user(auth_session, user_id).await?;
+    if !user.totp_enabled {
+        return Err(AppError::Forbidden(anyhow::anyhow!(
+            "Two-factor authentication is not enabled"
+        )));
+    }
+    if !verify_second_factor(auth_session.backend.db(), &user, code)
+        .await
+        .map_err(AppError::Other)?
+    {
+        return Err(AppError::Unauthorized(anyhow::anyhow!("Invalid code")));
+    }
+    // A used recovery code is gone now, so read the user again.
+    current_user(auth_session, user_id).await
+}
@@ -6,10 +6,15 @@ use sea_orm::prelude::Uuid;
 use serde::Deserialize;
 use utoipa::IntoParams;
 use utoipa_axum::{router::OpenApiRouter, routes};
+use validator::Validate;
 
 use crate::{
-    AppError, AppResult, ValidatedJson, XUserId, database,
-    model::transaction::{TransactionExpandedModel, TransactionReq},
+    AppError, AppResult, ValidatedJson, XLedger, database,
+    model::{
+        audit::AuditContext,
+        import_batch::{ImportBatchExpandedModel, ImportBatchModel, ImportBatchReq},
+        transaction::{TransactionExpandedModel, TransactionReq},
+    },
 };
 
 pub fn router() -> OpenApiRouter<()> {
@@ -18,6 +23,9 @@ pub fn router() -> OpenApiRouter<()> {
         .routes(routes![put_transactions])
         .routes(routes![transaction_by_id])
         .routes(routes![delete_transaction_item])
+        .routes(routes![import_batches])
+        .routes(routes![import_batch_by_id])
+        .routes(routes![rollback_import_batch])
 }
 
 #[tracing::instrument]
@@ -25,8 +33,8 @@ pub fn router() -> OpenApiRouter<()> {
     (status = OK, body = Vec<TransactionExpandedModel>),
     AppError
 ))]
-async fn transaction(id: XUserId) -> AppResult<Json<Vec<TransactionExpandedModel>>> {
-    let db = database(&id.0).await?;
+async fn transaction(ledger: XLedger) -> AppResult<Json<Vec<TransactionExpandedModel>>> {
+    let db = database(ledger.id).await?;
     Ok(Json(TransactionReq::find_all_with_items(&db).await?))
 }
 
@@ -36,10 +44,10 @@ async fn transaction(id: XUserId) -> AppResult<Json<Vec<TransactionExpandedModel
     AppError
 ))]
 async fn transaction_by_id(
-    id: XUserId,
+    ledger: XLedger,
     Path(transaction_id): Path<Uuid>,
 ) -> AppResult<Json<Option<TransactionExpandedModel>>> {
-    let db = database(&id.0).await?;
+    let db = database(ledger.id).await?;
     Ok(Json(
         TransactionReq::find_one_with_items(&db, transaction_id).await?,
     ))
@@ -57,11 +65,12 @@ struct DeleteTransactionParams {
     AppError
 ))]
 async fn delete_transaction(
-    id: XUserId,
+    ledger: XLedger,
+    audit: AuditContext,
     Query(DeleteTransactionParams { id: transaction_id }): Query<DeleteTransactionParams>,
 ) -> AppResult<()> {
-    let db = database(&id.0).await?;
-    TransactionReq::delete(&db, transaction_id).await?;
+    let db = database(ledger.id).await?;
+    TransactionReq::delete(&db, &audit, transaction_id).await?;
     Ok(())
 }
 
@@ -71,11 +80,12 @@ async fn delete_transaction(
     AppError
 ))]
 async fn delete_transaction_item(
-    id: XUserId,
+    ledger: XLedger,
+    audit: AuditContext,
     Query(DeleteTransactionParams { id: transaction_id }): Query<DeleteTransactionParams>,
 ) -> AppResult<()> {
-    let db = database(&id.0).await?;
-    TransactionReq::delete_item(&db, transaction_id).await?;
+    let db = database(ledger.id).await?;
+    TransactionReq::delete_item(&db, &audit, transaction_id).await?;
     Ok(())
 }
 
@@ -86,25 +96,69 @@ async fn delete_transaction_item(
     AppError
 ))]
 async fn put_transaction(
-    id: XUserId,
+    ledger: XLedger,
+    audit: AuditContext,
     ValidatedJson(transaction): ValidatedJson<TransactionReq>,
 ) -> AppResult<()> {
-    let db = database(&id.0).await?;
-    TransactionReq::upsert(&db, transaction).await?;
+    let db = database(ledger.id).await?;
+    TransactionReq::upsert(&db, &audit, transaction).await?;
     Ok(())
 }
 
 #[tracing::instrument(skip(transactions))]
-#[utoipa::path(put, path = "/import",
+#[utoipa::path(put, path = "/import", params(ImportBatchReq),
     request_body = Vec<TransactionReq>, responses(
-    (status = OK, body = ()),
+    (status = OK, body = Uuid),
     AppError
 ))]
 async fn put_transactions(
-    id: XUserId,
+    ledger: XLedger,
+    audit: AuditContext,
+    Query(batch): Query<ImportBatchReq>,
     ValidatedJson(transactions): ValidatedJson<Vec<TransactionReq>>,
+) -> AppResult<Json<Uuid>> {
+    batch.validate()?;
+    let db = database(ledger.id).await?;
+    let batch_id = TransactionReq::upsert_many(&db, &audit, batch, transactions).await?;
+    Ok(Json(batch_id))
+}
+
+#[tracing::instrument]
+#[utoipa::path(get, path = "/import-batch", responses(
+    (status = OK, body = Vec<ImportBatchModel>),
+    AppError
+))]
+async fn import_batches(ledger: XLedger) -> AppResult<Json<Vec<ImportBatchModel>>> {
+    let db = database(ledger.id).await?;
+    Ok(Json(ImportBatchReq::find_all(&db).await?))
+}
+
+#[tracing::instrument]
+#[utoipa::path(get, path = "/import-batch/{batch_id}", params(("batch_id" = Uuid, Path)), responses(
+    (status = OK, body = Option<ImportBatchExpandedModel>),
+    AppError
+))]
+async fn import_batch_by_id(
+    ledger: XLedger,
+    Path(batch_id): Path<Uuid>,
+) -> AppResult<Json<Option<ImportBatchExpandedModel>>> {
+    let db = database(ledger.id).await?;
+    Ok(Json(
+        ImportBatchReq::find_one_with_entries(&db, batch_id).await?,
+    ))
+}
+
+#[tracing::instrument]
+#[utoipa::path(post, path = "/import-batch/{batch_id}/rollback", params(("batch_id" = Uuid, Path)), responses(
+    (status = OK, body = ()),
+    AppError
+))]
+async fn rollback_import_batch(
+    ledger: XLedger,
+    audit: AuditContext,
+    Path(batch_id): Path<Uuid>,
 ) -> AppResult<()> {
-    let db = database(&id.0).await?;
-    TransactionReq::upsert_many(&db, transactions).await?;
+    let db = database(ledger.id).await?;
+    ImportBatchReq::rollback(&db, &audit, batch_id).await?;
     Ok(())
 }
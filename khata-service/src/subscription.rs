@@ -0,0 +1,219 @@
+//! Finds subscriptions in the spending of the ledger.
+//!
+//! A subscription is a payee that is charged at a regular interval, found
+//! the same way as the recurring transactions of the forecast, for an amount
+//! that rarely changes. Every change of the amount is kept as the price
+//! history, so a price rise is easy to spot.
+
+use std::collections::HashMap;
+
+use chrono::{Days, NaiveDate};
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    forecast::{self, Cadence},
+    insight::Spending,
+    model::subscription::{SubscriptionDecision, normalize_payee},
+};
+
+/// How much, in percent, an amount may differ from the previous charge
+/// without counting as a price change.
+const PRICE_TOLERANCE_PERCENT: i64 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, ToSchema, Serialize)]
+pub struct PriceChange {
+    pub date: NaiveDate,
+    pub from: i64,
+    pub to: i64,
+}
+
+/// A detected subscription. Amounts are positive, in minor units of the
+/// currency.
+#[derive(Debug, Clone, ToSchema, Serialize)]
+pub struct Subscription {
+    /// Title of the latest charge.
+    pub payee: String,
+    pub currency_code: String,
+    /// Account of the latest charge.
+    pub account_id: Uuid,
+    pub cadence: Cadence,
+    /// Amount of the latest charge.
+    pub amount: i64,
+    pub charges: usize,
+    pub first_charge: NaiveDate,
+    pub last_charge: NaiveDate,
+    pub next_charge: Option<NaiveDate>,
+    /// The latest amount charged for a year.
+    pub annual_cost: i64,
+    pub price_changes: Vec<PriceChange>,
+    /// Whether the user confirmed or dismissed the subscription.
+    pub decision: Option<SubscriptionDecision>,
+}
+
+/// Charges whose amount differs from the one before.
+pub fn price_changes(charges: &[(NaiveDate, i64)]) -> Vec<PriceChange> {
+    charges
+        .windows(2)
+        .filter(|w| (w[1].1 - w[0].1).abs() * 100 > w[0].1.abs() * PRICE_TOLERANCE_PERCENT)
+        .map(|w| PriceChange {
+            date: w[1].0,
+            from: w[0].1,
+            to: w[1].1,
+        })
+        .collect()
+}
+
+/// Subscriptions in the spending, most expensive per year first. The amount
+/// may change for at most one in three charges.
+pub fn detect(spending: &[Spending], today: NaiveDate) -> Vec<Subscription> {
+    let mut by_payee = HashMap::<(String, &str), Vec<&Spending>>::new();
+    for s in spending {
+        by_payee
+            .entry((normalize_payee(&s.payee), &s.currency_code))
+            .or_default()
+            .push(s);
+    }
+    let mut subscriptions = by_payee
+        .into_values()
+        .filter_map(|mut items| {
+            items.sort_by_key(|s| s.date);
+            let latest = *items.last()?;
+            let charges =
+                forecast::by_day(&items.iter().map(|s| (s.date, s.amount)).collect::<Vec<_>>());
+            let pattern = forecast::detect_pattern(&charges, today)?;
+            let price_changes = price_changes(&charges);
+            if price_changes.len() * 3 > charges.len() {
+                return None;
+            }
+            let amount = charges.last()?.1;
+            Some(Subscription {
+                payee: latest.payee.trim().to_string(),
+                currency_code: latest.currency_code.clone(),
+                account_id: latest.account_id,
+                cadence: pattern.cadence,
+                amount,
+                charges: charges.len(),
+                first_charge: charges.first()?.0,
+                last_charge: pattern.last,
+                // No cadence is longer than a year.
+                next_charge: pattern
+                    .dates(today, today + Days::new(366))
+                    .first()
+                    .copied(),
+                annual_cost: amount * pattern.cadence.per_year(),
+                price_changes,
+                decision: None,
+            })
+        })
+        .collect::<Vec<_>>();
+    subscriptions.sort_by(|a, b| {
+        b.annual_cost
+            .cmp(&a.annual_cost)
+            .then_with(|| a.payee.cmp(&b.payee))
+    });
+    subscriptions
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use migration::AccountType;
+
+    use super::*;
+    use crate::{insight, test_ledger};
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn charge(date: NaiveDate, payee: &str, amount: i64) -> Spending {
+        Spending {
+            transaction_id: Uuid::now_v7(),
+            date,
+            account_id: Uuid::nil(),
+            category_id: None,
+            payee: payee.to_string(),
+            currency_code: "INR".to_string(),
+            amount,
+        }
+    }
+
+    #[test]
+    fn monthly_subscription_with_price_rise() {
+        let spending = [
+            charge(date(2026, 5, 5), "Streamflix", 49_900),
+            charge(date(2026, 6, 5), "STREAMFLIX ", 49_900),
+            charge(date(2026, 7, 5), "Streamflix", 49_900),
+            charge(date(2026, 8, 5), "Streamflix", 64_900),
+            charge(date(2026, 9, 5), "Streamflix", 64_900),
+        ];
+        let subscriptions = detect(&spending, date(2026, 9, 20));
+        assert_eq!(subscriptions.len(), 1);
+        let s = &subscriptions[0];
+        assert_eq!(s.cadence, Cadence::Monthly);
+        assert_eq!(s.amount, 64_900);
+        assert_eq!(s.annual_cost, 64_900 * 12);
+        assert_eq!(s.next_charge, Some(date(2026, 10, 5)));
+        assert_eq!(
+            s.price_changes,
+            vec![PriceChange {
+                date: date(2026, 8, 5),
+                from: 49_900,
+                to: 64_900,
+            }]
+        );
+    }
+
+    #[test]
+    fn varying_amounts_are_not_a_subscription() {
+        let spending = [
+            charge(date(2026, 6, 1), "Grocer", 1_200),
+            charge(date(2026, 7, 1), "Grocer", 3_400),
+            charge(date(2026, 8, 1), "Grocer", 2_100),
+            charge(date(2026, 9, 1), "Grocer", 4_500),
+        ];
+        assert!(detect(&spending, date(2026, 9, 10)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn uncategorized_charges_are_found_but_transfers_are_not() {
+        let db = test_ledger::ledger().await;
+        let card = test_ledger::account(&db, "Card", AccountType::CreditCard, "INR", true).await;
+        let savings = test_ledger::account(&db, "Savings", AccountType::Bank, "INR", false).await;
+        for month in 6..=9 {
+            let day = date(2026, month, 3);
+            test_ledger::transaction(&db, "Streamflix", day, &[(card, None, -49_900)]).await;
+            test_ledger::transaction(
+                &db,
+                "Savings",
+                day,
+                &[(card, None, -500_000), (savings, None, 500_000)],
+            )
+            .await;
+        }
+        let today = date(2026, 9, 20);
+        let tz = jiff::tz::TimeZone::UTC;
+
+        let categorized = insight::load(&db, &tz, today, 6, false).await.unwrap();
+        assert!(categorized.is_empty());
+        let spending = insight::load(&db, &tz, today, 6, true).await.unwrap();
+        let subscriptions = detect(&spending, today);
+        assert_eq!(subscriptions.len(), 1);
+        assert_eq!(subscriptions[0].payee, "Streamflix");
+        assert_eq!(subscriptions[0].account_id, card);
+        assert_eq!(subscriptions[0].charges, 4);
+    }
+
+    #[test]
+    fn cancelled_subscription_is_dropped() {
+        let spending = [
+            charge(date(2026, 1, 10), "Gym", 150_000),
+            charge(date(2026, 2, 10), "Gym", 150_000),
+            charge(date(2026, 3, 10), "Gym", 150_000),
+        ];
+        assert_eq!(detect(&spending, date(2026, 3, 20)).len(), 1);
+        assert!(detect(&spending, date(2026, 6, 1)).is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+//! An in-memory ledger database for tests, with helpers to fill it.
+
+#![allow(clippy::unwrap_used)]
+
+use chrono::{DateTime, NaiveDate, Utc};
+use migration::{AccountType, MigratorTrait};
+use sea_orm::{ActiveValue, DbConn, EntityTrait};
+use uuid::Uuid;
+
+use crate::entity::{account, category, currency, transaction, transaction_item};
+
+/// A migrated ledger with the currencies `INR` and `USD`, both with two
+/// decimal digits.
+pub async fn ledger() -> DbConn {
+    let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+    migration::Migrator::up(&db, None).await.unwrap();
+    for (code, name) in [("INR", "Indian Rupee"), ("USD", "US Dollar")] {
+        currency::Entity::insert(currency::ActiveModel {
+            code: ActiveValue::Set(code.to_string()),
+            name: ActiveValue::Set(name.to_string()),
+            decimal_digits: ActiveValue::Set(2),
+        })
+        .exec(&db)
+        .await
+        .unwrap();
+    }
+    db
+}
+
+pub async fn account(
+    db: &DbConn,
+    name: &str,
+    account_type: AccountType,
+    currency_code: &str,
+    is_cash_flow: bool,
+) -> Uuid {
+    let id = Uuid::now_v7();
+    account::Entity::insert(account::ActiveModel {
+        id: ActiveValue::Set(id),
+        name: ActiveValue::Set(name.to_string()),
+        account_type: ActiveValue::Set(serde_json::to_string(&account_type).unwrap()),
+        currency_code: ActiveValue::Set(currency_code.to_string()),
+        starting_balance: ActiveValue::Set(0),
+        created_at: ActiveValue::Set(Utc::now()),
+        is_cash_flow: ActiveValue::Set(is_cash_flow),
+        is_active: ActiveValue::Set(true),
+        account_extra: ActiveValue::Set(None),
+    })
+    .exec(db)
+    .await
+    .unwrap();
+    id
+}
+
+pub async fn category(db: &DbConn, name: &str, group: &str) -> Uuid {
+    let id = Uuid::now_v7();
+    category::Entity::insert(category::ActiveModel {
+        id: ActiveValue::Set(id),
+        name: ActiveValue::Set(name.to_string()),
+        group: ActiveValue::Set(group.to_string()),
+        icon: ActiveValue::Set(String::new()),
+    })
+    .exec(db)
+    .await
+    .unwrap();
+    id
+}
+
+/// Adds a transaction at noon UTC of `date` with items of
+/// `(account_id, category_id, amount)`.
+pub async fn transaction(
+    db: &DbConn,
+    title: &str,
+    date: NaiveDate,
+    items: &[(Uuid, Option<Uuid>, i64)],
+) -> Uuid {
+    let timestamp = date.and_hms_opt(12, 0, 0).unwrap().and_utc();
+    transaction_at(db, title, timestamp, items).await
+}
+
+pub async fn transaction_at(
+    db: &DbConn,
+    title: &str,
+    timestamp: DateTime<Utc>,
+    items: &[(Uuid, Option<Uuid>, i64)],
+) -> Uuid {
+    let id = Uuid::now_v7();
+    transaction::Entity::insert(transaction::ActiveModel {
+        id: ActiveValue::Set(id),
+        title: ActiveValue::Set(title.to_string()),
+        timestamp: ActiveValue::Set(timestamp),
+        import_batch_id: ActiveValue::Set(None),
+    })
+    .exec(db)
+    .await
+    .unwrap();
+    for &(account_id, category_id, amount) in items {
+        transaction_item::Entity::insert(transaction_item::ActiveModel {
+            id: ActiveValue::Set(Uuid::now_v7()),
+            notes: ActiveValue::Set(String::new()),
+            transaction_id: ActiveValue::Set(id),
+            account_id: ActiveValue::Set(account_id),
+            category_id: ActiveValue::Set(category_id),
+            amount: ActiveValue::Set(amount),
+        })
+        .exec(db)
+        .await
+        .unwrap();
+    }
+    id
+}
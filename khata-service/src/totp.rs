@@ -0,0 +1,164 @@
+//! Time-based one-time passwords (RFC 6238) and recovery codes used as the
+//! second factor at sign in.
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::Utc;
+use sea_orm::DbConn;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use crate::{KEYS, keys::Keys, model::user::User};
+
+const ISSUER: &str = "Khata";
+const RECOVERY_CODE_COUNT: usize = 10;
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+pub fn generate_secret() -> anyhow::Result<Vec<u8>> {
+    Secret::generate_secret()
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("Invalid TOTP secret: {e:?}"))
+}
+
+pub fn totp(secret: Vec<u8>, email: &str) -> anyhow::Result<TOTP> {
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        secret,
+        Some(ISSUER.to_string()),
+        email.to_string(),
+    )
+    .map_err(|e| anyhow::anyhow!("Invalid TOTP parameters: {e:?}"))
+}
+
+/// The TOTP of the user, decrypted from the stored secret.
+pub fn user_totp(user: &User) -> anyhow::Result<Option<TOTP>> {
+    user.totp_secret
+        .as_deref()
+        .map(|secret| totp(KEYS.decrypt(secret)?, &user.email))
+        .transpose()
+}
+
+/// Returns new recovery codes in plain text and their hashes for storage.
+pub fn generate_recovery_codes() -> (Vec<String>, Vec<String>) {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let chars: String = (0..10)
+                .map(|_| {
+                    let i = OsRng.next_u32() as usize % RECOVERY_CODE_ALPHABET.len();
+                    char::from(RECOVERY_CODE_ALPHABET[i])
+                })
+                .collect();
+            format!("{}-{}", &chars[..5], &chars[5..])
+        })
+        .collect();
+    let hashes = codes.iter().map(|c| hash_recovery_code(&KEYS, c)).collect();
+    (codes, hashes)
+}
+
+/// Recovery codes are short, so they are stored keyed rather than plainly
+/// hashed, which would be quick to brute force from a leaked database.
+fn hash_recovery_code(keys: &Keys, code: &str) -> String {
+    let code: String = code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    keys.mac(code.as_bytes())
+}
+
+/// The time step, within the skew allowed by `totp`, whose code is `code`.
+fn matching_step(totp: &TOTP, code: &str, time: u64) -> Option<u64> {
+    let exact = TOTP {
+        skew: 0,
+        ..totp.clone()
+    };
+    let current = time / totp.step;
+    let skew = u64::from(totp.skew);
+    (current.saturating_sub(skew)..=current + skew).find(|step| exact.check(code, step * totp.step))
+}
+
+/// Checks a TOTP for the user. It is rejected when its time step, or a later
+/// one, was already accepted, so an observed code cannot be replayed.
+pub async fn verify_totp(db: &DbConn, user: &User, code: &str) -> anyhow::Result<bool> {
+    let Some(totp) = user_totp(user)? else {
+        return Ok(false);
+    };
+    let now = u64::try_from(Utc::now().timestamp())?;
+    match matching_step(&totp, code.trim(), now) {
+        Some(step) => Ok(User::use_totp_step(db, user.id, i64::try_from(step)?).await?),
+        None => Ok(false),
+    }
+}
+
+/// Checks a TOTP, see [`verify_totp`], or a recovery code for the user. A
+/// recovery code can only be used once and is removed when it matches.
+pub async fn verify_second_factor(db: &DbConn, user: &User, code: &str) -> anyhow::Result<bool> {
+    if user.totp_secret.is_none() {
+        return Ok(false);
+    }
+    if verify_totp(db, user, code).await? {
+        return Ok(true);
+    }
+    let hash = hash_recovery_code(&KEYS, code);
+    Ok(User::use_recovery_code(db, user.id, &hash).await?)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use user_migration::MigratorTrait;
+
+    use super::*;
+
+    #[test]
+    fn matching_step_allows_one_step_of_skew() {
+        let totp = totp(vec![7; 20], "ada@example.com").unwrap();
+        let time: u64 = 1_800_000_015;
+        let step = time / 30;
+        for offset in [-1, 0, 1] {
+            let code = totp.generate(step.checked_add_signed(offset).unwrap() * 30);
+            assert_eq!(
+                matching_step(&totp, &code, time),
+                step.checked_add_signed(offset)
+            );
+        }
+        let too_old = totp.generate((step - 2) * 30);
+        assert_eq!(matching_step(&totp, &too_old, time), None);
+    }
+
+    #[tokio::test]
+    async fn totp_cannot_be_replayed() {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        user_migration::Migrator::up(&db, None).await.unwrap();
+        let user = User::create_user(&db, "Ada", "ada@example.com", "hash")
+            .await
+            .unwrap();
+        assert!(User::use_totp_step(&db, user.id, 100).await.unwrap());
+        assert!(!User::use_totp_step(&db, user.id, 100).await.unwrap());
+        assert!(!User::use_totp_step(&db, user.id, 99).await.unwrap());
+        assert!(User::use_totp_step(&db, user.id, 101).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn recovery_codes_are_keyed_and_used_once() {
+        let keys = Keys::new(b"secret");
+        let hash = hash_recovery_code(&keys, "ABCDE-fghjk");
+        assert_eq!(hash, hash_recovery_code(&keys, "abcdefghjk"));
+        assert_ne!(hash, hash_recovery_code(&Keys::new(b"other"), "abcdefghjk"));
+
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        user_migration::Migrator::up(&db, None).await.unwrap();
+        let user = User::create_user(&db, "Ada", "ada@example.com", "hash")
+            .await
+            .unwrap();
+        let other = hash_recovery_code(&keys, "mnpqr-stuvw");
+        User::update_totp(&db, user.id, Some(vec![1]), true, &[hash.clone(), other])
+            .await
+            .unwrap();
+        assert!(User::use_recovery_code(&db, user.id, &hash).await.unwrap());
+        assert!(!User::use_recovery_code(&db, user.id, &hash).await.unwrap());
+        let user = User::find_by_id(&db, user.id).await.unwrap().unwrap();
+        assert_eq!(user.recovery_codes.len(), 1);
+    }
+}
@@ -0,0 +1,296 @@
+//! Self-service account deletion and "download all my data" exports.
+//!
+//! Deleting an account only schedules it. A background job removes the user,
+//! their sessions and the database files of their ledgers once the grace
+//! period is over, and cleans up exports once they expire.
+
+use std::{
+    io::{Cursor, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::Context;
+use chrono::Utc;
+use sea_orm::DbConn;
+use serde::Serialize;
+use uuid::Uuid;
+use zip::{ZipWriter, write::SimpleFileOptions};
+
+use crate::{
+    DATA_DIR, STARTED_AT,
+    auth::Backend,
+    database,
+    model::{
+        account::AccountReq, category::CategoryReq, currency::CurrencyReq, data_export::DataExport,
+        ledger::Ledger, rate_limit::RateLimit, transaction::TransactionReq, user::User,
+    },
+    remove_database,
+};
+
+/// Days between asking for the deletion of an account and the deletion.
+pub const DELETION_GRACE_DAYS: i64 = 7;
+/// Hours an export can be downloaded for.
+const EXPORT_TTL_HOURS: i64 = 24;
+/// Hours after which an export still being built is given up on.
+const EXPORT_STALE_HOURS: i64 = 1;
+
+pub fn export_path(export_id: Uuid) -> PathBuf {
+    DATA_DIR.join("exports").join(format!("{export_id}.zip"))
+}
+
+/// Builds the zip of an export and records whether that worked.
+pub async fn build_export(db: DbConn, export_id: Uuid, user_id: Uuid) {
+    let error = write_export(&db, export_id, user_id).await.err().map(|e| {
+        tracing::error!("Error building data export {}: {:?}", export_id, e);
+        e.to_string()
+    });
+    if let Err(e) = DataExport::complete(&db, export_id, error).await {
+        tracing::error!("Error completing data export {}: {:?}", export_id, e);
+    }
+}
+
+fn json<T: Serialize>(value: &T) -> anyhow::Result<Vec<u8>> {
+    Ok(serde_json::to_vec_pretty(value)?)
+}
+
+async fn write_export(db: &DbConn, export_id: Uuid, user_id: Uuid) -> anyhow::Result<()> {
+    let user = User::find_by_id(db, user_id)
+        .await?
+        .context("User not found")?;
+    let ledgers = Ledger::find_for_user(db, user_id).await?;
+
+    let mut files = vec![
+        ("profile.json".to_string(), json(&user)?),
+        ("settings.json".to_string(), json(&user.settings)?),
+        ("ledgers.json".to_string(), json(&ledgers)?),
+    ];
+    for ledger in &ledgers {
+        let ledger_db = database(ledger.id).await?;
+        let dir = format!("ledgers/{}", ledger.id);
+        files.push((
+            format!("{dir}/currencies.json"),
+            json(&CurrencyReq::find_all(&ledger_db).await?)?,
+        ));
+        files.push((
+            format!("{dir}/accounts.json"),
+            json(&AccountReq::find_all_with_currency(&ledger_db).await?)?,
+        ));
+        files.push((
+            format!("{dir}/categories.json"),
+            json(&CategoryReq::find_all(&ledger_db).await?)?,
+        ));
+        files.push((
+            format!("{dir}/transactions.json"),
+            json(&TransactionReq::find_all_with_items(&ledger_db).await?)?,
+        ));
+    }
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (name, content) in files {
+        zip.start_file(name, SimpleFileOptions::default())?;
+        zip.write_all(&content)?;
+    }
+    let content = zip.finish()?.into_inner();
+
+    let path = export_path(export_id);
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .context("Could not create export directory")?;
+    }
+    tokio::fs::write(&path, content)
+        .await
+        .context("Could not write export")?;
+    Ok(())
+}
+
+/// Deletes accounts whose grace period is over, expired exports and old
+/// rate limit hits.
+pub struct AccountReaper {
+    backend: Backend,
+}
+
+impl AccountReaper {
+    pub const fn new(backend: Backend) -> Self {
+        Self { backend }
+    }
+
+    /// Fails the exports a previous run left pending, then runs
+    /// [`Self::run_once`] every hour.
+    pub async fn run(self) {
+        let restarted =
+            DataExport::fail_pending_before(self.backend.db(), *STARTED_AT, "The server restarted")
+                .await;
+        if let Err(e) = restarted {
+            tracing::error!("Error failing interrupted exports: {:?}", e);
+        }
+        let mut interval = tokio::time::interval(Duration::from_hours(1));
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.run_once().await {
+                tracing::error!("Error removing deleted accounts: {:?}", e);
+            }
+        }
+    }
+
+    pub async fn run_once(&self) -> anyhow::Result<()> {
+        let db = self.backend.db();
+        for user in User::find_due_for_deletion(db).await? {
+            if let Err(e) = self.delete_user(&user).await {
+                tracing::error!("Error deleting user {}: {:?}", user.id, e);
+            }
+        }
+        let stale = Utc::now() - chrono::Duration::hours(EXPORT_STALE_HOURS);
+        DataExport::fail_pending_before(db, stale, "The export timed out").await?;
+        let expired = Utc::now() - chrono::Duration::hours(EXPORT_TTL_HOURS);
+        for export_id in DataExport::delete_older_than(db, expired).await? {
+            remove_export(export_id).await?;
+        }
+        RateLimit::prune(db).await?;
+        Ok(())
+    }
+
+    async fn delete_user(&self, user: &User) -> anyhow::Result<()> {
+        let (ledger_ids, export_ids) = self.delete_user_rows(user).await?;
+        for ledger_id in ledger_ids {
+            remove_database(ledger_id).await?;
+        }
+        for export_id in export_ids {
+            remove_export(export_id).await?;
+        }
+        tracing::info!("Deleted user {}", user.id);
+        Ok(())
+    }
+
+    /// Removes the user and their sessions, returning the ledgers and exports
+    /// whose files are left to remove.
+    async fn delete_user_rows(&self, user: &User) -> anyhow::Result<(Vec<Uuid>, Vec<Uuid>)> {
+        let db = self.backend.db();
+        self.backend.revoke_sessions(user.id, None, None).await?;
+        // Ledgers the user owns go with the user row, shared ones included.
+        let mut ledger_ids: Vec<Uuid> = Ledger::find_owned_by(db, user.id)
+            .await?
+            .into_iter()
+            .map(|l| l.id)
+            .collect();
+        if !ledger_ids.contains(&user.id) {
+            ledger_ids.push(user.id);
+        }
+        let export_ids = DataExport::find_for_user(db, user.id)
+            .await?
+            .into_iter()
+            .map(|e| e.id)
+            .collect();
+        User::delete(db, user.id).await?;
+        Ok((ledger_ids, export_ids))
+    }
+}
+
+async fn remove_export(export_id: Uuid) -> anyhow::Result<()> {
+    let path = export_path(export_id);
+    if tokio::fs::try_exists(&path)
+        .await
+        .context("Could not check export file")?
+    {
+        tokio::fs::remove_file(&path)
+            .await
+            .context("Could not remove export file")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use tower_sessions_sqlx_store::SqliteStore;
+    use user_migration::MigratorTrait;
+
+    use super::*;
+    use crate::model::ledger::{LedgerMemberReq, LedgerReq, LedgerRole};
+
+    #[tokio::test]
+    async fn deleting_a_user_removes_the_ledgers_they_own() {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        user_migration::Migrator::up(&db, None).await.unwrap();
+        let sessions = SqliteStore::new(db.get_sqlite_connection_pool().clone());
+        sessions.migrate().await.unwrap();
+        let reaper = AccountReaper::new(Backend::new(db.clone(), None, sessions));
+
+        let alice = User::create_user(&db, "Alice", "alice@example.com", "x")
+            .await
+            .unwrap();
+        let bob = User::create_user(&db, "Bob", "bob@example.com", "x")
+            .await
+            .unwrap();
+        Ledger::ensure_personal(&db, &alice).await.unwrap();
+        Ledger::ensure_personal(&db, &bob).await.unwrap();
+        let trip = Ledger::create(
+            &db,
+            alice.id,
+            LedgerReq {
+                name: "Trip".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let member = |email: &str, role| LedgerMemberReq {
+            email: email.to_string(),
+            role,
+        };
+        Ledger::upsert_member(&db, trip, member(&bob.email, LedgerRole::Editor))
+            .await
+            .unwrap();
+        // Owning a ledger someone else created does not take it down.
+        let house = Ledger::create(
+            &db,
+            bob.id,
+            LedgerReq {
+                name: "House".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        Ledger::upsert_member(&db, house, member(&alice.email, LedgerRole::Owner))
+            .await
+            .unwrap();
+        let export = DataExport::create_unless_pending(&db, alice.id)
+            .await
+            .unwrap()
+            .unwrap();
+
+        User::schedule_deletion(
+            &db,
+            alice.id,
+            Some(Utc::now() - chrono::Duration::minutes(1)),
+        )
+        .await
+        .unwrap();
+        let due = User::find_due_for_deletion(&db).await.unwrap();
+        assert_eq!(due.iter().map(|u| u.id).collect::<Vec<_>>(), vec![alice.id]);
+
+        let (mut ledger_ids, export_ids) = reaper.delete_user_rows(&due[0]).await.unwrap();
+        ledger_ids.sort();
+        let mut expected = vec![alice.id, trip];
+        expected.sort();
+        assert_eq!(ledger_ids, expected);
+        assert_eq!(export_ids, vec![export.id]);
+
+        assert!(User::find_by_id(&db, alice.id).await.unwrap().is_none());
+        let left: Vec<Uuid> = Ledger::find_for_user(&db, bob.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|l| l.id)
+            .collect();
+        assert!(left.contains(&bob.id));
+        assert!(left.contains(&house));
+        assert!(!left.contains(&trip));
+        assert!(
+            DataExport::find_for_user(&db, alice.id)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
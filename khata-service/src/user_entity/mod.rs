@@ -2,4 +2,16 @@
 
 pub mod prelude;
 
+pub mod api_token;
+pub mod auth_lockout;
+pub mod data_export;
+pub mod invite;
+pub mod ledger;
+pub mod ledger_member;
+pub mod notification;
+pub mod notification_rule;
+pub mod rate_limit_event;
+pub mod report_definition;
 pub mod user;
+pub mod user_identity;
+pub mod user_session;
@@ -1,3 +1,15 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.5
 
+pub use super::api_token::Entity as ApiToken;
+pub use super::auth_lockout::Entity as AuthLockout;
+pub use super::data_export::Entity as DataExport;
+pub use super::invite::Entity as Invite;
+pub use super::ledger::Entity as Ledger;
+pub use super::ledger_member::Entity as LedgerMember;
+pub use super::notification::Entity as Notification;
+pub use super::notification_rule::Entity as NotificationRule;
+pub use super::rate_limit_event::Entity as RateLimitEvent;
+pub use super::report_definition::Entity as ReportDefinition;
 pub use super::user::Entity as User;
+pub use super::user_identity::Entity as UserIdentity;
+pub use super::user_session::Entity as UserSession;
@@ -3,6 +3,8 @@
 use sea_orm::entity::prelude::*;
 use serde::{Deserialize, Serialize};
 
+// One boolean column per independent account flag.
+#[allow(clippy::struct_excessive_bools)]
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "user")]
 pub struct Model {
@@ -16,9 +18,99 @@ pub struct Model {
     pub created_at: DateTimeUtc,
     #[sea_orm(column_type = "JsonBinary", nullable)]
     pub settings: Option<Json>,
+    #[sea_orm(column_type = "Blob", nullable)]
+    pub totp_secret: Option<Vec<u8>>,
+    pub totp_enabled: bool,
+    #[sea_orm(column_type = "JsonBinary", nullable)]
+    pub recovery_codes: Option<Json>,
+    pub totp_last_step: Option<i64>,
+    pub deletion_scheduled_at: Option<DateTimeUtc>,
+    pub is_admin: bool,
+    pub disabled: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::api_token::Entity")]
+    ApiToken,
+    #[sea_orm(has_many = "super::data_export::Entity")]
+    DataExport,
+    #[sea_orm(has_many = "super::invite::Entity")]
+    Invite,
+    #[sea_orm(has_many = "super::ledger::Entity")]
+    Ledger,
+    #[sea_orm(has_many = "super::ledger_member::Entity")]
+    LedgerMember,
+    #[sea_orm(has_many = "super::notification::Entity")]
+    Notification,
+    #[sea_orm(has_many = "super::notification_rule::Entity")]
+    NotificationRule,
+    #[sea_orm(has_many = "super::report_definition::Entity")]
+    ReportDefinition,
+    #[sea_orm(has_many = "super::user_identity::Entity")]
+    UserIdentity,
+    #[sea_orm(has_many = "super::user_session::Entity")]
+    UserSession,
+}
+
+impl Related<super::api_token::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ApiToken.def()
+    }
+}
+
+impl Related<super::data_export::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::DataExport.def()
+    }
+}
+
+impl Related<super::invite::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Invite.def()
+    }
+}
+
+impl Related<super::ledger::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Ledger.def()
+    }
+}
+
+impl Related<super::ledger_member::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LedgerMember.def()
+    }
+}
+
+impl Related<super::notification::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Notification.def()
+    }
+}
+
+impl Related<super::notification_rule::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::NotificationRule.def()
+    }
+}
+
+impl Related<super::report_definition::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ReportDefinition.def()
+    }
+}
+
+impl Related<super::user_identity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserIdentity.def()
+    }
+}
+
+impl Related<super::user_session::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::UserSession.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
@@ -1,12 +1,40 @@
 pub use sea_orm_migration::prelude::*;
 
 mod m20250101_000000_create_table;
+mod m20261019_000001_create_ledger;
+mod m20261019_000002_create_notification;
+mod m20261019_000003_add_user_totp;
+mod m20261019_000004_create_api_token;
+mod m20261019_000005_create_user_identity;
+mod m20261019_000006_create_rate_limit;
+mod m20261019_000007_create_user_session;
+mod m20261019_000008_create_data_export;
+mod m20261019_000009_add_user_admin;
+mod m20261019_000010_create_invite;
+mod m20261019_000011_create_report_definition;
+mod m20261019_000012_add_user_totp_last_step;
+mod m20261019_000013_add_notification_next_attempt;
 
 pub struct Migrator;
 
 #[async_trait::async_trait]
 impl MigratorTrait for Migrator {
     fn migrations() -> Vec<Box<dyn MigrationTrait>> {
-        vec![Box::new(m20250101_000000_create_table::Migration)]
+        vec![
+            Box::new(m20250101_000000_create_table::Migration),
+            Box::new(m20261019_000001_create_ledger::Migration),
+            Box::new(m20261019_000002_create_notification::Migration),
+            Box::new(m20261019_000003_add_user_totp::Migration),
+            Box::new(m20261019_000004_create_api_token::Migration),
+            Box::new(m20261019_000005_create_user_identity::Migration),
+            Box::new(m20261019_000006_create_rate_limit::Migration),
+            Box::new(m20261019_000007_create_user_session::Migration),
+            Box::new(m20261019_000008_create_data_export::Migration),
+            Box::new(m20261019_000009_add_user_admin::Migration),
+            Box::new(m20261019_000010_create_invite::Migration),
+            Box::new(m20261019_000011_create_report_definition::Migration),
+            Box::new(m20261019_000012_add_user_totp_last_step::Migration),
+            Box::new(m20261019_000013_add_notification_next_attempt::Migration),
+        ]
     }
 }
@@ -0,0 +1,96 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Ledger::Table)
+                    .if_not_exists()
+                    .col(uuid(Ledger::Id).primary_key())
+                    .col(string(Ledger::Name))
+                    .col(uuid(Ledger::OwnerId))
+                    .col(timestamp(Ledger::CreatedAt).default("CURRENT_TIMESTAMP"))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_ledger_owner_id")
+                            .from(Ledger::Table, Ledger::OwnerId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(LedgerMember::Table)
+                    .if_not_exists()
+                    .col(uuid(LedgerMember::LedgerId))
+                    .col(uuid(LedgerMember::UserId))
+                    .col(string(LedgerMember::Role))
+                    .col(timestamp(LedgerMember::CreatedAt).default("CURRENT_TIMESTAMP"))
+                    .primary_key(
+                        Index::create()
+                            .col(LedgerMember::LedgerId)
+                            .col(LedgerMember::UserId),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_ledger_member_ledger_id")
+                            .from(LedgerMember::Table, LedgerMember::LedgerId)
+                            .to(Ledger::Table, Ledger::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_ledger_member_user_id")
+                            .from(LedgerMember::Table, LedgerMember::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(LedgerMember::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(Ledger::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Ledger {
+    Table,
+    Id,
+    Name,
+    OwnerId,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum LedgerMember {
+    Table,
+    LedgerId,
+    UserId,
+    Role,
+    CreatedAt,
+}
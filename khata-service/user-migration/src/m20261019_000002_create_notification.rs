@@ -0,0 +1,152 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(NotificationRule::Table)
+                    .if_not_exists()
+                    .col(uuid(NotificationRule::Id).primary_key())
+                    .col(uuid(NotificationRule::UserId))
+                    .col(uuid(NotificationRule::LedgerId))
+                    .col(json(NotificationRule::Condition))
+                    .col(json(NotificationRule::Channels))
+                    .col(string_null(NotificationRule::WebhookUrl))
+                    .col(boolean(NotificationRule::Enabled).default(true))
+                    .col(timestamp(NotificationRule::CreatedAt).default("CURRENT_TIMESTAMP"))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_notification_rule_user_id")
+                            .from(NotificationRule::Table, NotificationRule::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_notification_rule_ledger_id")
+                            .from(NotificationRule::Table, NotificationRule::LedgerId)
+                            .to(Ledger::Table, Ledger::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(Notification::Table)
+                    .if_not_exists()
+                    .col(uuid(Notification::Id).primary_key())
+                    .col(uuid(Notification::UserId))
+                    .col(uuid_null(Notification::RuleId))
+                    .col(string(Notification::Channel))
+                    .col(string(Notification::DedupeKey))
+                    .col(string(Notification::Title))
+                    .col(text(Notification::Body))
+                    .col(string(Notification::Status))
+                    .col(integer(Notification::Attempts).default(0))
+                    .col(text_null(Notification::LastError))
+                    .col(timestamp(Notification::CreatedAt).default("CURRENT_TIMESTAMP"))
+                    .col(timestamp_null(Notification::DeliveredAt))
+                    .col(timestamp_null(Notification::ReadAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_notification_user_id")
+                            .from(Notification::Table, Notification::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_notification_rule_id")
+                            .from(Notification::Table, Notification::RuleId)
+                            .to(NotificationRule::Table, NotificationRule::Id)
+                            .on_delete(ForeignKeyAction::SetNull)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_notification_dedupe")
+                    .table(Notification::Table)
+                    .col(Notification::UserId)
+                    .col(Notification::Channel)
+                    .col(Notification::DedupeKey)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_notification_status")
+                    .table(Notification::Table)
+                    .col(Notification::Status)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Notification::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(NotificationRule::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Ledger {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum NotificationRule {
+    Table,
+    Id,
+    UserId,
+    LedgerId,
+    Condition,
+    Channels,
+    WebhookUrl,
+    Enabled,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Notification {
+    Table,
+    Id,
+    UserId,
+    RuleId,
+    Channel,
+    DedupeKey,
+    Title,
+    Body,
+    Status,
+    Attempts,
+    LastError,
+    CreatedAt,
+    DeliveredAt,
+    ReadAt,
+}
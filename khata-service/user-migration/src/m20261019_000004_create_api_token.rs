@@ -0,0 +1,59 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ApiToken::Table)
+                    .if_not_exists()
+                    .col(uuid(ApiToken::Id).primary_key())
+                    .col(uuid(ApiToken::UserId))
+                    .col(string(ApiToken::Name))
+                    .col(string_uniq(ApiToken::TokenHash))
+                    .col(string(ApiToken::Scope))
+                    .col(timestamp_null(ApiToken::ExpiresAt))
+                    .col(timestamp_null(ApiToken::LastUsedAt))
+                    .col(timestamp(ApiToken::CreatedAt).default("CURRENT_TIMESTAMP"))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_api_token_user_id")
+                            .from(ApiToken::Table, ApiToken::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ApiToken::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum ApiToken {
+    Table,
+    Id,
+    UserId,
+    Name,
+    TokenHash,
+    Scope,
+    ExpiresAt,
+    LastUsedAt,
+    CreatedAt,
+}
@@ -0,0 +1,69 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(RateLimitEvent::Table)
+                    .if_not_exists()
+                    .col(uuid(RateLimitEvent::Id).primary_key())
+                    .col(string(RateLimitEvent::Key))
+                    .col(timestamp(RateLimitEvent::CreatedAt).default("CURRENT_TIMESTAMP"))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_rate_limit_event_key")
+                    .table(RateLimitEvent::Table)
+                    .col(RateLimitEvent::Key)
+                    .col(RateLimitEvent::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuthLockout::Table)
+                    .if_not_exists()
+                    .col(string(AuthLockout::Key).primary_key())
+                    .col(integer(AuthLockout::Failures).default(0))
+                    .col(timestamp_null(AuthLockout::LockedUntil))
+                    .col(timestamp(AuthLockout::UpdatedAt).default("CURRENT_TIMESTAMP"))
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuthLockout::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(RateLimitEvent::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum RateLimitEvent {
+    Table,
+    Id,
+    Key,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum AuthLockout {
+    Table,
+    Key,
+    Failures,
+    LockedUntil,
+    UpdatedAt,
+}
@@ -0,0 +1,57 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserSession::Table)
+                    .if_not_exists()
+                    .col(uuid(UserSession::Id).primary_key())
+                    .col(uuid(UserSession::UserId))
+                    .col(string_null(UserSession::SessionId))
+                    .col(string_null(UserSession::UserAgent))
+                    .col(string_null(UserSession::Ip))
+                    .col(timestamp(UserSession::CreatedAt).default("CURRENT_TIMESTAMP"))
+                    .col(timestamp(UserSession::LastSeenAt).default("CURRENT_TIMESTAMP"))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_user_session_user_id")
+                            .from(UserSession::Table, UserSession::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(UserSession::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum UserSession {
+    Table,
+    Id,
+    UserId,
+    SessionId,
+    UserAgent,
+    Ip,
+    CreatedAt,
+    LastSeenAt,
+}
@@ -0,0 +1,72 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(timestamp_null(User::DeletionScheduledAt))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_table(
+                Table::create()
+                    .table(DataExport::Table)
+                    .if_not_exists()
+                    .col(uuid(DataExport::Id).primary_key())
+                    .col(uuid(DataExport::UserId))
+                    .col(string(DataExport::Status))
+                    .col(text_null(DataExport::Error))
+                    .col(timestamp(DataExport::CreatedAt).default("CURRENT_TIMESTAMP"))
+                    .col(timestamp_null(DataExport::CompletedAt))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_data_export_user_id")
+                            .from(DataExport::Table, DataExport::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(DataExport::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(User::DeletionScheduledAt)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+    DeletionScheduledAt,
+}
+
+#[derive(DeriveIden)]
+enum DataExport {
+    Table,
+    Id,
+    UserId,
+    Status,
+    Error,
+    CreatedAt,
+    CompletedAt,
+}
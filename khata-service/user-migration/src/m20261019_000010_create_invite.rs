@@ -0,0 +1,57 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Invite::Table)
+                    .if_not_exists()
+                    .col(uuid(Invite::Id).primary_key())
+                    .col(string_uniq(Invite::CodeHash))
+                    .col(uuid(Invite::CreatedBy))
+                    .col(timestamp(Invite::ExpiresAt))
+                    .col(uuid_null(Invite::UsedBy))
+                    .col(timestamp_null(Invite::UsedAt))
+                    .col(timestamp(Invite::CreatedAt).default("CURRENT_TIMESTAMP"))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_invite_created_by")
+                            .from(Invite::Table, Invite::CreatedBy)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Invite::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Invite {
+    Table,
+    Id,
+    CodeHash,
+    CreatedBy,
+    ExpiresAt,
+    UsedBy,
+    UsedAt,
+    CreatedAt,
+}
@@ -0,0 +1,55 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(ReportDefinition::Table)
+                    .if_not_exists()
+                    .col(uuid(ReportDefinition::Id).primary_key())
+                    .col(uuid(ReportDefinition::UserId))
+                    .col(string(ReportDefinition::Name))
+                    .col(json(ReportDefinition::Query))
+                    .col(timestamp(ReportDefinition::CreatedAt).default("CURRENT_TIMESTAMP"))
+                    .col(timestamp(ReportDefinition::UpdatedAt).default("CURRENT_TIMESTAMP"))
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_report_definition_user_id")
+                            .from(ReportDefinition::Table, ReportDefinition::UserId)
+                            .to(User::Table, User::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ReportDefinition::Table).to_owned())
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum User {
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum ReportDefinition {
+    Table,
+    Id,
+    UserId,
+    Name,
+    Query,
+    CreatedAt,
+    UpdatedAt,
+}
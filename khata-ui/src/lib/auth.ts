@@ -35,6 +35,22 @@ export const useSignIn = defineMutation({
   },
 });
 
+export const useOidcSecondFactor = defineMutation({
+  mutation: async (data: { code: string }) => {
+    const { error } = await apiClient.POST("/khata-api/api/oidc/second-factor", {
+      body: data,
+    });
+    if (error) {
+      throw new Error(`Second Factor Error: ${error}`);
+    }
+  },
+  onSettled: (_data, error) => {
+    if (!error) {
+      queryCache.invalidateQueries({ key: USER_QUERY_KEYS.root });
+    }
+  },
+});
+
 export const useSignUp = defineMutation({
   mutation: async (data: { name: string; email: string; password: string }) => {
     const { error } = await apiClient.POST("/khata-api/api/signup", {
@@ -76,3 +92,14 @@ export const useResetAccount = defineMutation({
     }
   },
 });
+
+export const useResetPassword = defineMutation({
+  mutation: async (data: { token: string; password: string }) => {
+    const { error } = await apiClient.POST("/khata-api/api/reset-password", {
+      body: data,
+    });
+    if (error) {
+      throw new Error(`Reset Password Error: ${error}`);
+    }
+  },
+});
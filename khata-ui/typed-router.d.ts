@@ -38,6 +38,7 @@ declare module 'vue-router/auto-routes' {
     '/app/finance/transaction/[id].edit': RouteRecordInfo<'/app/finance/transaction/[id].edit', '/app/finance/transaction/:id/edit', { id: ParamValue<true> }, { id: ParamValue<false> }>,
     '/app/finance/transaction/new': RouteRecordInfo<'/app/finance/transaction/new', '/app/finance/transaction/new', Record<never, never>, Record<never, never>>,
     '/app/settings': RouteRecordInfo<'/app/settings', '/app/settings', Record<never, never>, Record<never, never>>,
+    '/reset-password': RouteRecordInfo<'/reset-password', '/reset-password', Record<never, never>, Record<never, never>>,
     '/signin': RouteRecordInfo<'/signin', '/signin', Record<never, never>, Record<never, never>>,
   }
 
@@ -132,6 +133,10 @@ declare module 'vue-router/auto-routes' {
       routes: '/app/settings'
       views: never
     }
+    'src/pages/reset-password.vue': {
+      routes: '/reset-password'
+      views: never
+    }
     'src/pages/signin.vue': {
       routes: '/signin'
       views: never